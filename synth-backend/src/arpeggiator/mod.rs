@@ -0,0 +1,409 @@
+//! Arpeggiator
+//!
+//! This module turns the keys that are held into a pattern of notes played one after another at a
+//! rate synced to the tempo. The arpeggiator sits between the key input and the voices: keys are
+//! passed to `key_down` and `key_up`, and `advance` is called once per sample to produce the timed
+//! `NoteEvent`s that start and stop the voices.
+//!
+//! The timing is counted in samples and the random mode uses its own seeded generator, so a pattern
+//! plays exactly the same in real time and in offline rendering.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::arpeggiator::{ArpMode, Arpeggiator, ArpeggiatorSettings, NoteEvent};
+//!
+//! // Play the held keys upwards over two octaves
+//! let mut arpeggiator = Arpeggiator::new(44100);
+//! arpeggiator.set_settings(ArpeggiatorSettings {
+//!     enabled: true,
+//!     mode: ArpMode::Up,
+//!     octaves: 2,
+//!     ..Default::default()
+//! });
+//! arpeggiator.key_down(60, 1.0);
+//! arpeggiator.key_down(64, 1.0);
+//!
+//! // The first note starts straight away
+//! arpeggiator.advance();
+//! assert_eq!(arpeggiator.pop_event(), Some(NoteEvent::NoteOn(60, 1.0)));
+//! ```
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use crate::transport::{DEFAULT_BPM, MAX_BPM, MIN_BPM};
+use crate::lifecycle::Prepare;
+
+/// Highest swing, at which the first step of each pair is three times as long as the second.
+pub const MAX_SWING: f32 = 0.5;
+/// Most notes the arpeggiator keeps track of at once.
+const MAX_NOTES: usize = 512;
+const NUM_KEYS: usize = 128;
+
+/// The order the arpeggiator plays the held keys in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArpMode {
+    /// From the lowest key to the highest.
+    #[default]
+    Up,
+    /// From the highest key to the lowest.
+    Down,
+    /// Up and back down again, without repeating the top and bottom keys.
+    UpDown,
+    /// A random held key on every step.
+    Random,
+    /// In the order the keys were pressed.
+    AsPlayed,
+    /// All held keys together on every step.
+    Chord,
+}
+
+impl ArpMode {
+    /// All arpeggiator modes, in the order they are offered in the user interface.
+    pub const ALL: [ArpMode; 6] = [ArpMode::Up, ArpMode::Down, ArpMode::UpDown, ArpMode::Random, ArpMode::AsPlayed, ArpMode::Chord];
+
+    /// Returns the display name of the mode.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArpMode::Up => "Up",
+            ArpMode::Down => "Down",
+            ArpMode::UpDown => "Up/Down",
+            ArpMode::Random => "Random",
+            ArpMode::AsPlayed => "As Played",
+            ArpMode::Chord => "Chord",
+        }
+    }
+}
+
+/// Length of one arpeggiator step as a note value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArpRate {
+    Quarter,
+    Eighth,
+    EighthTriplet,
+    #[default]
+    Sixteenth,
+    SixteenthTriplet,
+    ThirtySecond,
+}
+
+impl ArpRate {
+    /// All rates, in the order they are offered in the user interface.
+    pub const ALL: [ArpRate; 6] = [
+        ArpRate::Quarter,
+        ArpRate::Eighth,
+        ArpRate::EighthTriplet,
+        ArpRate::Sixteenth,
+        ArpRate::SixteenthTriplet,
+        ArpRate::ThirtySecond,
+    ];
+
+    /// Returns the display name of the rate.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArpRate::Quarter => "1/4",
+            ArpRate::Eighth => "1/8",
+            ArpRate::EighthTriplet => "1/8T",
+            ArpRate::Sixteenth => "1/16",
+            ArpRate::SixteenthTriplet => "1/16T",
+            ArpRate::ThirtySecond => "1/32",
+        }
+    }
+
+    /// Returns the length of a step in beats.
+    pub fn beats(&self) -> f64 {
+        match self {
+            ArpRate::Quarter => 1.0,
+            ArpRate::Eighth => 0.5,
+            ArpRate::EighthTriplet => 1.0 / 3.0,
+            ArpRate::Sixteenth => 0.25,
+            ArpRate::SixteenthTriplet => 1.0 / 6.0,
+            ArpRate::ThirtySecond => 0.125,
+        }
+    }
+}
+
+/// Per-patch settings of the arpeggiator.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArpeggiatorSettings {
+    /// Whether held keys are arpeggiated or played directly.
+    pub enabled: bool,
+    /// The order the held keys are played in.
+    pub mode: ArpMode,
+    /// The length of a step.
+    pub rate: ArpRate,
+    /// Over how many octaves the held keys are repeated, from 1 to 4.
+    pub octaves: u8,
+    /// How much of each step a note is held for, from 0.05 to 1.0.
+    pub gate: f32,
+    /// How much the first step of each pair is lengthened at the cost of the second, from 0.0 to `MAX_SWING`.
+    pub swing: f32,
+    /// Keep playing the pattern after the keys are let go, until a new key is pressed.
+    pub latch: bool,
+}
+
+impl Default for ArpeggiatorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: ArpMode::Up,
+            rate: ArpRate::Sixteenth,
+            octaves: 1,
+            gate: 0.5,
+            swing: 0.0,
+            latch: false,
+        }
+    }
+}
+
+/// A note started or stopped by the arpeggiator or the step sequencer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoteEvent {
+    /// Start the MIDI key at the velocity, between 0.0 and 1.0.
+    NoteOn(u8, f32),
+    /// Stop the MIDI key.
+    NoteOff(u8),
+}
+
+/// Sample-timed arpeggiator.
+#[derive(Clone, Debug)]
+pub struct Arpeggiator {
+    sample_rate: u32,
+    settings: ArpeggiatorSettings,
+    bpm: f32,
+    held: Vec<(u8, f32)>,
+    keys_down: [bool; NUM_KEYS],
+    pattern: Vec<(u8, f32)>,
+    sounding: Vec<u8>,
+    events: VecDeque<NoteEvent>,
+    running: bool,
+    step: usize,
+    position: f64,
+    step_samples: f64,
+    gate_samples: f64,
+    random_state: u32,
+}
+
+impl Arpeggiator {
+    /// Creates a new disabled `Arpeggiator` at `DEFAULT_BPM`.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            settings: ArpeggiatorSettings::default(),
+            bpm: DEFAULT_BPM,
+            held: Vec::with_capacity(NUM_KEYS),
+            keys_down: [false; NUM_KEYS],
+            pattern: Vec::with_capacity(MAX_NOTES),
+            sounding: Vec::with_capacity(MAX_NOTES),
+            events: VecDeque::with_capacity(2 * MAX_NOTES),
+            running: false,
+            step: 0,
+            position: 0.0,
+            step_samples: 0.0,
+            gate_samples: 0.0,
+            random_state: 1,
+        }
+    }
+
+    pub fn settings(&self) -> ArpeggiatorSettings {
+        self.settings
+    }
+
+    /// Sets the arpeggiator settings. The values are clamped to their ranges, and turning the
+    /// arpeggiator off stops the notes it is playing and forgets the held keys.
+    pub fn set_settings(&mut self, settings: ArpeggiatorSettings) {
+        let was_enabled = self.settings.enabled;
+        let latch_released = self.settings.latch && !settings.latch;
+        self.settings = ArpeggiatorSettings {
+            octaves: settings.octaves.clamp(1, 4),
+            gate: settings.gate.clamp(0.05, 1.0),
+            swing: settings.swing.clamp(0.0, MAX_SWING),
+            ..settings
+        };
+        if was_enabled && !settings.enabled {
+            self.clear();
+        } else if latch_released && !self.any_key_down() {
+            self.held.clear();
+        }
+        self.update_pattern();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    /// Sets the tempo the rate is synced to, in beats per minute.
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+    }
+
+    /// Returns the keys the pattern is made of, in the order they were pressed.
+    pub fn held_keys(&self) -> impl Iterator<Item = u8> + '_ {
+        self.held.iter().map(|(key, _)| *key)
+    }
+
+    /// Adds a key to the pattern. With latch on, the first key pressed after all keys were let go
+    /// starts a new pattern. A key that is already down, such as a repeat from a held computer key,
+    /// is ignored.
+    pub fn key_down(&mut self, key: u8, velocity: f32) {
+        let Some(down) = self.keys_down.get(key as usize).copied() else {
+            return;
+        };
+        if down {
+            return;
+        }
+        if self.settings.latch && !self.any_key_down() {
+            self.held.clear();
+        }
+        self.keys_down[key as usize] = true;
+        self.held.retain(|(held, _)| *held != key);
+        if self.held.len() < self.held.capacity() {
+            self.held.push((key, velocity.clamp(0.0, 1.0)));
+        }
+        self.update_pattern();
+    }
+
+    /// Removes a key from the pattern, unless latch is on.
+    pub fn key_up(&mut self, key: u8) {
+        if let Some(down) = self.keys_down.get_mut(key as usize) {
+            *down = false;
+        }
+        if !self.settings.latch {
+            self.held.retain(|(held, _)| *held != key);
+            self.update_pattern();
+        }
+    }
+
+    fn any_key_down(&self) -> bool {
+        self.keys_down.iter().any(|down| *down)
+    }
+
+    /// Stops the notes that are playing and forgets the held keys.
+    pub fn clear(&mut self) {
+        self.held.clear();
+        self.keys_down = [false; NUM_KEYS];
+        self.pattern.clear();
+        self.release_sounding();
+        self.running = false;
+    }
+
+    /// Moves the arpeggiator on by one sample. The notes started and stopped at this sample can then
+    /// be taken with `pop_event`.
+    pub fn advance(&mut self) {
+        if self.pattern.is_empty() {
+            self.release_sounding();
+            self.running = false;
+            return;
+        }
+        if !self.running {
+            self.running = true;
+            self.step = 0;
+            self.position = 0.0;
+            self.random_state = 1;
+            self.trigger_step();
+            return;
+        }
+        self.position += 1.0;
+        if self.position >= self.step_samples {
+            self.position -= self.step_samples;
+            self.step += 1;
+            self.release_sounding();
+            self.trigger_step();
+        } else if self.position >= self.gate_samples {
+            self.release_sounding();
+        }
+    }
+
+    /// Takes the next note started or stopped by `advance`.
+    pub fn pop_event(&mut self) -> Option<NoteEvent> {
+        self.events.pop_front()
+    }
+
+    fn trigger_step(&mut self) {
+        let beat_samples = 60.0 / self.bpm as f64 * self.sample_rate as f64;
+        let swing = self.settings.swing as f64;
+        let swing = if self.step.is_multiple_of(2) { 1.0 + swing } else { 1.0 - swing };
+        self.step_samples = (beat_samples * self.settings.rate.beats() * swing).max(1.0);
+        self.gate_samples = if self.settings.gate >= 1.0 {
+            f64::INFINITY
+        } else {
+            self.step_samples * self.settings.gate as f64
+        };
+
+        let length = self.pattern.len();
+        match self.settings.mode {
+            ArpMode::Chord => {
+                for index in 0..length {
+                    self.start(self.pattern[index]);
+                }
+            },
+            ArpMode::UpDown if length > 1 => {
+                let index = self.step % (2 * length - 2);
+                let index = if index < length { index } else { 2 * length - 2 - index };
+                self.start(self.pattern[index]);
+            },
+            ArpMode::Down => self.start(self.pattern[length - 1 - self.step % length]),
+            ArpMode::Random => {
+                let index = self.next_random() as usize % length;
+                self.start(self.pattern[index]);
+            },
+            _ => self.start(self.pattern[self.step % length]),
+        }
+    }
+
+    fn start(&mut self, (key, velocity): (u8, f32)) {
+        if !self.sounding.contains(&key) {
+            self.sounding.push(key);
+            self.events.push_back(NoteEvent::NoteOn(key, velocity));
+        }
+    }
+
+    fn release_sounding(&mut self) {
+        for key in self.sounding.drain(..) {
+            self.events.push_back(NoteEvent::NoteOff(key));
+        }
+    }
+
+    /// Rebuilds the pattern from the held keys: sorted for every mode but `AsPlayed`, and repeated
+    /// an octave higher for every extra octave.
+    fn update_pattern(&mut self) {
+        self.pattern.clear();
+        for octave in 0..self.settings.octaves {
+            for &(key, velocity) in self.held.iter() {
+                let key = key as u16 + 12 * octave as u16;
+                if key <= 127 && self.pattern.len() < MAX_NOTES {
+                    self.pattern.push((key as u8, velocity));
+                }
+            }
+        }
+        if self.settings.mode != ArpMode::AsPlayed {
+            self.pattern.sort_unstable_by_key(|(key, _)| *key);
+            self.pattern.dedup_by_key(|(key, _)| *key);
+        }
+    }
+
+    /// Xorshift generator, so that random patterns repeat exactly from the same start.
+    fn next_random(&mut self) -> u32 {
+        let mut state = self.random_state;
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        self.random_state = state;
+        state
+    }
+}
+
+impl Prepare for Arpeggiator {
+    /// Keeps the tempo and the place in the current step, so the pattern stays in time.
+    fn prepare(&mut self, sample_rate: u32, _max_block_size: usize) {
+        let ratio = sample_rate as f64 / self.sample_rate as f64;
+        self.sample_rate = sample_rate;
+        self.position *= ratio;
+        self.step_samples *= ratio;
+        self.gate_samples *= ratio;
+    }
+}
@@ -0,0 +1,222 @@
+//! cpal backend
+//!
+//! Plays the engine on an output device through cpal: the Web Audio API in the browser, or ALSA,
+//! PulseAudio, CoreAudio or WASAPI on the desktop. The stream format is negotiated with the device
+//! by `negotiate`, which looks through the configurations the device supports for the one closest
+//! to `StreamPreferences`:
+//!
+//! - the sample format comes first, preferring `F32`, then `I32`, `I16` and `U16`, the last two
+//!   dithered, as any other rate is only resampled from the internal rate of the engine;
+//! - then the sample rate closest to the one asked for;
+//! - then the channel count, preferring stereo, as the engine renders stereo frames.
+//!
+//! The buffer size is kept within the range the configuration supports.
+use std::sync::{Arc, Mutex};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, FromSample, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig, SupportedBufferSize, SupportedStreamConfigRange};
+use super::{prepare_engine, write_buffer, AudioBackend, Dither, Resampler};
+use crate::lifecycle::DEFAULT_MAX_BLOCK_SIZE;
+use crate::engine::IterablePolyphonyHashMap;
+use crate::utils::report_stream_error;
+
+/// Sample formats the backend can play, best first.
+pub const SUPPORTED_FORMATS: [SampleFormat; 4] = [SampleFormat::F32, SampleFormat::I32, SampleFormat::I16, SampleFormat::U16];
+/// Sample rate asked for when there is no preference.
+pub const PREFERRED_SAMPLE_RATE: u32 = 44100;
+/// Channel count asked for when there is no preference.
+pub const PREFERRED_CHANNELS: u16 = 2;
+
+/// What to ask an output device for. Anything left as `None` is left to the negotiation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamPreferences {
+    /// The sample rate, in Hz.
+    pub sample_rate: Option<u32>,
+    /// The number of channels.
+    pub channels: Option<u16>,
+    /// The number of frames the device asks for at a time.
+    pub buffer_size: Option<u32>,
+}
+
+/// The stream format agreed with an output device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamFormat {
+    /// The sample rate, in Hz.
+    pub sample_rate: u32,
+    /// The number of channels.
+    pub channels: u16,
+    /// The format of the samples.
+    pub sample_format: SampleFormat,
+    /// The number of frames the device asks for at a time, or `None` if it is left to the device.
+    pub buffer_size: Option<u32>,
+}
+
+impl StreamFormat {
+    /// Returns how long one buffer lasts in milliseconds, if the buffer size is known.
+    pub fn latency_ms(&self) -> Option<f32> {
+        self.buffer_size.map(|frames| frames as f32 * 1000.0 / self.sample_rate as f32)
+    }
+
+    fn config(&self) -> StreamConfig {
+        StreamConfig {
+            channels: self.channels,
+            sample_rate: SampleRate(self.sample_rate),
+            buffer_size: self.buffer_size.map_or(BufferSize::Default, BufferSize::Fixed),
+        }
+    }
+}
+
+/// Picks the stream format closest to `preferences` from the configurations a device supports.
+///
+/// # Errors
+///
+/// Returns an error if none of the configurations has a sample format the backend can play.
+pub fn negotiate(configs: &[SupportedStreamConfigRange], preferences: StreamPreferences) -> Result<StreamFormat, String> {
+    let wanted_rate = preferences.sample_rate.unwrap_or(PREFERRED_SAMPLE_RATE);
+    let wanted_channels = preferences.channels.unwrap_or(PREFERRED_CHANNELS);
+    configs.iter()
+        .filter_map(|config| {
+            let format_rank = SUPPORTED_FORMATS.iter().position(|format| *format == config.sample_format())?;
+            let sample_rate = wanted_rate.clamp(config.min_sample_rate().0, config.max_sample_rate().0);
+            let channels = config.channels();
+            // Fewer channels than wanted loses some, more channels than wanted are only left silent
+            let channel_rank = (channels < wanted_channels, channels.abs_diff(wanted_channels));
+            Some(((format_rank, sample_rate.abs_diff(wanted_rate), channel_rank), config, sample_rate))
+        })
+        .min_by_key(|(rank, _, _)| *rank)
+        .map(|(_, config, sample_rate)| StreamFormat {
+            sample_rate,
+            channels: config.channels(),
+            sample_format: config.sample_format(),
+            buffer_size: match *config.buffer_size() {
+                SupportedBufferSize::Range { min, max } => preferences.buffer_size.map(|frames| frames.clamp(min, max)),
+                SupportedBufferSize::Unknown => preferences.buffer_size,
+            },
+        })
+        .ok_or_else(|| "The output device plays no sample format the synthesizer supports!".to_owned())
+}
+
+/// Returns the names of the output devices of the default host.
+///
+/// # Errors
+///
+/// Returns an error if the devices cannot be listed.
+pub fn output_device_names() -> Result<Vec<String>, String> {
+    let devices = cpal::default_host().output_devices()
+        .map_err(|err| format!("The output devices cannot be listed: {err}"))?;
+    Ok(devices.filter_map(|device| device.name().ok()).collect())
+}
+
+/// Plays the engine on a cpal output device.
+pub struct CpalBackend {
+    device: cpal::Device,
+    format: StreamFormat,
+    stream: Option<Stream>,
+}
+
+impl CpalBackend {
+    /// Creates a new `CpalBackend` that plays on `device` in `format`.
+    pub fn new(device: cpal::Device, format: StreamFormat) -> Self {
+        Self { device, format, stream: None }
+    }
+
+    /// Creates a new `CpalBackend` that plays on `device` in the format negotiated from `preferences`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device cannot list its configurations or plays no supported format.
+    pub fn open(device: cpal::Device, preferences: StreamPreferences) -> Result<Self, String> {
+        let configs: Vec<SupportedStreamConfigRange> = device.supported_output_configs()
+            .map_err(|err| format!("The output device cannot be configured: {err}"))?
+            .collect();
+        let format = negotiate(&configs, preferences)?;
+        Ok(Self::new(device, format))
+    }
+
+    /// Creates a new `CpalBackend` that plays on the output device called `name`, or on the default
+    /// output device if `name` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such device, or it cannot be configured.
+    pub fn open_named(name: Option<&str>, preferences: StreamPreferences) -> Result<Self, String> {
+        let host = cpal::default_host();
+        let device = match name {
+            Some(name) => host.output_devices()
+                .map_err(|err| format!("The output devices cannot be listed: {err}"))?
+                .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+                .ok_or_else(|| format!("No output device called {name}!"))?,
+            None => host.default_output_device().ok_or("No output device found!")?,
+        };
+        Self::open(device, preferences)
+    }
+
+    /// Creates a new `CpalBackend` that plays on the default output device of the default host.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no output device, or it cannot be configured.
+    pub fn default_output() -> Result<Self, String> {
+        Self::open_named(None, StreamPreferences::default())
+    }
+
+    /// Returns the stream format agreed with the device.
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    fn build_stream<T: SizedSample + FromSample<f32>>(
+        &self,
+        engine: Arc<Mutex<IterablePolyphonyHashMap>>,
+        mut resampler: Option<Resampler>
+    ) -> Result<Stream, String> {
+        let channels = self.format.channels as usize;
+        let mut dither = Dither::for_format(self.format.sample_format);
+        self.device
+            .build_output_stream(
+                &self.format.config(),
+                move |data: &mut [T], _info: &cpal::OutputCallbackInfo| write_buffer(&engine, data, channels, &mut resampler, &mut dither),
+                report_stream_error,
+                None,
+            )
+            .map_err(|err| format!("The output stream could not be created: {err}"))
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn name(&self) -> String {
+        self.device.name().unwrap_or_else(|_| "Unknown device".to_owned())
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.format.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.format.channels
+    }
+
+    fn start(&mut self, engine: Arc<Mutex<IterablePolyphonyHashMap>>) -> Result<(), String> {
+        let max_block_size = self.format.buffer_size.map_or(DEFAULT_MAX_BLOCK_SIZE, |frames| frames as usize);
+        let resampler = prepare_engine(&engine, self.sample_rate(), max_block_size);
+        self.stop();
+        let stream = match self.format.sample_format {
+            SampleFormat::F32 => self.build_stream::<f32>(engine, resampler),
+            SampleFormat::I32 => self.build_stream::<i32>(engine, resampler),
+            SampleFormat::I16 => self.build_stream::<i16>(engine, resampler),
+            SampleFormat::U16 => self.build_stream::<u16>(engine, resampler),
+            format => Err(format!("The output device plays an unsupported sample format: {format}")),
+        }?;
+        stream.play().map_err(|err| format!("The output stream could not be started: {err}"))?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        // Dropping the stream closes it
+        self.stream = None;
+    }
+
+    fn is_running(&self) -> bool {
+        self.stream.is_some()
+    }
+}
@@ -0,0 +1,51 @@
+//! Dither
+//!
+//! Adds triangular noise of one least significant bit to samples before they are cut down to 16
+//! bits, which turns the distortion of quiet passages into a low, even hiss.
+use cpal::SampleFormat;
+
+/// Adds triangular dither noise for a sample format.
+#[derive(Clone, Copy, Debug)]
+pub struct Dither {
+    amplitude: f32,
+    state: u32,
+}
+
+impl Dither {
+    /// Creates a new `Dither` for samples cut down to `bits` bits. Formats of more than 16 bits
+    /// are not dithered, as their rounding noise is already far below hearing.
+    pub fn new(bits: u32) -> Self {
+        let amplitude = if bits == 0 || bits > 16 { 0.0 } else { 1.0 / (1u32 << (bits - 1)) as f32 };
+        Self { amplitude, state: 0x9E37_79B9 }
+    }
+
+    /// Creates a new `Dither` for a cpal sample format.
+    pub fn for_format(format: SampleFormat) -> Self {
+        match format {
+            SampleFormat::F32 | SampleFormat::F64 => Self::none(),
+            format => Self::new(format.sample_size() as u32 * 8),
+        }
+    }
+
+    /// Creates a `Dither` that leaves samples as they are.
+    pub fn none() -> Self {
+        Self::new(0)
+    }
+
+    /// Returns `sample` with dither noise added.
+    pub fn apply(&mut self, sample: f32) -> f32 {
+        if self.amplitude == 0.0 {
+            return sample;
+        }
+        // The difference of two uniform values is triangular between -1 and 1
+        (self.uniform() - self.uniform()).mul_add(self.amplitude, sample)
+    }
+
+    /// Returns a value between 0.0 and 1.0, from a xorshift generator that does not allocate or lock.
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
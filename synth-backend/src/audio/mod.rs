@@ -0,0 +1,114 @@
+//! Audio backends
+//!
+//! This module defines `AudioBackend`, the interface between the engine and whatever takes its
+//! samples away. The engine is shared as an `Arc<Mutex<IterablePolyphonyHashMap>>` and does not know
+//! which backend it is attached to. Starting a backend prepares the engine for the sample rate of the
+//! backend, or, when the engine has an internal sample rate, converts its output to the rate of the
+//! backend with a `Resampler`. The backends are:
+//!
+//! - `CpalBackend` plays on an output device through cpal, in the browser or on the desktop, in a
+//!   format negotiated with the device.
+//! - `OfflineBackend` renders as fast as it is asked to, into memory or a WAV file.
+//! - `NullBackend` runs the engine in real time and throws the samples away, for computers without a
+//!   sound card such as CI machines. It needs threads, so it is not available in the browser.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::{Arc, Mutex};
+//! use synth_backend::audio::{AudioBackend, OfflineBackend};
+//! use synth_backend::engine::IterablePolyphonyHashMap;
+//!
+//! let mut backend = OfflineBackend::new(44100, 2);
+//! let engine = Arc::new(Mutex::new(IterablePolyphonyHashMap::new(backend.sample_rate())));
+//! backend.start(Arc::clone(&engine)).unwrap();
+//!
+//! // Render one second of stereo audio
+//! backend.render(44100).unwrap();
+//! assert_eq!(backend.samples().len(), 2 * 44100);
+//! ```
+use std::sync::{Arc, Mutex};
+use cpal::{FromSample, SizedSample};
+use crate::lifecycle::Prepare;
+use crate::engine::IterablePolyphonyHashMap;
+use crate::utils::write_frames;
+
+pub mod cpal_backend;
+pub mod dither;
+pub mod offline;
+pub mod resampler;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod null;
+pub use cpal_backend::{negotiate, output_device_names, CpalBackend, StreamFormat, StreamPreferences};
+pub use dither::Dither;
+pub use offline::OfflineBackend;
+pub use resampler::Resampler;
+#[cfg(not(target_arch = "wasm32"))]
+pub use null::NullBackend;
+
+/// Something that runs the engine and takes its samples.
+pub trait AudioBackend {
+    /// Returns the name of the backend, such as the name of the device it plays on.
+    fn name(&self) -> String;
+
+    /// Returns the sample rate the engine has to run at.
+    fn sample_rate(&self) -> u32;
+
+    /// Returns the number of output channels.
+    fn channels(&self) -> u16;
+
+    /// Starts taking samples from `engine`, replacing any engine the backend was running. The engine
+    /// is prepared to run at the sample rate of the backend.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend cannot be started.
+    fn start(&mut self, engine: Arc<Mutex<IterablePolyphonyHashMap>>) -> Result<(), String>;
+
+    /// Stops taking samples and lets go of the engine.
+    fn stop(&mut self);
+
+    /// Returns whether the backend is running an engine.
+    fn is_running(&self) -> bool;
+}
+
+/// Prepares `engine` to run at the sample rate and block size of a backend, if it is not already.
+/// An engine with an internal sample rate is prepared for that rate instead, and the `Resampler` that
+/// converts it to the rate of the backend is returned.
+pub(crate) fn prepare_engine(engine: &Mutex<IterablePolyphonyHashMap>, sample_rate: u32, max_block_size: usize) -> Option<Resampler> {
+    let mut engine = engine.lock().unwrap();
+    let engine_rate = engine.internal_sample_rate().unwrap_or(sample_rate);
+    // One more frame than the block needs at the engine rate, for the resampler to catch up
+    let max_block_size = match engine_rate == sample_rate {
+        true => max_block_size,
+        false => (max_block_size as u64 * engine_rate as u64).div_ceil(sample_rate as u64) as usize + 1,
+    };
+    if engine.sample_rate() != engine_rate || engine.max_block_size() != max_block_size {
+        engine.prepare(engine_rate, max_block_size);
+    }
+    (engine_rate != sample_rate).then(|| Resampler::new(engine_rate, sample_rate))
+}
+
+/// Returns the next frame of `engine` at the rate of a backend, through `resampler` if there is one.
+pub(crate) fn next_frame(engine: &mut IterablePolyphonyHashMap, resampler: &mut Option<Resampler>) -> (f32, f32) {
+    match resampler {
+        Some(resampler) => resampler.next_frame(&mut || engine.get_stereo_sample()),
+        None => engine.get_stereo_sample(),
+    }
+}
+
+/// Fills the buffer of a device callback from `engine`, which is locked once for the whole buffer.
+/// When the interface is holding the engine, or a panic has poisoned it, the buffer is left silent
+/// instead of waiting or panicking on the audio thread.
+pub(crate) fn write_buffer<T: SizedSample + FromSample<f32>>(
+    engine: &Mutex<IterablePolyphonyHashMap>,
+    buffer: &mut [T],
+    channels: usize,
+    resampler: &mut Option<Resampler>,
+    dither: &mut Dither
+) {
+    match engine.try_lock() {
+        Ok(mut engine) => write_frames(buffer, channels, &mut || next_frame(&mut engine, resampler), dither),
+        Err(_) => buffer.fill(T::EQUILIBRIUM),
+    }
+}
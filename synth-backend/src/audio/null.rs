@@ -0,0 +1,93 @@
+//! Null backend
+//!
+//! Runs the engine in real time on a thread of its own and throws the samples away, as a sound card
+//! would take them, so that the arpeggiator, the sequencer and everything else driven by the audio
+//! keeps time on computers without an output device.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use super::{next_frame, prepare_engine, AudioBackend};
+use crate::engine::IterablePolyphonyHashMap;
+
+/// Sample rate of the null backend when none is asked for.
+pub const NULL_SAMPLE_RATE: u32 = 44100;
+/// Number of frames generated at a time.
+const BLOCK_FRAMES: usize = 512;
+
+/// Runs the engine in real time without playing it.
+#[derive(Debug)]
+pub struct NullBackend {
+    sample_rate: u32,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self::new(NULL_SAMPLE_RATE)
+    }
+}
+
+impl NullBackend {
+    /// Creates a new `NullBackend` that runs the engine at `sample_rate`.
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate: sample_rate.max(1), running: Arc::new(AtomicBool::new(false)), thread: None }
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn name(&self) -> String {
+        "Null".to_owned()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn start(&mut self, engine: Arc<Mutex<IterablePolyphonyHashMap>>) -> Result<(), String> {
+        let mut resampler = prepare_engine(&engine, self.sample_rate, BLOCK_FRAMES);
+        self.stop();
+        self.running = Arc::new(AtomicBool::new(true));
+        let running = Arc::clone(&self.running);
+        let block = Duration::from_secs_f64(BLOCK_FRAMES as f64 / self.sample_rate as f64);
+        self.thread = Some(std::thread::spawn(move || {
+            let mut deadline = Instant::now();
+            while running.load(Ordering::Relaxed) {
+                {
+                    let mut engine = engine.lock().unwrap();
+                    for _ in 0..BLOCK_FRAMES {
+                        next_frame(&mut engine, &mut resampler);
+                    }
+                }
+                deadline += block;
+                // Blocks are generated as they fall due, as a sound card would ask for them
+                if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                    std::thread::sleep(wait);
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.thread.is_some()
+    }
+}
+
+impl Drop for NullBackend {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,117 @@
+//! Offline backend
+//!
+//! Renders the engine only when asked to, as fast as the computer allows, and keeps the samples so
+//! that they can be inspected or written to a WAV file.
+use std::path::Path;
+use cpal::Sample;
+use std::sync::{Arc, Mutex};
+use super::{next_frame, prepare_engine, AudioBackend, Dither, Resampler};
+use crate::lifecycle::DEFAULT_MAX_BLOCK_SIZE;
+use crate::engine::IterablePolyphonyHashMap;
+use crate::utils::write_frames;
+
+/// Renders the engine on demand into interleaved samples.
+#[derive(Debug)]
+pub struct OfflineBackend {
+    sample_rate: u32,
+    channels: u16,
+    engine: Option<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    resampler: Option<Resampler>,
+    samples: Vec<f32>,
+}
+
+impl OfflineBackend {
+    /// Creates a new `OfflineBackend` with no samples rendered. It has at least one channel.
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self { sample_rate, channels: channels.max(1), engine: None, resampler: None, samples: Vec::new() }
+    }
+
+    /// Renders `frames` more frames of the engine.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend is not running an engine.
+    pub fn render(&mut self, frames: usize) -> Result<(), String> {
+        let engine = self.engine.as_ref().ok_or("The offline backend is not running an engine!")?;
+        let mut engine = engine.lock().unwrap();
+        let start = self.samples.len();
+        self.samples.resize(start + frames * self.channels as usize, 0.0);
+        let resampler = &mut self.resampler;
+        write_frames(&mut self.samples[start..], self.channels as usize, &mut || next_frame(&mut engine, resampler), &mut Dither::none());
+        Ok(())
+    }
+
+    /// Returns the interleaved samples rendered so far.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// Forgets the samples rendered so far.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Encodes the samples rendered so far as a dithered 16-bit PCM WAV file.
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data_length = (self.samples.len() * 2) as u32;
+        let block_align = self.channels * 2;
+        let mut wav = Vec::with_capacity(44 + data_length as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_length).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&self.channels.to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_length.to_le_bytes());
+        let mut dither = Dither::new(16);
+        for sample in &self.samples {
+            wav.extend_from_slice(&dither.apply(*sample).to_sample::<i16>().to_le_bytes());
+        }
+        wav
+    }
+
+    /// Writes the samples rendered so far to a 16-bit PCM WAV file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write_wav(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_wav()).map_err(|err| format!("Cannot write {}: {err}", path.display()))
+    }
+}
+
+impl AudioBackend for OfflineBackend {
+    fn name(&self) -> String {
+        "Offline".to_owned()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn start(&mut self, engine: Arc<Mutex<IterablePolyphonyHashMap>>) -> Result<(), String> {
+        self.resampler = prepare_engine(&engine, self.sample_rate, DEFAULT_MAX_BLOCK_SIZE);
+        self.engine = Some(engine);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.engine = None;
+        self.resampler = None;
+    }
+
+    fn is_running(&self) -> bool {
+        self.engine.is_some()
+    }
+}
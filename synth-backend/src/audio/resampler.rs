@@ -0,0 +1,157 @@
+//! Resampler
+//!
+//! Converts stereo frames from one sample rate to another with a band-limited polyphase filter, so
+//! that the engine can run at a fixed internal rate whatever rate the output device plays at. The
+//! same conversion runs the engine oversampled: an engine at 88.2 kHz on a 44.1 kHz device is filtered
+//! down to the device rate, and what it generates above the device Nyquist frequency is removed.
+//!
+//! The filter is a Kaiser-windowed sinc, cut off a little below the lower of the two Nyquist
+//! frequencies. It is stored as a table of phases between two input samples, and the coefficients for
+//! positions between two phases are interpolated from their neighbours.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::audio::Resampler;
+//!
+//! // A 440 Hz tone generated at 44.1 kHz, played at 48 kHz
+//! let mut resampler = Resampler::new(44100, 48000);
+//! let mut n = 0;
+//! let mut tone = || {
+//!     let sample = (2.0 * std::f32::consts::PI * 440.0 * n as f32 / 44100.0).sin();
+//!     n += 1;
+//!     (sample, sample)
+//! };
+//! let (left, right) = resampler.next_frame(&mut tone);
+//! ```
+
+/// Number of phases the filter is stored at between two input samples.
+const PHASES: usize = 256;
+/// Number of zero crossings of the sinc on each side of its centre when the rate goes up.
+const ZERO_CROSSINGS: usize = 16;
+/// Fraction of the lower Nyquist frequency the filter passes.
+const ROLLOFF: f64 = 0.92;
+/// Shape of the Kaiser window, trading the width of the transition band for stopband attenuation.
+const KAISER_BETA: f64 = 8.6;
+
+/// Zeroth order modified Bessel function of the first kind, for the Kaiser window.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..50 {
+        term *= half_x / k as f64;
+        let squared = term * term;
+        sum += squared;
+        if squared < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Band-limited polyphase sample rate converter for stereo frames.
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    taps: usize,
+    coefficients: Vec<f32>,
+    history: Vec<(f32, f32)>,
+    write_index: usize,
+    position: u64,
+}
+
+impl Resampler {
+    /// Creates a new `Resampler` from `input_rate` to `output_rate`, in Hz, with silent history.
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let input_rate = input_rate.max(1);
+        let output_rate = output_rate.max(1);
+        // Going down, the filter is stretched so that it cuts off below the output Nyquist frequency
+        let scale = (output_rate as f64 / input_rate as f64).min(1.0);
+        let half = (ZERO_CROSSINGS as f64 / scale).ceil() as usize;
+        let taps = 2 * half;
+        let cutoff = 0.5 * scale * ROLLOFF;
+        let window_norm = bessel_i0(KAISER_BETA);
+
+        let mut coefficients = Vec::with_capacity((PHASES + 1) * taps);
+        for phase in 0..=PHASES {
+            let frac = phase as f64 / PHASES as f64;
+            let row: Vec<f64> = (0..taps).map(|tap| {
+                let t = tap as f64 - (half - 1) as f64 - frac;
+                let x = t / half as f64;
+                let window = if x.abs() >= 1.0 { 0.0 } else { bessel_i0(KAISER_BETA * (1.0 - x * x).sqrt()) / window_norm };
+                let argument = 2.0 * cutoff * t;
+                let sinc = if argument == 0.0 { 1.0 } else { (std::f64::consts::PI * argument).sin() / (std::f64::consts::PI * argument) };
+                2.0 * cutoff * sinc * window
+            }).collect();
+            // Every phase passes DC at unity gain, so a constant input stays constant
+            let sum: f64 = row.iter().sum();
+            coefficients.extend(row.iter().map(|coefficient| (coefficient / sum) as f32));
+        }
+
+        Self {
+            input_rate,
+            output_rate,
+            taps,
+            coefficients,
+            history: vec![(0.0, 0.0); 2 * taps],
+            write_index: 0,
+            position: 0,
+        }
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    /// Returns how far the output lags behind the input, in output frames.
+    pub fn latency(&self) -> f32 {
+        (self.taps / 2) as f32 * self.output_rate as f32 / self.input_rate as f32
+    }
+
+    /// Clears the history, so that the next frames start from silence.
+    pub fn reset(&mut self) {
+        self.history.iter_mut().for_each(|frame| *frame = (0.0, 0.0));
+        self.write_index = 0;
+        self.position = 0;
+    }
+
+    /// Returns the next output frame, taking as many input frames from `input` as it needs.
+    pub fn next_frame(&mut self, input: &mut dyn FnMut() -> (f32, f32)) -> (f32, f32) {
+        // The position between the two newest input frames is counted in steps of 1 / output_rate
+        // of an input frame, so it never drifts
+        self.position += self.input_rate as u64;
+        while self.position >= self.output_rate as u64 {
+            self.position -= self.output_rate as u64;
+            self.push(input());
+        }
+
+        let phase = self.position as f64 * PHASES as f64 / self.output_rate as f64;
+        let index = (phase as usize).min(PHASES - 1);
+        let weight = (phase - index as f64) as f32;
+        let first = &self.coefficients[index * self.taps..(index + 1) * self.taps];
+        let second = &self.coefficients[(index + 1) * self.taps..(index + 2) * self.taps];
+        let frames = &self.history[self.write_index..self.write_index + self.taps];
+
+        let mut output = (0.0, 0.0);
+        for ((frame, first), second) in frames.iter().zip(first).zip(second) {
+            let coefficient = first + (second - first) * weight;
+            output.0 += frame.0 * coefficient;
+            output.1 += frame.1 * coefficient;
+        }
+        output
+    }
+
+    /// Stores an input frame twice, so that the newest `taps` frames are always in one slice, oldest
+    /// first, starting at `write_index`.
+    fn push(&mut self, frame: (f32, f32)) {
+        self.history[self.write_index] = frame;
+        self.history[self.write_index + self.taps] = frame;
+        self.write_index = (self.write_index + 1) % self.taps;
+    }
+}
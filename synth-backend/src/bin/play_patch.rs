@@ -0,0 +1,91 @@
+//! Plays a chord with the voice and settings of a patch on the default output device, or on the null
+//! backend when there is none. With an output file the chord is rendered offline to a WAV file instead.
+//!
+//! ```text
+//! cargo run -p synth-backend --no-default-features --features native --bin play_patch -- patch.json [seconds] [output.wav]
+//! ```
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use synth_backend::audio::{AudioBackend, OfflineBackend};
+use synth_backend::native::default_backend;
+use synth_backend::patch::Patch;
+use synth_backend::engine::IterablePolyphonyHashMap;
+
+/// Keys of the chord that is played, C major.
+const CHORD: [u8; 3] = [60, 64, 67];
+/// How long the chord is held by default, in seconds.
+const DEFAULT_SECONDS: f32 = 4.0;
+/// How long the effects are left to ring out after the chord, in seconds.
+const TAIL_SECONDS: f32 = 1.0;
+/// Sample rate of offline renders.
+const OFFLINE_SAMPLE_RATE: u32 = 48000;
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("play_patch: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or("Usage: play_patch <patch.json> [seconds] [output.wav]")?;
+    let seconds = match args.next() {
+        Some(seconds) => seconds.parse::<f32>().map_err(|_| format!("Not a number of seconds: {seconds}"))?.max(0.0),
+        None => DEFAULT_SECONDS,
+    };
+    let output = args.next();
+    let json = std::fs::read_to_string(&path).map_err(|err| format!("Cannot read {path}: {err}"))?;
+    let patch = Patch::from_json(&json)?;
+
+    match output {
+        Some(output) => {
+            let mut backend = OfflineBackend::new(OFFLINE_SAMPLE_RATE, 2);
+            let engine = start(&mut backend, &patch)?;
+            play_chord(&engine, &patch, backend.sample_rate(), |hold| {
+                backend.render((hold * OFFLINE_SAMPLE_RATE as f32) as usize)
+            }, seconds)?;
+            backend.write_wav(&output)?;
+            println!("Rendered {output}");
+        },
+        None => {
+            let mut backend = default_backend();
+            let engine = start(backend.as_mut(), &patch)?;
+            println!("Playing on {} at {} Hz", backend.name(), backend.sample_rate());
+            play_chord(&engine, &patch, backend.sample_rate(), |hold| {
+                std::thread::sleep(Duration::from_secs_f32(hold));
+                Ok(())
+            }, seconds)?;
+            backend.stop();
+        }
+    }
+    Ok(())
+}
+
+/// Creates an engine with the patch and attaches it to `backend`.
+fn start(backend: &mut dyn AudioBackend, patch: &Patch) -> Result<Arc<Mutex<IterablePolyphonyHashMap>>, String> {
+    let mut engine = IterablePolyphonyHashMap::new(backend.sample_rate());
+    engine.load_patch(patch)?;
+    let engine = Arc::new(Mutex::new(engine));
+    backend.start(Arc::clone(&engine))?;
+    Ok(engine)
+}
+
+/// Holds the chord for `seconds` and lets it ring out, with `hold` letting the time pass.
+fn play_chord(
+    engine: &Mutex<IterablePolyphonyHashMap>,
+    patch: &Patch,
+    sample_rate: u32,
+    mut hold: impl FnMut(f32) -> Result<(), String>,
+    seconds: f32
+) -> Result<(), String> {
+    let voice = patch.voice(sample_rate)?;
+    for key in CHORD {
+        engine.lock().unwrap().note_on(key, voice.clone(), 0.8)?;
+    }
+    hold(seconds)?;
+    for key in CHORD {
+        engine.lock().unwrap().note_off(key)?;
+    }
+    hold(TAIL_SECONDS)
+}
@@ -0,0 +1,178 @@
+//! Controllers
+//!
+//! This module describes the continuous performance controllers of the synthesizer: the pitch bend
+//! wheel and the mod wheel. Both act on every sounding voice at once without restarting their notes.
+//!
+//! The pitch bend is kept between -1.0 (fully down) and 1.0 (fully up) and is scaled by the bend
+//! range of the patch. The mod wheel is kept between 0.0 and 1.0 and by default sets the depth of
+//! the voice LFO. MIDI pitch bend and control change values are converted with `pitch_bend_from_midi`
+//! and `mod_wheel_from_midi`.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::controllers::{pitch_bend_from_midi, ControllerSettings, Controllers};
+//!
+//! // Bend up to a fifth, down by an octave
+//! let settings = ControllerSettings {
+//!     bend_up_semitones: 7.0,
+//!     bend_down_semitones: 12.0,
+//!     ..Default::default()
+//! };
+//! let mut controllers = Controllers::new(settings);
+//!
+//! // A MIDI pitch bend message with the wheel fully up
+//! controllers.set_pitch_bend(pitch_bend_from_midi(16383));
+//! let ratio = controllers.pitch_ratio();
+//! ```
+use serde::{Deserialize, Serialize};
+
+/// MIDI control change number of the mod wheel.
+pub const MOD_WHEEL_CC: u8 = 1;
+/// Position of the mod wheel before it is first moved. It starts fully up so that the LFO of a
+/// patch sounds at its full depth until the wheel is used.
+pub const DEFAULT_MOD_WHEEL: f32 = 1.0;
+/// Value of a centred MIDI pitch bend message.
+const MIDI_PITCH_BEND_CENTER: u16 = 8192;
+
+/// Converts a 14-bit MIDI pitch bend value between 0 and 16383 to a bend between -1.0 and 1.0.
+pub fn pitch_bend_from_midi(value: u16) -> f32 {
+    let offset = value.min(16383) as f32 - MIDI_PITCH_BEND_CENTER as f32;
+    if offset >= 0.0 {
+        offset / (16383 - MIDI_PITCH_BEND_CENTER) as f32
+    } else {
+        offset / MIDI_PITCH_BEND_CENTER as f32
+    }
+}
+
+/// Converts a bend between -1.0 and 1.0 to a 14-bit MIDI pitch bend value.
+pub fn pitch_bend_to_midi(bend: f32) -> u16 {
+    let bend = bend.clamp(-1.0, 1.0);
+    let range = if bend >= 0.0 { 16383 - MIDI_PITCH_BEND_CENTER } else { MIDI_PITCH_BEND_CENTER };
+    (MIDI_PITCH_BEND_CENTER as f32 + bend * range as f32).round() as u16
+}
+
+/// Converts a MIDI control change value between 0 and 127 to a mod wheel position between 0.0 and 1.0.
+pub fn mod_wheel_from_midi(value: u8) -> f32 {
+    value.min(127) as f32 / 127.0
+}
+
+/// What the mod wheel controls.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ModWheelTarget {
+    /// The mod wheel does nothing.
+    Off,
+    /// The mod wheel sets the depth of the voice LFO.
+    #[default]
+    LfoDepth,
+}
+
+impl ModWheelTarget {
+    /// All mod wheel targets, in the order they are offered in the user interface.
+    pub const ALL: [ModWheelTarget; 2] = [ModWheelTarget::Off, ModWheelTarget::LfoDepth];
+
+    /// Returns the display name of the target.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModWheelTarget::Off => "Off",
+            ModWheelTarget::LfoDepth => "LFO Depth",
+        }
+    }
+}
+
+/// Per-patch settings of the performance controllers.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerSettings {
+    /// How many semitones the pitch bend wheel raises the pitch by when fully up.
+    pub bend_up_semitones: f32,
+    /// How many semitones the pitch bend wheel lowers the pitch by when fully down.
+    pub bend_down_semitones: f32,
+    /// What the mod wheel controls.
+    pub mod_wheel_target: ModWheelTarget,
+}
+
+impl Default for ControllerSettings {
+    fn default() -> Self {
+        Self {
+            bend_up_semitones: 2.0,
+            bend_down_semitones: 2.0,
+            mod_wheel_target: ModWheelTarget::LfoDepth,
+        }
+    }
+}
+
+/// Current positions of the performance controllers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Controllers {
+    settings: ControllerSettings,
+    pitch_bend: f32,
+    mod_wheel: f32,
+}
+
+impl Default for Controllers {
+    fn default() -> Self {
+        Self::new(ControllerSettings::default())
+    }
+}
+
+impl Controllers {
+    /// Creates new `Controllers` with the pitch bend centred and the mod wheel at `DEFAULT_MOD_WHEEL`.
+    pub fn new(settings: ControllerSettings) -> Self {
+        Self {
+            settings,
+            pitch_bend: 0.0,
+            mod_wheel: DEFAULT_MOD_WHEEL,
+        }
+    }
+
+    pub fn settings(&self) -> ControllerSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ControllerSettings) {
+        self.settings = settings;
+    }
+
+    /// Returns the pitch bend, between -1.0 and 1.0.
+    pub fn pitch_bend(&self) -> f32 {
+        self.pitch_bend
+    }
+
+    /// Sets the pitch bend, between -1.0 (fully down) and 1.0 (fully up).
+    pub fn set_pitch_bend(&mut self, bend: f32) {
+        self.pitch_bend = bend.clamp(-1.0, 1.0);
+    }
+
+    /// Returns the mod wheel position, between 0.0 and 1.0.
+    pub fn mod_wheel(&self) -> f32 {
+        self.mod_wheel
+    }
+
+    /// Sets the mod wheel position, between 0.0 and 1.0.
+    pub fn set_mod_wheel(&mut self, position: f32) {
+        self.mod_wheel = position.clamp(0.0, 1.0);
+    }
+
+    /// Returns how many semitones the current pitch bend moves the pitch by.
+    pub fn pitch_bend_semitones(&self) -> f32 {
+        if self.pitch_bend >= 0.0 {
+            self.pitch_bend * self.settings.bend_up_semitones.max(0.0)
+        } else {
+            self.pitch_bend * self.settings.bend_down_semitones.max(0.0)
+        }
+    }
+
+    /// Returns the factor the frequency of every voice is multiplied by.
+    pub fn pitch_ratio(&self) -> f32 {
+        f32::powf(2.0, self.pitch_bend_semitones() / 12.0)
+    }
+
+    /// Returns the depth of the voice LFO, between 0.0 and 1.0.
+    pub fn lfo_depth(&self) -> f32 {
+        match self.settings.mod_wheel_target {
+            ModWheelTarget::Off => 1.0,
+            ModWheelTarget::LfoDepth => self.mod_wheel,
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Distortion
+//!
+//! This module provides a waveshaping drive stage. The signal is amplified by the input gain, bent by
+//! one of several transfer curves, smoothed by a tone control and scaled by the output gain. The curves
+//! add harmonics above the Nyquist frequency, so the shaping can run oversampled.
+//!
+//! A `Distortion` processes a single channel. It can be placed on each voice through
+//! `Synth::set_distortion`, before the voice filter, or on the master bus as the `Distortion` effect.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::distortion::{Distortion, DistortionParam, DistortionType, Oversampling};
+//!
+//! // Create a tanh drive stage for a 44100 Hz stream
+//! let mut distortion = Distortion::new(DistortionType::Tanh, 44100.0);
+//!
+//! // Push it harder and run the curve at four times the sample rate
+//! distortion.set_param(DistortionParam::InputGainDb, 18.0);
+//! distortion.set_oversampling(Oversampling::X4);
+//!
+//! // Process an input sample
+//! let output = distortion.process(0.5);
+//! ```
+use crate::filters::{Filter, FilterParam, FilterType};
+use crate::lifecycle::Prepare;
+use serde::{Deserialize, Serialize};
+
+pub mod oversampling;
+
+pub use oversampling::{Oversampler, Oversampling};
+
+/// Highest frequency of the tone control, at which it is left open.
+const MAX_TONE_HZ: f32 = 20000.0;
+/// Bias of the tube curve, which makes it clip earlier on positive half-waves.
+const TUBE_BIAS: f32 = 0.3;
+/// Pole of the DC blocker that removes the offset added by asymmetric curves.
+const DC_BLOCKER_POLE: f32 = 0.995;
+
+/// Transfer curves of the distortion.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistortionType {
+    /// Smooth saturation.
+    #[default]
+    Tanh,
+    /// Hard clipping at full scale.
+    HardClip,
+    /// Folds the signal back down whenever it passes full scale.
+    Foldback,
+    /// Asymmetric saturation that adds even harmonics.
+    Tube,
+    /// Bit depth and sample rate reduction.
+    Crush,
+}
+
+impl DistortionType {
+    /// All distortion types, in the order they are offered in the user interface.
+    pub const ALL: [DistortionType; 5] = [
+        DistortionType::Tanh,
+        DistortionType::HardClip,
+        DistortionType::Foldback,
+        DistortionType::Tube,
+        DistortionType::Crush,
+    ];
+
+    /// Returns the display name of the distortion type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DistortionType::Tanh => "Tanh",
+            DistortionType::HardClip => "Hard Clip",
+            DistortionType::Foldback => "Foldback",
+            DistortionType::Tube => "Tube",
+            DistortionType::Crush => "Crush",
+        }
+    }
+}
+
+/// Parameters that can be set for a distortion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DistortionParam {
+    /// Gain applied before the curve, in decibels.
+    InputGainDb,
+    /// Gain applied after the tone control, in decibels.
+    OutputGainDb,
+    /// Cutoff frequency of the lowpass tone control in Hertz.
+    ToneHz,
+    /// Bit depth of the crush curve.
+    Bits,
+    /// Sample rate the crush curve holds samples at, in Hertz.
+    CrushRateHz,
+}
+
+/// The transfer curve and the state it needs.
+#[derive(Clone, Copy, Debug)]
+struct Shaper {
+    distortion_type: DistortionType,
+    bits: f32,
+    hold_increment: f32,
+    hold_phase: f32,
+    held: f32,
+}
+
+impl Shaper {
+    fn process(&mut self, input: f32) -> f32 {
+        match self.distortion_type {
+            DistortionType::Tanh => input.tanh(),
+            DistortionType::HardClip => input.clamp(-1.0, 1.0),
+            DistortionType::Foldback => ((input - 1.0).rem_euclid(4.0) - 2.0).abs() - 1.0,
+            DistortionType::Tube => (input + TUBE_BIAS).tanh() - TUBE_BIAS.tanh(),
+            DistortionType::Crush => {
+                self.hold_phase += self.hold_increment;
+                if self.hold_phase >= 1.0 {
+                    self.hold_phase -= self.hold_phase.floor();
+                    let levels = f32::powf(2.0, self.bits - 1.0);
+                    self.held = (input.clamp(-1.0, 1.0) * levels).round() / levels;
+                }
+                self.held
+            }
+        }
+    }
+}
+
+/// Single channel waveshaping distortion.
+#[derive(Debug)]
+pub struct Distortion {
+    sample_rate_hz: f32,
+    input_gain_db: f32,
+    output_gain_db: f32,
+    input_gain: f32,
+    output_gain: f32,
+    tone_hz: f32,
+    crush_rate_hz: f32,
+    shaper: Shaper,
+    oversampler: Oversampler,
+    tone: Filter,
+    dc_x1: f32,
+    dc_y1: f32,
+}
+
+impl Clone for Distortion {
+    fn clone(&self) -> Self {
+        Self {
+            oversampler: self.oversampler.clone(),
+            tone: self.tone.clone(),
+            ..*self
+        }
+    }
+
+    // Reuses the oversampling stages, so that copying a voice does not allocate
+    fn clone_from(&mut self, source: &Self) {
+        self.sample_rate_hz = source.sample_rate_hz;
+        self.input_gain_db = source.input_gain_db;
+        self.output_gain_db = source.output_gain_db;
+        self.input_gain = source.input_gain;
+        self.output_gain = source.output_gain;
+        self.tone_hz = source.tone_hz;
+        self.crush_rate_hz = source.crush_rate_hz;
+        self.shaper = source.shaper;
+        self.oversampler.clone_from(&source.oversampler);
+        self.tone = source.tone.clone();
+        self.dc_x1 = source.dc_x1;
+        self.dc_y1 = source.dc_y1;
+    }
+}
+
+impl Distortion {
+    /// Creates a new `Distortion` with unity gain, the tone control open, 8 bits and crushing to a
+    /// quarter of the sample rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `distortion_type` - The transfer curve.
+    /// * `sample_rate_hz` - The sample rate in Hertz.
+    pub fn new(distortion_type: DistortionType, sample_rate_hz: f32) -> Self {
+        let tone_hz = MAX_TONE_HZ.min(0.45 * sample_rate_hz);
+        let mut distortion = Self {
+            sample_rate_hz,
+            input_gain_db: 0.0,
+            output_gain_db: 0.0,
+            input_gain: 1.0,
+            output_gain: 1.0,
+            tone_hz,
+            crush_rate_hz: sample_rate_hz / 4.0,
+            shaper: Shaper {
+                distortion_type,
+                bits: 8.0,
+                hold_increment: 1.0,
+                hold_phase: 0.0,
+                held: 0.0,
+            },
+            oversampler: Oversampler::new(Oversampling::Off),
+            tone: Filter::new(FilterType::LowPass, sample_rate_hz, tone_hz, 0.0),
+            dc_x1: 0.0,
+            dc_y1: 0.0,
+        };
+        distortion.update_hold_increment();
+        distortion
+    }
+
+    /// Process an input sample through the distortion and returns the output.
+    pub fn process(&mut self, input: f32) -> f32 {
+        let driven = input * self.input_gain;
+        let shaper = &mut self.shaper;
+        let shaped = self.oversampler.process(driven, |value| shaper.process(value));
+
+        let blocked = shaped - self.dc_x1 + DC_BLOCKER_POLE * self.dc_y1;
+        self.dc_x1 = shaped;
+        self.dc_y1 = blocked;
+
+        // The tone filter rings close to Nyquist, so it is left out entirely when fully open
+        let toned = if self.tone_hz < self.max_tone_hz() {
+            self.tone.process(blocked)
+        } else {
+            blocked
+        };
+        toned * self.output_gain
+    }
+
+    pub fn reset(&mut self) {
+        self.oversampler.reset();
+        self.tone.reset();
+        self.shaper.hold_phase = 0.0;
+        self.shaper.held = 0.0;
+        self.dc_x1 = 0.0;
+        self.dc_y1 = 0.0;
+    }
+
+    pub fn distortion_type(&self) -> DistortionType {
+        self.shaper.distortion_type
+    }
+
+    pub fn set_type(&mut self, distortion_type: DistortionType) {
+        self.shaper.distortion_type = distortion_type;
+    }
+
+    pub fn oversampling(&self) -> Oversampling {
+        self.oversampler.oversampling()
+    }
+
+    /// Sets the oversampling factor of the transfer curve.
+    pub fn set_oversampling(&mut self, oversampling: Oversampling) {
+        self.oversampler.set_oversampling(oversampling);
+        self.update_hold_increment();
+    }
+
+    pub fn get_param(&self, param: DistortionParam) -> f32 {
+        match param {
+            DistortionParam::InputGainDb => self.input_gain_db,
+            DistortionParam::OutputGainDb => self.output_gain_db,
+            DistortionParam::ToneHz => self.tone_hz,
+            DistortionParam::Bits => self.shaper.bits,
+            DistortionParam::CrushRateHz => self.crush_rate_hz,
+        }
+    }
+
+    /// Sets the parameter value of the distortion.
+    ///
+    /// # Arguments
+    ///
+    /// * `param` - The distortion parameter to set.
+    /// * `value` - The value to set the parameter to.
+    pub fn set_param(&mut self, param: DistortionParam, value: f32) {
+        match param {
+            DistortionParam::InputGainDb => {
+                self.input_gain_db = value;
+                self.input_gain = f32::powf(10.0, value / 20.0);
+            },
+            DistortionParam::OutputGainDb => {
+                self.output_gain_db = value;
+                self.output_gain = f32::powf(10.0, value / 20.0);
+            },
+            DistortionParam::ToneHz => {
+                self.tone_hz = value.clamp(20.0, self.max_tone_hz());
+                self.tone.set_param(FilterParam::FreqHz, self.tone_hz);
+            },
+            DistortionParam::Bits => self.shaper.bits = value.clamp(1.0, 24.0),
+            DistortionParam::CrushRateHz => {
+                self.crush_rate_hz = value.clamp(1.0, self.sample_rate_hz);
+                self.update_hold_increment();
+            }
+        }
+    }
+
+    fn max_tone_hz(&self) -> f32 {
+        MAX_TONE_HZ.min(0.45 * self.sample_rate_hz)
+    }
+
+    /// The crush curve runs at the oversampled rate, so its hold length is measured in oversampled
+    /// samples.
+    fn update_hold_increment(&mut self) {
+        let rate = self.sample_rate_hz * self.oversampling().factor() as f32;
+        self.shaper.hold_increment = self.crush_rate_hz / rate;
+    }
+}
+
+impl Prepare for Distortion {
+    /// Keeps the tone and crush rate in Hertz, within what the new rate can hold. A tone control that
+    /// is fully open stays fully open.
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        let open = self.tone_hz >= self.max_tone_hz();
+        self.sample_rate_hz = sample_rate as f32;
+        self.tone.prepare(sample_rate, max_block_size);
+        let tone_hz = if open { self.max_tone_hz() } else { self.tone_hz };
+        self.set_param(DistortionParam::ToneHz, tone_hz);
+        self.set_param(DistortionParam::CrushRateHz, self.crush_rate_hz);
+    }
+}
@@ -0,0 +1,207 @@
+//! Oversampling
+//!
+//! Runs a nonlinear function at 2, 4 or 8 times the sample rate so that the harmonics it creates above
+//! the original Nyquist frequency are filtered out instead of aliasing back into the audible band.
+//! Each factor of two is one stage of halfband interpolation on the way up and halfband decimation on
+//! the way down.
+use std::f32::consts::PI;
+
+/// Number of taps of each polyphase branch of the halfband filters. The equivalent halfband FIR has
+/// `4 * HALFBAND_HALF_TAPS - 1` taps.
+const HALFBAND_HALF_TAPS: usize = 8;
+const HALFBAND_TAPS: usize = 2 * HALFBAND_HALF_TAPS;
+/// Largest number of halfband stages, for 8x oversampling.
+const MAX_STAGES: usize = 3;
+const MAX_FACTOR: usize = 1 << MAX_STAGES;
+
+/// Oversampling factors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Oversampling {
+    /// Run at the original sample rate.
+    #[default]
+    Off,
+    /// Run at twice the sample rate.
+    X2,
+    /// Run at four times the sample rate.
+    X4,
+    /// Run at eight times the sample rate.
+    X8,
+}
+
+impl Oversampling {
+    /// All oversampling factors, in increasing order.
+    pub const ALL: [Oversampling; 4] = [Oversampling::Off, Oversampling::X2, Oversampling::X4, Oversampling::X8];
+
+    /// Returns the number of halfband stages needed for this factor.
+    pub fn stages(&self) -> usize {
+        match self {
+            Oversampling::Off => 0,
+            Oversampling::X2 => 1,
+            Oversampling::X4 => 2,
+            Oversampling::X8 => 3,
+        }
+    }
+
+    /// Returns the factor the sample rate is multiplied by.
+    pub fn factor(&self) -> usize {
+        1 << self.stages()
+    }
+}
+
+/// Coefficients of the odd polyphase branch of a Blackman windowed halfband lowpass. The even branch of
+/// a halfband filter is a single tap of 0.5 at its centre, so it reduces to a delay.
+fn halfband_coefficients() -> [f32; HALFBAND_TAPS] {
+    let mut coefficients = [0.0; HALFBAND_TAPS];
+    let half_length = HALFBAND_HALF_TAPS as f32;
+    for (i, coefficient) in coefficients.iter_mut().enumerate() {
+        // Distance from the centre, in samples at the lower rate
+        let distance = i as f32 - half_length + 0.5;
+        let sinc = (PI * distance).sin() / (PI * distance);
+        let window_position = (distance + half_length) / (2.0 * half_length);
+        let window = 0.42 - 0.5 * (2.0 * PI * window_position).cos() + 0.08 * (4.0 * PI * window_position).cos();
+        *coefficient = sinc * window;
+    }
+    let sum: f32 = coefficients.iter().sum();
+    coefficients.iter_mut().for_each(|coefficient| *coefficient /= sum);
+    coefficients
+}
+
+/// One stage of halfband interpolation and decimation.
+#[derive(Clone, Debug)]
+struct HalfbandStage {
+    coefficients: [f32; HALFBAND_TAPS],
+    up_history: [f32; HALFBAND_TAPS],
+    up_index: usize,
+    down_even: [f32; HALFBAND_TAPS],
+    down_odd: [f32; HALFBAND_TAPS],
+    down_index: usize,
+}
+
+impl HalfbandStage {
+    fn new() -> Self {
+        Self {
+            coefficients: halfband_coefficients(),
+            up_history: [0.0; HALFBAND_TAPS],
+            up_index: 0,
+            down_even: [0.0; HALFBAND_TAPS],
+            down_odd: [0.0; HALFBAND_TAPS],
+            down_index: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.up_history = [0.0; HALFBAND_TAPS];
+        self.down_even = [0.0; HALFBAND_TAPS];
+        self.down_odd = [0.0; HALFBAND_TAPS];
+        self.up_index = 0;
+        self.down_index = 0;
+    }
+
+    /// Sum of `history` weighted by the coefficients, oldest value first, where `index` is the position
+    /// of the oldest value.
+    fn convolve(&self, history: &[f32; HALFBAND_TAPS], index: usize) -> f32 {
+        let mut sum = 0.0;
+        for (i, coefficient) in self.coefficients.iter().enumerate() {
+            sum += coefficient * history[(index + i) % HALFBAND_TAPS];
+        }
+        sum
+    }
+
+    /// Turns one sample into two at twice the rate.
+    fn upsample(&mut self, input: f32) -> (f32, f32) {
+        self.up_history[self.up_index] = input;
+        self.up_index = (self.up_index + 1) % HALFBAND_TAPS;
+        // The oldest value now sits at `up_index`; the original sample passes through delayed by half
+        // the filter length and the interpolated one falls halfway between it and the next.
+        let centre = self.up_history[(self.up_index + HALFBAND_HALF_TAPS - 1) % HALFBAND_TAPS];
+        (centre, self.convolve(&self.up_history, self.up_index))
+    }
+
+    /// Turns two samples at twice the rate into one.
+    fn downsample(&mut self, even: f32, odd: f32) -> f32 {
+        let centre = self.down_even[(self.down_index + HALFBAND_HALF_TAPS) % HALFBAND_TAPS];
+        let output = 0.5 * centre + 0.5 * self.convolve(&self.down_odd, self.down_index);
+        self.down_even[self.down_index] = even;
+        self.down_odd[self.down_index] = odd;
+        self.down_index = (self.down_index + 1) % HALFBAND_TAPS;
+        output
+    }
+}
+
+/// Runs a function at a multiple of the sample rate.
+#[derive(Debug)]
+pub struct Oversampler {
+    oversampling: Oversampling,
+    stages: Vec<HalfbandStage>,
+}
+
+impl Clone for Oversampler {
+    fn clone(&self) -> Self {
+        Self {
+            oversampling: self.oversampling,
+            stages: self.stages.clone(),
+        }
+    }
+
+    // Copies into the stages already allocated when the factors match
+    fn clone_from(&mut self, source: &Self) {
+        self.oversampling = source.oversampling;
+        self.stages.clone_from(&source.stages);
+    }
+}
+
+impl Oversampler {
+    /// Creates a new `Oversampler`.
+    ///
+    /// # Arguments
+    ///
+    /// * `oversampling` - The oversampling factor.
+    pub fn new(oversampling: Oversampling) -> Self {
+        Self {
+            oversampling,
+            stages: (0..oversampling.stages()).map(|_| HalfbandStage::new()).collect(),
+        }
+    }
+
+    pub fn oversampling(&self) -> Oversampling {
+        self.oversampling
+    }
+
+    /// Changes the oversampling factor. The filter state is cleared.
+    pub fn set_oversampling(&mut self, oversampling: Oversampling) {
+        if oversampling != self.oversampling {
+            *self = Self::new(oversampling);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.stages.iter_mut().for_each(HalfbandStage::reset);
+    }
+
+    /// Upsamples `input`, runs `function` on every upsampled value and decimates the result back to
+    /// the original rate.
+    pub fn process<F: FnMut(f32) -> f32>(&mut self, input: f32, mut function: F) -> f32 {
+        let mut buffer = [0.0; MAX_FACTOR];
+        let mut scratch = [0.0; MAX_FACTOR];
+        buffer[0] = input;
+        let mut length = 1;
+        for stage in self.stages.iter_mut() {
+            for i in 0..length {
+                (scratch[2 * i], scratch[2 * i + 1]) = stage.upsample(buffer[i]);
+            }
+            std::mem::swap(&mut buffer, &mut scratch);
+            length *= 2;
+        }
+        for value in buffer.iter_mut().take(length) {
+            *value = function(*value);
+        }
+        for stage in self.stages.iter_mut().rev() {
+            length /= 2;
+            for i in 0..length {
+                scratch[i] = stage.downsample(buffer[2 * i], buffer[2 * i + 1]);
+            }
+            std::mem::swap(&mut buffer, &mut scratch);
+        }
+        buffer[0]
+    }
+}
@@ -0,0 +1,512 @@
+//! Dynamics
+//!
+//! This module provides the master dynamics section that sits after the effects rack, so that the sum
+//! of all voices never leaves the range of the output stream.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::dynamics::{CompressorParam, MasterDynamics};
+//!
+//! // Create the dynamics section for a 44100 Hz stream; the limiter is enabled by default
+//! let mut dynamics = MasterDynamics::new(44100);
+//!
+//! // Enable the compressor and lower its threshold
+//! dynamics.set_compressor_enabled(true);
+//! dynamics.compressor_mut().set_param(CompressorParam::ThresholdDb, -24.0);
+//!
+//! // Process a stereo frame and read back how much it was turned down
+//! let (left, right) = dynamics.process((1.5, 1.5));
+//! let reduction_db = dynamics.gain_reduction_db();
+//! ```
+//!
+//! # Compressor
+//!
+//! `Compressor` is a stereo-linked feed-forward compressor with a peak or RMS detector and a soft knee.
+//!
+//! # Limiter
+//!
+//! `Limiter` is a brickwall limiter. It delays the signal by its lookahead time so that the gain is
+//! already down when a peak arrives, and it never lets a sample through above its ceiling.
+//!
+//! # Soft clipping
+//!
+//! `soft_clip` bends everything above `SOFT_CLIP_KNEE` smoothly towards full scale. It takes the place
+//! of the limiter in `MasterDynamics` when the limiter is disabled, so that the output stays in range
+//! without it. With the limiter enabled its ceiling is the highest level, which the soft clip would
+//! otherwise bend.
+use std::collections::VecDeque;
+use crate::ring_buffer::RingBuffer;
+use crate::lifecycle::Prepare;
+
+/// Level above which `soft_clip` starts bending the signal.
+pub const SOFT_CLIP_KNEE: f32 = 0.8;
+/// Time over which the RMS detector averages.
+const RMS_WINDOW_MS: f32 = 10.0;
+/// Lookahead of the master limiter.
+const LIMITER_LOOKAHEAD_MS: f32 = 5.0;
+
+/// Converts a level in decibels to a linear gain.
+fn db_to_gain(db: f32) -> f32 {
+    f32::powf(10.0, db / 20.0)
+}
+
+/// Converts a linear gain to a level in decibels.
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-9).log10()
+}
+
+/// Coefficient of a one-pole smoother with the given time constant.
+fn smoothing_coefficient(time_ms: f32, sample_rate_hz: f32) -> f32 {
+    if time_ms <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-1000.0 / (time_ms * sample_rate_hz)).exp()
+    }
+}
+
+/// Passes the signal unchanged below `SOFT_CLIP_KNEE` and bends it smoothly towards, but never past,
+/// full scale above it.
+pub fn soft_clip(input: f32) -> f32 {
+    let magnitude = input.abs();
+    if magnitude <= SOFT_CLIP_KNEE {
+        input
+    } else {
+        let headroom = 1.0 - SOFT_CLIP_KNEE;
+        input.signum() * (SOFT_CLIP_KNEE + headroom * ((magnitude - SOFT_CLIP_KNEE) / headroom).tanh())
+    }
+}
+
+/// How the compressor measures the level of the signal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DetectorMode {
+    /// Reacts to the instantaneous peak level.
+    Peak,
+    /// Reacts to the average power, closer to perceived loudness.
+    #[default]
+    Rms,
+}
+
+/// Parameters that can be set for a compressor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressorParam {
+    /// Level above which the signal is compressed, in decibels.
+    ThresholdDb,
+    /// Input to output ratio above the threshold.
+    Ratio,
+    /// Width of the soft knee around the threshold, in decibels.
+    KneeDb,
+    /// Time to react to a rise in level, in milliseconds.
+    AttackMs,
+    /// Time to recover after the level falls, in milliseconds.
+    ReleaseMs,
+    /// Gain applied after compression, in decibels.
+    MakeupGainDb,
+}
+
+/// Stereo-linked feed-forward compressor.
+#[derive(Clone, Debug)]
+pub struct Compressor {
+    sample_rate_hz: f32,
+    detector: DetectorMode,
+    threshold_db: f32,
+    ratio: f32,
+    knee_db: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    makeup_gain_db: f32,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    rms_coefficient: f32,
+    mean_square: f32,
+    reduction_db: f32,
+}
+
+impl Compressor {
+    /// Creates a new `Compressor` with an RMS detector, a -18 dB threshold, a 4:1 ratio, a 6 dB knee,
+    /// 10 ms attack and 100 ms release.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate_hz` - The sample rate in Hertz.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let mut compressor = Self {
+            sample_rate_hz,
+            detector: DetectorMode::Rms,
+            threshold_db: -18.0,
+            ratio: 4.0,
+            knee_db: 6.0,
+            attack_ms: 10.0,
+            release_ms: 100.0,
+            makeup_gain_db: 0.0,
+            attack_coefficient: 1.0,
+            release_coefficient: 1.0,
+            rms_coefficient: smoothing_coefficient(RMS_WINDOW_MS, sample_rate_hz),
+            mean_square: 0.0,
+            reduction_db: 0.0,
+        };
+        compressor.update_coefficients();
+        compressor
+    }
+
+    /// Process a stereo frame through the compressor and returns the output.
+    pub fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        let level = match self.detector {
+            DetectorMode::Peak => input.0.abs().max(input.1.abs()),
+            DetectorMode::Rms => {
+                let power = 0.5 * (input.0 * input.0 + input.1 * input.1);
+                self.mean_square += (power - self.mean_square) * self.rms_coefficient;
+                self.mean_square.sqrt()
+            }
+        };
+        let target_db = self.static_reduction_db(gain_to_db(level));
+        let coefficient = if target_db > self.reduction_db {
+            self.attack_coefficient
+        } else {
+            self.release_coefficient
+        };
+        self.reduction_db += (target_db - self.reduction_db) * coefficient;
+
+        let gain = db_to_gain(self.makeup_gain_db - self.reduction_db);
+        (input.0 * gain, input.1 * gain)
+    }
+
+    /// Returns by how many decibels a steady signal at `level_db` is turned down.
+    fn static_reduction_db(&self, level_db: f32) -> f32 {
+        let overshoot = level_db - self.threshold_db;
+        let slope = 1.0 - 1.0 / self.ratio;
+        if 2.0 * overshoot < -self.knee_db {
+            0.0
+        } else if 2.0 * overshoot.abs() <= self.knee_db {
+            slope * (overshoot + self.knee_db / 2.0).powi(2) / (2.0 * self.knee_db)
+        } else {
+            slope * overshoot
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.mean_square = 0.0;
+        self.reduction_db = 0.0;
+    }
+
+    /// Returns the current gain reduction in decibels, as a positive value.
+    pub fn gain_reduction_db(&self) -> f32 {
+        self.reduction_db
+    }
+
+    pub fn detector(&self) -> DetectorMode {
+        self.detector
+    }
+
+    pub fn set_detector(&mut self, detector: DetectorMode) {
+        self.detector = detector;
+    }
+
+    pub fn get_param(&self, param: CompressorParam) -> f32 {
+        match param {
+            CompressorParam::ThresholdDb => self.threshold_db,
+            CompressorParam::Ratio => self.ratio,
+            CompressorParam::KneeDb => self.knee_db,
+            CompressorParam::AttackMs => self.attack_ms,
+            CompressorParam::ReleaseMs => self.release_ms,
+            CompressorParam::MakeupGainDb => self.makeup_gain_db,
+        }
+    }
+
+    /// Sets the parameter value of the compressor.
+    ///
+    /// # Arguments
+    ///
+    /// * `param` - The compressor parameter to set.
+    /// * `value` - The value to set the parameter to.
+    pub fn set_param(&mut self, param: CompressorParam, value: f32) {
+        match param {
+            CompressorParam::ThresholdDb => self.threshold_db = value.min(0.0),
+            CompressorParam::Ratio => self.ratio = value.max(1.0),
+            CompressorParam::KneeDb => self.knee_db = value.max(0.0),
+            CompressorParam::AttackMs => self.attack_ms = value.max(0.0),
+            CompressorParam::ReleaseMs => self.release_ms = value.max(0.0),
+            CompressorParam::MakeupGainDb => self.makeup_gain_db = value,
+        }
+        self.update_coefficients();
+    }
+
+    fn update_coefficients(&mut self) {
+        self.attack_coefficient = smoothing_coefficient(self.attack_ms, self.sample_rate_hz);
+        self.release_coefficient = smoothing_coefficient(self.release_ms, self.sample_rate_hz);
+    }
+}
+
+/// Parameters that can be set for a limiter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimiterParam {
+    /// Highest level the output reaches, in decibels.
+    CeilingDb,
+    /// Time to recover after a peak, in milliseconds.
+    ReleaseMs,
+}
+
+/// Stereo-linked lookahead brickwall limiter.
+///
+/// The gain needed to keep each frame under the ceiling is held at its minimum over the lookahead
+/// window and then averaged over the same window, so the gain glides down before a peak and is fully
+/// down when the delayed peak comes out.
+#[derive(Clone, Debug)]
+pub struct Limiter {
+    sample_rate_hz: f32,
+    lookahead_ms: f32,
+    ceiling_db: f32,
+    ceiling: f32,
+    release_ms: f32,
+    release_coefficient: f32,
+    window: usize,
+    delay_lines: [RingBuffer<f32>; 2],
+    minimum: VecDeque<(usize, f32)>,
+    averaging: Vec<f32>,
+    averaging_index: usize,
+    averaging_sum: f32,
+    released_gain: f32,
+    frame: usize,
+    gain: f32,
+}
+
+impl Limiter {
+    /// Creates a new `Limiter` with a -0.3 dB ceiling and 100 ms release.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate_hz` - The sample rate in Hertz.
+    /// * `lookahead_ms` - How far ahead the limiter looks for peaks, which is also its latency.
+    pub fn new(sample_rate_hz: f32, lookahead_ms: f32) -> Self {
+        let lookahead = ((lookahead_ms * sample_rate_hz / 1000.0).round() as usize).max(1);
+        let window = lookahead + 1;
+        let mut limiter = Self {
+            sample_rate_hz,
+            lookahead_ms,
+            ceiling_db: -0.3,
+            ceiling: db_to_gain(-0.3),
+            release_ms: 100.0,
+            release_coefficient: 1.0,
+            window,
+            delay_lines: [RingBuffer::new(lookahead), RingBuffer::new(lookahead)],
+            minimum: VecDeque::with_capacity(window + 1),
+            averaging: vec![1.0; window],
+            averaging_index: 0,
+            averaging_sum: window as f32,
+            released_gain: 1.0,
+            frame: 0,
+            gain: 1.0,
+        };
+        limiter.release_coefficient = smoothing_coefficient(limiter.release_ms, sample_rate_hz);
+        limiter
+    }
+
+    /// Returns the latency of the limiter in samples.
+    pub fn latency(&self) -> usize {
+        self.window - 1
+    }
+
+    /// Process a stereo frame through the limiter and returns the output, delayed by `latency`.
+    pub fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        let peak = input.0.abs().max(input.1.abs());
+        let required = if peak > self.ceiling { self.ceiling / peak } else { 1.0 };
+
+        // Minimum of the required gain over the window
+        while self.minimum.back().is_some_and(|&(_, gain)| gain >= required) {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.frame, required));
+        while self.minimum.front().is_some_and(|&(frame, _)| frame + self.window <= self.frame) {
+            self.minimum.pop_front();
+        }
+        let held = self.minimum.front().map_or(1.0, |&(_, gain)| gain);
+        self.frame += 1;
+
+        // Release towards unity, but never above what the window needs
+        self.released_gain = held.min(self.released_gain + (1.0 - self.released_gain) * self.release_coefficient);
+
+        self.averaging_sum += self.released_gain - self.averaging[self.averaging_index];
+        self.averaging[self.averaging_index] = self.released_gain;
+        self.averaging_index += 1;
+        if self.averaging_index == self.window {
+            // Recompute the sum once per window so rounding errors do not build up
+            self.averaging_index = 0;
+            self.averaging_sum = self.averaging.iter().sum();
+        }
+        self.gain = (self.averaging_sum / self.window as f32).min(1.0);
+
+        let left = self.delay_lines[0].pop();
+        let right = self.delay_lines[1].pop();
+        self.delay_lines[0].push(input.0);
+        self.delay_lines[1].push(input.1);
+        // The average can land a rounding error above the required gain
+        let output = (left * self.gain, right * self.gain);
+        (output.0.clamp(-self.ceiling, self.ceiling), output.1.clamp(-self.ceiling, self.ceiling))
+    }
+
+    pub fn reset(&mut self) {
+        self.delay_lines.iter_mut().for_each(RingBuffer::reset);
+        self.minimum.clear();
+        self.averaging.iter_mut().for_each(|gain| *gain = 1.0);
+        self.averaging_index = 0;
+        self.averaging_sum = self.window as f32;
+        self.released_gain = 1.0;
+        self.frame = 0;
+        self.gain = 1.0;
+    }
+
+    /// Returns the current gain reduction in decibels, as a positive value.
+    pub fn gain_reduction_db(&self) -> f32 {
+        -gain_to_db(self.gain)
+    }
+
+    pub fn get_param(&self, param: LimiterParam) -> f32 {
+        match param {
+            LimiterParam::CeilingDb => self.ceiling_db,
+            LimiterParam::ReleaseMs => self.release_ms,
+        }
+    }
+
+    /// Sets the parameter value of the limiter.
+    ///
+    /// # Arguments
+    ///
+    /// * `param` - The limiter parameter to set.
+    /// * `value` - The value to set the parameter to.
+    pub fn set_param(&mut self, param: LimiterParam, value: f32) {
+        match param {
+            LimiterParam::CeilingDb => {
+                self.ceiling_db = value.min(0.0);
+                self.ceiling = db_to_gain(self.ceiling_db);
+            },
+            LimiterParam::ReleaseMs => {
+                self.release_ms = value.max(0.0);
+                self.release_coefficient = smoothing_coefficient(self.release_ms, self.sample_rate_hz);
+            }
+        }
+    }
+}
+
+/// Compressor, then limiter or soft clipper, on the master bus.
+#[derive(Clone, Debug)]
+pub struct MasterDynamics {
+    compressor: Compressor,
+    limiter: Limiter,
+    compressor_enabled: bool,
+    limiter_enabled: bool,
+}
+
+impl MasterDynamics {
+    /// Creates a new `MasterDynamics` with the compressor disabled and the limiter enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - The sample rate in Hz.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            compressor: Compressor::new(sample_rate as f32),
+            limiter: Limiter::new(sample_rate as f32, LIMITER_LOOKAHEAD_MS),
+            compressor_enabled: false,
+            limiter_enabled: true,
+        }
+    }
+
+    /// Process a stereo frame through the enabled stages, and the soft clipper when the limiter is
+    /// disabled.
+    pub fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        let mut frame = input;
+        if self.compressor_enabled {
+            frame = self.compressor.process(frame);
+        }
+        if self.limiter_enabled {
+            self.limiter.process(frame)
+        } else {
+            (soft_clip(frame.0), soft_clip(frame.1))
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.compressor.reset();
+        self.limiter.reset();
+    }
+
+    /// Returns the combined gain reduction of the compressor and limiter in decibels, as a positive
+    /// value. Disabled stages do not contribute.
+    pub fn gain_reduction_db(&self) -> f32 {
+        let mut reduction = 0.0;
+        if self.compressor_enabled {
+            reduction += self.compressor.gain_reduction_db();
+        }
+        if self.limiter_enabled {
+            reduction += self.limiter.gain_reduction_db();
+        }
+        reduction
+    }
+
+    pub fn compressor(&self) -> &Compressor {
+        &self.compressor
+    }
+
+    pub fn compressor_mut(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
+    pub fn limiter(&self) -> &Limiter {
+        &self.limiter
+    }
+
+    pub fn limiter_mut(&mut self) -> &mut Limiter {
+        &mut self.limiter
+    }
+
+    pub fn is_compressor_enabled(&self) -> bool {
+        self.compressor_enabled
+    }
+
+    /// Enables or disables the compressor. It starts from a clean state when enabled again.
+    pub fn set_compressor_enabled(&mut self, enabled: bool) {
+        if enabled && !self.compressor_enabled {
+            self.compressor.reset();
+        }
+        self.compressor_enabled = enabled;
+    }
+
+    pub fn is_limiter_enabled(&self) -> bool {
+        self.limiter_enabled
+    }
+
+    /// Enables or disables the limiter. It starts from a clean state when enabled again.
+    pub fn set_limiter_enabled(&mut self, enabled: bool) {
+        if enabled && !self.limiter_enabled {
+            self.limiter.reset();
+        }
+        self.limiter_enabled = enabled;
+    }
+}
+
+impl Prepare for Compressor {
+    /// Keeps the attack and release times in milliseconds.
+    fn prepare(&mut self, sample_rate: u32, _max_block_size: usize) {
+        self.sample_rate_hz = sample_rate as f32;
+        self.rms_coefficient = smoothing_coefficient(RMS_WINDOW_MS, self.sample_rate_hz);
+        self.update_coefficients();
+    }
+}
+
+impl Prepare for Limiter {
+    /// Keeps the lookahead and release times in milliseconds and the ceiling. The lookahead window is
+    /// sized again for the new rate and starts out clean.
+    fn prepare(&mut self, sample_rate: u32, _max_block_size: usize) {
+        let mut limiter = Limiter::new(sample_rate as f32, self.lookahead_ms);
+        limiter.set_param(LimiterParam::CeilingDb, self.ceiling_db);
+        limiter.set_param(LimiterParam::ReleaseMs, self.release_ms);
+        *self = limiter;
+    }
+}
+
+impl Prepare for MasterDynamics {
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        self.compressor.prepare(sample_rate, max_block_size);
+        self.limiter.prepare(sample_rate, max_block_size);
+    }
+}
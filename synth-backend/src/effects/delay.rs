@@ -0,0 +1,178 @@
+//! Delay
+//!
+//! A stereo delay with filtered feedback. The delay time is either set in milliseconds or synced to a
+//! note division at a given tempo, and changes to it are smoothed so that sweeping the time bends the
+//! pitch of the repeats like a tape delay instead of producing zipper noise.
+use crate::filters::{Filter, FilterParam, FilterType};
+use crate::ring_buffer::RingBuffer;
+use super::{clamp_param, delay_tap, delay_write, Effect, EffectParamInfo, EffectType};
+
+/// Longest delay, in seconds, the delay lines are sized for.
+const MAX_DELAY_SEC: f32 = 4.0;
+/// Time constant, in seconds, of the smoothing applied to delay time changes.
+const SMOOTHING_SEC: f32 = 0.05;
+
+/// Note divisions the delay time can be synced to, with their length in beats.
+/// Index 0 means the delay is not synced.
+pub const SYNC_DIVISIONS: [(&str, f32); 11] = [
+    ("Off", 0.0),
+    ("1/1", 4.0),
+    ("1/2", 2.0),
+    ("1/4", 1.0),
+    ("1/8", 0.5),
+    ("1/16", 0.25),
+    ("1/32", 0.125),
+    ("1/4 dotted", 1.5),
+    ("1/8 dotted", 0.75),
+    ("1/4 triplet", 2.0 / 3.0),
+    ("1/8 triplet", 1.0 / 3.0),
+];
+
+const TIME_MS: usize = 0;
+const SYNC: usize = 1;
+const BPM: usize = 2;
+const FEEDBACK: usize = 3;
+const LOW_CUT_HZ: usize = 4;
+const HIGH_CUT_HZ: usize = 5;
+const PING_PONG: usize = 6;
+
+const PARAMETERS: [EffectParamInfo; 7] = [
+    EffectParamInfo { name: "Time (ms)", min: 1.0, max: 2000.0, default: 375.0 },
+    EffectParamInfo { name: "Sync", min: 0.0, max: (SYNC_DIVISIONS.len() - 1) as f32, default: 0.0 },
+    EffectParamInfo { name: "BPM", min: 20.0, max: 300.0, default: 120.0 },
+    EffectParamInfo { name: "Feedback", min: 0.0, max: 0.95, default: 0.4 },
+    EffectParamInfo { name: "Low Cut (Hz)", min: 20.0, max: 2000.0, default: 20.0 },
+    EffectParamInfo { name: "High Cut (Hz)", min: 500.0, max: 20000.0, default: 8000.0 },
+    EffectParamInfo { name: "Ping-Pong", min: 0.0, max: 1.0, default: 0.0 },
+];
+
+/// Stereo delay with a low cut and high cut filter in the feedback path.
+#[derive(Clone, Debug)]
+pub struct Delay {
+    sample_rate_hz: f32,
+    params: [f32; 7],
+    target_delay_samples: f32,
+    delay_samples: f32,
+    smoothing: f32,
+    delay_lines: [RingBuffer<f32>; 2],
+    low_cut: [Filter; 2],
+    high_cut: [Filter; 2],
+}
+
+impl Delay {
+    /// Creates a new `Delay` with default parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate_hz` - The sample rate in Hertz.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let capacity = (MAX_DELAY_SEC * sample_rate_hz) as usize + 2;
+        let mut params = [0.0; 7];
+        for (value, info) in params.iter_mut().zip(PARAMETERS.iter()) {
+            *value = info.default;
+        }
+        let low_cut = Filter::new(FilterType::HighPass, sample_rate_hz, params[LOW_CUT_HZ], 0.0);
+        let high_cut = Filter::new(FilterType::LowPass, sample_rate_hz, params[HIGH_CUT_HZ], 0.0);
+        let mut delay = Self {
+            sample_rate_hz,
+            params,
+            target_delay_samples: 1.0,
+            delay_samples: 1.0,
+            smoothing: 1.0 - (-1.0 / (SMOOTHING_SEC * sample_rate_hz)).exp(),
+            delay_lines: [RingBuffer::new(capacity), RingBuffer::new(capacity)],
+            low_cut: [low_cut.clone(), low_cut],
+            high_cut: [high_cut.clone(), high_cut],
+        };
+        delay.update_delay_time();
+        delay.delay_samples = delay.target_delay_samples;
+        delay
+    }
+
+    /// Returns the delay time in milliseconds, taking tempo sync into account.
+    pub fn delay_ms(&self) -> f32 {
+        let division = self.params[SYNC].round() as usize;
+        if division == 0 {
+            self.params[TIME_MS]
+        } else {
+            60000.0 / self.params[BPM] * SYNC_DIVISIONS[division].1
+        }
+    }
+
+    fn update_delay_time(&mut self) {
+        let max_delay = (self.delay_lines[0].capacity() - 1) as f32;
+        self.target_delay_samples = (self.delay_ms() * self.sample_rate_hz / 1000.0).clamp(1.0, max_delay);
+    }
+}
+
+impl Effect for Delay {
+    fn effect_type(&self) -> EffectType {
+        EffectType::Delay
+    }
+
+    fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        self.delay_samples += (self.target_delay_samples - self.delay_samples) * self.smoothing;
+        let left = delay_tap(&self.delay_lines[0], self.delay_samples);
+        let right = delay_tap(&self.delay_lines[1], self.delay_samples);
+
+        let feedback = self.params[FEEDBACK];
+        let feedback_left = self.high_cut[0].process(self.low_cut[0].process(left)) * feedback;
+        let feedback_right = self.high_cut[1].process(self.low_cut[1].process(right)) * feedback;
+
+        if self.params[PING_PONG] >= 0.5 {
+            // The input enters on the left only and every repeat crosses over to the other side.
+            delay_write(&mut self.delay_lines[0], (input.0 + input.1) * 0.5 + feedback_right);
+            delay_write(&mut self.delay_lines[1], feedback_left);
+        } else {
+            delay_write(&mut self.delay_lines[0], input.0 + feedback_left);
+            delay_write(&mut self.delay_lines[1], input.1 + feedback_right);
+        }
+        (left, right)
+    }
+
+    fn reset(&mut self) {
+        for channel in 0..2 {
+            self.delay_lines[channel].reset();
+            self.low_cut[channel].reset();
+            self.high_cut[channel].reset();
+        }
+        self.delay_samples = self.target_delay_samples;
+    }
+
+    fn parameters(&self) -> &'static [EffectParamInfo] {
+        &PARAMETERS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        self.params.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        if index >= PARAMETERS.len() {
+            return;
+        }
+        self.params[index] = clamp_param(&PARAMETERS[index], value);
+        match index {
+            TIME_MS | SYNC | BPM => self.update_delay_time(),
+            LOW_CUT_HZ => {
+                for filter in self.low_cut.iter_mut() {
+                    filter.set_param(FilterParam::FreqHz, self.params[LOW_CUT_HZ]);
+                }
+            },
+            HIGH_CUT_HZ => {
+                for filter in self.high_cut.iter_mut() {
+                    filter.set_param(FilterParam::FreqHz, self.params[HIGH_CUT_HZ]);
+                }
+            },
+            _ => ()
+        }
+    }
+
+    /// Sets the tempo used when the delay time is synced to a note division.
+    fn set_tempo(&mut self, bpm: f32) {
+        self.set_param(BPM, bpm);
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,96 @@
+//! Distortion
+//!
+//! The waveshaping drive stage of `crate::distortion` on the master bus, with one `Distortion` per
+//! channel.
+use crate::distortion::{Distortion as DriveStage, DistortionParam, DistortionType, Oversampling};
+use super::{clamp_param, Effect, EffectParamInfo, EffectType};
+
+const TYPE: usize = 0;
+const DRIVE_DB: usize = 1;
+const TONE_HZ: usize = 2;
+const OUTPUT_DB: usize = 3;
+const BITS: usize = 4;
+const CRUSH_RATE_HZ: usize = 5;
+const OVERSAMPLING: usize = 6;
+
+const PARAMETERS: [EffectParamInfo; 7] = [
+    EffectParamInfo { name: "Type", min: 0.0, max: (DistortionType::ALL.len() - 1) as f32, default: 0.0 },
+    EffectParamInfo { name: "Drive (dB)", min: 0.0, max: 48.0, default: 12.0 },
+    EffectParamInfo { name: "Tone (Hz)", min: 200.0, max: 20000.0, default: 20000.0 },
+    EffectParamInfo { name: "Output (dB)", min: -24.0, max: 12.0, default: -6.0 },
+    EffectParamInfo { name: "Bits", min: 1.0, max: 16.0, default: 8.0 },
+    EffectParamInfo { name: "Crush Rate (Hz)", min: 500.0, max: 48000.0, default: 11025.0 },
+    EffectParamInfo { name: "Oversampling", min: 0.0, max: (Oversampling::ALL.len() - 1) as f32, default: 1.0 },
+];
+
+/// Stereo waveshaping distortion.
+#[derive(Clone, Debug)]
+pub struct Distortion {
+    params: [f32; 7],
+    stages: [DriveStage; 2],
+}
+
+impl Distortion {
+    /// Creates a new `Distortion` with default parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate_hz` - The sample rate in Hertz.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let stage = DriveStage::new(DistortionType::Tanh, sample_rate_hz);
+        let mut distortion = Self {
+            params: [0.0; 7],
+            stages: [stage.clone(), stage],
+        };
+        for (index, info) in PARAMETERS.iter().enumerate() {
+            distortion.set_param(index, info.default);
+        }
+        distortion
+    }
+}
+
+impl Effect for Distortion {
+    fn effect_type(&self) -> EffectType {
+        EffectType::Distortion
+    }
+
+    fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        (self.stages[0].process(input.0), self.stages[1].process(input.1))
+    }
+
+    fn reset(&mut self) {
+        self.stages.iter_mut().for_each(DriveStage::reset);
+    }
+
+    fn parameters(&self) -> &'static [EffectParamInfo] {
+        &PARAMETERS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        self.params.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        if index >= PARAMETERS.len() {
+            return;
+        }
+        self.params[index] = clamp_param(&PARAMETERS[index], value);
+        let value = self.params[index];
+        for stage in self.stages.iter_mut() {
+            match index {
+                TYPE => stage.set_type(DistortionType::ALL[value.round() as usize]),
+                DRIVE_DB => stage.set_param(DistortionParam::InputGainDb, value),
+                TONE_HZ => stage.set_param(DistortionParam::ToneHz, value),
+                OUTPUT_DB => stage.set_param(DistortionParam::OutputGainDb, value),
+                BITS => stage.set_param(DistortionParam::Bits, value),
+                CRUSH_RATE_HZ => stage.set_param(DistortionParam::CrushRateHz, value),
+                OVERSAMPLING => stage.set_oversampling(Oversampling::ALL[value.round() as usize]),
+                _ => ()
+            }
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,82 @@
+//! Gain
+//!
+//! A utility stage that changes the output level and balances the signal between the left and right
+//! channels.
+use super::{clamp_param, Effect, EffectParamInfo, EffectType};
+
+const PARAMETERS: [EffectParamInfo; 2] = [
+    EffectParamInfo { name: "Gain (dB)", min: -24.0, max: 24.0, default: 0.0 },
+    EffectParamInfo { name: "Pan", min: -1.0, max: 1.0, default: 0.0 },
+];
+
+/// Output gain and stereo balance.
+#[derive(Clone, Debug)]
+pub struct Gain {
+    gain_db: f32,
+    pan: f32,
+    gain_left: f32,
+    gain_right: f32,
+}
+
+impl Gain {
+    /// Creates a new `Gain` stage at unity gain, centred.
+    pub fn new() -> Self {
+        let mut gain = Self {
+            gain_db: PARAMETERS[0].default,
+            pan: PARAMETERS[1].default,
+            gain_left: 1.0,
+            gain_right: 1.0,
+        };
+        gain.update_gains();
+        gain
+    }
+
+    fn update_gains(&mut self) {
+        let linear = f32::powf(10.0, self.gain_db / 20.0);
+        self.gain_left = linear * (1.0 - self.pan).min(1.0);
+        self.gain_right = linear * (1.0 + self.pan).min(1.0);
+    }
+}
+
+impl Default for Gain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for Gain {
+    fn effect_type(&self) -> EffectType {
+        EffectType::Gain
+    }
+
+    fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        (input.0 * self.gain_left, input.1 * self.gain_right)
+    }
+
+    fn reset(&mut self) {}
+
+    fn parameters(&self) -> &'static [EffectParamInfo] {
+        &PARAMETERS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        match index {
+            0 => self.gain_db,
+            1 => self.pan,
+            _ => 0.0
+        }
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.gain_db = clamp_param(&PARAMETERS[0], value),
+            1 => self.pan = clamp_param(&PARAMETERS[1], value),
+            _ => ()
+        }
+        self.update_gains();
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+}
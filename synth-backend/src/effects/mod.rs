@@ -0,0 +1,505 @@
+//! Effects
+//!
+//! This module provides the effects rack that sits on the master bus, after the voices in
+//! `IterablePolyphonyHashMap` are summed.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::effects::{EffectsChain, EffectType};
+//!
+//! // Create an empty chain for a 44100 Hz stream
+//! let mut chain = EffectsChain::new(44100);
+//!
+//! // Add a gain stage and set it to half wet
+//! let index = chain.push(EffectType::Gain);
+//! chain.set_mix(index, 0.5).unwrap();
+//!
+//! // Process one stereo frame through the chain
+//! let (left, right) = chain.process((0.5, 0.5));
+//!
+//! // Save the chain so it can be stored with a patch
+//! let state = chain.state();
+//! ```
+//!
+//! # Effect
+//!
+//! Every effect implements the `Effect` trait. An effect only produces the wet signal; bypass and the
+//! wet/dry balance are handled by the `EffectSlot` that holds it, so that every effect behaves the same
+//! way in the rack.
+//!
+//! # EffectsChain
+//!
+//! `EffectsChain` is an ordered list of `EffectSlot`s. Slots can be added, removed and reordered while
+//! audio is running, and the whole chain can be saved to and restored from a list of `EffectSlotState`s.
+use serde::{Deserialize, Serialize};
+use crate::ring_buffer::RingBuffer;
+use crate::transport::DEFAULT_BPM;
+use crate::lifecycle::Prepare;
+
+pub mod gain;
+pub mod delay;
+pub mod reverb;
+pub mod modulation;
+pub mod distortion;
+
+use gain::Gain;
+use delay::Delay;
+use reverb::Reverb;
+use modulation::{Chorus, Flanger, Phaser};
+use distortion::Distortion;
+
+/// Describes a single parameter exposed by an effect.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffectParamInfo {
+    /// Display name of the parameter.
+    pub name: &'static str,
+    /// Smallest accepted value.
+    pub min: f32,
+    /// Largest accepted value.
+    pub max: f32,
+    /// Value the parameter has when the effect is created.
+    pub default: f32,
+}
+
+/// Common interface of every effect that can be placed in an `EffectsChain`.
+///
+/// Effects process one stereo frame at a time and return the fully wet signal. Parameters are
+/// addressed by their index in the list returned by `parameters`.
+pub trait Effect: Send + std::fmt::Debug {
+    /// Returns the kind of effect, used to rebuild the effect from a saved state.
+    fn effect_type(&self) -> EffectType;
+
+    /// Processes one stereo frame and returns the wet output.
+    fn process(&mut self, input: (f32, f32)) -> (f32, f32);
+
+    /// Clears all internal state such as delay lines and filter memories.
+    fn reset(&mut self);
+
+    /// Returns the list of parameters exposed by the effect.
+    fn parameters(&self) -> &'static [EffectParamInfo];
+
+    /// Returns the current value of the parameter at `index`.
+    fn get_param(&self, index: usize) -> f32;
+
+    /// Sets the parameter at `index`. Values are clamped to the range given by `parameters`.
+    fn set_param(&mut self, index: usize, value: f32);
+
+    /// Follows the tempo of the transport, in beats per minute. Effects with times synced to note
+    /// divisions override this; the default does nothing.
+    fn set_tempo(&mut self, _bpm: f32) {}
+
+    /// Clones the effect into a new box.
+    fn clone_box(&self) -> Box<dyn Effect>;
+}
+
+impl Clone for Box<dyn Effect> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Kinds of effects available in the rack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EffectType {
+    /// Output gain and stereo panning.
+    Gain,
+    /// Tempo-synced stereo delay with filtered feedback.
+    Delay,
+    /// Algorithmic stereo reverb.
+    Reverb,
+    /// Multi-voice stereo chorus.
+    Chorus,
+    /// Stereo flanger with feedback.
+    Flanger,
+    /// Swept allpass phaser.
+    Phaser,
+    /// Oversampled waveshaping distortion.
+    Distortion,
+}
+
+impl EffectType {
+    /// All effect types, in the order they are offered in the user interface.
+    pub const ALL: [EffectType; 7] = [
+        EffectType::Gain,
+        EffectType::Delay,
+        EffectType::Reverb,
+        EffectType::Chorus,
+        EffectType::Flanger,
+        EffectType::Phaser,
+        EffectType::Distortion,
+    ];
+
+    /// Returns the display name of the effect type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectType::Gain => "Gain",
+            EffectType::Delay => "Delay",
+            EffectType::Reverb => "Reverb",
+            EffectType::Chorus => "Chorus",
+            EffectType::Flanger => "Flanger",
+            EffectType::Phaser => "Phaser",
+            EffectType::Distortion => "Distortion",
+        }
+    }
+
+    /// Returns the wet/dry mix a new slot of this type starts with.
+    pub fn default_mix(&self) -> f32 {
+        match self {
+            EffectType::Gain | EffectType::Distortion => 1.0,
+            EffectType::Delay => 0.35,
+            EffectType::Reverb => 0.3,
+            EffectType::Chorus | EffectType::Flanger | EffectType::Phaser => 0.5,
+        }
+    }
+
+    /// Creates a new effect of this type with default parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - The sample rate in Hz.
+    pub fn build(&self, sample_rate: u32) -> Box<dyn Effect> {
+        match self {
+            EffectType::Gain => Box::new(Gain::new()),
+            EffectType::Delay => Box::new(Delay::new(sample_rate as f32)),
+            EffectType::Reverb => Box::new(Reverb::new(sample_rate as f32)),
+            EffectType::Chorus => Box::new(Chorus::new(sample_rate as f32)),
+            EffectType::Flanger => Box::new(Flanger::new(sample_rate as f32)),
+            EffectType::Phaser => Box::new(Phaser::new(sample_rate as f32)),
+            EffectType::Distortion => Box::new(Distortion::new(sample_rate as f32)),
+        }
+    }
+}
+
+/// Reads a delay line written with `delay_write`, `delay` samples behind the next value to be written.
+///
+/// Fractional delays are linearly interpolated with `RingBuffer::get_frac`. `delay` must be between 1.0
+/// and the capacity of the delay line.
+pub(crate) fn delay_tap(delay_line: &RingBuffer<f32>, delay: f32) -> f32 {
+    delay_line.get_frac(delay_line.capacity() as f32 - delay)
+}
+
+/// Writes the next value into a delay line, dropping the oldest one.
+pub(crate) fn delay_write(delay_line: &mut RingBuffer<f32>, value: f32) {
+    let _ = delay_line.pop();
+    delay_line.push(value);
+}
+
+/// Clamps `value` to the range of the parameter described by `info`.
+pub(crate) fn clamp_param(info: &EffectParamInfo, value: f32) -> f32 {
+    value.clamp(info.min, info.max)
+}
+
+/// A single position in the effects chain, holding an effect with its bypass and wet/dry settings.
+#[derive(Clone, Debug)]
+pub struct EffectSlot {
+    effect: Box<dyn Effect>,
+    bypassed: bool,
+    mix: f32,
+}
+
+impl EffectSlot {
+    /// Creates a new, active slot around `effect`, using the default mix of its type.
+    pub fn new(effect: Box<dyn Effect>) -> Self {
+        let mix = effect.effect_type().default_mix();
+        Self {
+            effect,
+            bypassed: false,
+            mix,
+        }
+    }
+
+    /// Processes one stereo frame, applying bypass and the wet/dry mix.
+    pub fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        if self.bypassed {
+            return input;
+        }
+        let wet = self.effect.process(input);
+        (
+            input.0 * (1.0 - self.mix) + wet.0 * self.mix,
+            input.1 * (1.0 - self.mix) + wet.1 * self.mix,
+        )
+    }
+
+    /// Returns the effect held by the slot.
+    pub fn effect(&self) -> &dyn Effect {
+        self.effect.as_ref()
+    }
+
+    /// Returns the effect held by the slot for modification.
+    pub fn effect_mut(&mut self) -> &mut dyn Effect {
+        self.effect.as_mut()
+    }
+
+    /// Returns `true` if the slot passes audio through unchanged.
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed
+    }
+
+    /// Enables or disables bypass. The effect is reset when it is switched back on,
+    /// so no stale tail from before the bypass is heard.
+    pub fn set_bypass(&mut self, bypassed: bool) {
+        if self.bypassed && !bypassed {
+            self.effect.reset();
+        }
+        self.bypassed = bypassed;
+    }
+
+    /// Returns the wet/dry mix, where 0.0 is fully dry and 1.0 is fully wet.
+    pub fn mix(&self) -> f32 {
+        self.mix
+    }
+
+    /// Sets the wet/dry mix.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `mix` is not between 0.0 and 1.0.
+    pub fn set_mix(&mut self, mix: f32) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&mix) {
+            return Err("Mix must be between 0.0 and 1.0!".to_owned());
+        }
+        self.mix = mix;
+        Ok(())
+    }
+
+    /// Returns the serializable state of the slot.
+    pub fn state(&self) -> EffectSlotState {
+        EffectSlotState {
+            effect_type: self.effect.effect_type(),
+            bypassed: self.bypassed,
+            mix: self.mix,
+            params: (0..self.effect.parameters().len())
+                .map(|index| self.effect.get_param(index))
+                .collect(),
+        }
+    }
+}
+
+/// Serializable description of an `EffectSlot`, stored with a patch.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EffectSlotState {
+    /// The kind of effect in the slot.
+    pub effect_type: EffectType,
+    /// Whether the slot is bypassed.
+    pub bypassed: bool,
+    /// Wet/dry mix between 0.0 and 1.0.
+    pub mix: f32,
+    /// Parameter values, in the order given by `Effect::parameters`.
+    pub params: Vec<f32>,
+}
+
+/// Ordered chain of effects applied to the master output.
+#[derive(Clone, Debug)]
+pub struct EffectsChain {
+    slots: Vec<EffectSlot>,
+    sample_rate: u32,
+    bpm: f32,
+}
+
+impl EffectsChain {
+    /// Creates a new, empty `EffectsChain`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - The sample rate in Hz, used when building new effects.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            slots: Vec::new(),
+            sample_rate,
+            bpm: DEFAULT_BPM,
+        }
+    }
+
+    /// Builds a new effect of the given type, following the tempo of the chain.
+    fn build(&self, effect_type: EffectType) -> Box<dyn Effect> {
+        let mut effect = effect_type.build(self.sample_rate);
+        effect.set_tempo(self.bpm);
+        effect
+    }
+
+    /// Sets the tempo every effect in the chain follows, in beats per minute.
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = bpm;
+        for slot in self.slots.iter_mut() {
+            slot.effect.set_tempo(bpm);
+        }
+    }
+
+    /// Appends a new effect of the given type to the end of the chain and returns its index.
+    pub fn push(&mut self, effect_type: EffectType) -> usize {
+        self.slots.push(EffectSlot::new(self.build(effect_type)));
+        self.slots.len() - 1
+    }
+
+    /// Inserts a new effect of the given type at `index`, shifting later slots down.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is past the end of the chain.
+    pub fn insert(&mut self, index: usize, effect_type: EffectType) -> Result<(), String> {
+        if index > self.slots.len() {
+            return Err("Slot index out of bounds!".to_owned());
+        }
+        self.slots.insert(index, EffectSlot::new(self.build(effect_type)));
+        Ok(())
+    }
+
+    /// Removes the slot at `index` and returns it, or `None` if there is no such slot.
+    pub fn remove(&mut self, index: usize) -> Option<EffectSlot> {
+        if index < self.slots.len() {
+            Some(self.slots.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Moves the slot at `from` to position `to`, keeping the order of the other slots.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either index is out of bounds.
+    pub fn move_slot(&mut self, from: usize, to: usize) -> Result<(), String> {
+        if from >= self.slots.len() || to >= self.slots.len() {
+            return Err("Slot index out of bounds!".to_owned());
+        }
+        let slot = self.slots.remove(from);
+        self.slots.insert(to, slot);
+        Ok(())
+    }
+
+    /// Removes all slots from the chain.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+    }
+
+    /// Returns the number of slots in the chain.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Checks if the chain is empty.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Returns the slot at `index`.
+    pub fn get(&self, index: usize) -> Option<&EffectSlot> {
+        self.slots.get(index)
+    }
+
+    /// Returns the slot at `index` for modification.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut EffectSlot> {
+        self.slots.get_mut(index)
+    }
+
+    /// Returns an iterator over the slots, in processing order.
+    pub fn iter(&self) -> std::slice::Iter<'_, EffectSlot> {
+        self.slots.iter()
+    }
+
+    /// Enables or disables bypass on the slot at `index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is out of bounds.
+    pub fn set_bypass(&mut self, index: usize, bypassed: bool) -> Result<(), String> {
+        match self.slots.get_mut(index) {
+            Some(slot) => {
+                slot.set_bypass(bypassed);
+                Ok(())
+            },
+            None => Err("Slot index out of bounds!".to_owned())
+        }
+    }
+
+    /// Sets the wet/dry mix of the slot at `index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is out of bounds or `mix` is not between 0.0 and 1.0.
+    pub fn set_mix(&mut self, index: usize, mix: f32) -> Result<(), String> {
+        match self.slots.get_mut(index) {
+            Some(slot) => slot.set_mix(mix),
+            None => Err("Slot index out of bounds!".to_owned())
+        }
+    }
+
+    /// Sets a parameter of the effect in the slot at `index`.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the slot.
+    /// * `param_index` - The index of the parameter, as given by `Effect::parameters`.
+    /// * `value` - The new value of the parameter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either index is out of bounds.
+    pub fn set_param(&mut self, index: usize, param_index: usize, value: f32) -> Result<(), String> {
+        match self.slots.get_mut(index) {
+            Some(slot) => {
+                if param_index >= slot.effect.parameters().len() {
+                    return Err("Parameter index out of bounds!".to_owned());
+                }
+                slot.effect.set_param(param_index, value);
+                Ok(())
+            },
+            None => Err("Slot index out of bounds!".to_owned())
+        }
+    }
+
+    /// Processes one stereo frame through every slot, in order.
+    pub fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        let mut frame = input;
+        for slot in self.slots.iter_mut() {
+            frame = slot.process(frame);
+        }
+        frame
+    }
+
+    /// Resets the internal state of every effect in the chain.
+    pub fn reset(&mut self) {
+        for slot in self.slots.iter_mut() {
+            slot.effect.reset();
+        }
+    }
+
+    /// Returns the serializable state of every slot, in processing order.
+    pub fn state(&self) -> Vec<EffectSlotState> {
+        self.slots.iter().map(|slot| slot.state()).collect()
+    }
+
+    /// Replaces the chain with the slots described by `state`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a slot has an invalid mix. The chain is left unchanged in that case.
+    pub fn load_state(&mut self, state: &[EffectSlotState]) -> Result<(), String> {
+        let mut slots = Vec::with_capacity(state.len());
+        for slot_state in state {
+            let mut slot = EffectSlot::new(slot_state.effect_type.build(self.sample_rate));
+            slot.set_mix(slot_state.mix)?;
+            slot.bypassed = slot_state.bypassed;
+            let num_params = slot.effect.parameters().len();
+            for (index, value) in slot_state.params.iter().take(num_params).enumerate() {
+                slot.effect.set_param(index, *value);
+            }
+            slot.effect.set_tempo(self.bpm);
+            slots.push(slot);
+        }
+        self.slots = slots;
+        Ok(())
+    }
+}
+
+impl Prepare for EffectsChain {
+    /// Builds every effect again at the new rate from the state of its slot, so the parameters, mix,
+    /// bypass and tempo are kept. Delay lines and reverb tails start out silent.
+    fn prepare(&mut self, sample_rate: u32, _max_block_size: usize) {
+        let state = self.state();
+        self.sample_rate = sample_rate;
+        // The state was taken from valid slots, so it loads without errors
+        let _ = self.load_state(&state);
+    }
+}
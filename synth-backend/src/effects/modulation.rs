@@ -0,0 +1,324 @@
+//! Modulation effects
+//!
+//! Chorus, flanger and phaser. The chorus and flanger are built from `Vibrato` modulated delay lines;
+//! the phaser sweeps a chain of first-order allpass filters. All three run their right channel a quarter
+//! of a cycle behind the left one to widen the stereo image.
+use std::f32::consts::PI;
+use crate::oscillators::{Oscillator, WaveTableOscillator};
+use crate::vibrato::Vibrato;
+use super::{clamp_param, Effect, EffectParamInfo, EffectType};
+
+/// Size of the wave tables used by the modulation oscillators.
+const LFO_TABLE_SIZE: usize = 2048;
+/// Phase difference between the left and right channel oscillators, as a fraction of a cycle.
+const STEREO_PHASE: f32 = 0.25;
+/// Largest number of voices of the chorus.
+const MAX_CHORUS_VOICES: usize = 4;
+/// Largest number of allpass stages of the phaser.
+const MAX_PHASER_STAGES: usize = 12;
+/// Lowest frequency the phaser notches are swept to.
+const PHASER_MIN_HZ: f32 = 200.0;
+/// Number of octaves the phaser notches are swept over at full depth.
+const PHASER_OCTAVES: f32 = 5.0;
+
+const RATE_HZ: usize = 0;
+const DEPTH: usize = 1;
+const FEEDBACK: usize = 2;
+
+/// Builds a `WaveTableOscillator` suitable for driving a modulation effect.
+fn modulation_lfo(sample_rate_hz: f32, frequency: f32, phase: f32) -> WaveTableOscillator {
+    let mut lfo = WaveTableOscillator::new(sample_rate_hz as u32, LFO_TABLE_SIZE, Oscillator::Sine, 1.0, frequency);
+    lfo.set_phase(phase);
+    lfo
+}
+
+/// Reads the default values of a parameter list into an array.
+fn default_params<const N: usize>(parameters: &[EffectParamInfo; N]) -> [f32; N] {
+    let mut params = [0.0; N];
+    for (value, info) in params.iter_mut().zip(parameters.iter()) {
+        *value = info.default;
+    }
+    params
+}
+
+const CHORUS_VOICES: usize = 3;
+const CHORUS_DELAY_MS: usize = 4;
+
+const CHORUS_PARAMETERS: [EffectParamInfo; 5] = [
+    EffectParamInfo { name: "Rate (Hz)", min: 0.05, max: 5.0, default: 0.8 },
+    EffectParamInfo { name: "Depth (ms)", min: 0.0, max: 10.0, default: 3.0 },
+    EffectParamInfo { name: "Feedback", min: 0.0, max: 0.9, default: 0.0 },
+    EffectParamInfo { name: "Voices", min: 1.0, max: MAX_CHORUS_VOICES as f32, default: 3.0 },
+    EffectParamInfo { name: "Delay (ms)", min: 5.0, max: 30.0, default: 15.0 },
+];
+
+/// Multi-voice stereo chorus.
+#[derive(Clone, Debug)]
+pub struct Chorus {
+    params: [f32; 5],
+    voices: [Vec<Vibrato>; 2],
+}
+
+impl Chorus {
+    /// Creates a new `Chorus` with default parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate_hz` - The sample rate in Hertz.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let params = default_params(&CHORUS_PARAMETERS);
+        let channel = |offset: f32| (0..MAX_CHORUS_VOICES).map(|voice| {
+            let phase = voice as f32 / MAX_CHORUS_VOICES as f32 + offset;
+            Vibrato::new(sample_rate_hz, 0.0, modulation_lfo(sample_rate_hz, params[RATE_HZ], phase))
+        }).collect::<Vec<Vibrato>>();
+        let mut chorus = Self {
+            params,
+            voices: [channel(0.0), channel(STEREO_PHASE)],
+        };
+        chorus.update_voices();
+        chorus
+    }
+
+    fn num_voices(&self) -> usize {
+        self.params[CHORUS_VOICES].round() as usize
+    }
+
+    fn update_voices(&mut self) {
+        let params = self.params;
+        for vibrato in self.voices.iter_mut().flatten() {
+            vibrato.set_delay(params[CHORUS_DELAY_MS] / 1000.0);
+            vibrato.set_width(params[DEPTH] / 1000.0);
+            vibrato.set_feedback(params[FEEDBACK]);
+            let _ = vibrato.set_frequency(params[RATE_HZ]);
+        }
+    }
+}
+
+impl Effect for Chorus {
+    fn effect_type(&self) -> EffectType {
+        EffectType::Chorus
+    }
+
+    fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        let num_voices = self.num_voices();
+        let mut output = [0.0; 2];
+        for (channel, value) in [input.0, input.1].into_iter().enumerate() {
+            for vibrato in self.voices[channel].iter_mut().take(num_voices) {
+                output[channel] += vibrato.process(value);
+            }
+        }
+        (output[0] / num_voices as f32, output[1] / num_voices as f32)
+    }
+
+    fn reset(&mut self) {
+        self.voices.iter_mut().flatten().for_each(Vibrato::reset);
+    }
+
+    fn parameters(&self) -> &'static [EffectParamInfo] {
+        &CHORUS_PARAMETERS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        self.params.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        if index < CHORUS_PARAMETERS.len() {
+            self.params[index] = clamp_param(&CHORUS_PARAMETERS[index], value);
+            self.update_voices();
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+}
+
+const FLANGER_DELAY_MS: usize = 3;
+
+const FLANGER_PARAMETERS: [EffectParamInfo; 4] = [
+    EffectParamInfo { name: "Rate (Hz)", min: 0.05, max: 5.0, default: 0.25 },
+    EffectParamInfo { name: "Depth (ms)", min: 0.0, max: 5.0, default: 1.5 },
+    EffectParamInfo { name: "Feedback", min: -0.95, max: 0.95, default: 0.5 },
+    EffectParamInfo { name: "Delay (ms)", min: 0.5, max: 10.0, default: 2.0 },
+];
+
+/// Stereo flanger with feedback.
+#[derive(Clone, Debug)]
+pub struct Flanger {
+    params: [f32; 4],
+    vibratos: [Vibrato; 2],
+}
+
+impl Flanger {
+    /// Creates a new `Flanger` with default parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate_hz` - The sample rate in Hertz.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let params = default_params(&FLANGER_PARAMETERS);
+        let channel = |phase: f32| Vibrato::new(sample_rate_hz, 0.0, modulation_lfo(sample_rate_hz, params[RATE_HZ], phase));
+        let mut flanger = Self {
+            params,
+            vibratos: [channel(0.0), channel(STEREO_PHASE)],
+        };
+        flanger.update_vibratos();
+        flanger
+    }
+
+    fn update_vibratos(&mut self) {
+        let params = self.params;
+        for vibrato in self.vibratos.iter_mut() {
+            vibrato.set_delay(params[FLANGER_DELAY_MS] / 1000.0);
+            vibrato.set_width(params[DEPTH] / 1000.0);
+            vibrato.set_feedback(params[FEEDBACK]);
+            let _ = vibrato.set_frequency(params[RATE_HZ]);
+        }
+    }
+}
+
+impl Effect for Flanger {
+    fn effect_type(&self) -> EffectType {
+        EffectType::Flanger
+    }
+
+    fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        (self.vibratos[0].process(input.0), self.vibratos[1].process(input.1))
+    }
+
+    fn reset(&mut self) {
+        self.vibratos.iter_mut().for_each(Vibrato::reset);
+    }
+
+    fn parameters(&self) -> &'static [EffectParamInfo] {
+        &FLANGER_PARAMETERS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        self.params.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        if index < FLANGER_PARAMETERS.len() {
+            self.params[index] = clamp_param(&FLANGER_PARAMETERS[index], value);
+            self.update_vibratos();
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+}
+
+const PHASER_STAGES: usize = 3;
+
+const PHASER_PARAMETERS: [EffectParamInfo; 4] = [
+    EffectParamInfo { name: "Rate (Hz)", min: 0.05, max: 5.0, default: 0.5 },
+    EffectParamInfo { name: "Depth", min: 0.0, max: 1.0, default: 0.8 },
+    EffectParamInfo { name: "Feedback", min: -0.9, max: 0.9, default: 0.3 },
+    EffectParamInfo { name: "Stages", min: 2.0, max: MAX_PHASER_STAGES as f32, default: 4.0 },
+];
+
+/// First-order allpass filter with a coefficient that can change every sample.
+#[derive(Clone, Copy, Debug, Default)]
+struct AllpassStage {
+    x1: f32,
+    y1: f32,
+}
+
+impl AllpassStage {
+    fn process(&mut self, input: f32, coefficient: f32) -> f32 {
+        let output = coefficient * input + self.x1 - coefficient * self.y1;
+        self.x1 = input;
+        self.y1 = output;
+        output
+    }
+}
+
+/// Stereo phaser built from a chain of swept allpass filters.
+#[derive(Clone, Debug)]
+pub struct Phaser {
+    sample_rate_hz: f32,
+    params: [f32; 4],
+    lfos: [WaveTableOscillator; 2],
+    stages: [[AllpassStage; MAX_PHASER_STAGES]; 2],
+    last_output: [f32; 2],
+}
+
+impl Phaser {
+    /// Creates a new `Phaser` with default parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate_hz` - The sample rate in Hertz.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let params = default_params(&PHASER_PARAMETERS);
+        Self {
+            sample_rate_hz,
+            params,
+            lfos: [
+                modulation_lfo(sample_rate_hz, params[RATE_HZ], 0.0),
+                modulation_lfo(sample_rate_hz, params[RATE_HZ], STEREO_PHASE),
+            ],
+            stages: [[AllpassStage::default(); MAX_PHASER_STAGES]; 2],
+            last_output: [0.0; 2],
+        }
+    }
+
+    fn process_channel(&mut self, channel: usize, input: f32) -> f32 {
+        let modulator = (self.lfos[channel].get_sample() + 1.0) * 0.5;
+        let frequency = (PHASER_MIN_HZ * f32::powf(2.0, self.params[DEPTH] * PHASER_OCTAVES * modulator))
+            .min(0.45 * self.sample_rate_hz);
+        let tan = (PI * frequency / self.sample_rate_hz).tan();
+        let coefficient = (tan - 1.0) / (tan + 1.0);
+
+        let num_stages = self.params[PHASER_STAGES].round() as usize;
+        let mut value = input + self.params[FEEDBACK] * self.last_output[channel];
+        for stage in self.stages[channel].iter_mut().take(num_stages) {
+            value = stage.process(value, coefficient);
+        }
+        self.last_output[channel] = value;
+        // Summing the allpass output with the input creates the moving notches.
+        0.5 * (input + value)
+    }
+}
+
+impl Effect for Phaser {
+    fn effect_type(&self) -> EffectType {
+        EffectType::Phaser
+    }
+
+    fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        (self.process_channel(0, input.0), self.process_channel(1, input.1))
+    }
+
+    fn reset(&mut self) {
+        self.stages = [[AllpassStage::default(); MAX_PHASER_STAGES]; 2];
+        self.last_output = [0.0; 2];
+    }
+
+    fn parameters(&self) -> &'static [EffectParamInfo] {
+        &PHASER_PARAMETERS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        self.params.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        if index >= PHASER_PARAMETERS.len() {
+            return;
+        }
+        self.params[index] = clamp_param(&PHASER_PARAMETERS[index], value);
+        if index == RATE_HZ {
+            for lfo in self.lfos.iter_mut() {
+                let _ = lfo.set_frequency(self.params[RATE_HZ]);
+            }
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+}
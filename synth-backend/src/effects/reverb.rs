@@ -0,0 +1,181 @@
+//! Reverb
+//!
+//! An algorithmic reverb based on the Freeverb network by Jezar at Dreampoint: eight parallel lowpass
+//! feedback comb filters followed by four series allpass filters per channel. The right channel uses
+//! slightly longer delay lines than the left one to decorrelate the two sides.
+use crate::ring_buffer::RingBuffer;
+use super::{clamp_param, delay_tap, delay_write, Effect, EffectParamInfo, EffectType};
+
+/// Comb filter lengths in samples at 44100 Hz.
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// Allpass filter lengths in samples at 44100 Hz.
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+/// Extra length of the right channel delay lines in samples at 44100 Hz.
+const STEREO_SPREAD: usize = 23;
+/// Gain applied to the input before it enters the comb filters.
+const INPUT_GAIN: f32 = 0.015;
+/// Gain applied to the output of the network.
+const OUTPUT_GAIN: f32 = 3.0;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+const MAX_PRE_DELAY_MS: f32 = 200.0;
+
+const ROOM_SIZE: usize = 0;
+const DAMPING: usize = 1;
+const PRE_DELAY_MS: usize = 2;
+const WIDTH: usize = 3;
+
+const PARAMETERS: [EffectParamInfo; 4] = [
+    EffectParamInfo { name: "Room Size", min: 0.0, max: 1.0, default: 0.5 },
+    EffectParamInfo { name: "Damping", min: 0.0, max: 1.0, default: 0.5 },
+    EffectParamInfo { name: "Pre-Delay (ms)", min: 0.0, max: MAX_PRE_DELAY_MS, default: 10.0 },
+    EffectParamInfo { name: "Width", min: 0.0, max: 1.0, default: 1.0 },
+];
+
+/// Lowpass feedback comb filter.
+#[derive(Clone, Debug)]
+struct Comb {
+    delay_line: RingBuffer<f32>,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn new(length: usize) -> Self {
+        Self {
+            delay_line: RingBuffer::new(length.max(1)),
+            filter_store: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.delay_line.pop();
+        self.filter_store = output * (1.0 - damping) + self.filter_store * damping;
+        self.delay_line.push(input + self.filter_store * feedback);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.delay_line.reset();
+        self.filter_store = 0.0;
+    }
+}
+
+/// Schroeder allpass filter.
+#[derive(Clone, Debug)]
+struct Allpass {
+    delay_line: RingBuffer<f32>,
+}
+
+impl Allpass {
+    fn new(length: usize) -> Self {
+        Self {
+            delay_line: RingBuffer::new(length.max(1)),
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.delay_line.pop();
+        self.delay_line.push(input + delayed * ALLPASS_FEEDBACK);
+        delayed - input
+    }
+
+    fn reset(&mut self) {
+        self.delay_line.reset();
+    }
+}
+
+/// Stereo Freeverb-style reverb.
+#[derive(Clone, Debug)]
+pub struct Reverb {
+    sample_rate_hz: f32,
+    params: [f32; 4],
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+    pre_delay: RingBuffer<f32>,
+}
+
+impl Reverb {
+    /// Creates a new `Reverb` with default parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate_hz` - The sample rate in Hertz. Delay line lengths are scaled from their 44100 Hz values.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let scale = |length: usize| (length as f32 * sample_rate_hz / 44100.0).round() as usize;
+        let channel = |spread: usize| (
+            COMB_TUNING.iter().map(|length| Comb::new(scale(length + spread))).collect::<Vec<Comb>>(),
+            ALLPASS_TUNING.iter().map(|length| Allpass::new(scale(length + spread))).collect::<Vec<Allpass>>(),
+        );
+        let (combs_left, allpasses_left) = channel(0);
+        let (combs_right, allpasses_right) = channel(STEREO_SPREAD);
+        let mut params = [0.0; 4];
+        for (value, info) in params.iter_mut().zip(PARAMETERS.iter()) {
+            *value = info.default;
+        }
+        Self {
+            sample_rate_hz,
+            params,
+            combs: [combs_left, combs_right],
+            allpasses: [allpasses_left, allpasses_right],
+            pre_delay: RingBuffer::new((MAX_PRE_DELAY_MS * sample_rate_hz / 1000.0) as usize + 2),
+        }
+    }
+
+    fn process_channel(&mut self, channel: usize, input: f32, feedback: f32, damping: f32) -> f32 {
+        let mut output = 0.0;
+        for comb in self.combs[channel].iter_mut() {
+            output += comb.process(input, feedback, damping);
+        }
+        for allpass in self.allpasses[channel].iter_mut() {
+            output = allpass.process(output);
+        }
+        output
+    }
+}
+
+impl Effect for Reverb {
+    fn effect_type(&self) -> EffectType {
+        EffectType::Reverb
+    }
+
+    fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        let pre_delay_samples = (self.params[PRE_DELAY_MS] * self.sample_rate_hz / 1000.0).max(1.0);
+        let delayed = delay_tap(&self.pre_delay, pre_delay_samples);
+        delay_write(&mut self.pre_delay, (input.0 + input.1) * INPUT_GAIN);
+
+        let feedback = self.params[ROOM_SIZE] * 0.28 + 0.7;
+        let damping = self.params[DAMPING] * 0.4;
+        let left = self.process_channel(0, delayed, feedback, damping);
+        let right = self.process_channel(1, delayed, feedback, damping);
+
+        let width = self.params[WIDTH];
+        let direct = OUTPUT_GAIN * (width / 2.0 + 0.5);
+        let cross = OUTPUT_GAIN * ((1.0 - width) / 2.0);
+        (left * direct + right * cross, right * direct + left * cross)
+    }
+
+    fn reset(&mut self) {
+        for channel in 0..2 {
+            self.combs[channel].iter_mut().for_each(Comb::reset);
+            self.allpasses[channel].iter_mut().for_each(Allpass::reset);
+        }
+        self.pre_delay.reset();
+    }
+
+    fn parameters(&self) -> &'static [EffectParamInfo] {
+        &PARAMETERS
+    }
+
+    fn get_param(&self, index: usize) -> f32 {
+        self.params.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        if index < PARAMETERS.len() {
+            self.params[index] = clamp_param(&PARAMETERS[index], value);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+}
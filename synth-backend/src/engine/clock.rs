@@ -0,0 +1,161 @@
+//! Clock
+//!
+//! `Clock` keeps the time of the engine: the `Transport` with its tempo and position, and the
+//! `Arpeggiator` and `Sequencer` that play notes in time with it. Each pattern keeps the voice it plays
+//! its notes with, and the notes it produces are taken with `pop_event` to be started by the engine.
+use crate::wrapper::Synth;
+use crate::arpeggiator::{Arpeggiator, ArpeggiatorSettings, NoteEvent};
+use crate::sequencer::Sequencer;
+use crate::transport::Transport;
+use crate::lifecycle::Prepare;
+
+/// The transport and the patterns that follow it.
+#[derive(Clone, Debug)]
+pub(crate) struct Clock {
+    transport: Transport,
+    arpeggiator: Arpeggiator,
+    arp_voice: Option<Synth>,
+    sequencer: Sequencer,
+    sequencer_voice: Option<Synth>,
+}
+
+impl Clock {
+    /// Creates a new `Clock` at the given sample rate, with the transport stopped.
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        Self {
+            transport: Transport::new(sample_rate),
+            arpeggiator: Arpeggiator::new(sample_rate),
+            arp_voice: None,
+            sequencer: Sequencer::new(sample_rate),
+            sequencer_voice: None,
+        }
+    }
+
+    pub(crate) fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    /// Returns the transport for handling clock messages. Tempo changes are passed on with `sync_tempo`
+    /// and changes of the play state with `follow_transport`.
+    pub(crate) fn transport_mut(&mut self) -> &mut Transport {
+        &mut self.transport
+    }
+
+    pub(crate) fn arpeggiator(&self) -> &Arpeggiator {
+        &self.arpeggiator
+    }
+
+    pub(crate) fn set_arpeggiator(&mut self, settings: ArpeggiatorSettings) {
+        self.arpeggiator.set_settings(settings);
+    }
+
+    /// Adds `k` to the pattern of the arpeggiator, which plays it with `voice`.
+    pub(crate) fn arp_key_down(&mut self, k: u8, voice: Synth, velocity: f32) {
+        self.arp_voice = Some(voice);
+        self.arpeggiator.key_down(k, velocity);
+    }
+
+    pub(crate) fn arp_key_up(&mut self, k: u8) {
+        self.arpeggiator.key_up(k);
+    }
+
+    pub(crate) fn sequencer(&self) -> &Sequencer {
+        &self.sequencer
+    }
+
+    pub(crate) fn sequencer_mut(&mut self) -> &mut Sequencer {
+        &mut self.sequencer
+    }
+
+    /// Arms the sequencer to play its steps with `voice`, and starts the transport when it runs on its
+    /// own clock.
+    pub(crate) fn start_sequencer(&mut self, voice: Synth) {
+        self.sequencer_voice = Some(voice);
+        self.transport.play();
+    }
+
+    pub(crate) fn is_sequencer_armed(&self) -> bool {
+        self.sequencer_voice.is_some()
+    }
+
+    /// Disarms the sequencer, and stops the transport and returns it to the start of the song.
+    pub(crate) fn stop_sequencer(&mut self) {
+        self.sequencer_voice = None;
+        self.transport.stop();
+        self.transport.set_position_beats(0.0);
+    }
+
+    /// Returns the voices the arpeggiator and sequencer play their notes with.
+    pub(crate) fn voices(&self) -> impl Iterator<Item = &Synth> {
+        [&self.arp_voice, &self.sequencer_voice].into_iter().flatten()
+    }
+
+    /// Advances the transport by one sample. Returns whether its tempo has moved away from the
+    /// patterns, which `sync_tempo` brings them back to.
+    pub(crate) fn advance_transport(&mut self) -> bool {
+        self.transport.advance();
+        self.transport.bpm() != self.sequencer.bpm()
+    }
+
+    /// Passes the tempo of the transport on to the patterns, and returns it.
+    pub(crate) fn sync_tempo(&mut self) -> f32 {
+        let bpm = self.transport.bpm();
+        self.arpeggiator.set_bpm(bpm);
+        self.sequencer.set_bpm(bpm);
+        bpm
+    }
+
+    /// Starts the armed sequencer when the transport starts, at the step the song position is on,
+    /// and stops it when the transport stops.
+    pub(crate) fn follow_transport(&mut self) {
+        let playing = self.transport.is_playing() && self.sequencer_voice.is_some();
+        if playing && !self.sequencer.is_playing() {
+            self.sequencer.play_from(self.transport.position_beats());
+        } else if !playing && self.sequencer.is_playing() {
+            self.sequencer.stop();
+        }
+    }
+
+    /// Advances the patterns that are running by one sample.
+    pub(crate) fn advance_patterns(&mut self) {
+        if self.arpeggiator.is_enabled() {
+            self.arpeggiator.advance();
+        }
+        if self.sequencer.is_playing() {
+            self.sequencer.advance();
+        }
+    }
+
+    /// Takes the next note the arpeggiator or sequencer has played, with the voice to play it with,
+    /// the notes of the arpeggiator first.
+    pub(crate) fn pop_event(&mut self) -> Option<(NoteEvent, Option<&Synth>)> {
+        if let Some(event) = self.arpeggiator.pop_event() {
+            return Some((event, self.arp_voice.as_ref()));
+        }
+        let event = self.sequencer.pop_event()?;
+        Some((event, self.sequencer_voice.as_ref()))
+    }
+
+    /// Stops the patterns and drops the notes they have not handed over yet.
+    pub(crate) fn clear(&mut self) {
+        self.arpeggiator.clear();
+        while self.arpeggiator.pop_event().is_some() {}
+        self.sequencer.stop();
+        while self.sequencer.pop_event().is_some() {}
+    }
+}
+
+impl Prepare for Clock {
+    /// Prepares the voices of the patterns and keeps the patterns and transport in time.
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        if let Some(ref mut voice) = self.arp_voice {
+            voice.prepare(sample_rate, max_block_size);
+        }
+        if let Some(ref mut voice) = self.sequencer_voice {
+            voice.prepare(sample_rate, max_block_size);
+        }
+        self.arpeggiator.prepare(sample_rate, max_block_size);
+        self.sequencer.prepare(sample_rate, max_block_size);
+        self.transport.prepare(sample_rate, max_block_size);
+    }
+}
@@ -0,0 +1,660 @@
+//! Engine
+//!
+//! `IterablePolyphonyHashMap` is the engine of the synthesizer: it takes notes, MIDI messages and settings,
+//! and generates the stereo frames the audio backends play. It implements the `Source` trait from the `rodio`
+//! crate, so it can also be played through rodio.
+//!
+//! ## Examples
+//!
+//! ```
+//! use synth_backend::engine::IterablePolyphonyHashMap;
+//! use synth_backend::lfo::LFOType;
+//! use synth_backend::oscillators::{MultiOscillator, Oscillator, WaveTableOscillator, DEFAULT_TABLE_SIZE};
+//! use synth_backend::wrapper::Synth;
+//!
+//! // Create a new engine with a sample rate of 44100 Hz
+//! let mut engine = IterablePolyphonyHashMap::new(44100);
+//!
+//! // Play MIDI key 60 with a sine voice
+//! let osc = MultiOscillator::from(WaveTableOscillator::new(44100, DEFAULT_TABLE_SIZE, Oscillator::Sine, 0.5, 0.0));
+//! engine.note_on(60, Synth::new(osc, 44100, None, None, None, LFOType::Amplitude), 1.0).unwrap();
+//!
+//! // Generate audio samples
+//! let (left, right) = engine.get_stereo_sample();
+//! ```
+//!
+//! The engine is made of three parts, which it passes notes and events between:
+//!
+//! - The note routing turns keys into voices. Keys become frequencies through the `Tuning`, which defaults
+//!   to 12-tone equal temperament at A4 = 440 Hz and can be retuned in real time by MIDI Tuning Standard
+//!   messages passed to `midi_tuning`. In the single voice modes of `VoiceModeSettings` the engine holds at
+//!   most one voice, stored under the key it plays; keys should then be started with `note_on` and released
+//!   with `note_off`, so that the voice can return to the keys that are still held. The pitch bend and mod
+//!   wheel in `Controllers` act on every voice. Notes received with `mpe_note_on` on the member channels of
+//!   an MPE zone follow the pitch bend, pressure and timbre of their own channel, which `Mpe` routes to the
+//!   pitch, gain and filter cutoff of the voice.
+//! - The clock keeps time. When the `Arpeggiator` is enabled, the keys passed to `note_on` and `note_off`
+//!   are held by the arpeggiator, which starts and stops the voices itself at exact sample positions while
+//!   the samples are generated. The `Sequencer` plays its patterns through the same voices while the
+//!   `Transport` is playing, once it is armed with `start_sequencer`. The transport keeps the tempo for the
+//!   arpeggiator, the sequencer and the effects, and can follow MIDI clock or a host.
+//! - The signal chain takes the summed voices through the master `EffectsChain` and then through
+//!   `MasterDynamics`, which keeps the output from clipping.
+//!
+//! Keys played with `note_on` and the notes of the arpeggiator and sequencer are also sent to the `MidiOutput`,
+//! whose messages are collected with `take_midi_output`. With local control off they are only sent, not sounded.
+//! Notes received with `mpe_note_on` always sound and are not sent back out.
+//!
+//! The engine implements `Prepare`, so it can be moved to another sample rate while notes are playing. With an
+//! internal sample rate set, the audio backends keep it at that rate and resample its output instead.
+//!
+//! Voices are kept in a `VoicePool`, which reuses the voices of notes that have stopped. With `set_real_time`
+//! the pool is allocated up front for a fixed polyphony, and generating samples does not allocate at all.
+mod clock;
+mod notes;
+mod signal_chain;
+
+use crate::wrapper::Synth;
+use crate::effects::EffectsChain;
+use crate::dynamics::MasterDynamics;
+use crate::controllers::{mod_wheel_from_midi, pitch_bend_from_midi, ControllerSettings, Controllers, MOD_WHEEL_CC};
+use crate::arpeggiator::{Arpeggiator, ArpeggiatorSettings, NoteEvent};
+use crate::voice_mode::VoiceModeSettings;
+use crate::sequencer::Sequencer;
+use crate::transport::{ClockSource, TimeSignature, Transport};
+use crate::tuning::{MtsMessage, Tuning};
+use crate::mpe::{Mpe, MpeSettings, TIMBRE_CC};
+use crate::midi::{MidiMessage, MidiOutput, MidiOutputSettings};
+use crate::patch::Patch;
+use crate::lifecycle::{Prepare, DEFAULT_MAX_BLOCK_SIZE};
+use crate::voice_pool::{VoicePool, VoiceSource, DEFAULT_POLYPHONY};
+use clock::Clock;
+use notes::NoteRouter;
+use signal_chain::SignalChain;
+use rodio::Source;
+use std::collections::HashMap;
+
+/// The engine: voices played from MIDI keys, in time with a clock, through the master signal chain.
+#[derive(Clone, Debug)]
+pub struct IterablePolyphonyHashMap {
+    sample_rate: u32,
+    max_block_size: usize,
+    internal_sample_rate: Option<u32>,
+    notes: NoteRouter,
+    clock: Clock,
+    chain: SignalChain,
+    midi_output: MidiOutput
+}
+
+impl IterablePolyphonyHashMap {
+    /// Creates a new `IterablePolyphonyHashMap` with the given sample rate.
+    pub fn new(sample_rate: u32) -> Self{
+        Self::with_voices(sample_rate, VoicePool::default())
+    }
+
+    /// Creates a new `IterablePolyphonyHashMap` from an existing HashMap.
+    pub fn from(hashmap: HashMap<u8, Synth>) -> Self {
+        if hashmap.is_empty() {
+            panic!("Empty Hashmap! Use new() instead");
+        }
+        let mut sample_rate = 0;
+        for (_, synth) in hashmap.iter() {
+            sample_rate = synth.osc.sample_rate();
+        }
+        let mut voices = VoicePool::new(DEFAULT_POLYPHONY.max(hashmap.len()));
+        for (key, synth) in hashmap {
+            voices.insert(key, synth);
+        }
+        Self::with_voices(sample_rate, voices)
+    }
+
+    fn with_voices(sample_rate: u32, voices: VoicePool) -> Self {
+        Self {
+            sample_rate,
+            max_block_size: DEFAULT_MAX_BLOCK_SIZE,
+            internal_sample_rate: None,
+            notes: NoteRouter::new(voices),
+            clock: Clock::new(sample_rate),
+            chain: SignalChain::new(sample_rate),
+            midi_output: MidiOutput::default()
+        }
+    }
+
+    /// Returns the sample rate the engine runs at, in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the most frames the engine is prepared to be asked for at a time.
+    pub fn max_block_size(&self) -> usize {
+        self.max_block_size
+    }
+
+    /// Returns the fixed rate the engine runs at whatever the output device plays at, if there is one.
+    pub fn internal_sample_rate(&self) -> Option<u32> {
+        self.internal_sample_rate
+    }
+
+    /// Sets a fixed rate for the engine to run at, in Hz, or `None` to run at the rate of the output
+    /// device. Audio backends resample the engine to their own rate, so a patch sounds the same on any
+    /// device, and an internal rate above the device rate runs the whole engine oversampled. Takes effect
+    /// when the engine is next started on a backend.
+    pub fn set_internal_sample_rate(&mut self, sample_rate: Option<u32>) {
+        self.internal_sample_rate = sample_rate.map(|sample_rate| sample_rate.max(1));
+    }
+
+    /// Returns whether the engine is in real-time mode.
+    pub fn is_real_time(&self) -> bool {
+        self.notes.voices().is_real_time()
+    }
+
+    /// Turns real-time mode on or off. In real-time mode the engine plays at most `polyphony` voices,
+    /// all allocated before any samples are generated, and a new note takes over the oldest voice when
+    /// every voice is playing. Generating samples then never allocates, including the notes started by
+    /// the arpeggiator and sequencer, so the audio callback cannot be held up by the allocator.
+    pub fn set_real_time(&mut self, real_time: bool) {
+        self.notes.voices_mut().set_real_time(real_time);
+        self.reserve_voices();
+    }
+
+    /// Returns how many voices play at once in real-time mode.
+    pub fn polyphony(&self) -> usize {
+        self.notes.voices().polyphony()
+    }
+
+    /// Sets how many voices play at once in real-time mode. Every voice is stopped.
+    pub fn set_polyphony(&mut self, polyphony: usize) {
+        self.clear();
+        self.notes.voices_mut().set_polyphony(polyphony);
+        self.reserve_voices();
+    }
+
+    /// Copies the voices kept for the arpeggiator and sequencer into the voices that are not playing.
+    fn reserve_voices(&mut self) {
+        for voice in self.clock.voices() {
+            self.notes.voices_mut().reserve(voice);
+        }
+    }
+
+    /// Inserts a synthesizer into the MIDI map with the given MIDI key.
+    pub fn insert(&mut self, k: u8, v: Synth){
+        self.notes.voices_mut().insert(k, v);
+    }
+
+    /// Starts a note: `voice` is tuned to the MIDI key, played at `velocity` (between 0.0 and 1.0)
+    /// and inserted into the MIDI map, replacing any voice already playing that key. The voice follows
+    /// the current pitch bend and mod wheel.
+    ///
+    /// In the single voice modes the key is only played if the note priority picks it, and a voice
+    /// that is already playing is reused when the mode is legato. With the arpeggiator enabled the key
+    /// is added to its pattern, and `voice` is kept to play the pattern with. Without the arpeggiator the
+    /// key is sent to the MIDI output, and only sounded if local control is on.
+    pub fn note_on(&mut self, k: u8, voice: Synth, velocity: f32) -> Result<(), String> {
+        if !self.clock.arpeggiator().is_enabled() {
+            self.midi_output.note_on(k, velocity);
+            if !self.midi_output.is_local() {
+                return Ok(());
+            }
+        }
+        self.press_key(k, voice, velocity)
+    }
+
+    /// Releases a note. In the single voice modes the voice returns to the key picked by the note
+    /// priority among the keys that are still held. With the arpeggiator enabled the key is taken out
+    /// of its pattern.
+    pub fn note_off(&mut self, k: u8) -> Result<(), String> {
+        if !self.clock.arpeggiator().is_enabled() {
+            self.midi_output.note_off(k);
+        }
+        self.release_key(k)
+    }
+
+    fn press_key(&mut self, k: u8, mut voice: Synth, velocity: f32) -> Result<(), String> {
+        self.prepare_voice(&mut voice);
+        if self.clock.arpeggiator().is_enabled() {
+            self.notes.tuning().frequency(k)?;
+            self.notes.voices_mut().reserve(&voice);
+            self.clock.arp_key_down(k, voice, velocity);
+            return Ok(());
+        }
+        self.notes.play_note(k, VoiceSource::Voice(voice), velocity)
+    }
+
+    fn release_key(&mut self, k: u8) -> Result<(), String> {
+        if self.clock.arpeggiator().is_enabled() {
+            self.clock.arp_key_up(k);
+            return Ok(());
+        }
+        self.notes.release_note(k)
+    }
+
+    /// Returns the voice mode and portamento settings.
+    pub fn voice_mode(&self) -> VoiceModeSettings {
+        self.notes.voice_mode()
+    }
+
+    /// Sets the voice mode and portamento settings. Changing the voice mode stops every voice.
+    pub fn set_voice_mode(&mut self, settings: VoiceModeSettings) {
+        if settings.mode != self.notes.voice_mode().mode {
+            self.clear();
+        }
+        self.notes.set_voice_mode(settings);
+    }
+
+    /// Returns the arpeggiator.
+    pub fn arpeggiator(&self) -> &Arpeggiator {
+        self.clock.arpeggiator()
+    }
+
+    /// Sets the arpeggiator settings. Turning the arpeggiator on or off stops every voice.
+    pub fn set_arpeggiator(&mut self, settings: ArpeggiatorSettings) {
+        if settings.enabled != self.clock.arpeggiator().is_enabled() {
+            self.clear();
+        }
+        self.clock.set_arpeggiator(settings);
+        self.handle_clock_events();
+    }
+
+    /// Returns the tuning that turns keys into frequencies.
+    pub fn tuning(&self) -> &Tuning {
+        self.notes.tuning()
+    }
+
+    /// Sets the tuning, and retunes the voices that are sounding. Voices on keys the new tuning does
+    /// not map keep their pitch until they are released.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.notes.set_tuning(tuning);
+    }
+
+    /// Retunes keys from a MIDI Tuning Standard system exclusive message, including the voices
+    /// that are sounding.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not a MIDI Tuning Standard message or cannot be applied.
+    pub fn midi_tuning(&mut self, sysex: &[u8]) -> Result<(), String> {
+        let message = MtsMessage::parse(sysex)?;
+        self.notes.midi_tuning(&message)
+    }
+
+    /// Returns the transport.
+    pub fn transport(&self) -> &Transport {
+        self.clock.transport()
+    }
+
+    /// Sets the tempo of the transport, in beats per minute. The arpeggiator, the sequencer and the
+    /// effects follow it.
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.clock.transport_mut().set_bpm(bpm);
+        self.apply_tempo();
+    }
+
+    /// Sets the time signature of the transport.
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.clock.transport_mut().set_time_signature(time_signature);
+    }
+
+    /// Sets where the transport takes its tempo and position from. Changing the source stops the
+    /// transport.
+    pub fn set_clock_source(&mut self, source: ClockSource) {
+        self.clock.transport_mut().set_source(source);
+        self.follow_transport();
+    }
+
+    /// Handles a MIDI timing clock message.
+    pub fn midi_clock(&mut self) {
+        self.clock.transport_mut().midi_clock();
+        self.apply_tempo();
+        self.follow_transport();
+    }
+
+    /// Handles a MIDI start message.
+    pub fn midi_start(&mut self) {
+        self.clock.transport_mut().midi_start();
+        self.follow_transport();
+    }
+
+    /// Handles a MIDI continue message.
+    pub fn midi_continue(&mut self) {
+        self.clock.transport_mut().midi_continue();
+        self.follow_transport();
+    }
+
+    /// Handles a MIDI stop message.
+    pub fn midi_stop(&mut self) {
+        self.clock.transport_mut().midi_stop();
+        self.follow_transport();
+    }
+
+    /// Handles a MIDI song position pointer, given in sixteenth notes from the start of the song.
+    pub fn midi_song_position(&mut self, sixteenths: u16) {
+        self.clock.transport_mut().midi_song_position(sixteenths);
+    }
+
+    /// Takes the tempo, position and play state reported by a host.
+    pub fn set_host_time(&mut self, bpm: f32, position_beats: f64, playing: bool) {
+        self.clock.transport_mut().set_host_time(bpm, position_beats, playing);
+        self.apply_tempo();
+        self.follow_transport();
+    }
+
+    /// Returns the step sequencer.
+    pub fn sequencer(&self) -> &Sequencer {
+        self.clock.sequencer()
+    }
+
+    /// Returns the step sequencer for editing its patterns. Use `start_sequencer` and
+    /// `stop_sequencer` to start and stop it, so that it stays in step with the transport.
+    pub fn sequencer_mut(&mut self) -> &mut Sequencer {
+        self.clock.sequencer_mut()
+    }
+
+    /// Arms the sequencer to play its steps with `voice` whenever the transport is playing, and
+    /// starts the transport when it runs on its own clock.
+    pub fn start_sequencer(&mut self, mut voice: Synth) {
+        self.prepare_voice(&mut voice);
+        self.notes.voices_mut().reserve(&voice);
+        self.clock.start_sequencer(voice);
+        self.follow_transport();
+    }
+
+    /// Returns whether the sequencer is armed to play with the transport.
+    pub fn is_sequencer_armed(&self) -> bool {
+        self.clock.is_sequencer_armed()
+    }
+
+    /// Stops the transport and returns it to the start of the song, stopping the sequencer and
+    /// releasing the note it was playing.
+    pub fn stop_sequencer(&mut self) {
+        self.clock.stop_sequencer();
+        self.follow_transport();
+    }
+
+    /// Removes a synthesizer from the MIDI map based on the given MIDI key.
+    pub fn remove(&mut self, k:&u8) -> Option<Synth> {
+        self.notes.voices_mut().remove(*k)
+    }
+
+    /// Clears all synthesizers from the MIDI map.
+    pub fn clear(&mut self) {
+        self.clock.clear();
+        self.midi_output.release_all();
+        self.notes.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.notes.voices().len()
+    }
+
+    pub fn iterate_hashmap_mut(&mut self) -> impl Iterator<Item = (&u8, &mut Synth)> {
+        self.notes.voices_mut().iter_mut()
+    }
+
+    /// Checks if the MIDI map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.notes.voices().is_empty()
+    }
+
+    /// Retrieves a synthesizer from the MIDI map based on the given MIDI key.
+    pub fn get(&self, k: &u8) -> Option<&Synth> {
+        self.notes.voices().get(*k)
+    }
+
+    /// Returns the master effects chain.
+    pub fn effects(&self) -> &EffectsChain {
+        self.chain.effects()
+    }
+
+    /// Returns the master effects chain for modification.
+    pub fn effects_mut(&mut self) -> &mut EffectsChain {
+        self.chain.effects_mut()
+    }
+
+    /// Returns the master dynamics section.
+    pub fn dynamics(&self) -> &MasterDynamics {
+        self.chain.dynamics()
+    }
+
+    /// Returns the master dynamics section for modification.
+    pub fn dynamics_mut(&mut self) -> &mut MasterDynamics {
+        self.chain.dynamics_mut()
+    }
+
+    /// Returns the pitch bend and mod wheel.
+    pub fn controllers(&self) -> &Controllers {
+        self.notes.controllers()
+    }
+
+    /// Sets the bend range and mod wheel target, and applies them to every voice.
+    pub fn set_controller_settings(&mut self, settings: ControllerSettings) {
+        self.notes.set_controller_settings(settings);
+    }
+
+    /// Sets the pitch bend, between -1.0 (fully down) and 1.0 (fully up), on every voice without
+    /// restarting their notes.
+    pub fn set_pitch_bend(&mut self, bend: f32) {
+        self.notes.set_pitch_bend(bend);
+    }
+
+    /// Sets the pitch bend from a 14-bit MIDI pitch bend value between 0 and 16383.
+    pub fn midi_pitch_bend(&mut self, value: u16) {
+        self.set_pitch_bend(pitch_bend_from_midi(value));
+    }
+
+    /// Sets the mod wheel position, between 0.0 and 1.0, on every voice.
+    pub fn set_mod_wheel(&mut self, position: f32) {
+        self.notes.set_mod_wheel(position);
+    }
+
+    /// Handles a MIDI control change message. Returns whether the controller is used by the engine.
+    pub fn control_change(&mut self, controller: u8, value: u8) -> bool {
+        match controller {
+            MOD_WHEEL_CC => {
+                self.set_mod_wheel(mod_wheel_from_midi(value));
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Sets the effects, controllers, voice mode, arpeggiator and MPE settings stored in a patch. The
+    /// velocity settings belong to the voices passed to `note_on`, so they are left to the caller.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the effects chain of the patch cannot be loaded.
+    pub fn load_patch(&mut self, patch: &Patch) -> Result<(), String> {
+        self.chain.effects_mut().load_state(&patch.effects)?;
+        self.set_controller_settings(patch.controllers);
+        self.set_voice_mode(patch.voice_mode);
+        self.set_arpeggiator(patch.arpeggiator);
+        self.set_mpe_settings(patch.mpe);
+        Ok(())
+    }
+
+    /// Returns the MIDI output.
+    pub fn midi_output(&self) -> &MidiOutput {
+        &self.midi_output
+    }
+
+    /// Sets the MIDI output channel and local control. Turning local control on or off stops every
+    /// voice.
+    pub fn set_midi_output_settings(&mut self, settings: MidiOutputSettings) {
+        if settings.local_control != self.midi_output.is_local() {
+            self.clear();
+        }
+        self.midi_output.set_settings(settings);
+    }
+
+    /// Takes the MIDI messages waiting to be sent to the MIDI output, oldest first.
+    pub fn take_midi_output(&mut self) -> Vec<MidiMessage> {
+        self.midi_output.take_messages()
+    }
+
+    /// Returns the MPE zone and the expression of its member channels.
+    pub fn mpe(&self) -> &Mpe {
+        self.notes.mpe()
+    }
+
+    /// Sets the MPE zone and expression routing. Every voice is stopped when the zone or its member
+    /// channels change. Otherwise the notes keep playing and follow the new bend range and routing.
+    pub fn set_mpe_settings(&mut self, settings: MpeSettings) {
+        if self.notes.set_mpe_settings(settings) {
+            self.clear();
+        }
+    }
+
+    /// Starts a note received on a MIDI channel. On an MPE member channel the note takes the
+    /// expression of the channel, otherwise it is the same as `note_on`, except that the note is not
+    /// sent to the MIDI output.
+    pub fn mpe_note_on(&mut self, channel: u8, k: u8, voice: Synth, velocity: f32) -> Result<(), String> {
+        self.notes.mpe_mut().note_on(channel, k);
+        let result = self.press_key(k, voice, velocity);
+        if result.is_err() {
+            self.notes.mpe_mut().note_off(channel, k);
+        }
+        result
+    }
+
+    /// Releases a note received on a MIDI channel. On an MPE member channel the voice is only released
+    /// if the key is still playing on that channel.
+    pub fn mpe_note_off(&mut self, channel: u8, k: u8) -> Result<(), String> {
+        if self.notes.mpe().is_member(channel) && !self.notes.mpe_mut().note_off(channel, k) {
+            return Ok(());
+        }
+        self.release_key(k)
+    }
+
+    /// Handles a 14-bit MIDI pitch bend received on a MIDI channel. On an MPE member channel it bends
+    /// the note of that channel by the MPE bend range, otherwise it bends every voice.
+    pub fn mpe_pitch_bend(&mut self, channel: u8, value: u16) {
+        if !self.notes.mpe().is_member(channel) {
+            self.midi_pitch_bend(value);
+        } else if let Some(key) = self.notes.mpe_mut().set_pitch_bend(channel, pitch_bend_from_midi(value)) {
+            self.notes.apply_expression(key);
+        }
+    }
+
+    /// Handles a MIDI channel pressure message. Returns whether it is used, which it is on MPE member
+    /// channels only.
+    pub fn mpe_pressure(&mut self, channel: u8, value: u8) -> bool {
+        if !self.notes.mpe().is_member(channel) {
+            return false;
+        }
+        if let Some(key) = self.notes.mpe_mut().set_pressure(channel, value.min(127) as f32 / 127.0) {
+            self.notes.apply_expression(key);
+        }
+        true
+    }
+
+    /// Handles a MIDI control change message received on a MIDI channel. CC74 on an MPE member channel
+    /// sets the timbre of its note, everything else is handled as by `control_change`. Returns whether
+    /// the controller is used by the engine.
+    pub fn mpe_control_change(&mut self, channel: u8, controller: u8, value: u8) -> bool {
+        if controller == TIMBRE_CC && self.notes.mpe().is_member(channel) {
+            if let Some(key) = self.notes.mpe_mut().set_timbre(channel, value.min(127) as f32 / 127.0) {
+                self.notes.apply_expression(key);
+            }
+            return true;
+        }
+        self.control_change(controller, value)
+    }
+
+    /// Starts and stops the voices for the notes the arpeggiator and sequencer have produced.
+    fn handle_clock_events(&mut self) {
+        while let Some((event, voice)) = self.clock.pop_event() {
+            let _ = match event {
+                NoteEvent::NoteOn(key, velocity) => {
+                    self.midi_output.note_on(key, velocity);
+                    match voice {
+                        // The voice is copied into the pool, which does not allocate in real-time mode
+                        Some(voice) if self.midi_output.is_local() => self.notes.play_note(key, VoiceSource::Template(voice), velocity),
+                        _ => Ok(())
+                    }
+                },
+                NoteEvent::NoteOff(key) => {
+                    self.midi_output.note_off(key);
+                    self.notes.release_note(key)
+                }
+            };
+        }
+    }
+
+    /// Passes the tempo of the transport on to everything that is synced to it.
+    fn apply_tempo(&mut self) {
+        let bpm = self.clock.sync_tempo();
+        self.chain.effects_mut().set_tempo(bpm);
+    }
+
+    /// Starts or stops the armed sequencer with the transport, releasing its note when it stops.
+    fn follow_transport(&mut self) {
+        self.clock.follow_transport();
+        self.handle_clock_events();
+    }
+
+    /// Moves a voice made for another sample rate to the rate of the engine.
+    fn prepare_voice(&self, voice: &mut Synth) {
+        if voice.sample_rate != self.sample_rate {
+            voice.prepare(self.sample_rate, self.max_block_size);
+        }
+    }
+
+    /// Generates a stereo frame by summing all synthesizers in the MIDI map and passing the result
+    /// through the master effects chain and the master dynamics.
+    pub fn get_stereo_sample(&mut self) -> (f32, f32) {
+        if self.clock.advance_transport() {
+            self.apply_tempo();
+        }
+        self.clock.follow_transport();
+        self.clock.advance_patterns();
+        self.handle_clock_events();
+        let sample = self.notes.mix();
+        self.chain.process(sample)
+    }
+
+    /// Generates audio samples from all synthesizers in the MIDI map, mixed down to mono.
+    pub fn get_sample(&mut self) -> f32 {
+        let (left, right) = self.get_stereo_sample();
+        (left + right) * 0.5
+    }
+}
+
+impl Prepare for IterablePolyphonyHashMap {
+    /// Prepares every voice, the voices kept for the arpeggiator and sequencer, the effects, the
+    /// dynamics and the clocks. Notes keep playing at the same pitch and the patterns stay in time.
+    /// Voices passed to `note_on` afterwards that were made for another rate are prepared as they
+    /// start.
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.max_block_size = max_block_size;
+        self.notes.voices_mut().prepare(sample_rate, max_block_size);
+        self.clock.prepare(sample_rate, max_block_size);
+        self.chain.prepare(sample_rate, max_block_size);
+    }
+}
+
+impl Iterator for IterablePolyphonyHashMap {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.get_sample())
+    }
+}
+
+impl Source for IterablePolyphonyHashMap {
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
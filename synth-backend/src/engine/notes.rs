@@ -0,0 +1,258 @@
+//! Note routing
+//!
+//! `NoteRouter` turns keys into sounding voices. It tunes each key through the `Tuning`, follows the
+//! voice mode with its held keys and glides, and keeps the voices of the engine in a `VoicePool`. The
+//! pitch bend and mod wheel of the `Controllers` act on every voice, and the expression of an `Mpe`
+//! member channel on the voice of its note.
+use crate::wrapper::Synth;
+use crate::controllers::{ControllerSettings, Controllers};
+use crate::voice_mode::{HeldNotes, VoiceMode, VoiceModeSettings};
+use crate::tuning::{MtsMessage, Tuning};
+use crate::mpe::{Mpe, MpeSettings};
+use crate::voice_pool::{VoicePool, VoiceSource};
+
+/// Starts, stops and steers the voices of the engine.
+#[derive(Clone, Debug)]
+pub(crate) struct NoteRouter {
+    voices: VoicePool,
+    voice_mode: VoiceModeSettings,
+    held_notes: HeldNotes,
+    mono_key: Option<u8>,
+    last_mono_pitch: Option<f32>,
+    tuning: Tuning,
+    controllers: Controllers,
+    mpe: Mpe,
+}
+
+impl NoteRouter {
+    /// Creates a new `NoteRouter` that plays its notes with `voices`.
+    pub(crate) fn new(voices: VoicePool) -> Self {
+        Self {
+            voices,
+            voice_mode: VoiceModeSettings::default(),
+            held_notes: HeldNotes::new(),
+            mono_key: None,
+            last_mono_pitch: None,
+            tuning: Tuning::default(),
+            controllers: Controllers::default(),
+            mpe: Mpe::default(),
+        }
+    }
+
+    pub(crate) fn voices(&self) -> &VoicePool {
+        &self.voices
+    }
+
+    pub(crate) fn voices_mut(&mut self) -> &mut VoicePool {
+        &mut self.voices
+    }
+
+    /// Starts a note on `k` with a voice from `voice`, or in the single voice modes moves the voice
+    /// to the key the note priority picks.
+    pub(crate) fn play_note(&mut self, k: u8, voice: VoiceSource, velocity: f32) -> Result<(), String> {
+        let frequency = self.tuning.frequency(k)?;
+        if self.voice_mode.mode.is_mono() {
+            let legato = !self.held_notes.is_empty();
+            self.held_notes.press(k, velocity);
+            return match self.held_notes.select(self.voice_mode.priority) {
+                Some((key, velocity)) if Some(key) != self.mono_key => self.play_mono(key, velocity, Some(voice), legato),
+                _ => Ok(())
+            };
+        }
+        let started = self.voices.start(k, voice);
+        if let Err(error) = started.note_on(frequency, velocity) {
+            self.voices.release(k);
+            return Err(error);
+        }
+        Self::apply_controllers(&self.controllers, &self.mpe, k, started);
+        Ok(())
+    }
+
+    /// Stops the note on `k`. In the single voice modes the voice returns to the key the note priority
+    /// picks among the keys that are still held.
+    pub(crate) fn release_note(&mut self, k: u8) -> Result<(), String> {
+        if !self.voice_mode.mode.is_mono() {
+            self.voices.release(k);
+            return Ok(());
+        }
+        self.held_notes.release(k);
+        if self.mono_key != Some(k) {
+            return Ok(());
+        }
+        match self.held_notes.select(self.voice_mode.priority) {
+            Some((key, velocity)) => self.play_mono(key, velocity, None, true),
+            None => {
+                self.last_mono_pitch = self.mono_pitch();
+                self.mono_key = None;
+                self.voices.release(k);
+                Ok(())
+            }
+        }
+    }
+
+    /// Stops every voice and forgets the held keys.
+    pub(crate) fn clear(&mut self) {
+        self.voices.clear();
+        self.held_notes.clear();
+        self.mono_key = None;
+        self.last_mono_pitch = None;
+    }
+
+    pub(crate) fn voice_mode(&self) -> VoiceModeSettings {
+        self.voice_mode
+    }
+
+    /// Sets the voice mode and portamento. Voices should be stopped first when the mode changes.
+    pub(crate) fn set_voice_mode(&mut self, settings: VoiceModeSettings) {
+        self.voice_mode = settings;
+    }
+
+    pub(crate) fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    /// Sets the tuning and retunes the voices that are sounding.
+    pub(crate) fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+        self.retune_voices();
+    }
+
+    /// Applies a MIDI Tuning Standard message and retunes the voices that are sounding.
+    pub(crate) fn midi_tuning(&mut self, message: &MtsMessage) -> Result<(), String> {
+        self.tuning.apply_mts(message)?;
+        self.retune_voices();
+        Ok(())
+    }
+
+    pub(crate) fn controllers(&self) -> &Controllers {
+        &self.controllers
+    }
+
+    /// Sets the bend range and mod wheel target, and applies them to every voice.
+    pub(crate) fn set_controller_settings(&mut self, settings: ControllerSettings) {
+        self.controllers.set_settings(settings);
+        self.apply_pitch_bend();
+        self.apply_mod_wheel();
+    }
+
+    pub(crate) fn set_pitch_bend(&mut self, bend: f32) {
+        self.controllers.set_pitch_bend(bend);
+        self.apply_pitch_bend();
+    }
+
+    pub(crate) fn set_mod_wheel(&mut self, position: f32) {
+        self.controllers.set_mod_wheel(position);
+        self.apply_mod_wheel();
+    }
+
+    pub(crate) fn mpe(&self) -> &Mpe {
+        &self.mpe
+    }
+
+    /// Returns the MPE state for channel messages. Changes of expression are applied with
+    /// `apply_expression`.
+    pub(crate) fn mpe_mut(&mut self) -> &mut Mpe {
+        &mut self.mpe
+    }
+
+    /// Sets the MPE zone and expression routing. Returns whether the zone or its member channels
+    /// changed, in which case the voices should be stopped. Otherwise every voice follows the new bend
+    /// range and routing.
+    pub(crate) fn set_mpe_settings(&mut self, settings: MpeSettings) -> bool {
+        if self.mpe.set_settings(settings) {
+            return true;
+        }
+        for (key, voice) in self.voices.iter_mut() {
+            voice.set_pitch_ratio(self.controllers.pitch_ratio() * self.mpe.pitch_ratio(*key));
+            let (gain, cutoff_ratio) = self.mpe.voice_expression(*key);
+            voice.set_expression(gain, cutoff_ratio);
+        }
+        false
+    }
+
+    /// Applies the per-note pitch bend, pressure and timbre of an MPE member channel to its voice.
+    pub(crate) fn apply_expression(&mut self, key: u8) {
+        if let Some(voice) = self.voices.get_mut(key) {
+            voice.set_pitch_ratio(self.controllers.pitch_ratio() * self.mpe.pitch_ratio(key));
+            let (gain, cutoff_ratio) = self.mpe.voice_expression(key);
+            voice.set_expression(gain, cutoff_ratio);
+        }
+    }
+
+    /// Sums one sample of every voice that is playing.
+    pub(crate) fn mix(&mut self) -> f32 {
+        self.voices.iter_mut().map(|(_, synth)| synth.get_sample()).sum()
+    }
+
+    /// Sets the frequency of every sounding voice from the tuning. Voices on keys the tuning does not
+    /// map keep their pitch until they are released.
+    fn retune_voices(&mut self) {
+        for (key, voice) in self.voices.iter_mut() {
+            if let Ok(frequency) = self.tuning.frequency(*key) {
+                let _ = voice.global_set_frequency(frequency);
+            }
+        }
+    }
+
+    fn apply_controllers(controllers: &Controllers, mpe: &Mpe, key: u8, voice: &mut Synth) {
+        voice.set_pitch_ratio(controllers.pitch_ratio() * mpe.pitch_ratio(key));
+        voice.set_lfo_depth(controllers.lfo_depth());
+        let (gain, cutoff_ratio) = mpe.voice_expression(key);
+        voice.set_expression(gain, cutoff_ratio);
+    }
+
+    /// Returns the pitch of the single voice in semitones, including any glide still in progress.
+    fn mono_pitch(&self) -> Option<f32> {
+        match self.mono_key.and_then(|key| self.voices.get(key).map(|voice| (key, voice))) {
+            Some((key, voice)) => self.tuning.pitch_semitones(key).ok().map(|pitch| pitch + voice.glide_offset_semitones()),
+            None => self.last_mono_pitch
+        }
+    }
+
+    /// Moves the single voice to `key`. `template` is used when a new voice is needed, otherwise the
+    /// playing voice is retuned, restarting its envelope unless the mode is legato and `legato` is set.
+    fn play_mono(&mut self, key: u8, velocity: f32, template: Option<VoiceSource>, legato: bool) -> Result<(), String> {
+        let frequency = self.tuning.frequency(key)?;
+        let pitch = self.tuning.pitch_semitones(key)?;
+        let previous_pitch = self.mono_pitch();
+        let playing = self.mono_key.take().is_some_and(|old_key| self.voices.rekey(old_key, key));
+        let retune = playing && legato && self.voice_mode.mode == VoiceMode::Legato;
+        let voice = match template {
+            Some(source) if !retune => self.voices.start(key, source),
+            _ => match self.voices.get_mut(key) {
+                Some(voice) if playing => voice,
+                _ => return Ok(())
+            }
+        };
+        let result = if retune {
+            voice.global_set_frequency(frequency)
+        } else {
+            voice.note_on(frequency, velocity).map(|_| Self::apply_controllers(&self.controllers, &self.mpe, key, voice))
+        };
+        if let Err(error) = result {
+            self.voices.release(key);
+            return Err(error);
+        }
+        if let Some(previous_pitch) = previous_pitch {
+            if legato || !self.voice_mode.legato_glide_only {
+                voice.start_glide(previous_pitch - pitch, self.voice_mode.glide_ms, self.voice_mode.glide_mode);
+            }
+        }
+        self.mono_key = Some(key);
+        Ok(())
+    }
+
+    fn apply_pitch_bend(&mut self) {
+        let pitch_ratio = self.controllers.pitch_ratio();
+        for (key, synth) in self.voices.iter_mut() {
+            synth.set_pitch_ratio(pitch_ratio * self.mpe.pitch_ratio(*key));
+        }
+    }
+
+    fn apply_mod_wheel(&mut self) {
+        let depth = self.controllers.lfo_depth();
+        for (_, synth) in self.voices.iter_mut() {
+            synth.set_lfo_depth(depth);
+        }
+    }
+}
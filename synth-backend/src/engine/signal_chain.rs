@@ -0,0 +1,53 @@
+//! Signal chain
+//!
+//! `SignalChain` is what the summed voices pass through on their way out: the master `EffectsChain`,
+//! then the `MasterDynamics`, which keeps the output from clipping.
+use crate::effects::EffectsChain;
+use crate::dynamics::MasterDynamics;
+use crate::lifecycle::Prepare;
+
+/// The master effects and dynamics.
+#[derive(Clone, Debug)]
+pub(crate) struct SignalChain {
+    effects: EffectsChain,
+    dynamics: MasterDynamics,
+}
+
+impl SignalChain {
+    /// Creates a new `SignalChain` with no effects at the given sample rate.
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        Self {
+            effects: EffectsChain::new(sample_rate),
+            dynamics: MasterDynamics::new(sample_rate),
+        }
+    }
+
+    pub(crate) fn effects(&self) -> &EffectsChain {
+        &self.effects
+    }
+
+    pub(crate) fn effects_mut(&mut self) -> &mut EffectsChain {
+        &mut self.effects
+    }
+
+    pub(crate) fn dynamics(&self) -> &MasterDynamics {
+        &self.dynamics
+    }
+
+    pub(crate) fn dynamics_mut(&mut self) -> &mut MasterDynamics {
+        &mut self.dynamics
+    }
+
+    /// Passes a mono sample through the effects and the dynamics and returns the stereo frame.
+    pub(crate) fn process(&mut self, sample: f32) -> (f32, f32) {
+        let frame = self.effects.process((sample, sample));
+        self.dynamics.process(frame)
+    }
+}
+
+impl Prepare for SignalChain {
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        self.effects.prepare(sample_rate, max_block_size);
+        self.dynamics.prepare(sample_rate, max_block_size);
+    }
+}
@@ -24,6 +24,7 @@
 //! // Set the attack time to 50 ms
 //! envelope.set_param(EnvelopeParam::AttackMs, 50.0);
 //! ```
+use crate::lifecycle::Prepare;
 
 #[derive(Clone, Debug)]
 pub enum EnvelopeParam {
@@ -41,6 +42,9 @@ pub enum EnvelopeParam {
 pub struct Envelope {
     current_step: usize,
     sample_rate_hz: f32,
+    attack_ms: f32,
+    decay_ms: f32,
+    release_ms: f32,
     attack_step: usize,
     decay_step: usize,
     sustain_percentage: f32,
@@ -69,14 +73,26 @@ impl Envelope {
         release_ms: f32
     ) -> Self 
     {
-        Self {
+        let mut envelope = Self {
             current_step: 0,
             sample_rate_hz: sample_rate_hz,
-            attack_step: (attack_ms * sample_rate_hz / 1000.0) as usize,
-            decay_step: (decay_ms * sample_rate_hz / 1000.0) as usize,
+            attack_ms,
+            decay_ms,
+            release_ms,
+            attack_step: 0,
+            decay_step: 0,
             sustain_percentage: sustain_percentage,
-            release_step: (release_ms * sample_rate_hz / 1000.0) as usize
-        }
+            release_step: 0
+        };
+        envelope.update_steps();
+        envelope
+    }
+
+    /// Works out the length of each stage in samples from its time in milliseconds.
+    fn update_steps(&mut self) {
+        self.attack_step = (self.attack_ms * self.sample_rate_hz / 1000.0) as usize;
+        self.decay_step = (self.decay_ms * self.sample_rate_hz / 1000.0) as usize;
+        self.release_step = (self.release_ms * self.sample_rate_hz / 1000.0) as usize;
     }
 
     /// Returns the current amplitude of the envelope.
@@ -110,18 +126,36 @@ impl Envelope {
     /// * `value` - New value of the parameter.
     pub fn set_param(&mut self, param: EnvelopeParam, value: f32) {
         match param {
-            EnvelopeParam::AttackMs => {
-                self.attack_step = (value * self.sample_rate_hz / 1000.0) as usize;
-            }
-            EnvelopeParam::DecayMs => {
-                self.decay_step = (value * self.sample_rate_hz / 1000.0) as usize;
-            }
-            EnvelopeParam::SustainPercentage => {
-                self.sustain_percentage = value;
-            }
-            EnvelopeParam::ReleaseMs => {
-                self.release_step = (value * self.sample_rate_hz / 1000.0) as usize;
-            }
+            EnvelopeParam::AttackMs => self.attack_ms = value,
+            EnvelopeParam::DecayMs => self.decay_ms = value,
+            EnvelopeParam::SustainPercentage => self.sustain_percentage = value,
+            EnvelopeParam::ReleaseMs => self.release_ms = value,
         }
+        self.update_steps();
+    }
+
+    /// Returns the current value of a parameter of the envelope.
+    ///
+    /// # Arguments
+    ///
+    /// * `param` - Parameter to read.
+    pub fn get_param(&self, param: EnvelopeParam) -> f32 {
+        match param {
+            EnvelopeParam::AttackMs => self.attack_ms,
+            EnvelopeParam::DecayMs => self.decay_ms,
+            EnvelopeParam::SustainPercentage => self.sustain_percentage,
+            EnvelopeParam::ReleaseMs => self.release_ms,
+        }
+    }
+}
+
+impl Prepare for Envelope {
+    /// Keeps the stage times in milliseconds and how far the envelope has run in time, so that a
+    /// note that is playing carries on from the same point.
+    fn prepare(&mut self, sample_rate: u32, _max_block_size: usize) {
+        let ratio = sample_rate as f32 / self.sample_rate_hz;
+        self.current_step = (self.current_step as f32 * ratio).round() as usize;
+        self.sample_rate_hz = sample_rate as f32;
+        self.update_steps();
     }
 }
\ No newline at end of file
@@ -23,6 +23,8 @@
 //! filter.change_filter_type(FilterType::HighPass);
 //! ```
 use std::f32::consts::PI;
+use crate::lifecycle::Prepare;
+use serde::{Deserialize, Serialize};
 
 /// Parameters that can be set for a filter.
 #[derive(Debug, Clone, Copy)]
@@ -36,7 +38,7 @@ pub enum FilterParam {
 }
 
 /// Types of digital filters.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FilterType {
     /// Low-pass filter.
     LowPass,
@@ -182,6 +184,19 @@ impl Filter {
         }
     }
 
+    /// Returns the current value of a parameter of the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `param` - The parameter to read.
+    pub fn get_param(&self, param: FilterParam) -> f32 {
+        match param {
+            FilterParam::SampleRateHz => self.sample_rate_hz,
+            FilterParam::FreqHz => self.freq_hz,
+            FilterParam::BandwidthHz => self.bandwidth_hz,
+        }
+    }
+
     /// Changes the filter type to the specified type.
     ///
     /// # Arguments
@@ -202,3 +217,10 @@ impl Filter {
         }
     }
 }
+
+impl Prepare for Filter {
+    /// Keeps the cutoff and bandwidth in Hertz and works out the coefficients for the new rate.
+    fn prepare(&mut self, sample_rate: u32, _max_block_size: usize) {
+        self.set_param(FilterParam::SampleRateHz, sample_rate as f32);
+    }
+}
\ No newline at end of file
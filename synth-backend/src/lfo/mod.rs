@@ -1,17 +1,21 @@
 use crate::oscillators::{WaveTableOscillator, Oscillator, self};
 use crate::ring_buffer::RingBuffer;
+use crate::lifecycle::Prepare;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LFOType {
     Amplitude,
     Frequency
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct LFO {
     lfo_type: LFOType,
     sample_rate_hz: f32,
     lfo: WaveTableOscillator,
+    depth: f32,
+    width_sec: f32, // frequency
     width_sample: usize, // frequency
     delay_line: RingBuffer<f32>, // frequency
 }
@@ -23,6 +27,8 @@ impl LFO {
             lfo_type: lfo_type,
             sample_rate_hz: sample_rate_hz,
             lfo: lfo,
+            depth: 1.0,
+            width_sec,
             width_sample: width_sample,
             delay_line: RingBuffer::new(2 + width_sample * 3),
         }
@@ -43,27 +49,45 @@ impl LFO {
         self.lfo.set_frequency(frequency);
     }
 
+    /// Sets what the LFO modulates. The delay line is kept for both types, so switching only clears
+    /// it and does not allocate.
     pub fn set_type(&mut self, lfo_type: LFOType) {
         self.lfo_type = lfo_type;
-        match self.lfo_type {
-            LFOType::Amplitude => {},
-            LFOType::Frequency => {
-                self.delay_line = RingBuffer::new(2 + self.width_sample * 3);
-            }
-        }
+        self.delay_line.reset();
     }
 
     pub fn set_oscillator(&mut self, oscillator: Oscillator) {
         self.lfo.set_oscillator(oscillator);
     }
 
+    /// Sets how strongly the LFO modulates its input, between 0.0 (not at all) and 1.0 (fully).
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    pub fn get_depth(&self) -> f32 {
+        self.depth
+    }
+
+    /// Sets the width of the frequency modulation in seconds. The delay line is sized again when the
+    /// width changes.
     pub fn set_width(&mut self, width_sec: f32) {
-        self.width_sample = (width_sec * self.sample_rate_hz).round() as usize;
+        self.width_sec = width_sec;
+        let width_sample = (width_sec * self.sample_rate_hz).round() as usize;
+        if width_sample != self.width_sample {
+            self.width_sample = width_sample;
+            self.delay_line = RingBuffer::new(2 + width_sample * 3);
+        }
+    }
+
+    /// Returns the length of the delay line the frequency LFO modulates, in samples.
+    pub fn delay_line_len(&self) -> usize {
+        self.delay_line.capacity()
     }
 
     fn process_frequency(&mut self, input: f32) -> f32 {
         let modulator = self.lfo.get_sample();
-        let offset = 1.0 + self.width_sample as f32 + self.width_sample as f32 * modulator;
+        let offset = 1.0 + self.width_sample as f32 + self.width_sample as f32 * modulator * self.depth;
         let _ = self.delay_line.pop();
         self.delay_line.push(input);
         self.delay_line.get_frac(offset)
@@ -71,14 +95,47 @@ impl LFO {
 
     fn process_amplitude(&mut self, input: f32) -> f32 {
         let a = self.lfo.get_sample();
-        match self.lfo.get_oscillator() {
-            Oscillator::Square => input * a.abs(),
-            _ => input * (a + 1.0) / 2.0,
-        }
-        
+        let gain = match self.lfo.get_oscillator() {
+            Oscillator::Square => a.abs(),
+            _ => (a + 1.0) / 2.0,
+        };
+        input * (1.0 - self.depth * (1.0 - gain))
     }
 
     pub fn get_oscillator(&self) -> Oscillator {
         self.lfo.get_oscillator()
     }
+}
+
+impl Clone for LFO {
+    fn clone(&self) -> Self {
+        Self {
+            lfo_type: self.lfo_type.clone(),
+            lfo: self.lfo.clone(),
+            delay_line: self.delay_line.clone(),
+            ..*self
+        }
+    }
+
+    // Reuses the delay line, so that a voice can be copied from another without allocating
+    fn clone_from(&mut self, source: &Self) {
+        self.lfo_type = source.lfo_type.clone();
+        self.sample_rate_hz = source.sample_rate_hz;
+        self.lfo.clone_from(&source.lfo);
+        self.depth = source.depth;
+        self.width_sec = source.width_sec;
+        self.width_sample = source.width_sample;
+        self.delay_line.clone_from(&source.delay_line);
+    }
+}
+
+impl Prepare for LFO {
+    /// Keeps the rate and the width in seconds. The delay line of the frequency LFO is sized again
+    /// for the new rate.
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        self.sample_rate_hz = sample_rate as f32;
+        self.lfo.prepare(sample_rate, max_block_size);
+        self.width_sample = (self.width_sec * self.sample_rate_hz).round() as usize;
+        self.delay_line = RingBuffer::new(2 + self.width_sample * 3);
+    }
 }
\ No newline at end of file
@@ -11,7 +11,14 @@
 //!     - For multiple sounds to play together, one must use multiple sinks
 //!
 //! The `synth-backend` crate provides modules for implementing polyphonic MIDI mapping, including utilities,
-//! oscillators, ring buffers, filters, envelopes, and a high-level wrapper for synthesizer creation.
+//! oscillators, ring buffers, filters, envelopes, a high-level wrapper for synthesizer creation, a master effects rack,
+//! master dynamics, performance controllers, MPE, a MIDI parser, a transport clock, an arpeggiator, a step sequencer,
+//! microtonal tunings, serializable patches, the engine that plays voices through all of them and the audio backends
+//! it is played on. The `lifecycle` module lets every component follow a change of sample rate, and the `voice_pool`
+//! module lets the engine render without allocating.
+//!
+//! The `web` feature, on by default, builds the crate for the browser. The `native` feature adds the `native`
+//! module, which picks the audio backend of a desktop computer, and the `play_patch` binary.
 //!
 //! ## Examples
 //!
@@ -37,18 +44,47 @@
 //! The `Synth` struct provides methods for configuring and generating audio samples from a synthesizer.
 
 pub mod utils;
+pub mod lifecycle;
+pub mod tuning;
 pub mod oscillators;
 pub mod ring_buffer;
 pub mod filters;
+pub mod distortion;
 pub mod envelopes;
 pub mod lfo;
+pub mod vibrato;
+pub mod velocity;
+pub mod controllers;
+pub mod mpe;
+pub mod midi;
+pub mod voice_mode;
+pub mod voice_pool;
+pub mod transport;
+pub mod arpeggiator;
+pub mod sequencer;
 pub mod wrapper;
+pub mod effects;
+pub mod dynamics;
+pub mod patch;
+pub mod engine;
+pub mod audio;
+#[cfg(feature = "native")]
+pub mod native;
 
 #[cfg(test)]
 mod tests {
     use rand::random;
     use utils::{midi_cents_to_hz, midi_to_hz, is_close_f32};
     use super::*;
+
+    /// A voice playing one oscillator of `shape` at `gain`, through the given filter and envelope.
+    fn synth_voice(sample_rate: u32, shape: oscillators::Oscillator, gain: f32, filter: Option<filters::Filter>, envelope: Option<envelopes::Envelope>) -> wrapper::Synth {
+        let osc = oscillators::MultiOscillator::from(
+            oscillators::WaveTableOscillator::new(sample_rate, oscillators::DEFAULT_TABLE_SIZE, shape, gain, 0.0)
+        );
+        wrapper::Synth::new(osc, sample_rate, filter, envelope, None, lfo::LFOType::Amplitude)
+    }
+
     mod midi_to_hz_tests {
         use super::*;
 
@@ -127,4 +163,2317 @@ mod tests {
 
 
     }
+
+    mod effects_chain_tests {
+        use super::*;
+        use effects::{EffectsChain, EffectType};
+        use patch::Patch;
+
+        #[test]
+        fn test_1_empty_chain_passes_through() {
+            let mut chain = EffectsChain::new(44100);
+            let (left, right) = chain.process((0.25, -0.5));
+            assert!(is_close_f32(left, 0.25) && is_close_f32(right, -0.5));
+        }
+
+        #[test]
+        fn test_2_bypass_and_mix() {
+            let mut chain = EffectsChain::new(44100);
+            let index = chain.push(EffectType::Gain);
+            chain.set_param(index, 0, -24.0).unwrap();
+            let (left, _) = chain.process((1.0, 1.0));
+            assert!(left < 0.1, "Gain of -24 dB should attenuate, got {left}");
+
+            chain.set_mix(index, 0.0).unwrap();
+            let (left, _) = chain.process((1.0, 1.0));
+            assert!(is_close_f32(left, 1.0), "A fully dry slot should pass the input, got {left}");
+
+            chain.set_mix(index, 1.0).unwrap();
+            chain.set_bypass(index, true).unwrap();
+            let (left, _) = chain.process((1.0, 1.0));
+            assert!(is_close_f32(left, 1.0), "A bypassed slot should pass the input, got {left}");
+            assert!(chain.set_mix(index, 1.5).is_err());
+        }
+
+        #[test]
+        fn test_3_reorder_slots() {
+            let mut chain = EffectsChain::new(44100);
+            chain.push(EffectType::Gain);
+            chain.push(EffectType::Gain);
+            chain.set_param(1, 1, 1.0).unwrap();
+            chain.move_slot(1, 0).unwrap();
+            assert!(is_close_f32(chain.get(0).unwrap().effect().get_param(1), 1.0));
+            assert!(chain.move_slot(2, 0).is_err());
+            assert!(chain.remove(5).is_none());
+            assert!(chain.remove(0).is_some());
+            assert_eq!(chain.len(), 1);
+        }
+
+        #[test]
+        fn test_4_state_round_trip_through_patch() {
+            let mut chain = EffectsChain::new(44100);
+            chain.push(EffectType::Gain);
+            chain.set_param(0, 0, 6.0).unwrap();
+            chain.set_bypass(0, true).unwrap();
+            let json = Patch { effects: chain.state(), ..Default::default() }.to_json().unwrap();
+
+            let mut restored = EffectsChain::new(44100);
+            restored.load_state(&Patch::from_json(&json).unwrap().effects).unwrap();
+            assert_eq!(restored.state(), chain.state());
+        }
+    }
+
+    mod delay_tests {
+        use super::*;
+        use effects::Effect;
+        use effects::delay::Delay;
+
+        fn impulse_response(delay: &mut Delay, length: usize) -> Vec<(f32, f32)> {
+            (0..length).map(|i| {
+                let value = if i == 0 { 1.0 } else { 0.0 };
+                delay.process((value, value))
+            }).collect()
+        }
+
+        #[test]
+        fn test_1_echo_after_delay_time() {
+            let mut delay = Delay::new(44100.0);
+            delay.set_param(0, 10.0);
+            delay.set_param(3, 0.0);
+            delay.reset();
+            let response = impulse_response(&mut delay, 1000);
+            assert!(is_close_f32(response[441].0, 1.0), "Expected the echo at 441 samples, got {}", response[441].0);
+            assert!(response.iter().enumerate().all(|(i, frame)| i == 441 || frame.0.abs() < 0.01));
+        }
+
+        #[test]
+        fn test_2_tempo_sync() {
+            let mut delay = Delay::new(44100.0);
+            delay.set_param(1, 3.0);
+            delay.set_tempo(120.0);
+            assert!(is_close_f32(delay.delay_ms(), 500.0));
+            delay.set_tempo(60.0);
+            assert!(is_close_f32(delay.delay_ms(), 1000.0));
+        }
+
+        #[test]
+        fn test_3_ping_pong_alternates_sides() {
+            let mut delay = Delay::new(44100.0);
+            delay.set_param(0, 10.0);
+            delay.set_param(3, 0.5);
+            delay.set_param(4, 20.0);
+            delay.set_param(5, 20000.0);
+            delay.set_param(6, 1.0);
+            delay.reset();
+            let response = impulse_response(&mut delay, 1000);
+            assert!(response[441].0 > 0.9 && response[441].1.abs() < 0.01);
+            assert!(response[882].0.abs() < 0.01 && response[882].1 > 0.3);
+        }
+
+        #[test]
+        fn test_4_time_changes_are_smoothed() {
+            let mut delay = Delay::new(44100.0);
+            delay.set_param(0, 10.0);
+            delay.reset();
+            let _ = impulse_response(&mut delay, 10);
+            delay.set_param(0, 1000.0);
+            let mut previous = 0.0;
+            for i in 0..100 {
+                let sample = (i as f32 * 0.01).sin();
+                let (left, _) = delay.process((sample, sample));
+                assert!((left - previous).abs() < 0.1, "Output jumped from {previous} to {left}");
+                previous = left;
+            }
+        }
+    }
+
+    mod reverb_tests {
+        use super::*;
+        use effects::Effect;
+        use effects::reverb::Reverb;
+
+        fn tail_energy(reverb: &mut Reverb, start: usize, length: usize) -> f32 {
+            let mut energy = 0.0;
+            for i in 0..start + length {
+                let value = if i == 0 { 1.0 } else { 0.0 };
+                let (left, right) = reverb.process((value, value));
+                if i >= start {
+                    energy += left * left + right * right;
+                }
+            }
+            energy
+        }
+
+        #[test]
+        fn test_1_pre_delay_holds_back_the_tail() {
+            let mut reverb = Reverb::new(44100.0);
+            reverb.set_param(2, 100.0);
+            assert!(tail_energy(&mut reverb, 0, 4410) < 1e-9);
+            reverb.reset();
+            reverb.set_param(2, 0.0);
+            assert!(tail_energy(&mut reverb, 0, 4410) > 1e-6);
+        }
+
+        #[test]
+        fn test_2_larger_room_rings_longer() {
+            let mut small = Reverb::new(44100.0);
+            small.set_param(0, 0.1);
+            let mut large = Reverb::new(44100.0);
+            large.set_param(0, 0.9);
+            assert!(tail_energy(&mut large, 44100, 4410) > tail_energy(&mut small, 44100, 4410));
+        }
+
+        #[test]
+        fn test_3_zero_width_is_mono() {
+            let mut reverb = Reverb::new(48000.0);
+            reverb.set_param(3, 0.0);
+            for i in 0..4800 {
+                let value = if i == 0 { 1.0 } else { 0.0 };
+                let (left, right) = reverb.process((value, value));
+                assert!((left - right).abs() < 1e-6);
+            }
+        }
+    }
+
+    mod modulation_tests {
+        use super::*;
+        use effects::Effect;
+        use effects::modulation::{Chorus, Flanger, Phaser};
+        use oscillators::{Oscillator, WaveTableOscillator};
+        use vibrato::Vibrato;
+
+        #[test]
+        fn test_1_vibrato_delays_by_base_delay() {
+            let lfo = WaveTableOscillator::new(44100, 2048, Oscillator::Sine, 1.0, 5.0);
+            let mut vibrato = Vibrato::new(44100.0, 0.0, lfo);
+            vibrato.set_delay(10.0 / 44100.0);
+            let output: Vec<f32> = (0..20).map(|i| vibrato.process(if i == 0 { 1.0 } else { 0.0 })).collect();
+            assert!((output[10] - 1.0).abs() < 1e-6);
+            assert_eq!(output.iter().filter(|value| value.abs() > 1e-6).count(), 1);
+        }
+
+        #[test]
+        fn test_2_chorus_channels_differ() {
+            let mut chorus = Chorus::new(44100.0);
+            let mut difference = 0.0;
+            for i in 0..44100 {
+                let sample = (i as f32 * 0.05).sin();
+                let (left, right) = chorus.process((sample, sample));
+                difference += (left - right).abs();
+            }
+            assert!(difference > 1.0);
+        }
+
+        #[test]
+        fn test_3_flanger_feedback_adds_repeats() {
+            let mut flanger = Flanger::new(44100.0);
+            flanger.set_param(1, 0.0);
+            flanger.set_param(2, 0.9);
+            let mut repeats = 0;
+            for i in 0..2000 {
+                let value = if i == 0 { 1.0 } else { 0.0 };
+                let (left, _) = flanger.process((value, value));
+                if left.abs() > 1e-3 {
+                    repeats += 1;
+                }
+            }
+            assert!(repeats > 5);
+        }
+
+        #[test]
+        fn test_4_phaser_is_stable_and_colours_signal() {
+            let mut phaser = Phaser::new(44100.0);
+            phaser.set_param(3, 12.0);
+            let mut difference: f32 = 0.0;
+            for i in 0..44100 {
+                let sample = (i as f32 * 0.1).sin();
+                let (left, right) = phaser.process((sample, sample));
+                assert!(left.abs() <= 2.0 && right.abs() <= 2.0);
+                difference = difference.max((left - sample).abs());
+            }
+            assert!(difference > 0.1);
+        }
+    }
+
+    mod distortion_tests {
+        use super::*;
+        use distortion::{Distortion, DistortionParam, DistortionType, Oversampler, Oversampling};
+        use std::f32::consts::PI;
+
+        /// Magnitude of a single frequency in `signal`.
+        fn magnitude_at(signal: &[f32], frequency: f32, sample_rate: f32) -> f32 {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (n, value) in signal.iter().enumerate() {
+                let angle = 2.0 * PI * frequency * n as f32 / sample_rate;
+                re += value * angle.cos();
+                im -= value * angle.sin();
+            }
+            (re * re + im * im).sqrt() / signal.len() as f32
+        }
+
+        #[test]
+        fn test_1_curves_stay_bounded() {
+            for distortion_type in [DistortionType::Tanh, DistortionType::HardClip, DistortionType::Foldback] {
+                let mut distortion = Distortion::new(distortion_type, 44100.0);
+                distortion.set_param(DistortionParam::InputGainDb, 24.0);
+                for i in 0..4410 {
+                    let output = distortion.process((i as f32 * 0.05).sin());
+                    assert!(output.abs() <= 2.1, "{:?} produced {output}", distortion_type);
+                }
+            }
+        }
+
+        #[test]
+        fn test_2_crush_quantizes_and_holds() {
+            let jumps = |bits: f32, rate_hz: f32, step: f32| {
+                let mut distortion = Distortion::new(DistortionType::Crush, 44100.0);
+                distortion.set_param(DistortionParam::Bits, bits);
+                distortion.set_param(DistortionParam::CrushRateHz, rate_hz);
+                let output: Vec<f32> = (0..1000).map(|i| distortion.process((i as f32 * step).sin())).collect();
+                output.windows(2).filter(|pair| (pair[1] - pair[0]).abs() > 0.02).count()
+            };
+            // Two bits only leave five levels for a slow sine to step through
+            let quantized = jumps(2.0, 44100.0, 0.01);
+            assert!(quantized > 5 && quantized < 30, "{quantized} jumps");
+            // Holding at a tenth of the sample rate only lets the output move every tenth sample
+            let held = jumps(16.0, 4410.0, 0.0142);
+            assert!(held > 50 && held <= 100, "{held} jumps");
+        }
+
+        #[test]
+        fn test_3_oversampler_passes_low_frequencies() {
+            for oversampling in Oversampling::ALL {
+                let mut oversampler = Oversampler::new(oversampling);
+                let signal: Vec<f32> = (0..8820).map(|i| (2.0 * PI * 1000.0 * i as f32 / 44100.0).sin()).collect();
+                let output: Vec<f32> = signal.iter().map(|value| oversampler.process(*value, |x| x)).collect();
+                let magnitude = magnitude_at(&output[4410..], 1000.0, 44100.0);
+                assert!((magnitude - 0.5).abs() < 0.01, "{:?} gave {magnitude}", oversampling);
+            }
+        }
+
+        #[test]
+        fn test_4_oversampling_reduces_aliasing() {
+            // A clipped 5 kHz sine has its 7th harmonic at 35 kHz, which aliases to 9.1 kHz at 44.1 kHz.
+            let alias = |oversampling: Oversampling| {
+                let mut distortion = Distortion::new(DistortionType::HardClip, 44100.0);
+                distortion.set_param(DistortionParam::InputGainDb, 20.0);
+                distortion.set_oversampling(oversampling);
+                let output: Vec<f32> = (0..8820)
+                    .map(|i| distortion.process((2.0 * PI * 5000.0 * i as f32 / 44100.0).sin()))
+                    .collect();
+                magnitude_at(&output[4410..], 9100.0, 44100.0)
+            };
+            assert!(alias(Oversampling::X8) * 4.0 < alias(Oversampling::Off));
+        }
+
+        #[test]
+        fn test_5_synth_applies_per_voice_distortion() {
+            let osc = oscillators::MultiOscillator::from(
+                oscillators::WaveTableOscillator::new(44100, 44100, oscillators::Oscillator::Sine, 1.0, 440.0)
+            );
+            let mut clean = wrapper::Synth::new(osc, 44100, None, None, None, lfo::LFOType::Amplitude);
+            let mut driven = clean.clone();
+            driven.set_distortion(Some(DistortionType::HardClip));
+            driven.set_distortion_params(DistortionParam::InputGainDb, 6.0);
+            driven.set_distortion_params(DistortionParam::OutputGainDb, -6.0);
+            let peak = |synth: &mut wrapper::Synth| (0..4410).map(|_| synth.get_sample().abs()).fold(0.0, f32::max);
+            assert!(peak(&mut driven) < 0.75 * peak(&mut clean));
+        }
+    }
+
+    mod dynamics_tests {
+        use super::*;
+        use dynamics::{soft_clip, Compressor, CompressorParam, DetectorMode, Limiter, LimiterParam, MasterDynamics, SOFT_CLIP_KNEE};
+
+        #[test]
+        fn test_1_soft_clip_is_transparent_below_knee_and_bounded() {
+            for i in -100..=100 {
+                let value = i as f32 * SOFT_CLIP_KNEE / 100.0;
+                assert_eq!(soft_clip(value), value);
+            }
+            let mut previous = soft_clip(SOFT_CLIP_KNEE);
+            for i in 1..1000 {
+                let value = soft_clip(SOFT_CLIP_KNEE + i as f32 * 0.01);
+                assert!(value >= previous && value <= 1.0);
+                previous = value;
+            }
+        }
+
+        #[test]
+        fn test_2_compressor_follows_ratio() {
+            let mut compressor = Compressor::new(44100.0);
+            compressor.set_detector(DetectorMode::Peak);
+            compressor.set_param(CompressorParam::ThresholdDb, -20.0);
+            compressor.set_param(CompressorParam::Ratio, 4.0);
+            compressor.set_param(CompressorParam::KneeDb, 0.0);
+            // A steady 0 dB signal is 20 dB over the threshold and should come out 15 dB down
+            for _ in 0..44100 {
+                compressor.process((1.0, 1.0));
+            }
+            assert!((compressor.gain_reduction_db() - 15.0).abs() < 0.1);
+            let (left, _) = compressor.process((1.0, 1.0));
+            assert!(is_close_f32(left, f32::powf(10.0, -15.0 / 20.0)));
+        }
+
+        #[test]
+        fn test_3_limiter_never_exceeds_ceiling() {
+            let mut limiter = Limiter::new(44100.0, 5.0);
+            limiter.set_param(LimiterParam::CeilingDb, -1.0);
+            let ceiling = f32::powf(10.0, -1.0 / 20.0);
+            for i in 0..44100 {
+                let burst = if (i / 3000) % 2 == 0 { 4.0 } else { 0.5 };
+                let value = burst * (random::<f32>() * 2.0 - 1.0);
+                let (left, right) = limiter.process((value, -value));
+                assert!(left.abs() <= ceiling && right.abs() <= ceiling);
+            }
+            assert!(limiter.gain_reduction_db() > 0.0);
+        }
+
+        #[test]
+        fn test_4_limiter_leaves_quiet_signals_alone() {
+            let mut limiter = Limiter::new(44100.0, 5.0);
+            let latency = limiter.latency();
+            let input: Vec<f32> = (0..2000).map(|i| 0.5 * (i as f32 * 0.03).sin()).collect();
+            let output: Vec<f32> = input.iter().map(|value| limiter.process((*value, *value)).0).collect();
+            for i in latency..input.len() {
+                assert!((output[i] - input[i - latency]).abs() < 1e-6);
+            }
+            assert_eq!(limiter.gain_reduction_db(), 0.0);
+        }
+
+        #[test]
+        fn test_5_loud_chord_stays_in_range() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            for (key, frequency) in [(60, 261.63), (64, 329.63), (67, 392.0), (72, 523.25)] {
+                let mut osc = oscillators::MultiOscillator::from(
+                    oscillators::WaveTableOscillator::new(44100, 44100, oscillators::Oscillator::Square, 0.5, frequency)
+                );
+                let _ = osc.push(oscillators::WaveTableOscillator::new(44100, 44100, oscillators::Oscillator::Saw, 0.5, frequency));
+                polyphony.insert(key, wrapper::Synth::new(osc, 44100, None, None, None, lfo::LFOType::Amplitude));
+            }
+            let mut most_reduction: f32 = 0.0;
+            for _ in 0..4410 {
+                let (left, right) = polyphony.get_stereo_sample();
+                assert!(left.abs() <= 1.0 && right.abs() <= 1.0);
+                most_reduction = most_reduction.max(polyphony.dynamics().gain_reduction_db());
+            }
+            assert!(most_reduction > 3.0, "{most_reduction}");
+        }
+
+        #[test]
+        fn test_6_disabled_stages_do_not_report_reduction() {
+            let mut dynamics = MasterDynamics::new(44100);
+            dynamics.set_limiter_enabled(false);
+            for _ in 0..1000 {
+                let (left, _) = dynamics.process((3.0, 3.0));
+                assert!(left <= 1.0);
+            }
+            assert_eq!(dynamics.gain_reduction_db(), 0.0);
+        }
+
+        #[test]
+        fn test_7_limited_sine_peaks_at_the_ceiling() {
+            let mut dynamics = MasterDynamics::new(44100);
+            let ceiling = f32::powf(10.0, -0.3 / 20.0);
+            let mut peak: f32 = 0.0;
+            for i in 0..44100 {
+                let value = (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44100.0).sin();
+                let (left, _) = dynamics.process((value, value));
+                if i >= 22050 {
+                    peak = peak.max(left.abs());
+                }
+            }
+            assert!(peak <= ceiling && peak > ceiling - 0.005, "{peak}");
+        }
+    }
+
+    mod velocity_tests {
+        use super::*;
+        use velocity::{velocity_from_midi, velocity_to_midi, VelocityCurve, VelocitySettings};
+        use envelopes::{Envelope, EnvelopeParam};
+        use filters::{Filter, FilterParam, FilterType};
+
+        fn voice(filter: Option<Filter>, envelope: Option<Envelope>) -> wrapper::Synth {
+            synth_voice(44100, oscillators::Oscillator::Square, 0.5, filter, envelope)
+        }
+
+        fn peak(synth: &mut wrapper::Synth) -> f32 {
+            (0..4410).map(|_| synth.get_sample().abs()).fold(0.0, f32::max)
+        }
+
+        #[test]
+        fn test_1_midi_conversion_round_trips() {
+            for velocity in 0..=127 {
+                assert_eq!(velocity_to_midi(velocity_from_midi(velocity)), velocity);
+            }
+            assert_eq!(velocity_from_midi(200), 1.0);
+        }
+
+        #[test]
+        fn test_2_curves_keep_the_range() {
+            for curve in VelocityCurve::ALL {
+                assert_eq!(curve.apply(1.0), 1.0);
+                assert!(curve.apply(0.5) >= 0.0 && curve.apply(0.5) <= 1.0);
+            }
+            assert!(VelocityCurve::Soft.apply(0.5) > VelocityCurve::Linear.apply(0.5));
+            assert!(VelocityCurve::Hard.apply(0.5) < VelocityCurve::Linear.apply(0.5));
+            assert_eq!(VelocityCurve::Fixed.apply(0.0), 1.0);
+        }
+
+        #[test]
+        fn test_3_velocity_scales_amplitude() {
+            let mut soft = voice(None, None);
+            let mut loud = soft.clone();
+            soft.note_on(440.0, 0.25).unwrap();
+            loud.note_on(440.0, 1.0).unwrap();
+            assert!(is_close_f32(peak(&mut soft) * 4.0, peak(&mut loud)));
+        }
+
+        #[test]
+        fn test_4_velocity_scales_cutoff_and_attack() {
+            let filter = Filter::new(FilterType::LowPass, 44100.0, 4000.0, 0.0);
+            let envelope = Envelope::new(44100.0, 100.0, 0.0, 1.0, 0.0);
+            let mut synth = voice(Some(filter), Some(envelope));
+            synth.velocity_settings = VelocitySettings {
+                curve: VelocityCurve::Linear,
+                amplitude_amount: 0.0,
+                filter_octaves: 2.0,
+                attack_amount: 0.5,
+            };
+            synth.note_on(440.0, 0.5).unwrap();
+            let filter = synth.filter.as_ref().unwrap();
+            assert!(is_close_f32(filter.get_param(FilterParam::FreqHz), 2000.0));
+            let envelope = synth.envelope.as_ref().unwrap();
+            assert!((envelope.get_param(EnvelopeParam::AttackMs) - 75.0).abs() < 0.1);
+
+            // Changing the cutoff while the note plays keeps the velocity offset
+            synth.set_filter_params(FilterParam::FreqHz, 8000.0);
+            assert!(is_close_f32(synth.filter.as_ref().unwrap().get_param(FilterParam::FreqHz), 4000.0));
+        }
+
+        #[test]
+        fn test_5_engine_note_on_carries_velocity() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.note_on(69, voice(None, None), 0.5).unwrap();
+            let synth = polyphony.get(&69).unwrap();
+            assert_eq!(synth.velocity(), 0.5);
+            assert!(polyphony.note_on(200, voice(None, None), 0.5).is_err());
+        }
+
+        #[test]
+        fn test_6_repeated_notes_scale_the_same_attack() {
+            let envelope = Envelope::new(44100.0, 100.0, 0.0, 1.0, 0.0);
+            let mut synth = voice(None, Some(envelope));
+            synth.velocity_settings = VelocitySettings {
+                curve: VelocityCurve::Linear,
+                amplitude_amount: 0.0,
+                filter_octaves: 0.0,
+                attack_amount: 0.5,
+            };
+            let attack = |synth: &wrapper::Synth| synth.envelope.as_ref().unwrap().get_param(EnvelopeParam::AttackMs);
+            synth.note_on(440.0, 0.5).unwrap();
+            synth.note_on(440.0, 0.5).unwrap();
+            assert!((attack(&synth) - 75.0).abs() < 0.1);
+            synth.note_on(440.0, 0.0).unwrap();
+            assert!((attack(&synth) - 100.0).abs() < 0.1);
+
+            // A full scale down to no attack is undone by the next note
+            synth.velocity_settings.attack_amount = 1.0;
+            synth.note_on(440.0, 1.0).unwrap();
+            assert_eq!(attack(&synth), 0.0);
+            synth.note_on(440.0, 0.5).unwrap();
+            assert!((attack(&synth) - 50.0).abs() < 0.1);
+
+            // Setting the attack while a note plays keeps the velocity scaling
+            synth.set_envelope_params(EnvelopeParam::AttackMs, 200.0);
+            assert!((attack(&synth) - 100.0).abs() < 0.1);
+            synth.note_on(440.0, 0.0).unwrap();
+            assert!((attack(&synth) - 200.0).abs() < 0.1);
+        }
+
+        #[test]
+        fn test_7_mono_return_keeps_the_attack() {
+            let envelope = Envelope::new(44100.0, 100.0, 0.0, 1.0, 0.0);
+            let mut template = voice(None, Some(envelope));
+            template.velocity_settings = VelocitySettings {
+                curve: VelocityCurve::Linear,
+                amplitude_amount: 0.0,
+                filter_octaves: 0.0,
+                attack_amount: 0.5,
+            };
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_voice_mode(voice_mode::VoiceModeSettings { mode: voice_mode::VoiceMode::Mono, ..Default::default() });
+            polyphony.note_on(60, template.clone(), 0.5).unwrap();
+            for _ in 0..4 {
+                polyphony.note_on(64, template.clone(), 0.5).unwrap();
+                polyphony.note_off(64).unwrap();
+            }
+            let synth = polyphony.get(&60).unwrap();
+            assert!((synth.envelope.as_ref().unwrap().get_param(EnvelopeParam::AttackMs) - 75.0).abs() < 0.1);
+        }
+    }
+    mod controllers_tests {
+        use super::*;
+        use controllers::{pitch_bend_from_midi, pitch_bend_to_midi, ControllerSettings, ModWheelTarget};
+
+        fn voice() -> wrapper::Synth {
+            let mut synth = synth_voice(44100, oscillators::Oscillator::Sine, 0.5, None, None);
+            synth.set_lfo_osc(Some(oscillators::Oscillator::Sine), 5.0, lfo::LFOType::Amplitude);
+            synth
+        }
+
+        fn zero_crossings(synth: &mut wrapper::Synth, samples: usize) -> usize {
+            let mut previous = synth.get_sample();
+            let mut crossings = 0;
+            for _ in 0..samples {
+                let sample = synth.get_sample();
+                if (previous < 0.0) != (sample < 0.0) {
+                    crossings += 1;
+                }
+                previous = sample;
+            }
+            crossings
+        }
+
+        #[test]
+        fn test_1_midi_pitch_bend_conversion() {
+            assert_eq!(pitch_bend_from_midi(8192), 0.0);
+            assert_eq!(pitch_bend_from_midi(0), -1.0);
+            assert_eq!(pitch_bend_from_midi(16383), 1.0);
+            for value in [0, 1000, 8192, 12000, 16383] {
+                assert_eq!(pitch_bend_to_midi(pitch_bend_from_midi(value)), value);
+            }
+        }
+
+        #[test]
+        fn test_2_pitch_bend_retunes_sounding_voices() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_controller_settings(ControllerSettings {
+                bend_up_semitones: 12.0,
+                bend_down_semitones: 2.0,
+                ..Default::default()
+            });
+            polyphony.note_on(57, voice(), 1.0).unwrap();
+            let mut synth = polyphony.get(&57).unwrap().clone();
+            let unbent = zero_crossings(&mut synth, 44100);
+
+            polyphony.set_pitch_bend(1.0);
+            let mut synth = polyphony.get(&57).unwrap().clone();
+            let bent = zero_crossings(&mut synth, 44100);
+            assert!((bent as f32 / unbent as f32 - 2.0).abs() < 0.01);
+
+            polyphony.midi_pitch_bend(0);
+            let ratio = polyphony.get(&57).unwrap().osc.get_pitch_ratio();
+            assert!((ratio - f32::powf(2.0, -2.0 / 12.0)).abs() < 1e-4);
+            assert_eq!(polyphony.len(), 1);
+        }
+
+        #[test]
+        fn test_3_new_notes_follow_the_bend() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_pitch_bend(1.0);
+            polyphony.note_on(60, voice(), 1.0).unwrap();
+            let ratio = polyphony.get(&60).unwrap().osc.get_pitch_ratio();
+            assert!((ratio - f32::powf(2.0, 2.0 / 12.0)).abs() < 1e-4);
+        }
+
+        #[test]
+        fn test_4_mod_wheel_scales_lfo_depth() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.note_on(60, voice(), 1.0).unwrap();
+            assert!(polyphony.control_change(controllers::MOD_WHEEL_CC, 0));
+            assert!(!polyphony.control_change(7, 0));
+            assert_eq!(polyphony.controllers().mod_wheel(), 0.0);
+            assert_eq!(polyphony.get(&60).unwrap().lfo.as_ref().unwrap().get_depth(), 0.0);
+
+            // At zero depth the LFO leaves the sine as it is
+            let mut synth = polyphony.get(&60).unwrap().clone();
+            let mut dry = synth.clone();
+            dry.lfo = None;
+            for _ in 0..44100 {
+                assert!((synth.get_sample() - dry.get_sample()).abs() < 1e-6);
+            }
+
+            polyphony.set_controller_settings(ControllerSettings {
+                mod_wheel_target: ModWheelTarget::Off,
+                ..Default::default()
+            });
+            assert_eq!(polyphony.get(&60).unwrap().lfo.as_ref().unwrap().get_depth(), 1.0);
+        }
+    }
+    mod voice_mode_tests {
+        use super::*;
+        use envelopes::Envelope;
+        use voice_mode::{GlideMode, HeldNotes, NotePriority, VoiceMode, VoiceModeSettings};
+
+        fn voice() -> wrapper::Synth {
+            let envelope = Envelope::new(44100.0, 100.0, 0.0, 1.0, 0.0);
+            synth_voice(44100, oscillators::Oscillator::BidirectionalSquare, 1.0, None, Some(envelope))
+        }
+
+        fn engine(settings: VoiceModeSettings) -> engine::IterablePolyphonyHashMap {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_voice_mode(settings);
+            polyphony
+        }
+
+        fn advance(polyphony: &mut engine::IterablePolyphonyHashMap, samples: usize) {
+            for _ in 0..samples {
+                polyphony.get_sample();
+            }
+        }
+
+        fn glide_offset(polyphony: &engine::IterablePolyphonyHashMap, key: u8) -> f32 {
+            polyphony.get(&key).unwrap().glide_offset_semitones()
+        }
+
+        #[test]
+        fn test_1_note_priority() {
+            let mut held = HeldNotes::new();
+            held.press(60, 1.0);
+            held.press(64, 1.0);
+            held.press(62, 0.5);
+            assert_eq!(held.select(NotePriority::Last), Some((62, 0.5)));
+            assert_eq!(held.select(NotePriority::Low).unwrap().0, 60);
+            assert_eq!(held.select(NotePriority::High).unwrap().0, 64);
+            assert!(held.release(62));
+            assert!(!held.release(62));
+            assert_eq!(held.select(NotePriority::Last).unwrap().0, 64);
+        }
+
+        #[test]
+        fn test_2_mono_returns_to_held_note() {
+            let mut polyphony = engine(VoiceModeSettings { mode: VoiceMode::Mono, ..Default::default() });
+            polyphony.note_on(60, voice(), 1.0).unwrap();
+            polyphony.note_on(64, voice(), 1.0).unwrap();
+            assert_eq!(polyphony.len(), 1);
+            assert!(polyphony.get(&64).is_some());
+
+            polyphony.note_off(64).unwrap();
+            assert_eq!(polyphony.len(), 1);
+            assert!(polyphony.get(&60).is_some());
+            polyphony.note_off(60).unwrap();
+            assert!(polyphony.is_empty());
+
+            // A lower key does not take over with high note priority
+            polyphony.set_voice_mode(VoiceModeSettings { mode: VoiceMode::Mono, priority: NotePriority::High, ..Default::default() });
+            polyphony.note_on(64, voice(), 1.0).unwrap();
+            polyphony.note_on(60, voice(), 1.0).unwrap();
+            assert!(polyphony.get(&64).is_some());
+            polyphony.note_off(60).unwrap();
+            assert!(polyphony.get(&64).is_some());
+        }
+
+        #[test]
+        fn test_3_legato_keeps_the_envelope_running() {
+            for (mode, retriggers) in [(VoiceMode::Mono, true), (VoiceMode::Legato, false)] {
+                let mut polyphony = engine(VoiceModeSettings { mode, ..Default::default() });
+                polyphony.note_on(60, voice(), 1.0).unwrap();
+                advance(&mut polyphony, 2205);
+                polyphony.note_on(64, voice(), 1.0).unwrap();
+                let mut synth = polyphony.get(&64).unwrap().clone();
+                let peak = (0..100).map(|_| synth.get_sample().abs()).fold(0.0, f32::max);
+                assert_eq!(peak < 0.1, retriggers);
+            }
+        }
+
+        #[test]
+        fn test_4_glide_time_and_rate() {
+            let settings = VoiceModeSettings { mode: VoiceMode::Legato, glide_ms: 100.0, ..Default::default() };
+            let mut polyphony = engine(settings);
+            polyphony.note_on(57, voice(), 1.0).unwrap();
+            polyphony.note_on(69, voice(), 1.0).unwrap();
+            assert!((polyphony.get(&69).unwrap().osc.get_pitch_ratio() - 0.5).abs() < 1e-4);
+            advance(&mut polyphony, 2205);
+            assert!((glide_offset(&polyphony, 69) + 6.0).abs() < 0.01);
+            advance(&mut polyphony, 2205);
+            assert_eq!(glide_offset(&polyphony, 69), 0.0);
+            assert_eq!(polyphony.get(&69).unwrap().osc.get_pitch_ratio(), 1.0);
+
+            // At a constant rate two octaves take twice the glide time
+            let mut polyphony = engine(VoiceModeSettings { glide_mode: GlideMode::ConstantRate, ..settings });
+            polyphony.note_on(45, voice(), 1.0).unwrap();
+            polyphony.note_on(69, voice(), 1.0).unwrap();
+            advance(&mut polyphony, 4410);
+            assert!((glide_offset(&polyphony, 69) + 12.0).abs() < 0.01);
+
+            // Releasing the top key glides back down from where the glide got to
+            polyphony.note_off(69).unwrap();
+            assert!((glide_offset(&polyphony, 45) - 12.0).abs() < 0.01);
+        }
+
+        #[test]
+        fn test_5_glide_only_when_legato() {
+            for (legato_glide_only, offset) in [(false, -12.0), (true, 0.0)] {
+                let mut polyphony = engine(VoiceModeSettings {
+                    mode: VoiceMode::Mono,
+                    glide_ms: 100.0,
+                    legato_glide_only,
+                    ..Default::default()
+                });
+                polyphony.note_on(57, voice(), 1.0).unwrap();
+                polyphony.note_off(57).unwrap();
+                polyphony.note_on(69, voice(), 1.0).unwrap();
+                assert_eq!(glide_offset(&polyphony, 69), offset);
+            }
+        }
+    }
+    mod arpeggiator_tests {
+        use super::*;
+        use arpeggiator::{ArpMode, Arpeggiator, ArpeggiatorSettings, NoteEvent};
+
+        fn holding(settings: ArpeggiatorSettings, keys: &[u8]) -> Arpeggiator {
+            let mut arpeggiator = Arpeggiator::new(44100);
+            arpeggiator.set_settings(ArpeggiatorSettings { enabled: true, ..settings });
+            for key in keys {
+                arpeggiator.key_down(*key, 1.0);
+            }
+            arpeggiator
+        }
+
+        fn run(arpeggiator: &mut Arpeggiator, samples: usize) -> Vec<(usize, NoteEvent)> {
+            let mut events = Vec::new();
+            for sample in 0..samples {
+                arpeggiator.advance();
+                while let Some(event) = arpeggiator.pop_event() {
+                    events.push((sample, event));
+                }
+            }
+            events
+        }
+
+        fn notes_on(events: &[(usize, NoteEvent)]) -> Vec<u8> {
+            events.iter().filter_map(|(_, event)| match event {
+                NoteEvent::NoteOn(key, _) => Some(*key),
+                NoteEvent::NoteOff(_) => None
+            }).collect()
+        }
+
+        #[test]
+        fn test_1_up_over_two_octaves_in_time() {
+            let settings = ArpeggiatorSettings { octaves: 2, ..Default::default() };
+            let mut arpeggiator = holding(settings, &[64, 60]);
+            let events = run(&mut arpeggiator, 4 * 5513);
+            assert_eq!(notes_on(&events), vec![60, 64, 72, 76, 60]);
+
+            // Sixteenths at 120 BPM are 5512.5 samples long, and each note is held for half of that
+            let starts: Vec<usize> = events.iter().filter(|(_, event)| matches!(event, NoteEvent::NoteOn(..))).map(|(sample, _)| *sample).collect();
+            assert_eq!(starts, vec![0, 5513, 11025, 16538, 22050]);
+            assert_eq!(events[1], (2757, NoteEvent::NoteOff(60)));
+        }
+
+        #[test]
+        fn test_2_modes() {
+            let cases = [
+                (ArpMode::Down, vec![67, 64, 60, 67]),
+                (ArpMode::UpDown, vec![60, 64, 67, 64, 60, 64]),
+                (ArpMode::AsPlayed, vec![64, 67, 60, 64]),
+            ];
+            for (mode, expected) in cases {
+                let mut arpeggiator = holding(ArpeggiatorSettings { mode, ..Default::default() }, &[64, 67, 60]);
+                let events = run(&mut arpeggiator, (expected.len() - 1) * 5513 + 1);
+                assert_eq!(notes_on(&events), expected);
+            }
+
+            let mut arpeggiator = holding(ArpeggiatorSettings { mode: ArpMode::Chord, ..Default::default() }, &[64, 67, 60]);
+            let events = run(&mut arpeggiator, 1);
+            assert_eq!(notes_on(&events), vec![60, 64, 67]);
+
+            let mut arpeggiator = holding(ArpeggiatorSettings { mode: ArpMode::Random, ..Default::default() }, &[64, 67, 60]);
+            let events = run(&mut arpeggiator, 20 * 5513);
+            assert!(notes_on(&events).iter().all(|key| [60, 64, 67].contains(key)));
+        }
+
+        #[test]
+        fn test_3_rate_gate_and_swing() {
+            let settings = ArpeggiatorSettings { gate: 0.25, swing: 0.5, ..Default::default() };
+            let mut arpeggiator = holding(settings, &[60]);
+            arpeggiator.set_bpm(60.0);
+            let events = run(&mut arpeggiator, 11026);
+            // A sixteenth at 60 BPM is 11025 samples, swung to 16537.5 and 5512.5
+            assert_eq!(events[1], (4135, NoteEvent::NoteOff(60)));
+            assert_eq!(events.len(), 2);
+            let events = run(&mut arpeggiator, 5513);
+            assert_eq!(events[0], (5512, NoteEvent::NoteOn(60, 1.0)));
+        }
+
+        #[test]
+        fn test_4_latch_holds_until_new_keys() {
+            let settings = ArpeggiatorSettings { latch: true, ..Default::default() };
+            let mut arpeggiator = holding(settings, &[60, 64]);
+            arpeggiator.key_up(60);
+            arpeggiator.key_up(64);
+            assert_eq!(arpeggiator.held_keys().collect::<Vec<u8>>(), vec![60, 64]);
+
+            arpeggiator.key_down(67, 1.0);
+            assert_eq!(arpeggiator.held_keys().collect::<Vec<u8>>(), vec![67]);
+            arpeggiator.key_down(69, 1.0);
+            assert_eq!(arpeggiator.held_keys().collect::<Vec<u8>>(), vec![67, 69]);
+
+            // Without latch the pattern stops with the keys
+            arpeggiator.set_settings(ArpeggiatorSettings { enabled: true, ..Default::default() });
+            arpeggiator.key_up(67);
+            arpeggiator.key_up(69);
+            assert_eq!(arpeggiator.held_keys().count(), 0);
+        }
+
+        #[test]
+        fn test_5_engine_renders_the_same_every_time() {
+            let render = || {
+                let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+                polyphony.set_arpeggiator(ArpeggiatorSettings { enabled: true, mode: ArpMode::Random, octaves: 3, ..Default::default() });
+                let osc = oscillators::MultiOscillator::from(
+                    oscillators::WaveTableOscillator::new(44100, 4096, oscillators::Oscillator::Saw, 0.5, 0.0)
+                );
+                let voice = wrapper::Synth::new(osc, 44100, None, None, None, lfo::LFOType::Amplitude);
+                polyphony.note_on(60, voice.clone(), 1.0).unwrap();
+                polyphony.note_on(63, voice, 0.5).unwrap();
+                assert!(polyphony.is_empty());
+                let samples: Vec<f32> = (0..44100).map(|_| polyphony.get_sample()).collect();
+                samples
+            };
+            let first = render();
+            assert!(first.iter().any(|sample| sample.abs() > 0.01));
+            assert_eq!(first, render());
+        }
+
+        #[test]
+        fn test_6_repeated_key_downs_count_once() {
+            let settings = ArpeggiatorSettings { latch: true, mode: ArpMode::AsPlayed, ..Default::default() };
+            let mut arpeggiator = holding(settings, &[60, 64]);
+            // A held computer key repeats its key down
+            arpeggiator.key_down(60, 1.0);
+            arpeggiator.key_down(60, 1.0);
+            assert_eq!(arpeggiator.held_keys().collect::<Vec<u8>>(), vec![60, 64]);
+            arpeggiator.key_up(60);
+            arpeggiator.key_up(64);
+
+            // Every key is up, so the next key starts a new pattern
+            arpeggiator.key_down(67, 1.0);
+            assert_eq!(arpeggiator.held_keys().collect::<Vec<u8>>(), vec![67]);
+        }
+    }
+    mod sequencer_tests {
+        use super::*;
+        use arpeggiator::NoteEvent;
+        use sequencer::{Pattern, Sequencer, Step};
+
+        fn step(note: u8) -> Step {
+            Step { enabled: true, note, velocity: 1.0, ..Default::default() }
+        }
+
+        fn run(sequencer: &mut Sequencer, samples: usize) -> Vec<(usize, NoteEvent)> {
+            let mut events = Vec::new();
+            for sample in 0..samples {
+                sequencer.advance();
+                while let Some(event) = sequencer.pop_event() {
+                    events.push((sample, event));
+                }
+            }
+            events
+        }
+
+        fn notes_on(events: &[(usize, NoteEvent)]) -> Vec<u8> {
+            events.iter().filter_map(|(_, event)| match event {
+                NoteEvent::NoteOn(key, _) => Some(*key),
+                NoteEvent::NoteOff(_) => None
+            }).collect()
+        }
+
+        #[test]
+        fn test_1_steps_in_time() {
+            let mut sequencer = Sequencer::new(44100);
+            sequencer.set_step(0, 0, step(60)).unwrap();
+            sequencer.set_step(0, 2, step(64)).unwrap();
+            assert!(run(&mut sequencer, 100).is_empty());
+
+            sequencer.play();
+            let events = run(&mut sequencer, 11026);
+            // Sixteenths at 120 BPM are 5512.5 samples long, and each note is held for half of that
+            assert_eq!(events, vec![
+                (0, NoteEvent::NoteOn(60, 1.0)),
+                (2757, NoteEvent::NoteOff(60)),
+                (11025, NoteEvent::NoteOn(64, 1.0)),
+            ]);
+            assert_eq!(sequencer.current_step(), Some(2));
+
+            sequencer.stop();
+            assert_eq!(sequencer.pop_event(), Some(NoteEvent::NoteOff(64)));
+            assert_eq!(sequencer.current_step(), None);
+        }
+
+        #[test]
+        fn test_2_tie_holds_the_previous_note() {
+            let mut sequencer = Sequencer::new(44100);
+            sequencer.set_step(0, 0, step(60)).unwrap();
+            sequencer.set_step(0, 1, Step { tie: true, ..step(67) }).unwrap();
+            sequencer.play();
+            let events = run(&mut sequencer, 3 * 5513);
+            assert_eq!(events, vec![(0, NoteEvent::NoteOn(60, 1.0)), (8269, NoteEvent::NoteOff(60))]);
+        }
+
+        #[test]
+        fn test_3_probability() {
+            let mut sequencer = Sequencer::new(44100);
+            sequencer.set_bpm(300.0);
+            for index in 0..16 {
+                sequencer.set_step(0, index, Step { probability: 0.5, ..step(60) }).unwrap();
+            }
+            sequencer.play();
+            let first = notes_on(&run(&mut sequencer, 16 * 2205));
+            assert!(!first.is_empty() && first.len() < 16);
+            // The same steps play every time the sequencer starts
+            sequencer.play();
+            assert_eq!(notes_on(&run(&mut sequencer, 16 * 2205)), first);
+
+            for (probability, expected) in [(0.0, 0), (1.0, 16)] {
+                for index in 0..16 {
+                    sequencer.set_step(0, index, Step { probability, ..step(60) }).unwrap();
+                }
+                sequencer.play();
+                assert_eq!(notes_on(&run(&mut sequencer, 16 * 2205)).len(), expected);
+            }
+        }
+
+        #[test]
+        fn test_4_pattern_lengths_and_chaining() {
+            assert!(Pattern::new(24).is_err());
+            assert_eq!(Pattern::new(32).unwrap().len(), 32);
+
+            let mut sequencer = Sequencer::new(44100);
+            sequencer.set_bpm(300.0);
+            assert_eq!(sequencer.add_pattern(32), Ok(1));
+            assert!(sequencer.set_pattern_len(1, 20).is_err());
+            assert!(sequencer.set_step(1, 31, step(76)).is_ok());
+            assert!(sequencer.set_step(0, 16, step(76)).is_err());
+            assert!(sequencer.set_chain(vec![0, 2]).is_err());
+
+            sequencer.set_step(0, 0, step(60)).unwrap();
+            sequencer.set_step(1, 0, step(72)).unwrap();
+            sequencer.set_chain(vec![0, 1, 0]).unwrap();
+            sequencer.play();
+            let events = run(&mut sequencer, (16 + 32 + 16) * 2205 + 1);
+            assert_eq!(notes_on(&events), vec![60, 72, 76, 60, 60]);
+
+            // Removing a pattern takes it out of the chain
+            sequencer.remove_pattern(1).unwrap();
+            assert_eq!(sequencer.chain(), &[0, 0]);
+            assert!(sequencer.remove_pattern(0).is_err());
+        }
+
+        #[test]
+        fn test_5_engine_drives_the_voices() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_tempo(60.0);
+            assert_eq!(polyphony.sequencer().bpm(), 60.0);
+            polyphony.sequencer_mut().set_step(0, 0, step(60)).unwrap();
+            polyphony.sequencer_mut().set_step(0, 1, step(62)).unwrap();
+
+            let osc = oscillators::MultiOscillator::from(
+                oscillators::WaveTableOscillator::new(44100, 4096, oscillators::Oscillator::Saw, 0.5, 0.0)
+            );
+            let voice = wrapper::Synth::new(osc, 44100, None, None, None, lfo::LFOType::Amplitude);
+            polyphony.start_sequencer(voice);
+            assert!(polyphony.is_empty());
+            polyphony.get_sample();
+            assert!(polyphony.get(&60).is_some());
+            for _ in 0..11025 {
+                polyphony.get_sample();
+            }
+            assert!(polyphony.get(&60).is_none());
+            assert!(polyphony.get(&62).is_some());
+
+            polyphony.stop_sequencer();
+            assert!(polyphony.is_empty());
+        }
+    }
+    mod transport_tests {
+        use super::*;
+        use transport::{ClockSource, Position, TimeSignature, Transport};
+
+        fn advance(transport: &mut Transport, samples: usize) {
+            for _ in 0..samples {
+                transport.advance();
+            }
+        }
+
+        #[test]
+        fn test_1_position_in_time_signature() {
+            assert!(TimeSignature::new(0, 4).is_err());
+            assert!(TimeSignature::new(7, 6).is_err());
+
+            let mut transport = Transport::new(48000);
+            transport.set_time_signature(TimeSignature::new(6, 8).unwrap());
+            advance(&mut transport, 1000);
+            assert_eq!(transport.position_beats(), 0.0);
+
+            // At 120 BPM a quarter note is 24000 samples and an eighth note 12000
+            transport.play();
+            advance(&mut transport, 12000 * 7 + 3000);
+            assert_eq!(transport.position(), Position { bar: 2, beat: 2, tick: 240 });
+            assert_eq!(transport.position_ticks(), 3 * 960 + 600);
+
+            transport.stop();
+            advance(&mut transport, 1000);
+            assert!((transport.position_beats() - 3.625).abs() < 1e-9);
+            assert_eq!(transport.beats_to_ms(0.5), 250.0);
+            assert_eq!(transport.beats_to_hz(4.0), 0.5);
+        }
+
+        #[test]
+        fn test_2_follows_midi_clock() {
+            let mut transport = Transport::new(48000);
+            transport.set_source(ClockSource::MidiClock);
+            transport.play();
+            assert!(!transport.is_playing());
+
+            // 24 clocks per beat at 100 BPM are 1200 samples apart
+            transport.midi_start();
+            transport.midi_clock();
+            assert!(transport.is_playing());
+            for _ in 0..48 {
+                advance(&mut transport, 1200);
+                transport.midi_clock();
+            }
+            assert!((transport.position_beats() - 2.0).abs() < 1e-9);
+            assert!((transport.bpm() - 100.0).abs() < 0.01);
+
+            // Without clocks the position waits where the next clock is due
+            advance(&mut transport, 5000);
+            assert!((transport.position_beats() - (2.0 + 1.0 / 24.0)).abs() < 1e-9);
+
+            transport.midi_stop();
+            assert!(!transport.is_playing());
+            transport.midi_song_position(16);
+            transport.midi_continue();
+            transport.midi_clock();
+            assert!(transport.is_playing());
+            assert_eq!(transport.position_beats(), 4.0);
+        }
+
+        #[test]
+        fn test_3_follows_host_time() {
+            let mut transport = Transport::new(48000);
+            transport.set_host_time(90.0, 8.0, true);
+            assert!(!transport.is_playing());
+
+            transport.set_source(ClockSource::Host);
+            transport.set_host_time(90.0, 8.0, true);
+            assert_eq!(transport.bpm(), 90.0);
+            advance(&mut transport, 32000);
+            assert!((transport.position_beats() - 9.0).abs() < 1e-9);
+        }
+
+        #[test]
+        fn test_4_engine_shares_the_tempo() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            let delay = polyphony.effects_mut().push(effects::EffectType::Delay);
+            polyphony.effects_mut().set_param(delay, 1, 3.0).unwrap();
+            polyphony.set_tempo(60.0);
+            assert_eq!(polyphony.transport().bpm(), 60.0);
+            assert_eq!(polyphony.arpeggiator().bpm(), 60.0);
+            assert_eq!(polyphony.sequencer().bpm(), 60.0);
+            assert_eq!(polyphony.effects().get(delay).unwrap().effect().get_param(2), 60.0);
+
+            // Effects added later follow the tempo as well
+            let delay = polyphony.effects_mut().push(effects::EffectType::Delay);
+            assert_eq!(polyphony.effects().get(delay).unwrap().effect().get_param(2), 60.0);
+        }
+
+        #[test]
+        fn test_5_sequencer_follows_the_transport() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_clock_source(ClockSource::MidiClock);
+            let step = sequencer::Step { enabled: true, ..Default::default() };
+            polyphony.sequencer_mut().set_step(0, 4, step).unwrap();
+
+            let voice = wrapper::Synth::new(
+                oscillators::MultiOscillator::from(oscillators::WaveTableOscillator::new(44100, 4096, oscillators::Oscillator::Saw, 0.5, 0.0)),
+                44100, None, None, None, lfo::LFOType::Amplitude
+            );
+            polyphony.start_sequencer(voice);
+            polyphony.get_sample();
+            assert!(!polyphony.sequencer().is_playing());
+
+            // Continuing from the second beat joins the pattern at its fifth step
+            polyphony.midi_song_position(4);
+            polyphony.midi_continue();
+            polyphony.midi_clock();
+            polyphony.get_sample();
+            assert_eq!(polyphony.sequencer().current_step(), Some(4));
+            assert!(polyphony.get(&60).is_some());
+
+            polyphony.midi_stop();
+            assert!(!polyphony.sequencer().is_playing());
+            assert!(polyphony.is_empty());
+        }
+    }
+    mod tuning_tests {
+        use super::*;
+        use tuning::{KeyboardMapping, Scale, Tuning};
+
+        const JUST_MAJOR: &str = "! major.scl
+!
+Just major
+ 7
+!
+ 9/8
+ 5/4
+ 4/3
+ 701.955 cents
+ 5/3
+ 15/8
+ 2
+";
+
+        fn is_close(a: f32, b: f32) -> bool {
+            (a - b).abs() <= b * 1e-5
+        }
+
+        #[test]
+        fn test_1_default_is_equal_temperament() {
+            let tuning = Tuning::default();
+            for key in 0..128 {
+                assert!(is_close(tuning.frequency(key).unwrap(), midi_to_hz(key).unwrap()));
+            }
+            assert!(tuning.frequency(128).is_err());
+            assert_eq!(tuning.pitch_semitones(57).unwrap(), 57.0);
+
+            let mut tuning = Tuning::equal_division(12, 432.0).unwrap();
+            assert!(is_close(tuning.frequency(69).unwrap(), 432.0));
+            tuning.set_reference_hz(440.0).unwrap();
+            assert!(is_close(tuning.frequency(81).unwrap(), 880.0));
+        }
+
+        #[test]
+        fn test_2_reads_scala_scales() {
+            let scale = Scale::from_scl(JUST_MAJOR).unwrap();
+            assert_eq!(scale.description(), "Just major");
+            assert_eq!(scale.len(), 7);
+            assert!((scale.degree_cents(4) - 701.955).abs() < 1e-9);
+            assert!((scale.degree_cents(-7) + 1200.0).abs() < 1e-9);
+
+            assert!(Scale::from_scl("Too short\n 3\n 9/8\n 5/4\n").is_err());
+            assert!(Scale::from_scl("Not a pitch\n 1\n abc\n").is_err());
+            assert!(Scale::from_scl("No period\n 1\n 0.0\n").is_err());
+
+            let just = Tuning::just_intonation(440.0).unwrap();
+            assert!(is_close(just.frequency(60).unwrap(), 264.0));
+            assert!(is_close(just.frequency(67).unwrap(), 396.0));
+        }
+
+        #[test]
+        fn test_3_equal_divisions() {
+            for divisions in [19, 31] {
+                let tuning = Tuning::equal_division(divisions, 440.0).unwrap();
+                assert!(is_close(tuning.frequency(69).unwrap(), 440.0));
+                let octave = tuning.frequency(60 + divisions as u8).unwrap();
+                assert!(is_close(octave, tuning.frequency(60).unwrap() * 2.0));
+                let step = f32::powf(2.0, 1.0 / divisions as f32);
+                assert!(is_close(tuning.frequency(70).unwrap(), 440.0 * step));
+            }
+            assert!(Tuning::equal_division(0, 440.0).is_err());
+        }
+
+        #[test]
+        fn test_4_keyboard_mapping() {
+            // The just major scale on the white keys, with A4 at 432 Hz
+            let kbm = "! white.kbm
+12
+0
+127
+60
+69
+432.0
+7
+! mapping
+0
+x
+1
+x
+2
+3
+x
+4
+x
+5
+x
+6
+";
+            let mapping = KeyboardMapping::from_kbm(kbm).unwrap();
+            assert_eq!(mapping.degree(61, 7), None);
+            assert_eq!(mapping.degree(74, 7), Some(8));
+
+            let tuning = Tuning::from_scala(JUST_MAJOR, Some(kbm)).unwrap();
+            assert_eq!(tuning.name(), "Just major");
+            assert!(is_close(tuning.frequency(69).unwrap(), 432.0));
+            assert!(is_close(tuning.frequency(60).unwrap(), 259.2));
+            assert!(is_close(tuning.frequency(64).unwrap(), 324.0));
+            assert!(is_close(tuning.frequency(72).unwrap(), 518.4));
+            assert!(tuning.frequency(61).is_err());
+
+            assert!(KeyboardMapping::from_kbm("12\n0\n127\n60\n").is_err());
+            // The reference key has to play a degree of the scale
+            let unmapped_reference = kbm.replace("\n69\n", "\n70\n");
+            assert!(Tuning::from_scala(JUST_MAJOR, Some(&unmapped_reference)).is_err());
+        }
+
+        #[test]
+        fn test_5_notes_and_detune_follow_the_tuning() {
+            let mut osc = oscillators::WaveTableOscillator::new(44100, 4096, oscillators::Oscillator::Sine, 1.0, 445.0);
+            osc.set_detune_semitones(12).unwrap();
+            assert!(is_close(osc.get_frequency(), 890.0));
+            osc.set_detune_semitones(7).unwrap();
+            osc.set_detune_semitones(7).unwrap();
+            assert!(is_close(osc.get_frequency(), 445.0 * f32::powf(2.0, 7.0 / 12.0)));
+            osc.set_detune_semitones(0).unwrap();
+            assert!(is_close(osc.get_frequency(), 445.0));
+
+            let tuning = Tuning::equal_division(31, 440.0).unwrap();
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            let voice = wrapper::Synth::new(
+                oscillators::MultiOscillator::from(osc), 44100, None, None, None, lfo::LFOType::Amplitude
+            );
+            polyphony.note_on(70, voice, 1.0).unwrap();
+            assert!(is_close(polyphony.get(&70).unwrap().osc.get_frequency(0), midi_to_hz(70).unwrap()));
+
+            // Sounding notes are retuned
+            polyphony.set_tuning(tuning.clone());
+            assert!(is_close(polyphony.get(&70).unwrap().osc.get_frequency(0), tuning.frequency(70).unwrap()));
+        }
+    }
+    mod mts_tests {
+        use super::*;
+        use tuning::{MtsMessage, Tuning};
+
+        fn is_close(a: f32, b: f32) -> bool {
+            (a - b).abs() <= b * 1e-4
+        }
+
+        /// Builds a bulk tuning dump that tunes every key a quarter tone above equal temperament.
+        fn quarter_tone_dump() -> Vec<u8> {
+            let mut sysex = vec![0xF0, 0x7E, 0x7F, 0x08, 0x01, 0x00];
+            sysex.extend(b"Quarter tones   ");
+            for key in 0..128 {
+                sysex.extend([key, 0x40, 0x00]);
+            }
+            let checksum = sysex[1..].iter().fold(0, |checksum, byte| checksum ^ byte) & 0x7F;
+            sysex.extend([checksum, 0xF7]);
+            sysex
+        }
+
+        #[test]
+        fn test_1_reads_single_note_changes() {
+            // Key 60 to A4 plus 50 cents, key 61 left alone by 7F 7F 7F
+            let sysex = [0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x02, 60, 69, 0x40, 0x00, 61, 0x7F, 0x7F, 0x7F, 0xF7];
+            let message = MtsMessage::parse(&sysex).unwrap();
+            let mut tuning = Tuning::default();
+            tuning.apply_mts(&message).unwrap();
+            assert!(is_close(tuning.frequency(60).unwrap(), 440.0 * f32::powf(2.0, 0.5 / 12.0)));
+            assert!(is_close(tuning.frequency(61).unwrap(), midi_to_hz(61).unwrap()));
+
+            // The count has to match the changes
+            assert!(MtsMessage::parse(&sysex[..11]).is_err());
+            assert!(MtsMessage::parse(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]).is_err());
+        }
+
+        #[test]
+        fn test_2_reads_bulk_dumps() {
+            let sysex = quarter_tone_dump();
+            let mut tuning = Tuning::default();
+            tuning.apply_mts(&MtsMessage::parse(&sysex).unwrap()).unwrap();
+            assert_eq!(tuning.name(), "Quarter tones");
+            for key in [0, 60, 69, 127] {
+                assert!(is_close(tuning.frequency(key).unwrap(), midi_to_hz(key).unwrap() * f32::powf(2.0, 0.5 / 12.0)));
+            }
+
+            let mut corrupted = sysex.clone();
+            corrupted[100] ^= 0x01;
+            assert!(MtsMessage::parse(&corrupted).is_err());
+        }
+
+        #[test]
+        fn test_3_reads_scale_octave_tunings() {
+            // One byte form: E 14 cents flat, everything else in tune
+            let mut sysex = vec![0xF0, 0x7E, 0x7F, 0x08, 0x08, 0x03, 0x7F, 0x7F];
+            sysex.extend([64, 64, 64, 64, 50, 64, 64, 64, 64, 64, 64, 64]);
+            sysex.push(0xF7);
+            let message = MtsMessage::parse(&sysex).unwrap();
+            match &message {
+                MtsMessage::ScaleOctave { channels, cents } => {
+                    assert_eq!(*channels, 0xFFFF);
+                    assert_eq!(cents[4], -14.0);
+                },
+                _ => panic!("Expected a scale/octave tuning"),
+            }
+            let mut tuning = Tuning::default();
+            tuning.apply_mts(&message).unwrap();
+            assert!(is_close(tuning.frequency(64).unwrap(), midi_to_hz(64).unwrap() * f32::powf(2.0, -0.14 / 12.0)));
+            assert!(is_close(tuning.frequency(76).unwrap(), midi_to_hz(76).unwrap() * f32::powf(2.0, -0.14 / 12.0)));
+            assert!(is_close(tuning.frequency(67).unwrap(), midi_to_hz(67).unwrap()));
+
+            // Two byte form: G 50 cents sharp
+            let mut sysex = vec![0x7F, 0x7F, 0x08, 0x09, 0x00, 0x00, 0x01];
+            for note in 0..12 {
+                sysex.extend(if note == 7 { [0x60, 0x00] } else { [0x40, 0x00] });
+            }
+            let mut tuning = Tuning::default();
+            tuning.apply_mts(&MtsMessage::parse(&sysex).unwrap()).unwrap();
+            assert!(is_close(tuning.frequency(67).unwrap(), midi_to_hz(67).unwrap() * f32::powf(2.0, 0.5 / 12.0)));
+        }
+
+        #[test]
+        fn test_4_retunes_sounding_notes() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            let voice = wrapper::Synth::new(
+                oscillators::MultiOscillator::from(oscillators::WaveTableOscillator::new(44100, 4096, oscillators::Oscillator::Sine, 1.0, 440.0)),
+                44100, None, None, None, lfo::LFOType::Amplitude
+            );
+            polyphony.note_on(69, voice, 1.0).unwrap();
+            polyphony.midi_tuning(&quarter_tone_dump()).unwrap();
+            assert!(is_close(polyphony.get(&69).unwrap().osc.get_frequency(0), 440.0 * f32::powf(2.0, 0.5 / 12.0)));
+            assert!(polyphony.midi_tuning(&[0xF0, 0x43, 0x10, 0xF7]).is_err());
+        }
+
+        #[test]
+        fn test_5_scale_octave_tunings_keep_the_mapped_reference() {
+            let steps: String = (1..=12).map(|step| format!("{}.0\n", step * 100)).collect();
+            let scl = format!("Equal temperament\n12\n{steps}");
+            // Middle C at 256 Hz
+            let kbm = "0\n0\n127\n60\n60\n256.0\n0\n";
+            let mut tuning = Tuning::from_scala(&scl, Some(kbm)).unwrap();
+            let mut cents = [0.0; 12];
+            cents[9] = -10.0;
+            tuning.apply_mts(&MtsMessage::ScaleOctave { channels: 0xFFFF, cents }).unwrap();
+            assert!(is_close(tuning.frequency(60).unwrap(), 256.0));
+            assert!(is_close(tuning.frequency(72).unwrap(), 512.0));
+            assert!(is_close(tuning.frequency(69).unwrap(), 256.0 * f32::powf(2.0, 8.9 / 12.0)));
+            assert_eq!(tuning.set_frequency(200, 440.0).unwrap_err(), "MIDI must range between 0-127");
+        }
+    }
+    mod mpe_tests {
+        use super::*;
+        use filters::{Filter, FilterParam, FilterType};
+        use mpe::{ExpressionTarget, Mpe, MpeSettings, MpeZone, TIMBRE_CC};
+
+        fn voice() -> wrapper::Synth {
+            let filter = Filter::new(FilterType::LowPass, 44100.0, 1000.0, 100.0);
+            synth_voice(44100, oscillators::Oscillator::Sine, 1.0, Some(filter), None)
+        }
+
+        fn engine() -> engine::IterablePolyphonyHashMap {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_mpe_settings(MpeSettings { enabled: true, ..Default::default() });
+            polyphony
+        }
+
+        #[test]
+        fn test_1_zones_have_master_and_member_channels() {
+            let mut mpe = Mpe::new(MpeSettings { enabled: true, member_channels: 7, ..Default::default() });
+            assert_eq!(mpe.master_channel(), 0);
+            assert!(!mpe.is_member(0));
+            assert!(mpe.is_member(1) && mpe.is_member(7));
+            assert!(!mpe.is_member(8));
+
+            mpe.set_settings(MpeSettings { enabled: true, zone: MpeZone::Upper, member_channels: 3, ..Default::default() });
+            assert_eq!(mpe.master_channel(), 15);
+            assert!(mpe.is_member(12) && mpe.is_member(14));
+            assert!(!mpe.is_member(11) && !mpe.is_member(15));
+
+            // Nothing is a member channel with MPE off
+            mpe.set_settings(MpeSettings::default());
+            assert!(!mpe.is_member(1));
+        }
+
+        #[test]
+        fn test_2_notes_are_mapped_to_their_channels() {
+            let mut polyphony = engine();
+            polyphony.mpe_note_on(1, 60, voice(), 1.0).unwrap();
+            polyphony.mpe_note_on(2, 64, voice(), 1.0).unwrap();
+            assert_eq!(polyphony.mpe().key(1), Some(60));
+            assert_eq!(polyphony.mpe().channel(64), Some(2));
+
+            // The same key played on another channel moves there, and the old channel cannot release it
+            polyphony.mpe_note_on(3, 60, voice(), 1.0).unwrap();
+            assert_eq!(polyphony.mpe().key(1), None);
+            polyphony.mpe_note_off(1, 60).unwrap();
+            assert!(polyphony.get(&60).is_some());
+            polyphony.mpe_note_off(3, 60).unwrap();
+            assert!(polyphony.get(&60).is_none());
+            assert_eq!(polyphony.len(), 1);
+        }
+
+        #[test]
+        fn test_3_pitch_bend_is_per_note() {
+            let mut polyphony = engine();
+            polyphony.mpe_note_on(1, 60, voice(), 1.0).unwrap();
+            polyphony.mpe_note_on(2, 64, voice(), 1.0).unwrap();
+
+            // A quarter of the 48 semitone range bends one note up an octave
+            polyphony.mpe_pitch_bend(1, 8192 + 2048);
+            let ratio = polyphony.get(&60).unwrap().osc.get_pitch_ratio();
+            assert!((ratio - 2.0).abs() < 1e-3);
+            assert_eq!(polyphony.get(&64).unwrap().osc.get_pitch_ratio(), 1.0);
+
+            // The master channel bends every note, on top of their own bend
+            polyphony.mpe_pitch_bend(0, 16383);
+            let ratio = polyphony.get(&60).unwrap().osc.get_pitch_ratio();
+            assert!((ratio - 2.0 * f32::powf(2.0, 2.0 / 12.0)).abs() < 1e-3);
+            let ratio = polyphony.get(&64).unwrap().osc.get_pitch_ratio();
+            assert!((ratio - f32::powf(2.0, 2.0 / 12.0)).abs() < 1e-4);
+        }
+
+        #[test]
+        fn test_4_pressure_and_timbre_are_routed() {
+            let mut polyphony = engine();
+            polyphony.mpe_note_on(1, 60, voice(), 1.0).unwrap();
+            polyphony.mpe_note_on(2, 64, voice(), 1.0).unwrap();
+            let dry = polyphony.get(&60).unwrap().clone();
+
+            // Full pressure doubles the gain of the note
+            assert!(polyphony.mpe_pressure(1, 127));
+            assert!(!polyphony.mpe_pressure(0, 127));
+            let mut pressed = polyphony.get(&60).unwrap().clone();
+            let mut dry_voice = dry.clone();
+            for _ in 0..1000 {
+                assert!((pressed.get_sample() - 2.0 * dry_voice.get_sample()).abs() < 1e-4);
+            }
+
+            // Timbre opens the filter of its note only, and other controllers still work
+            assert!(polyphony.mpe_control_change(2, TIMBRE_CC, 127));
+            let cutoff = polyphony.get(&64).unwrap().filter.as_ref().unwrap().get_param(FilterParam::FreqHz);
+            assert!((cutoff - 1000.0 * f32::powf(2.0, 4.0 * (1.0 - 0.5))).abs() < 1.0);
+            let cutoff = polyphony.get(&60).unwrap().filter.as_ref().unwrap().get_param(FilterParam::FreqHz);
+            assert!((cutoff - 1000.0).abs() < 1e-3);
+            assert!(polyphony.mpe_control_change(1, controllers::MOD_WHEEL_CC, 0));
+            assert!(!polyphony.mpe_control_change(1, 7, 0));
+
+            // Routing can be swapped
+            polyphony.set_mpe_settings(MpeSettings {
+                enabled: true,
+                pressure_target: ExpressionTarget::Cutoff,
+                timbre_target: ExpressionTarget::Off,
+                ..Default::default()
+            });
+            polyphony.mpe_note_on(1, 60, voice(), 1.0).unwrap();
+            polyphony.mpe_pressure(1, 127);
+            let cutoff = polyphony.get(&60).unwrap().filter.as_ref().unwrap().get_param(FilterParam::FreqHz);
+            assert!((cutoff - 16000.0).abs() < 1.0);
+        }
+
+        #[test]
+        fn test_5_settings_that_keep_the_channels_keep_the_notes() {
+            let mut polyphony = engine();
+            polyphony.mpe_note_on(1, 60, voice(), 1.0).unwrap();
+            polyphony.mpe_note_on(2, 64, voice(), 1.0).unwrap();
+            polyphony.mpe_pitch_bend(1, 8192 + 2048);
+
+            // Halving the bend range halves the bend of the sounding note
+            polyphony.set_mpe_settings(MpeSettings { enabled: true, bend_range_semitones: 24.0, ..Default::default() });
+            assert_eq!(polyphony.len(), 2);
+            let ratio = polyphony.get(&60).unwrap().osc.get_pitch_ratio();
+            assert!((ratio - f32::powf(2.0, 0.5)).abs() < 1e-3);
+            assert!(polyphony.mpe().is_member(1));
+
+            // Loading a patch with the same zone does not cut the notes off
+            let patch = patch::Patch { mpe: polyphony.mpe().settings(), ..Default::default() };
+            polyphony.load_patch(&patch).unwrap();
+            assert_eq!(polyphony.len(), 2);
+
+            // Moving the zone does
+            polyphony.set_mpe_settings(MpeSettings { enabled: true, zone: MpeZone::Upper, ..Default::default() });
+            assert!(polyphony.is_empty());
+        }
+    }
+    mod midi_tests {
+        use super::*;
+        use midi::{MidiDispatcher, MidiMessage, MidiOutputSettings, MidiParser, MAX_SYSEX_LENGTH};
+        use mpe::MpeSettings;
+        use transport::ClockSource;
+
+        fn voice() -> wrapper::Synth {
+            synth_voice(44100, oscillators::Oscillator::Sine, 1.0, None, None)
+        }
+
+        #[test]
+        fn test_1_running_status_and_velocity_zero_note_off() {
+            let mut parser = MidiParser::new();
+            let messages = parser.parse(&[0x91, 60, 100, 64, 90, 60, 0, 0x81, 64, 40]);
+            assert_eq!(messages, vec![
+                MidiMessage::NoteOn { channel: 1, key: 60, velocity: 100 },
+                MidiMessage::NoteOn { channel: 1, key: 64, velocity: 90 },
+                MidiMessage::NoteOff { channel: 1, key: 60, velocity: 0 },
+                MidiMessage::NoteOff { channel: 1, key: 64, velocity: 40 },
+            ]);
+
+            // Messages can be split across blocks, and data without a status byte is ignored
+            let mut parser = MidiParser::new();
+            assert!(parser.parse(&[60, 100, 0x9F, 62]).is_empty());
+            assert_eq!(parser.parse(&[80]), vec![MidiMessage::NoteOn { channel: 15, key: 62, velocity: 80 }]);
+        }
+
+        #[test]
+        fn test_2_channel_messages() {
+            let mut parser = MidiParser::new();
+            let messages = parser.parse(&[
+                0xB2, 74, 127,
+                0xE3, 0x00, 0x40,
+                0xE3, 0x7F, 0x7F,
+                0xA4, 60, 33,
+                0xD5, 99, 100,
+                0xC6, 12,
+            ]);
+            assert_eq!(messages, vec![
+                MidiMessage::ControlChange { channel: 2, controller: 74, value: 127 },
+                MidiMessage::PitchBend { channel: 3, value: 8192 },
+                MidiMessage::PitchBend { channel: 3, value: 16383 },
+                MidiMessage::PolyPressure { channel: 4, key: 60, pressure: 33 },
+                MidiMessage::ChannelPressure { channel: 5, pressure: 99 },
+                MidiMessage::ChannelPressure { channel: 5, pressure: 100 },
+                MidiMessage::ProgramChange { channel: 6, program: 12 },
+            ]);
+        }
+
+        #[test]
+        fn test_3_realtime_and_system_messages() {
+            let mut parser = MidiParser::new();
+            // Clocks in the middle of a note and of a system exclusive message change nothing
+            let messages = parser.parse(&[
+                0x90, 60, 0xF8, 100,
+                0xF0, 0x7E, 0xF8, 0x7F, 0x09, 0xF7,
+                0xFA, 0xFB, 0xFC, 0xFE,
+                0xF2, 0x10, 0x01,
+                0x62, 0x40,
+            ]);
+            assert_eq!(messages, vec![
+                MidiMessage::Clock,
+                MidiMessage::NoteOn { channel: 0, key: 60, velocity: 100 },
+                MidiMessage::Clock,
+                MidiMessage::SysEx(vec![0x7E, 0x7F, 0x09]),
+                MidiMessage::Start,
+                MidiMessage::Continue,
+                MidiMessage::Stop,
+                MidiMessage::SongPosition(144),
+            ]);
+
+            // An unfinished system exclusive message is dropped, and so is one that is too long
+            assert_eq!(parser.parse(&[0xF0, 0x01, 0x02, 0x80, 60, 0]).len(), 1);
+            let mut long = vec![0xF0];
+            long.extend(vec![0x01; MAX_SYSEX_LENGTH + 1]);
+            long.push(0xF7);
+            assert!(parser.parse(&long).is_empty());
+        }
+
+        #[test]
+        fn test_4_dispatches_notes_and_controllers() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            let mut dispatcher = MidiDispatcher::new(voice());
+            let unused = dispatcher.receive(&[0x90, 60, 127, 64, 64, 0xE0, 0x7F, 0x7F, 0xB0, 1, 0, 0xC0, 5], &mut polyphony);
+            assert_eq!(unused, vec![MidiMessage::ProgramChange { channel: 0, program: 5 }]);
+            assert_eq!(polyphony.len(), 2);
+            assert_eq!(polyphony.get(&60).unwrap().velocity(), 1.0);
+            assert_eq!(polyphony.controllers().pitch_bend(), 1.0);
+            assert_eq!(polyphony.controllers().mod_wheel(), 0.0);
+
+            dispatcher.receive(&[0x80, 60, 0, 0x90, 64, 0], &mut polyphony);
+            assert!(polyphony.is_empty());
+
+            // Member channels of an MPE zone bend their own note
+            polyphony.set_mpe_settings(MpeSettings { enabled: true, ..Default::default() });
+            dispatcher.receive(&[0xE0, 0x00, 0x40, 0x91, 60, 100, 0x92, 67, 100, 0xE1, 0x00, 0x50], &mut polyphony);
+            assert!(polyphony.get(&60).unwrap().osc.get_pitch_ratio() > 1.0);
+            assert_eq!(polyphony.get(&67).unwrap().osc.get_pitch_ratio(), 1.0);
+        }
+
+        #[test]
+        fn test_5_dispatches_clock_and_tuning() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            let mut dispatcher = MidiDispatcher::new(voice());
+            // Clock messages are only used when the transport follows MIDI clock
+            assert_eq!(dispatcher.receive(&[0xFA], &mut polyphony), vec![MidiMessage::Start]);
+            polyphony.set_clock_source(ClockSource::MidiClock);
+            assert!(dispatcher.receive(&[0xF2, 0x08, 0x00, 0xFB, 0xF8], &mut polyphony).is_empty());
+            assert!(polyphony.transport().is_playing());
+            assert_eq!(polyphony.transport().position_beats(), 2.0);
+            dispatcher.receive(&[0xFC], &mut polyphony);
+            assert!(!polyphony.transport().is_playing());
+
+            // Key 60 retuned to A4 by a single note tuning change
+            dispatcher.receive(&[0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 60, 69, 0x00, 0x00, 0xF7], &mut polyphony);
+            assert_eq!(polyphony.tuning().frequency(60).unwrap(), 440.0);
+            let unused = dispatcher.receive(&[0xF0, 0x43, 0x10, 0xF7], &mut polyphony);
+            assert_eq!(unused, vec![MidiMessage::SysEx(vec![0x43, 0x10])]);
+        }
+        #[test]
+        fn test_6_filters_channels() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            let mut dispatcher = MidiDispatcher::new(voice());
+            dispatcher.set_channel(Some(2));
+            let unused = dispatcher.receive(&[0x90, 60, 100, 0x92, 64, 100, 0xC1, 3, 0xC2, 4], &mut polyphony);
+            assert_eq!(unused, vec![MidiMessage::ProgramChange { channel: 2, program: 4 }]);
+            assert!(polyphony.get(&60).is_none());
+            assert!(polyphony.get(&64).is_some());
+            assert_eq!(dispatcher.keys(), &[64]);
+            assert_eq!(MidiMessage::Clock.channel(), None);
+
+            dispatcher.set_channel(None);
+            dispatcher.receive(&[0x90, 60, 100, 0x82, 64, 0], &mut polyphony);
+            assert!(polyphony.get(&60).is_some());
+            assert_eq!(dispatcher.keys(), &[60]);
+        }
+
+        #[test]
+        fn test_7_messages_encode_to_bytes_the_parser_reads() {
+            let messages = vec![
+                MidiMessage::NoteOn { channel: 3, key: 60, velocity: 100 },
+                MidiMessage::NoteOff { channel: 3, key: 60, velocity: 0 },
+                MidiMessage::ControlChange { channel: 0, controller: 1, value: 64 },
+                MidiMessage::ProgramChange { channel: 15, program: 7 },
+                MidiMessage::PitchBend { channel: 2, value: 12345 },
+                MidiMessage::SysEx(vec![0x7E, 0x7F, 0x08]),
+                MidiMessage::SongPosition(300),
+                MidiMessage::Clock,
+                MidiMessage::Stop,
+            ];
+            let bytes: Vec<u8> = messages.iter().flat_map(|message| message.to_bytes()).collect();
+            assert_eq!(&bytes[..3], &[0x93, 60, 100]);
+            assert_eq!(MidiParser::new().parse(&bytes), messages);
+        }
+
+        #[test]
+        fn test_8_keyboard_and_arpeggiator_notes_are_sent() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_midi_output_settings(MidiOutputSettings { enabled: true, channel: 4, local_control: true });
+            polyphony.note_on(60, voice(), 1.0).unwrap();
+            polyphony.note_off(60).unwrap();
+            assert_eq!(polyphony.take_midi_output(), vec![
+                MidiMessage::NoteOn { channel: 4, key: 60, velocity: 127 },
+                MidiMessage::NoteOff { channel: 4, key: 60, velocity: 0 },
+            ]);
+            assert!(polyphony.take_midi_output().is_empty());
+
+            // Notes received from MIDI are not sent back out
+            polyphony.mpe_note_on(0, 62, voice(), 1.0).unwrap();
+            assert!(polyphony.get(&62).is_some());
+            assert!(polyphony.take_midi_output().is_empty());
+
+            // Arpeggiated notes are sent instead of the keys that are held
+            polyphony.set_arpeggiator(arpeggiator::ArpeggiatorSettings { enabled: true, ..Default::default() });
+            polyphony.note_on(64, voice(), 0.5).unwrap();
+            for _ in 0..44100 {
+                polyphony.get_sample();
+            }
+            let sent = polyphony.take_midi_output();
+            assert!(sent.contains(&MidiMessage::NoteOn { channel: 4, key: 64, velocity: 64 }));
+            assert!(sent.contains(&MidiMessage::NoteOff { channel: 4, key: 64, velocity: 0 }));
+        }
+
+        #[test]
+        fn test_9_local_off_sends_without_sounding() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_midi_output_settings(MidiOutputSettings { enabled: true, channel: 0, local_control: false });
+            polyphony.note_on(60, voice(), 1.0).unwrap();
+            assert!(polyphony.get(&60).is_none());
+            assert_eq!(polyphony.take_midi_output(), vec![MidiMessage::NoteOn { channel: 0, key: 60, velocity: 127 }]);
+
+            // Moving to another channel sends off the notes left on the old one
+            polyphony.set_midi_output_settings(MidiOutputSettings { enabled: true, channel: 1, local_control: false });
+            assert_eq!(polyphony.take_midi_output(), vec![MidiMessage::NoteOff { channel: 0, key: 60, velocity: 0 }]);
+            polyphony.note_off(60).unwrap();
+            assert!(polyphony.take_midi_output().is_empty());
+
+            // Incoming notes still sound
+            polyphony.mpe_note_on(0, 62, voice(), 1.0).unwrap();
+            assert!(polyphony.get(&62).is_some());
+        }
+
+        #[test]
+        fn test_10_repeated_key_downs_send_one_note_on() {
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.set_midi_output_settings(MidiOutputSettings { enabled: true, channel: 0, local_control: false });
+            // A held computer key repeats its key down
+            for _ in 0..3 {
+                polyphony.note_on(60, voice(), 1.0).unwrap();
+            }
+            polyphony.note_off(60).unwrap();
+            assert_eq!(polyphony.take_midi_output(), vec![
+                MidiMessage::NoteOn { channel: 0, key: 60, velocity: 127 },
+                MidiMessage::NoteOff { channel: 0, key: 60, velocity: 0 }
+            ]);
+        }
+    }
+
+    mod output_tests {
+        use super::*;
+        use std::sync::{Arc, Mutex};
+        use audio::{negotiate, AudioBackend, Dither, NullBackend, OfflineBackend, StreamPreferences};
+        use cpal::{SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfigRange};
+        use patch::Patch;
+
+        fn voice(sample_rate: u32) -> wrapper::Synth {
+            synth_voice(sample_rate, oscillators::Oscillator::Sine, 1.0, None, None)
+        }
+
+        #[test]
+        fn test_1_load_patch_sets_the_engine() {
+            let json = r#"{ "arpeggiator": { "enabled": true }, "mpe": { "enabled": true }, "voice_mode": { "glide_ms": 120.0 } }"#;
+            let patch = Patch::from_json(json).unwrap();
+            let mut polyphony = engine::IterablePolyphonyHashMap::new(44100);
+            polyphony.load_patch(&patch).unwrap();
+            assert!(polyphony.arpeggiator().is_enabled());
+            assert!(polyphony.mpe().is_enabled());
+            assert_eq!(polyphony.voice_mode().glide_ms, 120.0);
+        }
+
+        #[test]
+        fn test_2_null_backend_runs_the_engine_in_real_time() {
+            let mut backend = NullBackend::default();
+            let polyphony = Arc::new(Mutex::new(engine::IterablePolyphonyHashMap::new(backend.sample_rate())));
+            polyphony.lock().unwrap().start_sequencer(voice(backend.sample_rate()));
+            backend.start(Arc::clone(&polyphony)).unwrap();
+            assert!(backend.is_running());
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            backend.stop();
+            // At 120 beats per minute, 200 ms is 0.4 beats
+            let beats = polyphony.lock().unwrap().transport().position_beats();
+            assert!(beats > 0.2 && beats < 0.8, "{beats}");
+        }
+
+        #[test]
+        fn test_3_offline_backend_renders_to_wav() {
+            let mut backend = OfflineBackend::new(48000, 2);
+            let polyphony = Arc::new(Mutex::new(engine::IterablePolyphonyHashMap::new(48000)));
+            assert!(backend.render(10).is_err());
+            backend.start(Arc::clone(&polyphony)).unwrap();
+            polyphony.lock().unwrap().note_on(69, voice(48000), 1.0).unwrap();
+            backend.render(480).unwrap();
+            let samples = backend.samples();
+            assert_eq!(samples.len(), 960);
+            // Both channels carry the mono voice
+            assert!(samples.chunks(2).all(|frame| frame[0] == frame[1]));
+            assert!(samples.iter().any(|sample| sample.abs() > 0.01));
+
+            let wav = backend.to_wav();
+            assert_eq!(&wav[..4], b"RIFF");
+            assert_eq!(&wav[8..16], b"WAVEfmt ");
+            assert_eq!(u32::from_le_bytes([wav[24], wav[25], wav[26], wav[27]]), 48000);
+            assert_eq!(wav.len(), 44 + 960 * 2);
+        }
+
+        #[test]
+        fn test_4_backends_prepare_engines_at_another_rate() {
+            let polyphony = Arc::new(Mutex::new(engine::IterablePolyphonyHashMap::new(44100)));
+            OfflineBackend::new(48000, 2).start(Arc::clone(&polyphony)).unwrap();
+            assert_eq!(polyphony.lock().unwrap().sample_rate(), 48000);
+            let mut backend = NullBackend::new(96000);
+            backend.start(Arc::clone(&polyphony)).unwrap();
+            backend.stop();
+            assert_eq!(polyphony.lock().unwrap().sample_rate(), 96000);
+            assert_eq!(polyphony.lock().unwrap().max_block_size(), 512);
+        }
+
+        fn config(channels: u16, rates: (u32, u32), format: SampleFormat) -> SupportedStreamConfigRange {
+            let buffer_size = SupportedBufferSize::Range { min: 64, max: 4096 };
+            SupportedStreamConfigRange::new(channels, SampleRate(rates.0), SampleRate(rates.1), buffer_size, format)
+        }
+
+        #[test]
+        fn test_5_negotiation_prefers_f32_stereo_at_the_wanted_rate() {
+            let configs = [
+                config(2, (44100, 48000), SampleFormat::I16),
+                config(1, (44100, 48000), SampleFormat::F32),
+                config(2, (44100, 48000), SampleFormat::F32),
+                config(2, (96000, 96000), SampleFormat::F32),
+            ];
+            let preferences = StreamPreferences { sample_rate: Some(48000), buffer_size: Some(10000), ..Default::default() };
+            let format = negotiate(&configs, preferences).unwrap();
+            assert_eq!((format.sample_rate, format.channels, format.sample_format), (48000, 2, SampleFormat::F32));
+            assert_eq!(format.buffer_size, Some(4096));
+
+            // A device that only plays stereo i16 is used rather than refused
+            let format = negotiate(&configs[..1], StreamPreferences { channels: Some(1), ..Default::default() }).unwrap();
+            assert_eq!((format.sample_rate, format.channels, format.sample_format), (44100, 2, SampleFormat::I16));
+            assert_eq!(format.latency_ms(), None);
+
+            // Among configurations of the same format the closest sample rate wins
+            let format = negotiate(&configs[2..], StreamPreferences { sample_rate: Some(96000), buffer_size: Some(960), ..Default::default() }).unwrap();
+            assert_eq!(format.sample_rate, 96000);
+            assert_eq!(format.latency_ms(), Some(10.0));
+
+            // The sample format matters more than the sample rate, which the engine resamples to
+            let mixed = [config(2, (96000, 96000), SampleFormat::I16), config(2, (44100, 48000), SampleFormat::F32)];
+            let format = negotiate(&mixed, StreamPreferences { sample_rate: Some(96000), ..Default::default() }).unwrap();
+            assert_eq!((format.sample_rate, format.sample_format), (48000, SampleFormat::F32));
+
+            assert!(negotiate(&[config(2, (44100, 44100), SampleFormat::F64)], StreamPreferences::default()).is_err());
+            assert!(negotiate(&[], StreamPreferences::default()).is_err());
+        }
+
+        #[test]
+        fn test_6_dither_stays_within_one_bit() {
+            let mut dither = Dither::new(16);
+            let lsb = 1.0 / 32768.0;
+            let noise: Vec<f32> = (0..10000).map(|_| dither.apply(0.0)).collect();
+            assert!(noise.iter().all(|sample| sample.abs() <= lsb));
+            assert!(noise.iter().any(|sample| *sample != 0.0));
+            let mean = noise.iter().sum::<f32>() / noise.len() as f32;
+            assert!(mean.abs() < lsb * 0.05);
+
+            assert_eq!(Dither::for_format(SampleFormat::F32).apply(0.25), 0.25);
+            assert_eq!(Dither::for_format(SampleFormat::I32).apply(0.25), 0.25);
+        }
+
+        #[test]
+        fn test_7_callbacks_play_silence_when_the_engine_is_busy() {
+            let engine = Arc::new(Mutex::new(engine::IterablePolyphonyHashMap::new(44100)));
+            engine.lock().unwrap().note_on(69, voice(44100), 1.0).unwrap();
+            let mut buffer = [0i16; 4096];
+            let write = |buffer: &mut [i16]| audio::write_buffer(&engine, buffer, 2, &mut None, &mut Dither::none());
+
+            write(&mut buffer);
+            assert!(buffer.iter().any(|sample| *sample != 0));
+
+            // Held by the interface
+            let guard = engine.lock().unwrap();
+            write(&mut buffer);
+            assert!(buffer.iter().all(|sample| *sample == 0));
+            drop(guard);
+
+            // Poisoned by a panic while it was held
+            let poisoner = Arc::clone(&engine);
+            let _ = std::thread::spawn(move || {
+                let _guard = poisoner.lock().unwrap();
+                panic!("poisons the engine");
+            }).join();
+            assert!(engine.is_poisoned());
+            buffer.fill(1);
+            write(&mut buffer);
+            assert!(buffer.iter().all(|sample| *sample == 0));
+        }
+    }
+    mod lifecycle_tests {
+        use super::*;
+        use lifecycle::Prepare;
+        use envelopes::Envelope;
+        use filters::{Filter, FilterParam, FilterType};
+        use oscillators::{Oscillator, WaveTableOscillator};
+        use engine::IterablePolyphonyHashMap;
+        use rodio::Source;
+
+        fn voice(sample_rate: u32) -> wrapper::Synth {
+            let filter = Filter::new(FilterType::LowPass, sample_rate as f32, 2000.0, 0.0);
+            let envelope = Envelope::new(sample_rate as f32, 20.0, 50.0, 0.6, 100.0);
+            synth_voice(sample_rate, Oscillator::Saw, 0.5, Some(filter), Some(envelope))
+        }
+
+        #[test]
+        fn test_1_oscillator_keeps_its_frequency() {
+            let mut osc = WaveTableOscillator::new(44100, 4096, Oscillator::Sine, 1.0, 440.0);
+            osc.set_detune_semitones(12).unwrap();
+            osc.prepare(96000, 512);
+            assert!(is_close_f32(osc.get_frequency(), 880.0));
+            assert_eq!(osc.sample_rate(), 96000);
+        }
+
+        #[test]
+        fn test_2_filter_matches_one_made_at_the_new_rate() {
+            let mut prepared = Filter::new(FilterType::BandPass, 44100.0, 1000.0, 200.0);
+            prepared.prepare(48000, 512);
+            let mut fresh = Filter::new(FilterType::BandPass, 48000.0, 1000.0, 200.0);
+            assert_eq!(prepared.get_param(FilterParam::SampleRateHz), 48000.0);
+            for n in 0..100 {
+                let input = (n as f32 * 0.3).sin();
+                assert_eq!(prepared.process(input), fresh.process(input));
+            }
+        }
+
+        #[test]
+        fn test_3_envelope_carries_on_in_time() {
+            let mut envelope = Envelope::new(44100.0, 10.0, 10.0, 0.5, 10.0);
+            // 5 ms into the attack
+            for _ in 0..220 {
+                envelope.get_amplitude();
+            }
+            envelope.prepare(96000, 512);
+            assert!((envelope.get_amplitude() - 0.5).abs() < 0.01);
+            assert_eq!(envelope.get_param(envelopes::EnvelopeParam::AttackMs), 10.0);
+        }
+
+        #[test]
+        fn test_4_prepared_engine_sounds_like_one_made_at_the_rate() {
+            let mut fresh = IterablePolyphonyHashMap::new(96000);
+            fresh.note_on(60, voice(96000), 0.8).unwrap();
+
+            // The engine and its voice were made for 44.1 kHz
+            let mut prepared = IterablePolyphonyHashMap::new(44100);
+            prepared.prepare(96000, 256);
+            prepared.note_on(60, voice(44100), 0.8).unwrap();
+            assert_eq!(prepared.get(&60).unwrap().sample_rate, 96000);
+
+            for _ in 0..9600 {
+                let (left, right) = prepared.get_stereo_sample();
+                let (fresh_left, fresh_right) = fresh.get_stereo_sample();
+                assert!((left - fresh_left).abs() < 1e-4 && (right - fresh_right).abs() < 1e-4);
+            }
+        }
+
+        #[test]
+        fn test_5_playing_note_keeps_its_pitch() {
+            let mut polyphony = IterablePolyphonyHashMap::new(44100);
+            polyphony.note_on(69, voice(44100), 1.0).unwrap();
+            for _ in 0..441 {
+                polyphony.get_sample();
+            }
+            polyphony.prepare(48000, 1024);
+            let synth = polyphony.get(&69).unwrap();
+            assert!(is_close_f32(synth.osc.get_frequency(0), 440.0));
+            assert_eq!(synth.osc.sample_rate(), 48000);
+        }
+
+        #[test]
+        fn test_6_clocks_keep_the_tempo() {
+            let mut polyphony = IterablePolyphonyHashMap::new(44100);
+            polyphony.set_tempo(120.0);
+            polyphony.prepare(96000, 1024);
+            assert_eq!(polyphony.transport().samples_per_beat(), 48000.0);
+            assert_eq!(polyphony.transport().sample_rate(), 96000);
+
+            // The effects are built again at the new rate with their settings
+            let index = polyphony.effects_mut().push(effects::EffectType::Delay);
+            polyphony.effects_mut().set_mix(index, 0.25).unwrap();
+            polyphony.prepare(48000, 1024);
+            assert_eq!(polyphony.effects().get(index).unwrap().mix(), 0.25);
+            assert_eq!(polyphony.effects().get(index).unwrap().effect().effect_type(), effects::EffectType::Delay);
+        }
+    }
+    mod resampler_tests {
+        use super::*;
+        use std::f32::consts::PI;
+        use std::sync::{Arc, Mutex};
+        use audio::{AudioBackend, OfflineBackend, Resampler};
+
+        fn tone(frequency: f32, sample_rate: u32) -> impl FnMut() -> (f32, f32) {
+            let mut n = 0;
+            move || {
+                let sample = (2.0 * PI * frequency * n as f32 / sample_rate as f32).sin();
+                n += 1;
+                (sample, -sample)
+            }
+        }
+
+        fn zero_crossings(samples: &[f32]) -> usize {
+            samples.windows(2).filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0)).count()
+        }
+
+        #[test]
+        fn test_1_dc_passes_at_unity_gain() {
+            for (input_rate, output_rate) in [(44100, 48000), (96000, 44100), (48000, 48000)] {
+                let mut resampler = Resampler::new(input_rate, output_rate);
+                let frames: Vec<(f32, f32)> = (0..2000).map(|_| resampler.next_frame(&mut || (0.5, -0.25))).collect();
+                let (left, right) = frames[1999];
+                assert!((left - 0.5).abs() < 1e-4 && (right + 0.25).abs() < 1e-4, "{input_rate} -> {output_rate}");
+            }
+        }
+
+        #[test]
+        fn test_2_tone_keeps_its_pitch_and_level() {
+            let mut resampler = Resampler::new(44100, 48000);
+            let mut input = tone(1000.0, 44100);
+            let frames: Vec<(f32, f32)> = (0..48000 + 100).map(|_| resampler.next_frame(&mut input)).collect();
+            let left: Vec<f32> = frames[100..].iter().map(|frame| frame.0).collect();
+            let crossings = zero_crossings(&left);
+            assert!(crossings.abs_diff(2000) <= 2, "{crossings}");
+            let peak = left.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!((peak - 1.0).abs() < 0.01, "{peak}");
+            assert!(frames[100..].iter().all(|frame| (frame.0 + frame.1).abs() < 1e-5));
+        }
+
+        #[test]
+        fn test_3_downsampling_removes_what_the_output_cannot_hold() {
+            let mut resampler = Resampler::new(96000, 44100);
+            let mut input = tone(30000.0, 96000);
+            let frames: Vec<(f32, f32)> = (0..4410).map(|_| resampler.next_frame(&mut input)).collect();
+            let rms = (frames[200..].iter().map(|frame| frame.0 * frame.0).sum::<f32>() / (frames.len() - 200) as f32).sqrt();
+            assert!(rms < 1e-3, "{rms}");
+            assert!(resampler.latency() > 0.0);
+        }
+
+        #[test]
+        fn test_4_engine_keeps_its_internal_rate() {
+            let osc = oscillators::MultiOscillator::from(
+                oscillators::WaveTableOscillator::new(44100, 4096, oscillators::Oscillator::Sine, 1.0, 0.0)
+            );
+            let voice = wrapper::Synth::new(osc, 44100, None, None, None, lfo::LFOType::Amplitude);
+            let mut engine = engine::IterablePolyphonyHashMap::new(44100);
+            engine.set_internal_sample_rate(Some(44100));
+            engine.note_on(69, voice, 1.0).unwrap();
+            let engine = Arc::new(Mutex::new(engine));
+
+            for device_rate in [48000, 96000] {
+                let mut backend = OfflineBackend::new(device_rate, 1);
+                backend.start(Arc::clone(&engine)).unwrap();
+                assert_eq!(engine.lock().unwrap().sample_rate(), 44100);
+                backend.render(device_rate as usize).unwrap();
+                // One second of 440 Hz at the rate of the device
+                let crossings = zero_crossings(&backend.samples()[1000..]);
+                let expected = 880 * (device_rate as usize - 1000) / device_rate as usize;
+                assert!(crossings.abs_diff(expected) <= 2, "{device_rate}: {crossings}");
+            }
+        }
+    }
+
+    mod wavetable_tests {
+        use super::*;
+        use std::sync::Arc;
+        use oscillators::{octave_band, wave_table, OctaveTables, WaveTableKey, FULL_BAND, Oscillator, WaveTableOscillator};
+
+        /// Magnitude of harmonic `k` in one cycle of `table`.
+        fn harmonic(table: &[f32], k: usize) -> f32 {
+            let n = table.len() as f32;
+            let (re, im) = table.iter().enumerate().fold((0.0f32, 0.0f32), |(re, im), (i, sample)| {
+                let angle = 2.0 * std::f32::consts::PI * (k * i) as f32 / n;
+                (re + sample * angle.cos(), im - sample * angle.sin())
+            });
+            2.0 * (re * re + im * im).sqrt() / n
+        }
+
+        #[test]
+        fn test_1_same_key_shares_one_table() {
+            let first = wave_table(WaveTableKey { shape: Oscillator::Triangle, size: 1000, band: FULL_BAND });
+            let second = wave_table(WaveTableKey { shape: Oscillator::Triangle, size: 1000, band: FULL_BAND });
+            assert!(Arc::ptr_eq(&first, &second));
+            assert_eq!(first.len(), 1000);
+            // A sine is the same at every band
+            let sine = wave_table(WaveTableKey { shape: Oscillator::Sine, size: 1000, band: FULL_BAND });
+            let band_limited_sine = wave_table(WaveTableKey { shape: Oscillator::Sine, size: 1000, band: 8 });
+            assert!(Arc::ptr_eq(&sine, &band_limited_sine));
+        }
+
+        #[test]
+        fn test_2_oscillators_share_tables() {
+            let saw = WaveTableOscillator::new(44100, 4096, Oscillator::Saw, 0.5, 440.0);
+            let mut sine = WaveTableOscillator::new(44100, 4096, Oscillator::Sine, 1.0, 440.0);
+            assert!(!Arc::ptr_eq(saw.get_wave_table(), sine.get_wave_table()));
+            sine.set_oscillator(Oscillator::Saw);
+            assert!(Arc::ptr_eq(saw.get_wave_table(), sine.get_wave_table()));
+            let copy = sine.clone();
+            assert!(Arc::ptr_eq(copy.get_wave_table(), saw.get_wave_table()));
+        }
+
+        #[test]
+        fn test_3_gain_is_applied_once() {
+            let mut osc = WaveTableOscillator::new(44100, 4096, Oscillator::Sine, 0.5, 441.0);
+            let peak = (0..4410).map(|_| osc.get_sample()).fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!((peak - 0.5).abs() < 0.01, "{peak}");
+            osc.set_gain(0.25).unwrap();
+            let peak = (0..4410).map(|_| osc.get_sample()).fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!((peak - 0.25).abs() < 0.01, "{peak}");
+
+            // Squares keep their lower level, played low enough to have their edges
+            let mut square = WaveTableOscillator::new(44100, 4096, Oscillator::BidirectionalSquare, 0.5, 20.0);
+            let peak = (0..4410).map(|_| square.get_sample()).fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!((peak - 0.2).abs() < 0.01, "{peak}");
+        }
+
+        #[test]
+        fn test_4_band_limited_tables_stop_at_their_band() {
+            let full = wave_table(WaveTableKey { shape: Oscillator::Saw, size: 2048, band: FULL_BAND });
+            let limited = wave_table(WaveTableKey { shape: Oscillator::Saw, size: 2048, band: 16 });
+            for k in 1..=16 {
+                assert!((harmonic(&limited, k) - harmonic(&full, k)).abs() < 0.01, "{k}");
+            }
+            for k in 17..64 {
+                assert!(harmonic(&limited, k) < 1e-4, "{k}");
+                assert!(harmonic(&full, k) > 1e-3, "{k}");
+            }
+
+            let square = wave_table(WaveTableKey { shape: Oscillator::Square, size: 2048, band: 16 });
+            assert!(harmonic(&square, 2) < 1e-4 && harmonic(&square, 17) < 1e-4);
+        }
+
+        #[test]
+        fn test_5_notes_play_the_band_of_their_octave() {
+            // 16 harmonics of 1300 Hz fit under 22050 Hz, 32 do not
+            assert_eq!(octave_band(1300.0, 44100, 2048), 16);
+            assert_eq!(octave_band(40.0, 44100, 2048), FULL_BAND);
+            assert_eq!(octave_band(30000.0, 44100, 2048), 1);
+            assert_eq!(octave_band(0.0, 44100, 2048), FULL_BAND);
+            // A table cannot hold more than half its size
+            assert_eq!(octave_band(2000.0, 44100, 16), 8);
+
+            let octaves = OctaveTables::new(Oscillator::Square, 2048);
+            let mut osc = WaveTableOscillator::new(44100, 2048, Oscillator::Square, 1.0, 1300.0);
+            assert_eq!(osc.get_band(), 16);
+            assert!(Arc::ptr_eq(osc.get_wave_table(), octaves.get(16)));
+
+            // Detuning or retuning the note moves it to another band
+            osc.set_detune_semitones(12).unwrap();
+            assert_eq!(osc.get_band(), 8);
+            osc.set_frequency(40.0).unwrap();
+            assert_eq!(osc.get_band(), 256);
+            osc.set_detune_semitones(0).unwrap();
+            assert!(Arc::ptr_eq(osc.get_wave_table(), &wave_table(WaveTableKey { shape: Oscillator::Square, size: 2048, band: FULL_BAND })));
+
+            // The band is kept when the shape changes
+            osc.set_frequency(1300.0).unwrap();
+            osc.set_oscillator(Oscillator::Saw);
+            assert!(Arc::ptr_eq(osc.get_wave_table(), OctaveTables::new(Oscillator::Saw, 2048).get(16)));
+        }
+
+        #[test]
+        fn test_6_band_limited_tables_match_their_series() {
+            // 1000 samples is not a power of two, so the harmonics wrap at odd points of the cycle
+            let triangle = wave_table(WaveTableKey { shape: Oscillator::Triangle, size: 1000, band: 32 });
+            let saw = wave_table(WaveTableKey { shape: Oscillator::Saw, size: 1000, band: 32 });
+            let pi = std::f32::consts::PI;
+            for i in (0..1000).step_by(37) {
+                let x = 2.0 * pi * i as f32 / 1000.0;
+                let expected_triangle = -8.0 / (pi * pi) * (1..=32).step_by(2).map(|k| (k as f32 * x).cos() / (k * k) as f32).sum::<f32>();
+                let expected_saw = 2.0 / pi * (1..=32).map(|k| (k as f32 * x).sin() / k as f32).sum::<f32>();
+                assert!((triangle[i] - expected_triangle).abs() < 1e-4, "{i}");
+                assert!((saw[i] - expected_saw).abs() < 1e-4, "{i}");
+            }
+        }
+    }
+
+    mod voice_pool_tests {
+        use super::*;
+        use voice_pool::{VoicePool, VoiceSource};
+        use oscillators::{Oscillator, WaveTableOscillator};
+
+        fn voice(shape: Oscillator, lfo_hz: f32) -> wrapper::Synth {
+            let filter = filters::Filter::new(filters::FilterType::LowPass, 44100.0, 3000.0, 0.0);
+            let mut voice = synth_voice(44100, shape, 0.5, Some(filter), None);
+            voice.set_lfo_osc(Some(Oscillator::Sine), lfo_hz, lfo::LFOType::Frequency);
+            voice
+        }
+
+        #[test]
+        fn test_1_reused_voice_sounds_like_a_fresh_copy() {
+            let mut pool = VoicePool::new(1);
+            pool.set_real_time(true);
+            let first = voice(Oscillator::Saw, 3.0);
+            pool.reserve(&first);
+            let playing = pool.start(60, VoiceSource::Template(&first));
+            playing.note_on(261.63, 1.0).unwrap();
+            (0..1000).for_each(|_| { playing.get_sample(); });
+            pool.release(60);
+
+            let second = voice(Oscillator::BidirectionalSquare, 7.0);
+            assert!(second.has_layout_of(&first));
+            let mut fresh = second.clone();
+            fresh.note_on(329.63, 0.5).unwrap();
+            let reused = pool.start(64, VoiceSource::Template(&second));
+            reused.note_on(329.63, 0.5).unwrap();
+            for _ in 0..2000 {
+                assert_eq!(reused.get_sample(), fresh.get_sample());
+            }
+            assert!(pool.get(60).is_none());
+        }
+
+        #[test]
+        fn test_2_real_time_pool_takes_over_the_oldest_voice() {
+            let template = voice(Oscillator::Saw, 3.0);
+            let mut pool = VoicePool::new(2);
+            for key in [60, 64, 67] {
+                pool.start(key, VoiceSource::Template(&template));
+            }
+            // Outside real-time mode the pool grows
+            assert_eq!(pool.len(), 3);
+
+            pool.set_real_time(true);
+            assert_eq!(pool.len(), 2);
+            pool.clear();
+            for key in [60, 64, 67] {
+                pool.start(key, VoiceSource::Template(&template));
+            }
+            let keys: Vec<u8> = pool.iter().map(|(key, _)| *key).collect();
+            assert_eq!(pool.len(), 2);
+            assert!(!keys.contains(&60) && keys.contains(&64) && keys.contains(&67));
+
+            // The same key restarts its own voice
+            pool.start(67, VoiceSource::Template(&template));
+            assert_eq!(pool.len(), 2);
+            assert!(pool.rekey(67, 72));
+            assert!(pool.get(67).is_none() && pool.get(72).is_some());
+            assert!(pool.remove(72).is_some());
+            assert_eq!(pool.len(), 1);
+        }
+
+        #[test]
+        fn test_3_engine_polyphony_in_real_time_mode() {
+            let mut engine = engine::IterablePolyphonyHashMap::new(44100);
+            assert!(!engine.is_real_time());
+            engine.set_real_time(true);
+            engine.set_polyphony(3);
+            for key in [48, 52, 55, 60] {
+                engine.note_on(key, voice(Oscillator::Saw, 3.0), 1.0).unwrap();
+            }
+            assert_eq!(engine.len(), 3);
+            assert!(engine.get(&48).is_none());
+            engine.note_off(60).unwrap();
+            assert_eq!(engine.len(), 2);
+            engine.note_on(62, voice(Oscillator::Saw, 3.0), 1.0).unwrap();
+            assert_eq!(engine.len(), 3);
+            assert!(engine.get(&52).is_some());
+        }
+
+        #[test]
+        fn test_4_lfo_keeps_its_delay_line_sized_for_its_width() {
+            let osc = WaveTableOscillator::new(44100, 4096, Oscillator::Sine, 1.0, 5.0);
+            let mut lfo = lfo::LFO::new(lfo::LFOType::Amplitude, 44100.0, osc, 0.01);
+            assert_eq!(lfo.delay_line_len(), 2 + 441 * 3);
+            lfo.set_type(lfo::LFOType::Frequency);
+            assert_eq!(lfo.delay_line_len(), 2 + 441 * 3);
+            lfo.set_width(0.02);
+            assert_eq!(lfo.delay_line_len(), 2 + 882 * 3);
+            assert!((0..44100).map(|n| lfo.process((n as f32 * 0.05).sin())).all(f32::is_finite));
+        }
+     }
+    mod patch_tests {
+        use super::*;
+        use patch::{EnvelopeSettings, FilterSettings, LfoSettings, OscillatorSettings, Patch, VoiceSettings};
+        use filters::{FilterParam, FilterType};
+        use lfo::LFOType;
+        use oscillators::Oscillator;
+
+        #[test]
+        fn test_1_voice_settings_round_trip_and_build() {
+            let settings = VoiceSettings {
+                oscillators: vec![
+                    OscillatorSettings { shape: Oscillator::Square, gain: 0.4, detune_semitones: 0 },
+                    OscillatorSettings { shape: Oscillator::Triangle, gain: 0.2, detune_semitones: 7 },
+                ],
+                envelope: Some(EnvelopeSettings { attack_ms: 1.0, ..Default::default() }),
+                filter: Some(FilterSettings { filter_type: FilterType::HighPass, freq_hz: 300.0, ..Default::default() }),
+                distortion: Some(distortion::DistortionType::Foldback),
+                lfo: Some(LfoSettings { lfo_type: LFOType::Frequency, ..Default::default() }),
+            };
+            let patch = Patch { voice: settings, ..Default::default() };
+            let loaded = Patch::from_json(&patch.to_json().unwrap()).unwrap();
+            assert_eq!(loaded, patch);
+
+            let mut voice = loaded.voice(48000).unwrap();
+            assert_eq!(voice.num_sources(), 2);
+            voice.note_on(440.0, 1.0).unwrap();
+            assert!((voice.osc.get_frequency(1) - 440.0 * f32::powf(2.0, 7.0 / 12.0)).abs() < 0.01);
+            assert!(is_close_f32(voice.filter.as_ref().unwrap().get_param(FilterParam::FreqHz), 300.0));
+            assert!(voice.distortion.is_some());
+            assert_eq!(voice.get_lfo_osc(), Some(Oscillator::Sine));
+            assert_eq!(voice.lfo_type, LFOType::Frequency);
+        }
+
+        #[test]
+        fn test_2_patches_without_a_voice_play_a_saw() {
+            let patch = Patch::from_json("{}").unwrap();
+            assert_eq!(patch.voice, VoiceSettings::default());
+            let voice = patch.voice(44100).unwrap();
+            assert_eq!(voice.num_sources(), 1);
+            assert!(voice.envelope.is_some());
+            assert!(voice.filter.is_none());
+
+            let silent = Patch { voice: VoiceSettings { oscillators: Vec::new(), ..Default::default() }, ..Default::default() };
+            assert!(silent.voice(44100).is_err());
+        }
+    }
 }
\ No newline at end of file
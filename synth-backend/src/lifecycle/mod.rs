@@ -0,0 +1,38 @@
+//! Lifecycle
+//!
+//! Every DSP component of the engine is created for a sample rate, and keeps its times and frequencies
+//! in musical units such as milliseconds, Hertz and beats. `Prepare` moves a component to another
+//! sample rate: the values that are counted in samples, such as envelope steps, filter coefficients,
+//! phase increments and delay line lengths, are worked out again, so that the component sounds the
+//! same at 44.1, 48 or 96 kHz.
+//!
+//! `IterablePolyphonyHashMap` prepares everything it holds, so the engine can follow an output device
+//! that changes its sample rate while notes are playing. Audio backends prepare the engine they are
+//! started with.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::lifecycle::Prepare;
+//! use synth_backend::engine::IterablePolyphonyHashMap;
+//!
+//! let mut engine = IterablePolyphonyHashMap::new(44100);
+//!
+//! // The device now runs at 96 kHz and asks for up to 512 frames at a time
+//! engine.prepare(96000, 512);
+//! assert_eq!(engine.sample_rate(), 96000);
+//! ```
+
+/// Number of frames assumed to be asked for at a time when the output device does not say.
+pub const DEFAULT_MAX_BLOCK_SIZE: usize = 1024;
+
+/// A component that can be moved to another sample rate.
+pub trait Prepare {
+    /// Prepares the component to run at `sample_rate`, in Hz, with the output device asking for at
+    /// most `max_block_size` frames at a time.
+    ///
+    /// Parameters keep their musical values and notes keep playing. Delay lines that have to be
+    /// resized start out silent. Preparing allocates, so it should not be called from the audio
+    /// callback.
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize);
+}
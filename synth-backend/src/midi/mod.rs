@@ -0,0 +1,329 @@
+//! MIDI
+//!
+//! This module decodes a MIDI 1.0 byte stream into `MidiMessage`s and passes them on to the engine.
+//! `MidiParser` takes bytes one at a time, as they arrive from any MIDI input, and keeps track of
+//! running status, system exclusive messages and real-time messages that arrive in the middle of other
+//! messages. `MidiDispatcher` turns the messages into calls on `IterablePolyphonyHashMap`: notes,
+//! controllers, pitch bend and pressure go through the MPE methods, so they act per note on MPE member
+//! channels, MIDI Tuning Standard messages retune keys, and clock messages drive the transport.
+//! `MidiOutput` goes the other way, turning the notes the synthesizer plays into messages to send.
+//!
+//! Channels are numbered from 0, so channel 0 is MIDI channel 1.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::midi::{MidiMessage, MidiParser};
+//!
+//! let mut parser = MidiParser::new();
+//!
+//! // A note on followed by a note off in running status, as a note on with velocity 0
+//! let messages = parser.parse(&[0x90, 60, 100, 60, 0]);
+//! assert_eq!(messages, vec![
+//!     MidiMessage::NoteOn { channel: 0, key: 60, velocity: 100 },
+//!     MidiMessage::NoteOff { channel: 0, key: 60, velocity: 0 },
+//! ]);
+//! ```
+use crate::engine::IterablePolyphonyHashMap;
+use crate::transport::ClockSource;
+use crate::velocity::velocity_from_midi;
+use crate::wrapper::Synth;
+
+pub mod output;
+pub use output::{MidiOutput, MidiOutputSettings};
+
+/// Longest system exclusive message that is kept, in bytes. Longer ones are dropped.
+pub const MAX_SYSEX_LENGTH: usize = 4096;
+
+/// A MIDI 1.0 message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    /// A key was pressed.
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    /// A key was released. A note on with velocity 0 is read as a note off with velocity 0.
+    NoteOff { channel: u8, key: u8, velocity: u8 },
+    /// The pressure on a single key changed.
+    PolyPressure { channel: u8, key: u8, pressure: u8 },
+    /// A controller moved.
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    /// A program was selected.
+    ProgramChange { channel: u8, program: u8 },
+    /// The pressure on the keys of a channel changed.
+    ChannelPressure { channel: u8, pressure: u8 },
+    /// The pitch bend wheel moved, as a 14-bit value centred on 8192.
+    PitchBend { channel: u8, value: u16 },
+    /// A system exclusive message: the bytes between `0xF0` and `0xF7`.
+    SysEx(Vec<u8>),
+    /// A song position pointer, in sixteenth notes from the start of the song.
+    SongPosition(u16),
+    /// A timing clock, sent 24 times per beat.
+    Clock,
+    /// Start playing from the beginning of the song.
+    Start,
+    /// Carry on playing from the song position.
+    Continue,
+    /// Stop playing.
+    Stop,
+}
+
+impl MidiMessage {
+    /// Returns the channel of a channel message, or `None` for system messages.
+    pub fn channel(&self) -> Option<u8> {
+        match *self {
+            MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::PolyPressure { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(channel),
+            _ => None,
+        }
+    }
+
+    /// Encodes the message as MIDI 1.0 bytes, each message with its own status byte.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let status = |kind: u8, channel: u8| kind | (channel & 0x0F);
+        match *self {
+            MidiMessage::NoteOn { channel, key, velocity } => vec![status(0x90, channel), key & 0x7F, velocity & 0x7F],
+            MidiMessage::NoteOff { channel, key, velocity } => vec![status(0x80, channel), key & 0x7F, velocity & 0x7F],
+            MidiMessage::PolyPressure { channel, key, pressure } => vec![status(0xA0, channel), key & 0x7F, pressure & 0x7F],
+            MidiMessage::ControlChange { channel, controller, value } => vec![status(0xB0, channel), controller & 0x7F, value & 0x7F],
+            MidiMessage::ProgramChange { channel, program } => vec![status(0xC0, channel), program & 0x7F],
+            MidiMessage::ChannelPressure { channel, pressure } => vec![status(0xD0, channel), pressure & 0x7F],
+            MidiMessage::PitchBend { channel, value } => vec![status(0xE0, channel), (value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8],
+            MidiMessage::SysEx(ref data) => {
+                let mut bytes = Vec::with_capacity(data.len() + 2);
+                bytes.push(0xF0);
+                bytes.extend(data.iter().map(|byte| byte & 0x7F));
+                bytes.push(0xF7);
+                bytes
+            },
+            MidiMessage::SongPosition(sixteenths) => vec![0xF2, (sixteenths & 0x7F) as u8, ((sixteenths >> 7) & 0x7F) as u8],
+            MidiMessage::Clock => vec![0xF8],
+            MidiMessage::Start => vec![0xFA],
+            MidiMessage::Continue => vec![0xFB],
+            MidiMessage::Stop => vec![0xFC],
+        }
+    }
+}
+
+/// Decodes a MIDI 1.0 byte stream.
+#[derive(Clone, Debug, Default)]
+pub struct MidiParser {
+    status: Option<u8>,
+    data: [u8; 2],
+    data_length: usize,
+    sysex: Option<Vec<u8>>,
+}
+
+impl MidiParser {
+    /// Creates a new `MidiParser` that waits for a status byte.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes a block of bytes, returning the messages completed by it. Messages may span several
+    /// blocks.
+    pub fn parse(&mut self, bytes: &[u8]) -> Vec<MidiMessage> {
+        bytes.iter().filter_map(|byte| self.push(*byte)).collect()
+    }
+
+    /// Decodes one byte, returning the message it completes, if any.
+    pub fn push(&mut self, byte: u8) -> Option<MidiMessage> {
+        match byte {
+            // Real-time messages can arrive between any two bytes and leave everything else as it is
+            0xF8 => Some(MidiMessage::Clock),
+            0xFA => Some(MidiMessage::Start),
+            0xFB => Some(MidiMessage::Continue),
+            0xFC => Some(MidiMessage::Stop),
+            0xF9 | 0xFD..=0xFF => None,
+            0xF0 => {
+                self.status = None;
+                self.sysex = Some(Vec::new());
+                None
+            },
+            0xF7 => {
+                self.status = None;
+                self.sysex.take().map(MidiMessage::SysEx)
+            },
+            // Any other status byte ends an unfinished system exclusive message, which is dropped
+            0x80..=0xF6 => {
+                self.sysex = None;
+                self.status = Some(byte);
+                self.data_length = 0;
+                // Tune request and the undefined system common messages have no data bytes
+                if byte >= 0xF4 {
+                    self.status = None;
+                }
+                None
+            },
+            _ => self.push_data(byte),
+        }
+    }
+
+    fn push_data(&mut self, byte: u8) -> Option<MidiMessage> {
+        if let Some(sysex) = self.sysex.as_mut() {
+            sysex.push(byte);
+            if sysex.len() > MAX_SYSEX_LENGTH {
+                self.sysex = None;
+            }
+            return None;
+        }
+        // Data bytes without a status byte are ignored
+        let status = self.status?;
+        self.data[self.data_length] = byte;
+        self.data_length += 1;
+        if self.data_length < data_length(status) {
+            return None;
+        }
+        self.data_length = 0;
+        // System common messages do not set a running status
+        if status >= 0xF0 {
+            self.status = None;
+        }
+
+        let channel = status & 0x0F;
+        let [first, second] = self.data;
+        match status & 0xF0 {
+            0x80 => Some(MidiMessage::NoteOff { channel, key: first, velocity: second }),
+            0x90 if second == 0 => Some(MidiMessage::NoteOff { channel, key: first, velocity: 0 }),
+            0x90 => Some(MidiMessage::NoteOn { channel, key: first, velocity: second }),
+            0xA0 => Some(MidiMessage::PolyPressure { channel, key: first, pressure: second }),
+            0xB0 => Some(MidiMessage::ControlChange { channel, controller: first, value: second }),
+            0xC0 => Some(MidiMessage::ProgramChange { channel, program: first }),
+            0xD0 => Some(MidiMessage::ChannelPressure { channel, pressure: first }),
+            0xE0 => Some(MidiMessage::PitchBend { channel, value: ((second as u16) << 7) | first as u16 }),
+            _ => match status {
+                0xF2 => Some(MidiMessage::SongPosition(((second as u16) << 7) | first as u16)),
+                // MIDI time code quarter frames and song select are not used
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Returns the number of data bytes that follow a status byte.
+fn data_length(status: u8) -> usize {
+    match status & 0xF0 {
+        0xC0 | 0xD0 => 1,
+        0xF0 => match status {
+            0xF2 => 2,
+            0xF1 | 0xF3 => 1,
+            _ => 0,
+        },
+        _ => 2,
+    }
+}
+
+/// Decodes MIDI bytes and plays them on the engine.
+#[derive(Clone, Debug)]
+pub struct MidiDispatcher {
+    parser: MidiParser,
+    voice: Synth,
+    channel: Option<u8>,
+    keys: Vec<u8>,
+}
+
+impl MidiDispatcher {
+    /// Creates a new `MidiDispatcher` that starts notes with copies of `voice` and listens to every
+    /// channel.
+    pub fn new(voice: Synth) -> Self {
+        Self { parser: MidiParser::new(), voice, channel: None, keys: Vec::new() }
+    }
+
+    /// Returns the channel the dispatcher listens to, or `None` if it listens to every channel.
+    pub fn channel(&self) -> Option<u8> {
+        self.channel
+    }
+
+    /// Sets the channel to listen to, or `None` to listen to every channel, as MPE needs. Channel
+    /// messages on other channels are dropped, system messages always get through.
+    pub fn set_channel(&mut self, channel: Option<u8>) {
+        self.channel = channel;
+    }
+
+    /// Returns the keys held down on the MIDI input, in the order they were pressed.
+    pub fn keys(&self) -> &[u8] {
+        &self.keys
+    }
+
+    /// Sets the voice notes are started with.
+    pub fn set_voice(&mut self, voice: Synth) {
+        self.voice = voice;
+    }
+
+    /// Decodes a block of bytes and plays the messages on `engine`. Returns the messages the engine
+    /// did not use, such as program changes, so that the caller can act on them.
+    pub fn receive(&mut self, bytes: &[u8], engine: &mut IterablePolyphonyHashMap) -> Vec<MidiMessage> {
+        let mut unused = Vec::new();
+        for message in self.parser.parse(bytes) {
+            match (self.channel, message.channel()) {
+                (Some(listening), Some(channel)) if listening != channel => continue,
+                _ => ()
+            }
+            match message {
+                MidiMessage::NoteOn { key, .. } if !self.keys.contains(&key) => self.keys.push(key),
+                MidiMessage::NoteOff { key, .. } => self.keys.retain(|held| *held != key),
+                _ => ()
+            }
+            if !matches!(self.dispatch(&message, engine), Ok(true)) {
+                unused.push(message);
+            }
+        }
+        unused
+    }
+
+    /// Plays one message on `engine`. Returns whether the engine used the message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the engine could not carry out the message, such as a note on a key the
+    /// tuning does not map, or a system exclusive message that is not a MIDI Tuning Standard message.
+    pub fn dispatch(&self, message: &MidiMessage, engine: &mut IterablePolyphonyHashMap) -> Result<bool, String> {
+        let follows_clock = engine.transport().source() == ClockSource::MidiClock;
+        match *message {
+            MidiMessage::NoteOn { channel, key, velocity } => {
+                engine.mpe_note_on(channel, key, self.voice.clone(), velocity_from_midi(velocity))?;
+                Ok(true)
+            },
+            MidiMessage::NoteOff { channel, key, .. } => {
+                engine.mpe_note_off(channel, key)?;
+                Ok(true)
+            },
+            MidiMessage::ControlChange { channel, controller, value } => {
+                Ok(engine.mpe_control_change(channel, controller, value))
+            },
+            MidiMessage::ChannelPressure { channel, pressure } => Ok(engine.mpe_pressure(channel, pressure)),
+            MidiMessage::PitchBend { channel, value } => {
+                engine.mpe_pitch_bend(channel, value);
+                Ok(true)
+            },
+            MidiMessage::SysEx(ref data) => {
+                engine.midi_tuning(data)?;
+                Ok(true)
+            },
+            MidiMessage::SongPosition(sixteenths) => {
+                engine.midi_song_position(sixteenths);
+                Ok(follows_clock)
+            },
+            MidiMessage::Clock => {
+                engine.midi_clock();
+                Ok(follows_clock)
+            },
+            MidiMessage::Start => {
+                engine.midi_start();
+                Ok(follows_clock)
+            },
+            MidiMessage::Continue => {
+                engine.midi_continue();
+                Ok(follows_clock)
+            },
+            MidiMessage::Stop => {
+                engine.midi_stop();
+                Ok(follows_clock)
+            },
+            MidiMessage::PolyPressure { .. } | MidiMessage::ProgramChange { .. } => Ok(false),
+        }
+    }
+}
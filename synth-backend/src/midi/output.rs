@@ -0,0 +1,115 @@
+//! MIDI output
+//!
+//! Collects the notes played on the keyboard and by the arpeggiator and sequencer as MIDI messages,
+//! for the user interface to send to a MIDI output port. With local control off the notes are only
+//! sent, not sounded, so that the synthesizer can play another instrument as a controller.
+use serde::{Deserialize, Serialize};
+use super::MidiMessage;
+use crate::velocity::velocity_to_midi;
+
+/// Number of MIDI keys.
+const NUM_KEYS: usize = 128;
+/// Most messages kept waiting to be sent. Later messages are dropped until the queue is emptied.
+pub const MAX_PENDING_MESSAGES: usize = 256;
+
+/// Settings of the MIDI output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiOutputSettings {
+    /// Whether notes are sent.
+    pub enabled: bool,
+    /// The channel notes are sent on, from 0.
+    pub channel: u8,
+    /// Whether the notes sound on the synthesizer as well as being sent.
+    pub local_control: bool,
+}
+
+impl Default for MidiOutputSettings {
+    fn default() -> Self {
+        Self { enabled: false, channel: 0, local_control: true }
+    }
+}
+
+/// Turns notes into MIDI messages waiting to be sent.
+#[derive(Clone, Debug)]
+pub struct MidiOutput {
+    settings: MidiOutputSettings,
+    pending: Vec<MidiMessage>,
+    sounding: [bool; NUM_KEYS],
+}
+
+impl Default for MidiOutput {
+    fn default() -> Self {
+        Self::new(MidiOutputSettings::default())
+    }
+}
+
+impl MidiOutput {
+    /// Creates a new `MidiOutput` with no messages waiting.
+    pub fn new(settings: MidiOutputSettings) -> Self {
+        Self {
+            settings: MidiOutputSettings { channel: settings.channel & 0x0F, ..settings },
+            pending: Vec::with_capacity(MAX_PENDING_MESSAGES),
+            sounding: [false; NUM_KEYS],
+        }
+    }
+
+    pub fn settings(&self) -> MidiOutputSettings {
+        self.settings
+    }
+
+    /// Sets the channel and whether notes are sent. Notes that were sent on are sent off first when
+    /// the output is turned off or moves to another channel, so that none are left hanging.
+    pub fn set_settings(&mut self, settings: MidiOutputSettings) {
+        let settings = MidiOutputSettings { channel: settings.channel & 0x0F, ..settings };
+        if !settings.enabled || settings.channel != self.settings.channel {
+            self.release_all();
+        }
+        self.settings = settings;
+    }
+
+    /// Returns whether notes sound on the synthesizer as well as being sent.
+    pub fn is_local(&self) -> bool {
+        self.settings.local_control
+    }
+
+    /// Sends a note on, with a velocity between 0.0 and 1.0. A key that was already sent on is not
+    /// sent again, so that a repeated key down does not leave more note ons than note offs.
+    pub fn note_on(&mut self, key: u8, velocity: f32) {
+        if !self.settings.enabled || self.sounding.get(key as usize).copied().unwrap_or(true) {
+            return;
+        }
+        // A note on with velocity 0 would be read as a note off
+        let velocity = velocity_to_midi(velocity).max(1);
+        self.push(MidiMessage::NoteOn { channel: self.settings.channel, key, velocity });
+        self.sounding[key as usize] = true;
+    }
+
+    /// Sends a note off for a key that was sent on.
+    pub fn note_off(&mut self, key: u8) {
+        if !self.sounding.get(key as usize).copied().unwrap_or(false) {
+            return;
+        }
+        self.push(MidiMessage::NoteOff { channel: self.settings.channel, key, velocity: 0 });
+        self.sounding[key as usize] = false;
+    }
+
+    /// Sends a note off for every key that was sent on.
+    pub fn release_all(&mut self) {
+        for key in 0..NUM_KEYS as u8 {
+            self.note_off(key);
+        }
+    }
+
+    /// Takes the messages waiting to be sent, oldest first.
+    pub fn take_messages(&mut self) -> Vec<MidiMessage> {
+        // Draining keeps the capacity, so the audio thread does not allocate when pushing
+        self.pending.drain(..).collect()
+    }
+
+    fn push(&mut self, message: MidiMessage) {
+        if self.pending.len() < MAX_PENDING_MESSAGES {
+            self.pending.push(message);
+        }
+    }
+}
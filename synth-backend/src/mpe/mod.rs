@@ -0,0 +1,291 @@
+//! MPE
+//!
+//! This module implements MIDI Polyphonic Expression. An MPE controller plays each note on a channel
+//! of its own, so that pitch bend, channel pressure and CC74 (timbre) sent on that channel act on that
+//! note alone. The channels belong to a zone: the lower zone has its master channel on channel 1 and
+//! its member channels above it, the upper zone has its master channel on channel 16 and its member
+//! channels below it. Messages on the master channel act on every note of the zone.
+//!
+//! Channels are numbered from 0 as on the wire, so channel 0 is MIDI channel 1. Pressure and timbre
+//! are routed to voice parameters with `ExpressionTarget`.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::mpe::{Mpe, MpeSettings};
+//!
+//! let mut mpe = Mpe::new(MpeSettings { enabled: true, ..Default::default() });
+//!
+//! // A note on channel 2 bent up a whole tone, with the default range of 48 semitones
+//! mpe.note_on(2, 60);
+//! mpe.set_pitch_bend(2, 2.0 / 48.0);
+//! assert!((mpe.pitch_ratio(60) - f32::powf(2.0, 2.0 / 12.0)).abs() < 1e-6);
+//! ```
+use serde::{Deserialize, Serialize};
+
+/// Number of MIDI channels.
+pub const NUM_CHANNELS: usize = 16;
+/// MIDI control change number of the timbre dimension.
+pub const TIMBRE_CC: u8 = 74;
+/// Default pitch bend range of the member channels, in semitones.
+pub const DEFAULT_MPE_BEND_RANGE: f32 = 48.0;
+/// Largest pitch bend range of the member channels, in semitones.
+pub const MAX_MPE_BEND_RANGE: f32 = 96.0;
+/// Value of the timbre dimension before it is first sent.
+const DEFAULT_TIMBRE: f32 = 0.5;
+/// How many octaves the cutoff moves by over the full range of a dimension.
+const CUTOFF_OCTAVES: f32 = 4.0;
+
+/// Which end of the MIDI channels the zone sits at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MpeZone {
+    /// Master channel 1, member channels from 2 upwards.
+    #[default]
+    Lower,
+    /// Master channel 16, member channels from 15 downwards.
+    Upper,
+}
+
+impl MpeZone {
+    /// All zones, in the order they are offered in the user interface.
+    pub const ALL: [MpeZone; 2] = [MpeZone::Lower, MpeZone::Upper];
+
+    /// Returns the display name of the zone.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MpeZone::Lower => "Lower",
+            MpeZone::Upper => "Upper",
+        }
+    }
+}
+
+/// The voice parameter an expression dimension controls.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExpressionTarget {
+    /// The dimension does nothing.
+    #[default]
+    Off,
+    /// The dimension raises and lowers the gain of the note, up to twice as loud.
+    Gain,
+    /// The dimension moves the filter cutoff of the note, over `CUTOFF_OCTAVES` octaves.
+    Cutoff,
+}
+
+impl ExpressionTarget {
+    /// All expression targets, in the order they are offered in the user interface.
+    pub const ALL: [ExpressionTarget; 3] = [ExpressionTarget::Off, ExpressionTarget::Gain, ExpressionTarget::Cutoff];
+
+    /// Returns the display name of the target.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExpressionTarget::Off => "Off",
+            ExpressionTarget::Gain => "Gain",
+            ExpressionTarget::Cutoff => "Cutoff",
+        }
+    }
+}
+
+/// Per-patch MPE settings.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MpeSettings {
+    /// Whether notes are mapped to channels. When off, every channel acts on every note.
+    pub enabled: bool,
+    /// Which zone the controller plays in.
+    pub zone: MpeZone,
+    /// The number of member channels of the zone, between 1 and 15.
+    pub member_channels: u8,
+    /// How many semitones a fully bent member channel moves its note by.
+    pub bend_range_semitones: f32,
+    /// What channel pressure controls.
+    pub pressure_target: ExpressionTarget,
+    /// What CC74 controls.
+    pub timbre_target: ExpressionTarget,
+}
+
+impl Default for MpeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            zone: MpeZone::Lower,
+            member_channels: 15,
+            bend_range_semitones: DEFAULT_MPE_BEND_RANGE,
+            pressure_target: ExpressionTarget::Gain,
+            timbre_target: ExpressionTarget::Cutoff,
+        }
+    }
+}
+
+/// The expression of the note on a member channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Expression {
+    /// Pitch bend, between -1.0 and 1.0.
+    pub pitch_bend: f32,
+    /// Channel pressure, between 0.0 and 1.0.
+    pub pressure: f32,
+    /// Timbre, between 0.0 and 1.0.
+    pub timbre: f32,
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        Self { pitch_bend: 0.0, pressure: 0.0, timbre: DEFAULT_TIMBRE }
+    }
+}
+
+/// Maps member channels to the keys played on them and keeps their expression.
+#[derive(Clone, Debug, Default)]
+pub struct Mpe {
+    settings: MpeSettings,
+    keys: [Option<u8>; NUM_CHANNELS],
+    expressions: [Expression; NUM_CHANNELS],
+}
+
+impl Mpe {
+    /// Creates a new `Mpe` with no notes playing.
+    pub fn new(settings: MpeSettings) -> Self {
+        let mut mpe = Self::default();
+        mpe.set_settings(settings);
+        mpe
+    }
+
+    pub fn settings(&self) -> MpeSettings {
+        self.settings
+    }
+
+    /// Sets the zone and expression routing. When MPE is turned on or off, or the zone or its member
+    /// channels change, the notes that are playing are forgotten and `true` is returned. Otherwise the
+    /// notes keep their channels and expression.
+    pub fn set_settings(&mut self, settings: MpeSettings) -> bool {
+        let settings = MpeSettings {
+            member_channels: settings.member_channels.clamp(1, NUM_CHANNELS as u8 - 1),
+            bend_range_semitones: settings.bend_range_semitones.clamp(0.0, MAX_MPE_BEND_RANGE),
+            ..settings
+        };
+        let channels_changed = settings.enabled != self.settings.enabled
+            || settings.zone != self.settings.zone
+            || settings.member_channels != self.settings.member_channels;
+        self.settings = settings;
+        if channels_changed {
+            self.keys = [None; NUM_CHANNELS];
+            self.expressions = [Expression::default(); NUM_CHANNELS];
+        }
+        channels_changed
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Returns the master channel of the zone.
+    pub fn master_channel(&self) -> u8 {
+        match self.settings.zone {
+            MpeZone::Lower => 0,
+            MpeZone::Upper => NUM_CHANNELS as u8 - 1,
+        }
+    }
+
+    /// Returns whether `channel` is a member channel of the zone, with MPE enabled.
+    pub fn is_member(&self, channel: u8) -> bool {
+        let members = self.settings.member_channels as usize;
+        self.settings.enabled && (channel as usize) < NUM_CHANNELS && match self.settings.zone {
+            MpeZone::Lower => (1..=members).contains(&(channel as usize)),
+            MpeZone::Upper => (NUM_CHANNELS - 1 - members..NUM_CHANNELS - 1).contains(&(channel as usize)),
+        }
+    }
+
+    /// Returns the key playing on a member channel.
+    pub fn key(&self, channel: u8) -> Option<u8> {
+        self.keys.get(channel as usize).copied().flatten()
+    }
+
+    /// Returns the member channel `key` is playing on.
+    pub fn channel(&self, key: u8) -> Option<u8> {
+        self.keys.iter().position(|playing| *playing == Some(key)).map(|channel| channel as u8)
+    }
+
+    /// Returns the expression of a member channel.
+    pub fn expression(&self, channel: u8) -> Expression {
+        self.expressions.get(channel as usize).copied().unwrap_or_default()
+    }
+
+    /// Plays `key` on a member channel. A key can only play on one channel at a time, so it is
+    /// taken away from any other channel it was playing on. Does nothing if `channel` is not a
+    /// member channel.
+    pub fn note_on(&mut self, channel: u8, key: u8) {
+        if !self.is_member(channel) {
+            return;
+        }
+        for playing in self.keys.iter_mut().filter(|playing| **playing == Some(key)) {
+            *playing = None;
+        }
+        self.keys[channel as usize] = Some(key);
+    }
+
+    /// Releases `key` from a member channel. Returns whether the key was playing on the channel, and
+    /// so whether its voice should be released.
+    pub fn note_off(&mut self, channel: u8, key: u8) -> bool {
+        match self.keys.get_mut(channel as usize) {
+            Some(playing) if *playing == Some(key) => {
+                *playing = None;
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Sets the pitch bend of a member channel, between -1.0 and 1.0. Returns the key playing on it.
+    pub fn set_pitch_bend(&mut self, channel: u8, bend: f32) -> Option<u8> {
+        self.update(channel, |expression| expression.pitch_bend = bend.clamp(-1.0, 1.0))
+    }
+
+    /// Sets the pressure of a member channel, between 0.0 and 1.0. Returns the key playing on it.
+    pub fn set_pressure(&mut self, channel: u8, pressure: f32) -> Option<u8> {
+        self.update(channel, |expression| expression.pressure = pressure.clamp(0.0, 1.0))
+    }
+
+    /// Sets the timbre of a member channel, between 0.0 and 1.0. Returns the key playing on it.
+    pub fn set_timbre(&mut self, channel: u8, timbre: f32) -> Option<u8> {
+        self.update(channel, |expression| expression.timbre = timbre.clamp(0.0, 1.0))
+    }
+
+    /// Returns the factor the per-note pitch bend multiplies the frequency of `key` by.
+    pub fn pitch_ratio(&self, key: u8) -> f32 {
+        match self.channel(key) {
+            Some(channel) => {
+                let semitones = self.expression(channel).pitch_bend * self.settings.bend_range_semitones;
+                f32::powf(2.0, semitones / 12.0)
+            },
+            None => 1.0
+        }
+    }
+
+    /// Returns the gain and the filter cutoff factor the pressure and timbre of `key` set.
+    pub fn voice_expression(&self, key: u8) -> (f32, f32) {
+        let mut gain = 1.0;
+        let mut cutoff_ratio = 1.0;
+        if let Some(channel) = self.channel(key) {
+            let expression = self.expression(channel);
+            let dimensions = [
+                (self.settings.pressure_target, expression.pressure),
+                (self.settings.timbre_target, expression.timbre - DEFAULT_TIMBRE),
+            ];
+            for (target, amount) in dimensions {
+                match target {
+                    ExpressionTarget::Off => (),
+                    ExpressionTarget::Gain => gain *= (1.0 + amount).max(0.0),
+                    ExpressionTarget::Cutoff => cutoff_ratio *= f32::powf(2.0, CUTOFF_OCTAVES * amount),
+                }
+            }
+        }
+        (gain, cutoff_ratio)
+    }
+
+    fn update(&mut self, channel: u8, update: impl FnOnce(&mut Expression)) -> Option<u8> {
+        if !self.is_member(channel) {
+            return None;
+        }
+        update(&mut self.expressions[channel as usize]);
+        self.keys[channel as usize]
+    }
+}
@@ -0,0 +1,32 @@
+//! Native output
+//!
+//! This module picks the audio backend for desktop builds with the `native` feature: the default
+//! output device of the computer through cpal, such as ALSA or PulseAudio on Linux, or a
+//! `NullBackend` when there is no usable output device.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::sync::{Arc, Mutex};
+//! use synth_backend::native::default_backend;
+//! use synth_backend::engine::IterablePolyphonyHashMap;
+//!
+//! let mut backend = default_backend();
+//! let polyphony = Arc::new(Mutex::new(IterablePolyphonyHashMap::new(backend.sample_rate())));
+//!
+//! // Plays until the backend is stopped or dropped
+//! backend.start(Arc::clone(&polyphony)).unwrap();
+//! ```
+use crate::audio::{AudioBackend, CpalBackend, NullBackend};
+
+/// Opens the default output device, or a `NullBackend` if there is no usable device. The reason the
+/// device cannot be used is printed on the standard error.
+pub fn default_backend() -> Box<dyn AudioBackend> {
+    match CpalBackend::default_output() {
+        Ok(backend) => Box::new(backend),
+        Err(err) => {
+            eprintln!("{err} Running on the null backend instead.");
+            Box::new(NullBackend::default())
+        }
+    }
+}
@@ -24,7 +24,9 @@
 //! # WaveTableOscillator
 //!
 //! `WaveTableOscillator` generates audio waveforms using pre-calculated wave tables. It supports sine, square,
-//! bidirectional square, sawtooth, triangle, and white noise waveforms.
+//! bidirectional square, sawtooth, triangle, and white noise waveforms. The tables are shared between all
+//! oscillators through the cache in `wavetable`, so an oscillator itself only keeps its phase and parameters.
+//! Each frequency plays from the band-limited table of its octave, so that high notes do not alias.
 //!
 //! # MultiOscillator
 //!
@@ -41,15 +43,22 @@
 //!
 //! - The `MultiOscillator` struct supports adding and removing individual oscillators dynamically, as well as setting
 //!   frequency and gain for each oscillator separately.
-use std::{f32::consts::PI, ops::Add};
+pub mod wavetable;
+
+use std::{ops::Add, sync::Arc};
+use std::sync::atomic::{AtomicU32, Ordering};
 use rand::seq::index;
-use rand_distr::{Distribution, Uniform};
 use rodio::Source;
+use serde::{Deserialize, Serialize};
+use crate::lifecycle::Prepare;
+pub use wavetable::{octave_band, wave_table, OctaveTables, WaveTableKey, FULL_BAND};
+
 
-use crate::utils::{hz_to_midi, midi_to_hz};
+/// Number of samples in one cycle of the wave table of a voice oscillator.
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
 
 #[allow(dead_code)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Oscillator {
     /// Sine wave oscillator.
     Sine,
@@ -65,17 +74,53 @@ pub enum Oscillator {
     WhiteNoise
 }
 
+/// Seeds of the white noise generators, spread apart by the golden ratio.
+static NOISE_SEEDS: AtomicU32 = AtomicU32::new(0x9E37_79B9);
+
+/// White noise from a xorshift generator, which does not allocate or lock. Every generator starts from
+/// its own seed, copies included, so that voices playing noise together do not play the same noise.
+#[derive(Debug)]
+struct Noise {
+    state: u32,
+}
+
+impl Noise {
+    fn new() -> Self {
+        // A xorshift state of zero stays zero
+        Self { state: NOISE_SEEDS.fetch_add(0x9E37_79B9, Ordering::Relaxed).max(1) }
+    }
+
+    /// Returns a value between -1.0 and 1.0.
+    fn next(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+    }
+}
+
+impl Clone for Noise {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
 /// Convert WavetableOscillator parameters in to a vector and use aligned_allocator to play each sample from the wavetable
 #[derive(Clone, Debug)]
 pub struct WaveTableOscillator {
     sample_rate: u32,
     oscillator: Oscillator,
     wave_table_size: usize,
-    wave_table: Vec<f32>,
+    octave_tables: OctaveTables,
+    wave_table: Arc<[f32]>,
+    band: u32,
+    noise: Noise,
     gain: f32,
     detune_semitones: i8,
+    frequency: f32,
     index: f32,
-    index_increment: f32
+    index_increment: f32,
+    pitch_ratio: f32
 }
 
 impl WaveTableOscillator {
@@ -94,55 +139,22 @@ impl WaveTableOscillator {
     /// A new `WaveTableOscillator` instance.
     pub fn new(sample_rate: u32, wave_table_size: usize, oscillator: Oscillator, gain: f32, frequency: f32) -> Self {
         assert!(gain>=0.0 && gain<=1.0, "Gain must be between 0 and 1");
-        let mut wave_table: Vec<f32> = Vec::new();
-        match oscillator {
-            Oscillator::Sine => {
-                for i in 0..wave_table_size {
-                    wave_table.push((2.0 * PI * (i as f32)/(wave_table_size as f32)).sin() * gain);
-                }
-            },
-            Oscillator::Square => {
-                for i in 0..wave_table_size {
-                    if i < wave_table_size/2 {
-                        wave_table.push(0.4 * gain);
-                    } else {
-                        wave_table.push(0.0);
-                    }
-                }
-            },
-            Oscillator::BidirectionalSquare => {
-                for i in 0..wave_table_size {
-                    if i < wave_table_size/2 {
-                        wave_table.push(0.4 * gain);
-                    } else {
-                        wave_table.push(-0.4 * gain);
-                    }
-                }
-            },
-            Oscillator::Saw => {
-                for i in 1..=wave_table_size {
-                    wave_table.push((((wave_table_size as f32 - i as f32)/(wave_table_size as f32) * 2.0) - 1.0) * gain);
-                }
-            },
-            Oscillator::Triangle => {
-                for i in 0..wave_table_size/2 {
-                    wave_table.push((((i as f32/wave_table_size as f32)*4.0) - 1.0) * gain)
-                }
-                for i in wave_table_size/2..wave_table_size {
-                    wave_table.push(((-(i as f32/wave_table_size as f32)*4.0) + 3.0) * gain)
-                }
-            },
-            Oscillator::WhiteNoise => ()
-        }
+        let octave_tables = OctaveTables::new(oscillator, wave_table_size);
+        let band = octave_band(frequency, sample_rate, wave_table_size);
         Self {
             sample_rate,
             oscillator,
             gain,
             detune_semitones: 0,
+            frequency,
             wave_table_size,
-            wave_table,
+            wave_table: Arc::clone(octave_tables.get(band)),
+            octave_tables,
+            band,
+            noise: Noise::new(),
             index: 0.0,
-            index_increment: frequency * wave_table_size as f32 / sample_rate as f32
+            index_increment: frequency * wave_table_size as f32 / sample_rate as f32,
+            pitch_ratio: 1.0
         }
     }
 
@@ -150,87 +162,81 @@ impl WaveTableOscillator {
         if frequency < 0.0 {
             return Err("Frequency must be a positive floating point value!".to_owned());
         }
-        if self.detune_semitones == 0 {
-            self.index_increment = frequency * self.wave_table_size as f32 / self.sample_rate as f32;
-        } else {
-            let curr_midi = hz_to_midi(frequency).expect("Frequency is less than 0.0");
-            let new_midi = curr_midi as i8 + self.detune_semitones;
-            if new_midi > 0 {
-                self.index_increment = midi_to_hz(new_midi as u8).unwrap() * self.wave_table_size as f32 / self.sample_rate as f32;
-            } else {
-                return Err("Net midi is less than 0".to_owned());
-            }
-        }
+        self.frequency = frequency;
+        self.update_index_increment();
         Ok(())
     }
 
+    /// Works out the table step from the played frequency and the detune. The detune is applied as
+    /// an exact ratio, so that notes of any tuning keep their pitch relative to each other.
+    fn update_index_increment(&mut self) {
+        let detune_ratio = f32::powf(2.0, self.detune_semitones as f32 / 12.0);
+        self.index_increment = self.frequency * detune_ratio * self.wave_table_size as f32 / self.sample_rate as f32;
+        self.update_band();
+    }
+
+    /// Moves to the band of the played frequency. The pitch ratio is left out, as it bends the note
+    /// while it plays.
+    fn update_band(&mut self) {
+        let band = octave_band(self.get_frequency(), self.sample_rate, self.wave_table_size);
+        if band != self.band {
+            self.band = band;
+            self.update_wave_table();
+        }
+    }
+
     pub fn get_frequency(&self) -> f32 {
         self.index_increment * self.sample_rate as f32 / self.wave_table_size as f32
     }
 
     pub fn set_detune_semitones(&mut self, detune_semitones: i8) -> Result<(), String> {
         self.detune_semitones = detune_semitones;
-        let curr_frequency = self.get_frequency();
-        let curr_midi = hz_to_midi(curr_frequency).expect("Frequency is less than 0.0");
-        let new_midi = curr_midi as i8 + detune_semitones;
-        
-        if new_midi > 0 {
-            self.index_increment = midi_to_hz(new_midi as u8).unwrap() * self.wave_table_size as f32 / self.sample_rate as f32;
-        } else {
-            return Err("Net midi is less than 0".to_owned());
-        }
+        self.update_index_increment();
         Ok(())
     }
 
+    /// Switches to the shared tables of another shape. Tables that have been used before are not
+    /// generated again.
     pub fn set_oscillator(&mut self, oscillator: Oscillator) {
-        let mut wave_table: Vec<f32> = Vec::new();
-        self.oscillator = oscillator.clone();
-        match oscillator {
-            Oscillator::Sine => {
-                for i in 0..self.wave_table_size {
-                    wave_table.push((2.0 * PI * (i as f32)/(self.wave_table_size as f32)).sin() * self.gain);
-                }
-            },
-            Oscillator::Square => {
-                for i in 0..self.wave_table_size {
-                    if i < self.wave_table_size/2 {
-                        wave_table.push(0.99 * self.gain);
-                    } else {
-                        wave_table.push(0.0);
-                    }
-                }
-            },
-            Oscillator::BidirectionalSquare => {
-                for i in 0..self.wave_table_size {
-                    if i < self.wave_table_size/2 {
-                        wave_table.push(0.99 * self.gain);
-                    } else {
-                        wave_table.push(-0.99 * self.gain);
-                    }
-                }
-            },
-            Oscillator::Saw => {
-                for i in 1..=self.wave_table_size {
-                    wave_table.push((((self.wave_table_size as f32 - i as f32)/(self.wave_table_size as f32) * 2.0) - 1.0) * self.gain);
-                }
-            },
-            Oscillator::Triangle => {
-                for i in 0..self.wave_table_size/2 {
-                    wave_table.push((((i as f32/self.wave_table_size as f32)*4.0) - 1.0) * self.gain)
-                }
-                for i in self.wave_table_size/2..self.wave_table_size {
-                    wave_table.push(((-(i as f32/self.wave_table_size as f32)*4.0) + 3.0) * self.gain)
-                }
-            },
-            Oscillator::WhiteNoise => ()
-        }
-        self.wave_table = wave_table;
+        self.oscillator = oscillator;
+        self.octave_tables = OctaveTables::new(oscillator, self.wave_table_size);
+        self.update_wave_table();
     }
 
     pub fn get_oscillator(&self) -> Oscillator {
         self.oscillator
     }
 
+    /// Returns the band of the table the oscillator plays from, chosen by `octave_band` from the
+    /// frequency of the note.
+    pub fn get_band(&self) -> u32 {
+        self.band
+    }
+
+    /// Returns the table the oscillator plays from, shared with every oscillator of the same shape.
+    pub fn get_wave_table(&self) -> &Arc<[f32]> {
+        &self.wave_table
+    }
+
+    fn update_wave_table(&mut self) {
+        self.wave_table = Arc::clone(self.octave_tables.get(self.band));
+    }
+
+    /// Sets the factor the frequency is multiplied by while playing, used to bend the pitch without
+    /// changing the frequency of the note itself.
+    pub fn set_pitch_ratio(&mut self, pitch_ratio: f32) {
+        self.pitch_ratio = pitch_ratio.max(0.0);
+    }
+
+    pub fn get_pitch_ratio(&self) -> f32 {
+        self.pitch_ratio
+    }
+
+    /// Sets the current position in the wave table as a fraction of a cycle, between 0.0 and 1.0.
+    pub fn set_phase(&mut self, phase: f32) {
+        self.index = phase.rem_euclid(1.0) * self.wave_table_size as f32;
+    }
+
     #[allow(dead_code)]
     pub fn set_gain(&mut self, gain: f32) -> Result<(), String> {
         if gain < 0.0 || gain > 1.0 {
@@ -242,15 +248,11 @@ impl WaveTableOscillator {
 
     pub fn get_sample(&mut self) -> f32 {
         match self.oscillator {
-            Oscillator::WhiteNoise => {
-                let mut rng = rand::thread_rng();
-                let unif = Uniform::new(-1.0, 1.0);
-                unif.sample(&mut rng) * self.gain
-            },
+            Oscillator::WhiteNoise => self.noise.next() * self.gain,
             _ => {
                 let index_1 = self.index.trunc() as usize;
                 let frac = self.index - index_1 as f32;
-                self.index = (self.index + self.index_increment) % self.wave_table_size as f32;
+                self.index = (self.index + self.index_increment * self.pitch_ratio) % self.wave_table_size as f32;
                 WaveTableOscillator::lerp(self.wave_table[index_1], self.wave_table[(index_1 + 1)%self.wave_table_size], frac) * self.gain
             }
         }
@@ -287,6 +289,14 @@ impl Source for WaveTableOscillator {
     }
 }
 
+impl Prepare for WaveTableOscillator {
+    /// Keeps the frequency and phase, so that the oscillator plays the same pitch at the new rate.
+    fn prepare(&mut self, sample_rate: u32, _max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.update_index_increment();
+    }
+}
+
 impl Add for WaveTableOscillator {
     type Output = MultiOscillator;
     fn add(self, rhs: Self) -> Self::Output {
@@ -299,11 +309,29 @@ impl Add for WaveTableOscillator {
 }
 
 /// `MultiOscillator` combines multiple `WaveTableOscillator` instances into a single oscillator.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct MultiOscillator {
     multi_osc: Vec<WaveTableOscillator>,
     sample_rate: u32,
-    normalization: f32
+    normalization: f32,
+    pitch_ratio: f32
+}
+
+impl Clone for MultiOscillator {
+    fn clone(&self) -> Self {
+        Self {
+            multi_osc: self.multi_osc.clone(),
+            ..*self
+        }
+    }
+
+    // The oscillators share their tables, so copying into the existing list does not allocate
+    fn clone_from(&mut self, source: &Self) {
+        self.multi_osc.clone_from(&source.multi_osc);
+        self.sample_rate = source.sample_rate;
+        self.normalization = source.normalization;
+        self.pitch_ratio = source.pitch_ratio;
+    }
 }
 
 impl MultiOscillator{
@@ -320,7 +348,8 @@ impl MultiOscillator{
         Self {
             multi_osc: Vec::new(),
             sample_rate: sample_rate,
-            normalization: 1.0
+            normalization: 1.0,
+            pitch_ratio: 1.0
         }
     }
 
@@ -340,6 +369,7 @@ impl MultiOscillator{
     pub fn from(oscillator: WaveTableOscillator) -> Self {
         let mut m_osc = MultiOscillator::new(oscillator.sample_rate);
         m_osc.normalization = oscillator.gain;
+        m_osc.pitch_ratio = oscillator.pitch_ratio;
         m_osc.multi_osc.push(oscillator);
         m_osc
     }
@@ -356,11 +386,12 @@ impl MultiOscillator{
     /// # Returns
     ///
     /// A `Result` indicating success or an error message if the sample rates do not match.
-    pub fn push(&mut self, oscillator: WaveTableOscillator) -> Result<(), String> {
+    pub fn push(&mut self, mut oscillator: WaveTableOscillator) -> Result<(), String> {
         if oscillator.sample_rate != self.sample_rate {
             return Err("Sample rate must be the same!".to_owned());
         }
         self.normalization += oscillator.gain;
+        oscillator.set_pitch_ratio(self.pitch_ratio);
         self.multi_osc.push(oscillator);
         Ok(())
     }
@@ -388,6 +419,11 @@ impl MultiOscillator{
         Ok(())
     }
 
+    /// Returns the frequency a source oscillator plays in Hz, including its detune.
+    pub fn get_frequency(&self, source_index: usize) -> f32 {
+        self.multi_osc[source_index].get_frequency()
+    }
+
      /// Sets the frequency of all source oscillators in the `MultiOscillator`.
     ///
     /// This method sets the frequency of all oscillators within the `MultiOscillator` to the
@@ -407,6 +443,22 @@ impl MultiOscillator{
         Ok(())
     }
 
+    /// Sets the factor the frequency of all source oscillators is multiplied by while playing.
+    ///
+    /// # Arguments
+    ///
+    /// * `pitch_ratio` - The frequency factor, 1.0 leaves the pitch unchanged.
+    pub fn set_pitch_ratio(&mut self, pitch_ratio: f32) {
+        self.pitch_ratio = pitch_ratio.max(0.0);
+        for osc in self.multi_osc.iter_mut() {
+            osc.set_pitch_ratio(self.pitch_ratio);
+        }
+    }
+
+    pub fn get_pitch_ratio(&self) -> f32 {
+        self.pitch_ratio
+    }
+
     /// Sets the gain of a source oscillator in the `MultiOscillator`.
     ///
     /// This method sets the gain (amplitude) of the oscillator at the specified index within the
@@ -508,6 +560,15 @@ impl Source for MultiOscillator {
     }
 }
 
+impl Prepare for MultiOscillator {
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        for osc in self.multi_osc.iter_mut() {
+            osc.prepare(sample_rate, max_block_size);
+        }
+    }
+}
+
 impl Add for MultiOscillator {
     type Output = Self;
     fn add(self, rhs: Self) -> Self::Output {
@@ -528,7 +589,8 @@ impl Default for MultiOscillator {
         Self {
             multi_osc: Vec::new(),
             sample_rate: 44100,
-            normalization: 1.0
+            normalization: 1.0,
+            pitch_ratio: 1.0
         }
     }
 }
\ No newline at end of file
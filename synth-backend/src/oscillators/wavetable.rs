@@ -0,0 +1,176 @@
+//! Wave tables
+//!
+//! Every `WaveTableOscillator` of the same shape and table size plays from the same table. Tables are
+//! generated the first time they are asked for, kept in a cache for the life of the program and shared
+//! through an `Arc`, so creating, cloning or reshaping an oscillator only copies a pointer. Tables hold
+//! one cycle of the shape at full scale, or at `0.4` for the squares; the gain of each oscillator is
+//! applied once, when it is played.
+//!
+//! A table can also be band-limited: it then only holds the harmonics up to `band`, added up from the
+//! Fourier series of the shape, so that it does not alias when played `sample_rate / (2 * band)` Hz or
+//! lower. A `band` of `FULL_BAND` holds the shape with its sharp edges.
+//!
+//! `OctaveTables` holds the tables of one shape for the bands `1, 2, 4 … MAX_BAND` and the full band.
+//! An oscillator plays the one `octave_band` picks for its note, so that high notes do not alias while
+//! low notes keep their edges, and moves to another band by swapping a pointer.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//! use synth_backend::oscillators::Oscillator;
+//! use synth_backend::oscillators::wavetable::{octave_band, wave_table, OctaveTables, WaveTableKey, FULL_BAND};
+//!
+//! let saw = wave_table(WaveTableKey { shape: Oscillator::Saw, size: 2048, band: FULL_BAND });
+//! let same = wave_table(WaveTableKey { shape: Oscillator::Saw, size: 2048, band: FULL_BAND });
+//! assert!(Arc::ptr_eq(&saw, &same));
+//!
+//! // The first 32 harmonics of a saw
+//! let soft_saw = wave_table(WaveTableKey { shape: Oscillator::Saw, size: 2048, band: 32 });
+//! assert_eq!(soft_saw.len(), 2048);
+//!
+//! // A saw at 1000 Hz keeps the 16 harmonics under 22050 Hz
+//! let octaves = OctaveTables::new(Oscillator::Saw, 2048);
+//! assert_eq!(octave_band(1000.0, 44100, 2048), 16);
+//! assert!(Arc::ptr_eq(octaves.get(16), &wave_table(WaveTableKey { shape: Oscillator::Saw, size: 2048, band: 16 })));
+//! ```
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex, OnceLock};
+use super::Oscillator;
+
+/// `band` of a table that holds the shape with all of its harmonics.
+pub const FULL_BAND: u32 = 0;
+/// Highest band of `OctaveTables`. Notes with room for more harmonics than twice this play the full
+/// table, whose harmonics above it are too quiet to alias audibly.
+pub const MAX_BAND: u32 = 256;
+/// Level of the square shapes, which sound louder than the other shapes at the same peak.
+const SQUARE_LEVEL: f32 = 0.4;
+
+/// Identifies a shared wave table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WaveTableKey {
+    /// Shape of the table.
+    pub shape: Oscillator,
+    /// Number of samples in one cycle.
+    pub size: usize,
+    /// Highest harmonic in the table, or `FULL_BAND` for all of them.
+    pub band: u32,
+}
+
+/// The tables of one shape and size at every octave band, shared between oscillators like a table.
+#[derive(Clone, Debug)]
+pub struct OctaveTables {
+    /// The tables for the bands `1, 2, 4 …` up to the highest band, then the full band.
+    tables: Bands,
+}
+
+impl OctaveTables {
+    /// Returns the tables of `shape` with `size` samples, generating the ones no oscillator has asked
+    /// for before.
+    pub fn new(shape: Oscillator, size: usize) -> Self {
+        let mut octaves = OCTAVE_TABLES.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+        let tables = octaves.entry((shape, size)).or_insert_with(|| {
+            let bands = (0..=highest_band(size).trailing_zeros()).map(|octave| 1 << octave).chain([FULL_BAND]);
+            bands.map(|band| wave_table(WaveTableKey { shape, size, band })).collect()
+        });
+        Self { tables: Arc::clone(tables) }
+    }
+
+    /// Returns the table for `band`, which should come from `octave_band`. Does not allocate.
+    pub fn get(&self, band: u32) -> &Arc<[f32]> {
+        match band {
+            FULL_BAND => &self.tables[self.tables.len() - 1],
+            band => &self.tables[(band.ilog2() as usize).min(self.tables.len() - 2)],
+        }
+    }
+}
+
+/// Returns the band of `OctaveTables` to play a note at `frequency` Hz from: the highest power of two
+/// whose harmonics all stay under the Nyquist frequency, or `FULL_BAND` for notes low enough to have
+/// room for more than twice the highest band.
+pub fn octave_band(frequency: f32, sample_rate: u32, size: usize) -> u32 {
+    let harmonics = sample_rate as f32 / 2.0 / frequency;
+    // Silent oscillators have infinite room, or none that is a number
+    if harmonics.is_nan() || harmonics >= 2.0 * highest_band(size) as f32 {
+        return FULL_BAND;
+    }
+    1 << (harmonics as u32).max(1).ilog2()
+}
+
+/// Highest band of the octave tables of `size` samples, which cannot hold more than `size / 2`.
+fn highest_band(size: usize) -> u32 {
+    let room = (size / 2).max(1) as u32;
+    MAX_BAND.min(1 << room.ilog2())
+}
+
+type Cache<K, V> = OnceLock<Mutex<HashMap<K, V>>>;
+/// A table for each band, in the order of `OctaveTables`.
+type Bands = Arc<[Arc<[f32]>]>;
+
+static WAVE_TABLES: Cache<WaveTableKey, Arc<[f32]>> = OnceLock::new();
+static OCTAVE_TABLES: Cache<(Oscillator, usize), Bands> = OnceLock::new();
+
+/// Returns the table for `key`, generating it if no oscillator has asked for it before.
+///
+/// Generating a table allocates and takes time in proportion to its size, and to its band when it is
+/// band-limited, without working out a sine for every harmonic. Tables that are already cached are returned without allocating.
+pub fn wave_table(key: WaveTableKey) -> Arc<[f32]> {
+    let key = normalize(key);
+    let mut tables = WAVE_TABLES.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+    tables.entry(key).or_insert_with(|| generate(key)).clone()
+}
+
+/// Returns how many tables have been generated so far.
+pub fn cached_wave_tables() -> usize {
+    WAVE_TABLES.get().map_or(0, |tables| tables.lock().unwrap().len())
+}
+
+/// Maps keys that describe the same table onto one, so that they share it.
+fn normalize(key: WaveTableKey) -> WaveTableKey {
+    match key.shape {
+        // A sine has one harmonic, and white noise is not played from a table
+        Oscillator::Sine | Oscillator::WhiteNoise => WaveTableKey { band: FULL_BAND, ..key },
+        _ => key,
+    }
+}
+
+fn generate(key: WaveTableKey) -> Arc<[f32]> {
+    let size = key.size;
+    let table: Vec<f32> = match (key.shape, key.band) {
+        (Oscillator::WhiteNoise, _) => Vec::new(),
+        (Oscillator::Sine, _) => (0..size).map(|i| (2.0 * PI * i as f32 / size as f32).sin()).collect(),
+        (Oscillator::Square, FULL_BAND) => (0..size).map(|i| if i < size / 2 { SQUARE_LEVEL } else { 0.0 }).collect(),
+        (Oscillator::BidirectionalSquare, FULL_BAND) => (0..size).map(|i| if i < size / 2 { SQUARE_LEVEL } else { -SQUARE_LEVEL }).collect(),
+        (Oscillator::Saw, FULL_BAND) => (1..=size).map(|i| (size - i) as f32 / size as f32 * 2.0 - 1.0).collect(),
+        (Oscillator::Triangle, FULL_BAND) => (0..size).map(|i| {
+            let x = i as f32 / size as f32;
+            if i < size / 2 { x * 4.0 - 1.0 } else { 3.0 - x * 4.0 }
+        }).collect(),
+        (shape, band) => {
+            // Every harmonic is read from one cycle of a sine and a cosine, so that a table costs
+            // `band` additions per sample rather than `band` sines
+            let sine: Vec<f32> = (0..size).map(|i| (2.0 * PI * i as f32 / size as f32).sin()).collect();
+            let cosine: Vec<f32> = (0..size).map(|i| (2.0 * PI * i as f32 / size as f32).cos()).collect();
+            (0..size).map(|i| harmonics(shape, band, &sine, &cosine, i)).collect()
+        },
+    };
+    table.into()
+}
+
+/// Sums the Fourier series of `shape` up to harmonic `band`, at sample `i` of a table as long as
+/// `sine` and `cosine`, which hold one cycle each.
+fn harmonics(shape: Oscillator, band: u32, sine: &[f32], cosine: &[f32], i: usize) -> f32 {
+    // Harmonic `k` at sample `i` is the fundamental at sample `k * i`, wrapped to one cycle
+    let at = |k: u32| (k as u64 * i as u64 % sine.len() as u64) as usize;
+    let sines = |step: usize| (1..=band).step_by(step).map(|k| sine[at(k)] / k as f32).sum::<f32>();
+    match shape {
+        Oscillator::Saw => 2.0 / PI * sines(1),
+        Oscillator::BidirectionalSquare => SQUARE_LEVEL * 4.0 / PI * sines(2),
+        Oscillator::Square => SQUARE_LEVEL * (0.5 + 2.0 / PI * sines(2)),
+        Oscillator::Triangle => -8.0 / (PI * PI) * (1..=band).step_by(2).map(|k| cosine[at(k)] / (k * k) as f32).sum::<f32>(),
+        Oscillator::Sine => sine[i],
+        Oscillator::WhiteNoise => 0.0,
+    }
+}
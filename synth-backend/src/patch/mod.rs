@@ -0,0 +1,222 @@
+//! Patch
+//!
+//! This module defines `Patch`, the serializable description of a sound that can be saved and loaded
+//! again later. Besides the settings of the engine, a patch describes the voice its notes are played
+//! with, which `Patch::voice` builds.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::effects::{EffectsChain, EffectType};
+//! use synth_backend::patch::Patch;
+//!
+//! let mut chain = EffectsChain::new(44100);
+//! chain.push(EffectType::Gain);
+//!
+//! // Store the effects chain in a patch and convert it to JSON
+//! let patch = Patch { effects: chain.state(), ..Default::default() };
+//! let json = patch.to_json().unwrap();
+//!
+//! // Restore it again
+//! let loaded = Patch::from_json(&json).unwrap();
+//! chain.load_state(&loaded.effects).unwrap();
+//!
+//! // Build the voice the patch describes
+//! let voice = loaded.voice(44100).unwrap();
+//! assert_eq!(voice.num_sources(), 1);
+//! ```
+use serde::{Deserialize, Serialize};
+use crate::effects::EffectSlotState;
+use crate::velocity::VelocitySettings;
+use crate::controllers::ControllerSettings;
+use crate::voice_mode::VoiceModeSettings;
+use crate::arpeggiator::ArpeggiatorSettings;
+use crate::mpe::MpeSettings;
+use crate::oscillators::{MultiOscillator, Oscillator, WaveTableOscillator, DEFAULT_TABLE_SIZE};
+use crate::filters::{Filter, FilterType};
+use crate::distortion::DistortionType;
+use crate::envelopes::Envelope;
+use crate::lfo::LFOType;
+use crate::wrapper::Synth;
+
+/// Serializable description of a sound.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Patch {
+    /// The master effects chain, in processing order.
+    #[serde(default)]
+    pub effects: Vec<EffectSlotState>,
+    /// How the velocity of a note changes the voice.
+    #[serde(default)]
+    pub velocity: VelocitySettings,
+    /// The pitch bend range and what the mod wheel controls.
+    #[serde(default)]
+    pub controllers: ControllerSettings,
+    /// The voice mode and portamento.
+    #[serde(default)]
+    pub voice_mode: VoiceModeSettings,
+    /// The arpeggiator.
+    #[serde(default)]
+    pub arpeggiator: ArpeggiatorSettings,
+    /// The MPE zone and what pressure and timbre control.
+    #[serde(default)]
+    pub mpe: MpeSettings,
+    /// The voice notes are played with.
+    #[serde(default)]
+    pub voice: VoiceSettings,
+}
+
+/// One oscillator of a voice.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscillatorSettings {
+    pub shape: Oscillator,
+    pub gain: f32,
+    /// Detune from the note, in semitones.
+    pub detune_semitones: i8,
+}
+
+impl Default for OscillatorSettings {
+    fn default() -> Self {
+        Self { shape: Oscillator::Saw, gain: 0.1, detune_semitones: 0 }
+    }
+}
+
+/// The amplitude envelope of a voice.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvelopeSettings {
+    pub attack_ms: f32,
+    pub decay_ms: f32,
+    /// Sustain level between 0.0 and 1.0.
+    pub sustain: f32,
+    pub release_ms: f32,
+}
+
+impl Default for EnvelopeSettings {
+    fn default() -> Self {
+        Self { attack_ms: 10.0, decay_ms: 200.0, sustain: 0.7, release_ms: 300.0 }
+    }
+}
+
+/// The filter of a voice.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterSettings {
+    pub filter_type: FilterType,
+    /// Cutoff, or center frequency of a band-pass filter, in Hz.
+    pub freq_hz: f32,
+    /// Bandwidth of a band-pass filter, in Hz.
+    pub bandwidth_hz: f32,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self { filter_type: FilterType::LowPass, freq_hz: 2000.0, bandwidth_hz: 500.0 }
+    }
+}
+
+/// The low-frequency oscillator of a voice.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LfoSettings {
+    pub shape: Oscillator,
+    pub frequency_hz: f32,
+    /// Whether the LFO modulates the amplitude or the frequency of the voice.
+    pub lfo_type: LFOType,
+}
+
+impl Default for LfoSettings {
+    fn default() -> Self {
+        Self { shape: Oscillator::Sine, frequency_hz: 5.0, lfo_type: LFOType::Amplitude }
+    }
+}
+
+/// Serializable description of a voice. The default is a single saw with a short envelope.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceSettings {
+    /// The oscillators that are mixed, at least one.
+    pub oscillators: Vec<OscillatorSettings>,
+    pub envelope: Option<EnvelopeSettings>,
+    pub filter: Option<FilterSettings>,
+    /// The distortion in front of the filter.
+    pub distortion: Option<DistortionType>,
+    pub lfo: Option<LfoSettings>,
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self {
+            oscillators: vec![OscillatorSettings::default()],
+            envelope: Some(EnvelopeSettings::default()),
+            filter: None,
+            distortion: None,
+            lfo: None,
+        }
+    }
+}
+
+impl VoiceSettings {
+    /// Builds the voice at `sample_rate`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no oscillators, or if an oscillator cannot be added or detuned.
+    pub fn build(&self, sample_rate: u32) -> Result<Synth, String> {
+        let (first, rest) = self.oscillators.split_first().ok_or("A voice needs at least one oscillator")?;
+        let oscillator = |settings: &OscillatorSettings| {
+            WaveTableOscillator::new(sample_rate, DEFAULT_TABLE_SIZE, settings.shape, settings.gain, 0.0)
+        };
+        let mut osc = MultiOscillator::from(oscillator(first));
+        for settings in rest {
+            osc.push(oscillator(settings))?;
+        }
+        let filter = self.filter.as_ref().map(|filter| {
+            Filter::new(filter.filter_type.clone(), sample_rate as f32, filter.freq_hz, filter.bandwidth_hz)
+        });
+        let envelope = self.envelope.map(|envelope| {
+            Envelope::new(sample_rate as f32, envelope.attack_ms, envelope.decay_ms, envelope.sustain, envelope.release_ms)
+        });
+        let lfo_type = self.lfo.as_ref().map_or(LFOType::Amplitude, |lfo| lfo.lfo_type.clone());
+        let mut voice = Synth::new(osc, sample_rate, filter, envelope, None, lfo_type);
+        for (index, settings) in self.oscillators.iter().enumerate() {
+            voice.set_detune_semitones(index, settings.detune_semitones)?;
+        }
+        voice.set_distortion(self.distortion);
+        if let Some(lfo) = &self.lfo {
+            voice.set_lfo_osc(Some(lfo.shape), lfo.frequency_hz, lfo.lfo_type.clone());
+        }
+        Ok(voice)
+    }
+}
+
+impl Patch {
+    /// Converts the patch to a JSON string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the patch cannot be serialized.
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|err| err.to_string())
+    }
+
+    /// Reads a patch from a JSON string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the string is not a valid patch.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|err| err.to_string())
+    }
+
+    /// Builds the voice of the patch at `sample_rate`, responding to velocity as the patch does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the voice settings cannot be built.
+    pub fn voice(&self, sample_rate: u32) -> Result<Synth, String> {
+        let mut voice = self.voice.build(sample_rate)?;
+        voice.velocity_settings = self.velocity;
+        Ok(voice)
+    }
+}
@@ -0,0 +1,433 @@
+//! Step sequencer
+//!
+//! This module plays patterns of sixteenth note steps. Every step has its own note, velocity, gate
+//! length and probability, and can be tied to the step before it so that the note keeps sounding.
+//! Patterns have 16 or 32 steps and can be chained to play one after another.
+//!
+//! Like the arpeggiator, the sequencer is moved on once per sample by `advance` and produces
+//! `NoteEvent`s that start and stop the same voices the keyboard plays.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::sequencer::{Sequencer, Step};
+//!
+//! let mut sequencer = Sequencer::new(44100);
+//!
+//! // Four on the floor
+//! for step in [0, 4, 8, 12] {
+//!     sequencer.set_step(0, step, Step { enabled: true, note: 36, ..Default::default() }).unwrap();
+//! }
+//! sequencer.set_bpm(128.0);
+//! sequencer.play();
+//!
+//! sequencer.advance();
+//! let event = sequencer.pop_event();
+//! ```
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use crate::arpeggiator::{NoteEvent, MAX_SWING};
+use crate::transport::{DEFAULT_BPM, MAX_BPM, MIN_BPM};
+use crate::lifecycle::Prepare;
+
+/// Step lengths a pattern can have.
+pub const PATTERN_LENGTHS: [usize; 2] = [16, 32];
+/// Length of a step in beats.
+const STEP_BEATS: f64 = 0.25;
+
+/// One step of a pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Step {
+    /// Whether the step plays a note.
+    pub enabled: bool,
+    /// The MIDI key of the note.
+    pub note: u8,
+    /// The velocity of the note, between 0.0 and 1.0.
+    pub velocity: f32,
+    /// How much of the step the note is held for, between 0.05 and 1.0.
+    pub gate: f32,
+    /// Keep the note of the step before sounding instead of playing a new one.
+    pub tie: bool,
+    /// The chance that the step plays, between 0.0 and 1.0.
+    pub probability: f32,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            note: 60,
+            velocity: 100.0 / 127.0,
+            gate: 0.5,
+            tie: false,
+            probability: 1.0,
+        }
+    }
+}
+
+/// A pattern of 16 or 32 steps.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pattern {
+    steps: Vec<Step>,
+}
+
+impl Default for Pattern {
+    fn default() -> Self {
+        Self { steps: vec![Step::default(); PATTERN_LENGTHS[0]] }
+    }
+}
+
+impl Pattern {
+    /// Creates an empty pattern.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `length` is not one of `PATTERN_LENGTHS`.
+    pub fn new(length: usize) -> Result<Self, String> {
+        check_length(length)?;
+        Ok(Self { steps: vec![Step::default(); length] })
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Changes the number of steps. Steps added at the end are empty, steps cut off are lost.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `length` is not one of `PATTERN_LENGTHS`.
+    pub fn set_len(&mut self, length: usize) -> Result<(), String> {
+        check_length(length)?;
+        self.steps.resize(length, Step::default());
+        Ok(())
+    }
+}
+
+fn check_length(length: usize) -> Result<(), String> {
+    if PATTERN_LENGTHS.contains(&length) {
+        Ok(())
+    } else {
+        Err(format!("A pattern has 16 or 32 steps, not {length}!"))
+    }
+}
+
+/// Sample-timed step sequencer with its own transport.
+#[derive(Clone, Debug)]
+pub struct Sequencer {
+    sample_rate: u32,
+    patterns: Vec<Pattern>,
+    chain: Vec<usize>,
+    chain_position: usize,
+    pattern: usize,
+    step: usize,
+    steps_played: usize,
+    playing: bool,
+    started: bool,
+    bpm: f32,
+    swing: f32,
+    position: f64,
+    step_samples: f64,
+    gate_samples: f64,
+    sounding: Option<u8>,
+    events: VecDeque<NoteEvent>,
+    random_state: u32,
+}
+
+impl Sequencer {
+    /// Creates a stopped `Sequencer` with a single empty pattern of 16 steps at `DEFAULT_BPM`.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            patterns: vec![Pattern::default()],
+            chain: Vec::new(),
+            chain_position: 0,
+            pattern: 0,
+            step: 0,
+            steps_played: 0,
+            playing: false,
+            started: false,
+            bpm: DEFAULT_BPM,
+            swing: 0.0,
+            position: 0.0,
+            step_samples: 0.0,
+            gate_samples: 0.0,
+            sounding: None,
+            events: VecDeque::with_capacity(16),
+            random_state: 1,
+        }
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    /// Adds an empty pattern and returns its index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `length` is not one of `PATTERN_LENGTHS`.
+    pub fn add_pattern(&mut self, length: usize) -> Result<usize, String> {
+        self.patterns.push(Pattern::new(length)?);
+        Ok(self.patterns.len() - 1)
+    }
+
+    /// Removes a pattern, and every place it has in the chain. The last pattern cannot be removed.
+    pub fn remove_pattern(&mut self, index: usize) -> Result<Pattern, String> {
+        if index >= self.patterns.len() {
+            return Err("Pattern index out of range!".to_owned());
+        }
+        if self.patterns.len() == 1 {
+            return Err("The sequencer needs at least one pattern!".to_owned());
+        }
+        self.chain.retain(|pattern| *pattern != index);
+        self.chain.iter_mut().filter(|pattern| **pattern > index).for_each(|pattern| *pattern -= 1);
+        self.chain_position = 0;
+        if self.pattern >= index && self.pattern > 0 {
+            self.pattern -= 1;
+        }
+        self.step = 0;
+        Ok(self.patterns.remove(index))
+    }
+
+    /// Changes the number of steps of a pattern.
+    pub fn set_pattern_len(&mut self, index: usize, length: usize) -> Result<(), String> {
+        self.patterns.get_mut(index).ok_or("Pattern index out of range!")?.set_len(length)
+    }
+
+    /// Sets one step of a pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The index of the pattern.
+    /// * `step` - The index of the step in the pattern.
+    /// * `value` - The new step. Its values are clamped to their ranges.
+    pub fn set_step(&mut self, pattern: usize, step: usize, value: Step) -> Result<(), String> {
+        let pattern = self.patterns.get_mut(pattern).ok_or("Pattern index out of range!")?;
+        let step = pattern.steps.get_mut(step).ok_or("Step index out of range!")?;
+        *step = Step {
+            note: value.note.min(127),
+            velocity: value.velocity.clamp(0.0, 1.0),
+            gate: value.gate.clamp(0.05, 1.0),
+            probability: value.probability.clamp(0.0, 1.0),
+            ..value
+        };
+        Ok(())
+    }
+
+    /// Returns the indices of the patterns played one after another. An empty chain loops the
+    /// pattern that is playing.
+    pub fn chain(&self) -> &[usize] {
+        &self.chain
+    }
+
+    /// Sets the order the patterns are played in.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chain names a pattern that does not exist.
+    pub fn set_chain(&mut self, chain: Vec<usize>) -> Result<(), String> {
+        if chain.iter().any(|pattern| *pattern >= self.patterns.len()) {
+            return Err("Pattern index out of range!".to_owned());
+        }
+        self.chain = chain;
+        self.chain_position = 0;
+        Ok(())
+    }
+
+    /// Returns the index of the pattern that is playing, or plays first.
+    pub fn current_pattern(&self) -> usize {
+        self.pattern
+    }
+
+    /// Selects the pattern that plays when the chain is empty.
+    pub fn select_pattern(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.patterns.len() {
+            return Err("Pattern index out of range!".to_owned());
+        }
+        self.pattern = index;
+        self.step = 0;
+        Ok(())
+    }
+
+    /// Returns the step that is playing, if the sequencer is playing.
+    pub fn current_step(&self) -> Option<usize> {
+        if self.playing && self.started {
+            Some(self.step)
+        } else {
+            None
+        }
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    /// Sets the tempo in beats per minute.
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+    }
+
+    pub fn swing(&self) -> f32 {
+        self.swing
+    }
+
+    /// Sets how much every first step of a pair is lengthened at the cost of the second, from 0.0
+    /// to `MAX_SWING`.
+    pub fn set_swing(&mut self, swing: f32) {
+        self.swing = swing.clamp(0.0, MAX_SWING);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Starts playing from the first step of the chain, or of the selected pattern.
+    pub fn play(&mut self) {
+        self.release();
+        self.playing = true;
+        self.started = false;
+        self.chain_position = 0;
+        if let Some(pattern) = self.chain.first() {
+            self.pattern = *pattern;
+        }
+        self.step = 0;
+        self.steps_played = 0;
+        self.random_state = 1;
+    }
+
+    /// Starts playing from the step at `beats` beats into the chain, or into the selected pattern,
+    /// so that the sequencer can join a transport that is already running.
+    pub fn play_from(&mut self, beats: f64) {
+        self.play();
+        let steps = (beats.max(0.0) / STEP_BEATS) as usize;
+        let cycle = match self.chain.is_empty() {
+            true => self.patterns[self.pattern].len(),
+            false => self.chain.iter().map(|pattern| self.patterns[*pattern].len()).sum(),
+        };
+        for _ in 0..steps % cycle {
+            self.next_step();
+        }
+        self.steps_played = steps;
+    }
+
+    /// Stops playing and releases the note that is sounding.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.started = false;
+        self.release();
+    }
+
+    /// Moves the sequencer on by one sample. The notes started and stopped at this sample can then
+    /// be taken with `pop_event`.
+    pub fn advance(&mut self) {
+        if !self.playing {
+            return;
+        }
+        if !self.started {
+            self.started = true;
+            self.position = 0.0;
+            self.trigger_step();
+            return;
+        }
+        self.position += 1.0;
+        if self.position >= self.step_samples {
+            self.position -= self.step_samples;
+            self.next_step();
+            self.trigger_step();
+        } else if self.position >= self.gate_samples && !self.next_is_tie() {
+            self.release();
+        }
+    }
+
+    /// Takes the next note started or stopped by `advance`.
+    pub fn pop_event(&mut self) -> Option<NoteEvent> {
+        self.events.pop_front()
+    }
+
+    fn next_step(&mut self) {
+        self.steps_played += 1;
+        self.step += 1;
+        if self.step >= self.patterns[self.pattern].len() {
+            self.step = 0;
+            if !self.chain.is_empty() {
+                self.chain_position = (self.chain_position + 1) % self.chain.len();
+                self.pattern = self.chain[self.chain_position];
+            }
+        }
+    }
+
+    /// Returns whether the step after the one playing is tied to it.
+    fn next_is_tie(&self) -> bool {
+        let steps = &self.patterns[self.pattern].steps;
+        let next = if self.step + 1 < steps.len() {
+            steps[self.step + 1]
+        } else {
+            let pattern = match self.chain.is_empty() {
+                true => self.pattern,
+                false => self.chain[(self.chain_position + 1) % self.chain.len()],
+            };
+            self.patterns[pattern].steps[0]
+        };
+        next.enabled && next.tie
+    }
+
+    fn trigger_step(&mut self) {
+        let beat_samples = 60.0 / self.bpm as f64 * self.sample_rate as f64;
+        let swing = self.swing as f64;
+        let swing = if self.steps_played.is_multiple_of(2) { 1.0 + swing } else { 1.0 - swing };
+        self.step_samples = (beat_samples * STEP_BEATS * swing).max(1.0);
+
+        let step = self.patterns[self.pattern].steps[self.step];
+        self.gate_samples = if step.gate >= 1.0 {
+            f64::INFINITY
+        } else {
+            self.step_samples * step.gate as f64
+        };
+        if step.enabled && step.tie && self.sounding.is_some() {
+            return;
+        }
+        self.release();
+        if step.enabled && self.roll() < step.probability {
+            self.sounding = Some(step.note);
+            self.events.push_back(NoteEvent::NoteOn(step.note, step.velocity));
+        }
+    }
+
+    fn release(&mut self) {
+        if let Some(note) = self.sounding.take() {
+            self.events.push_back(NoteEvent::NoteOff(note));
+        }
+    }
+
+    /// Returns a number between 0.0 and 1.0 from a xorshift generator, so that probabilities play
+    /// out the same every time the sequencer starts.
+    fn roll(&mut self) -> f32 {
+        let mut state = self.random_state;
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        self.random_state = state;
+        (state >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+impl Prepare for Sequencer {
+    /// Keeps the tempo and the place in the current step, so the pattern stays in time.
+    fn prepare(&mut self, sample_rate: u32, _max_block_size: usize) {
+        let ratio = sample_rate as f64 / self.sample_rate as f64;
+        self.sample_rate = sample_rate;
+        self.position *= ratio;
+        self.step_samples *= ratio;
+        self.gate_samples *= ratio;
+    }
+}
@@ -0,0 +1,349 @@
+//! Transport
+//!
+//! This module keeps the musical time of the synthesizer: the tempo, the time signature, whether the
+//! song is playing and the song position. The position is kept in beats, where a beat is a quarter
+//! note, and is moved on sample by sample so that everything synced to it stays sample accurate.
+//!
+//! The clock either runs on its own, follows MIDI clock messages (24 per beat, with start, stop,
+//! continue and song position pointer), or follows the tempo and position reported by a host.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::transport::{Position, TimeSignature, Transport};
+//!
+//! let mut transport = Transport::new(48000);
+//! transport.set_bpm(90.0);
+//! transport.set_time_signature(TimeSignature::new(3, 4).unwrap());
+//! transport.play();
+//!
+//! // One bar of 3/4 at 90 BPM lasts two seconds
+//! for _ in 0..96000 {
+//!     transport.advance();
+//! }
+//! assert_eq!(transport.position(), Position { bar: 2, beat: 1, tick: 0 });
+//! ```
+use serde::{Deserialize, Serialize};
+use crate::lifecycle::Prepare;
+
+/// Tempo the transport runs at until it is set.
+pub const DEFAULT_BPM: f32 = 120.0;
+/// Resolution of the song position, in ticks per beat.
+pub const TICKS_PER_BEAT: u32 = 960;
+/// Number of MIDI clock messages sent per beat.
+pub const MIDI_CLOCKS_PER_BEAT: u32 = 24;
+/// Slowest tempo, in beats per minute.
+pub const MIN_BPM: f32 = 20.0;
+/// Fastest tempo, in beats per minute.
+pub const MAX_BPM: f32 = 300.0;
+/// How quickly the tempo measured from MIDI clock follows changes, between 0.0 and 1.0.
+const CLOCK_SMOOTHING: f64 = 0.1;
+
+/// Where the transport takes its tempo and position from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClockSource {
+    /// The transport runs on its own tempo.
+    #[default]
+    Internal,
+    /// The transport follows MIDI clock, start, stop and continue messages.
+    MidiClock,
+    /// The transport follows the tempo and position reported by a host.
+    Host,
+}
+
+impl ClockSource {
+    /// All clock sources, in the order they are offered in the user interface.
+    pub const ALL: [ClockSource; 3] = [ClockSource::Internal, ClockSource::MidiClock, ClockSource::Host];
+
+    /// Returns the display name of the clock source.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClockSource::Internal => "Internal",
+            ClockSource::MidiClock => "MIDI",
+            ClockSource::Host => "Host",
+        }
+    }
+}
+
+/// A time signature, such as 4/4 or 6/8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSignature {
+    /// The number of beats in a bar.
+    pub numerator: u8,
+    /// The note value of a beat, as a power of two: 4 is a quarter note, 8 an eighth note.
+    pub denominator: u8,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self { numerator: 4, denominator: 4 }
+    }
+}
+
+impl TimeSignature {
+    /// Creates a time signature.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the numerator is not between 1 and 32, or if the denominator is not a
+    /// power of two between 1 and 32.
+    pub fn new(numerator: u8, denominator: u8) -> Result<Self, String> {
+        if !(1..=32).contains(&numerator) {
+            return Err(format!("A bar has between 1 and 32 beats, not {numerator}!"));
+        }
+        if !(1..=32).contains(&denominator) || !denominator.is_power_of_two() {
+            return Err(format!("{denominator} is not a note value!"));
+        }
+        Ok(Self { numerator, denominator })
+    }
+
+    /// Returns the length of a beat of the time signature, in quarter notes.
+    pub fn beat_length(&self) -> f64 {
+        4.0 / self.denominator as f64
+    }
+
+    /// Returns the length of a bar, in quarter notes.
+    pub fn bar_length(&self) -> f64 {
+        self.numerator as f64 * self.beat_length()
+    }
+}
+
+/// A song position in bars, beats of the time signature and ticks. Bars and beats count from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub bar: u32,
+    pub beat: u32,
+    pub tick: u32,
+}
+
+/// Sample-accurate tempo clock and song position.
+#[derive(Clone, Debug)]
+pub struct Transport {
+    sample_rate: u32,
+    bpm: f32,
+    time_signature: TimeSignature,
+    source: ClockSource,
+    playing: bool,
+    position: f64,
+    clocks: u64,
+    samples_since_clock: Option<u32>,
+    clock_interval: Option<f64>,
+    waiting_for_clock: bool,
+}
+
+impl Transport {
+    /// Creates a stopped `Transport` at the start of the song, running on its own at `DEFAULT_BPM`.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            bpm: DEFAULT_BPM,
+            time_signature: TimeSignature::default(),
+            source: ClockSource::Internal,
+            playing: false,
+            position: 0.0,
+            clocks: 0,
+            samples_since_clock: None,
+            clock_interval: None,
+            waiting_for_clock: false,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    /// Sets the tempo in beats per minute. While following MIDI clock the tempo is measured from
+    /// the clock instead.
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    /// Sets where the tempo and position come from. Changing the source stops the transport.
+    pub fn set_source(&mut self, source: ClockSource) {
+        if source != self.source {
+            self.stop();
+            self.samples_since_clock = None;
+            self.clock_interval = None;
+        }
+        self.source = source;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Starts playing from the current position. Does nothing when following an external clock,
+    /// which starts and stops the transport itself.
+    pub fn play(&mut self) {
+        if self.source == ClockSource::Internal {
+            self.playing = true;
+        }
+    }
+
+    /// Stops playing, keeping the position.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.waiting_for_clock = false;
+    }
+
+    /// Returns the song position in beats.
+    pub fn position_beats(&self) -> f64 {
+        self.position
+    }
+
+    /// Moves the song position to `beats`.
+    pub fn set_position_beats(&mut self, beats: f64) {
+        self.position = beats.max(0.0);
+        self.clocks = (self.position * MIDI_CLOCKS_PER_BEAT as f64) as u64;
+    }
+
+    /// Returns the song position in ticks of `TICKS_PER_BEAT`.
+    pub fn position_ticks(&self) -> u64 {
+        (self.position * TICKS_PER_BEAT as f64) as u64
+    }
+
+    /// Returns the song position in bars and beats of the time signature.
+    pub fn position(&self) -> Position {
+        let beat_length = self.time_signature.beat_length();
+        // Rounded to whole ticks first, so that a position a hair short of a beat reads as the beat
+        let ticks = (self.position / beat_length * TICKS_PER_BEAT as f64).round() as u64;
+        let beats = ticks / TICKS_PER_BEAT as u64;
+        Position {
+            bar: (beats / self.time_signature.numerator as u64) as u32 + 1,
+            beat: (beats % self.time_signature.numerator as u64) as u32 + 1,
+            tick: (ticks % TICKS_PER_BEAT as u64) as u32,
+        }
+    }
+
+    /// Returns the length of a beat in samples.
+    pub fn samples_per_beat(&self) -> f64 {
+        60.0 / self.bpm as f64 * self.sample_rate as f64
+    }
+
+    /// Returns the frequency in Hz of something that repeats every `beats` beats, such as an LFO
+    /// synced to a note division.
+    pub fn beats_to_hz(&self, beats: f64) -> f32 {
+        (self.bpm as f64 / 60.0 / beats) as f32
+    }
+
+    /// Returns the length of `beats` beats in milliseconds, such as a delay time synced to a note
+    /// division.
+    pub fn beats_to_ms(&self, beats: f64) -> f32 {
+        (60000.0 / self.bpm as f64 * beats) as f32
+    }
+
+    /// Moves the transport on by one sample.
+    pub fn advance(&mut self) {
+        if let Some(samples) = self.samples_since_clock.as_mut() {
+            *samples = samples.saturating_add(1);
+        }
+        if !self.playing {
+            return;
+        }
+        let step = 1.0 / self.samples_per_beat();
+        self.position = match self.source {
+            // Between two clocks the position runs at the measured tempo, up to where the next clock
+            // is due
+            ClockSource::MidiClock => {
+                let next_clock = (self.clocks + 1) as f64 / MIDI_CLOCKS_PER_BEAT as f64;
+                (self.position + step).min(next_clock)
+            },
+            ClockSource::Internal | ClockSource::Host => self.position + step,
+        };
+    }
+
+    /// Handles a MIDI timing clock message received at the current sample.
+    pub fn midi_clock(&mut self) {
+        if self.source != ClockSource::MidiClock {
+            return;
+        }
+        if let Some(samples) = self.samples_since_clock {
+            let interval = samples.max(1) as f64;
+            let interval = match self.clock_interval {
+                Some(previous) => previous + (interval - previous) * CLOCK_SMOOTHING,
+                None => interval,
+            };
+            self.clock_interval = Some(interval);
+            let bpm = 60.0 * self.sample_rate as f64 / (interval * MIDI_CLOCKS_PER_BEAT as f64);
+            self.bpm = (bpm as f32).clamp(MIN_BPM, MAX_BPM);
+        }
+        self.samples_since_clock = Some(0);
+
+        // After start or continue, playing begins on the next clock
+        if self.waiting_for_clock {
+            self.waiting_for_clock = false;
+            self.playing = true;
+        } else if self.playing {
+            self.clocks += 1;
+            self.position = self.clocks as f64 / MIDI_CLOCKS_PER_BEAT as f64;
+        }
+    }
+
+    /// Handles a MIDI start message: the song starts from the beginning on the next clock.
+    pub fn midi_start(&mut self) {
+        if self.source == ClockSource::MidiClock {
+            self.set_position_beats(0.0);
+            self.playing = false;
+            self.waiting_for_clock = true;
+        }
+    }
+
+    /// Handles a MIDI continue message: the song carries on from its position on the next clock.
+    pub fn midi_continue(&mut self) {
+        if self.source == ClockSource::MidiClock {
+            self.set_position_beats(self.clocks as f64 / MIDI_CLOCKS_PER_BEAT as f64);
+            self.playing = false;
+            self.waiting_for_clock = true;
+        }
+    }
+
+    /// Handles a MIDI stop message.
+    pub fn midi_stop(&mut self) {
+        if self.source == ClockSource::MidiClock {
+            self.stop();
+        }
+    }
+
+    /// Handles a MIDI song position pointer, given in sixteenth notes from the start of the song.
+    pub fn midi_song_position(&mut self, sixteenths: u16) {
+        if self.source == ClockSource::MidiClock {
+            self.set_position_beats(sixteenths as f64 / 4.0);
+        }
+    }
+
+    /// Takes the tempo, position and play state reported by a host, usually once per block.
+    pub fn set_host_time(&mut self, bpm: f32, position_beats: f64, playing: bool) {
+        if self.source == ClockSource::Host {
+            self.set_bpm(bpm);
+            self.set_position_beats(position_beats);
+            self.playing = playing;
+        }
+    }
+}
+
+impl Prepare for Transport {
+    /// Keeps the tempo and song position. The time since the last MIDI clock is measured again in
+    /// samples of the new rate, so the measured tempo does not jump.
+    fn prepare(&mut self, sample_rate: u32, _max_block_size: usize) {
+        let ratio = sample_rate as f64 / self.sample_rate as f64;
+        self.sample_rate = sample_rate;
+        self.samples_since_clock = self.samples_since_clock.map(|samples| (samples as f64 * ratio).round() as u32);
+        self.clock_interval = self.clock_interval.map(|interval| interval * ratio);
+    }
+}
@@ -0,0 +1,415 @@
+//! Tuning
+//!
+//! This module turns MIDI keys into frequencies. A `Tuning` is made of a `Scale`, which lists the
+//! pitches of one period of the scale in cents, and a `KeyboardMapping`, which says which scale
+//! degree each key plays and which key sounds at the reference pitch. Both can be read from the
+//! Scala file formats: `.scl` for scales and `.kbm` for keyboard mappings.
+//!
+//! The default tuning is 12-tone equal temperament with A4 (key 69) at 440 Hz.
+//!
+//! A tuning can also be changed key by key, in real time, by MIDI Tuning Standard messages.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::tuning::Tuning;
+//!
+//! // 5-limit just intonation on C
+//! let scl = "! just.scl
+//! 5-limit just intonation
+//!  12
+//! !
+//!  16/15
+//!  9/8
+//!  6/5
+//!  5/4
+//!  4/3
+//!  45/32
+//!  3/2
+//!  8/5
+//!  5/3
+//!  9/5
+//!  15/8
+//!  2/1
+//! ";
+//! let tuning = Tuning::from_scala(scl, None).unwrap();
+//! assert_eq!(tuning.description(), "5-limit just intonation");
+//!
+//! // 31-tone equal temperament
+//! let tuning = Tuning::equal_division(31, 440.0).unwrap();
+//! ```
+use std::fmt;
+
+pub mod mts;
+
+pub use mts::MtsMessage;
+
+/// Number of MIDI keys.
+const NUM_KEYS: usize = 128;
+/// Frequency of A4 in the default tuning.
+pub const DEFAULT_REFERENCE_HZ: f32 = 440.0;
+/// Key that sounds at the reference pitch in the default tuning.
+pub const DEFAULT_REFERENCE_KEY: u8 = 69;
+
+/// The pitches of one period of a scale, in cents above its first degree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scale {
+    description: String,
+    /// Cents of degrees 1 to n. The last one is the period of the scale, usually an octave.
+    cents: Vec<f64>,
+}
+
+impl Scale {
+    /// Creates an equal division of the octave into `divisions` steps.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `divisions` is 0.
+    pub fn equal_division(divisions: u32) -> Result<Self, String> {
+        if divisions == 0 {
+            return Err("An octave must be divided into at least one step!".to_owned());
+        }
+        Ok(Self {
+            description: format!("{divisions} equal divisions of the octave"),
+            cents: (1..=divisions).map(|step| 1200.0 * step as f64 / divisions as f64).collect(),
+        })
+    }
+
+    /// Reads a scale from the text of a Scala `.scl` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is not a valid scale.
+    pub fn from_scl(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+        let description = lines.next().ok_or("The scale file is empty!")?.trim().to_owned();
+        let count = lines.next()
+            .and_then(|line| line.split_whitespace().next())
+            .ok_or("The scale file does not give its number of notes!")?;
+        let count: usize = count.parse().map_err(|_| format!("'{count}' is not a number of notes!"))?;
+        if count == 0 {
+            return Err("A scale must have at least one note!".to_owned());
+        }
+        let cents = lines.take(count)
+            .map(|line| parse_pitch(line.split_whitespace().next().unwrap_or("")))
+            .collect::<Result<Vec<f64>, String>>()?;
+        if cents.len() < count {
+            return Err(format!("The scale file lists {} of its {count} notes!", cents.len()));
+        }
+        if cents[count - 1] <= 0.0 {
+            return Err("The period of a scale must be above its first degree!".to_owned());
+        }
+        Ok(Self { description, cents })
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Returns the number of degrees in a period.
+    pub fn len(&self) -> usize {
+        self.cents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cents.is_empty()
+    }
+
+    /// Returns the period of the scale in cents.
+    pub fn period_cents(&self) -> f64 {
+        self.cents[self.cents.len() - 1]
+    }
+
+    /// Returns the pitch of a degree in cents above degree 0. Degrees past the period repeat it.
+    pub fn degree_cents(&self, degree: i64) -> f64 {
+        let len = self.cents.len() as i64;
+        let periods = degree.div_euclid(len);
+        let step = degree.rem_euclid(len);
+        let cents = if step == 0 { 0.0 } else { self.cents[step as usize - 1] };
+        periods as f64 * self.period_cents() + cents
+    }
+}
+
+/// Reads a pitch of a `.scl` file: cents if it has a decimal point, otherwise a ratio or a whole
+/// number.
+fn parse_pitch(pitch: &str) -> Result<f64, String> {
+    let invalid = || format!("'{pitch}' is not a pitch!");
+    if pitch.contains('.') {
+        return pitch.parse::<f64>().map_err(|_| invalid());
+    }
+    let (numerator, denominator) = pitch.split_once('/').unwrap_or((pitch, "1"));
+    let numerator: f64 = numerator.parse::<u64>().map_err(|_| invalid())? as f64;
+    let denominator: f64 = denominator.parse::<u64>().map_err(|_| invalid())? as f64;
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return Err(invalid());
+    }
+    Ok(1200.0 * (numerator / denominator).log2())
+}
+
+/// Which scale degree each MIDI key plays, and which key sounds at the reference pitch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    /// The lowest key that is played.
+    pub first_key: u8,
+    /// The highest key that is played.
+    pub last_key: u8,
+    /// The key that plays degree 0 of the scale.
+    pub middle_key: u8,
+    /// The key that sounds at `reference_hz`.
+    pub reference_key: u8,
+    /// The frequency of the reference key in Hz.
+    pub reference_hz: f64,
+    /// The degree each key of a repeating pattern plays, `None` for keys that are not played. An
+    /// empty pattern maps each key to the next degree.
+    pub pattern: Vec<Option<i64>>,
+    /// How many degrees the pattern moves up every time it repeats. Zero means the number of
+    /// degrees in the scale.
+    pub period_degrees: i64,
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        Self {
+            first_key: 0,
+            last_key: 127,
+            middle_key: 60,
+            reference_key: DEFAULT_REFERENCE_KEY,
+            reference_hz: DEFAULT_REFERENCE_HZ as f64,
+            pattern: Vec::new(),
+            period_degrees: 0,
+        }
+    }
+}
+
+impl KeyboardMapping {
+    /// Reads a keyboard mapping from the text of a Scala `.kbm` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is not a valid keyboard mapping.
+    pub fn from_kbm(text: &str) -> Result<Self, String> {
+        let mut values = text.lines()
+            .filter(|line| !line.starts_with('!'))
+            .filter_map(|line| line.split_whitespace().next());
+        let mut next = |name: &str| values.next().ok_or(format!("The keyboard mapping does not give its {name}!"));
+        let size = parse_number::<usize>(next("size")?)?;
+        let first_key = parse_key(next("first key")?)?;
+        let last_key = parse_key(next("last key")?)?;
+        let middle_key = parse_key(next("middle key")?)?;
+        let reference_key = parse_key(next("reference key")?)?;
+        let reference_hz = parse_number::<f64>(next("reference frequency")?)?;
+        let period_degrees = parse_number::<i64>(next("octave degree")?)?;
+        let pattern = (0..size).map(|_| match next("mapping")? {
+            "x" | "X" => Ok(None),
+            degree => parse_number::<i64>(degree).map(Some),
+        }).collect::<Result<Vec<Option<i64>>, String>>()?;
+        if first_key > last_key {
+            return Err("The first key of the keyboard mapping is above its last key!".to_owned());
+        }
+        if reference_hz <= 0.0 {
+            return Err("The reference frequency must be above 0 Hz!".to_owned());
+        }
+        Ok(Self { first_key, last_key, middle_key, reference_key, reference_hz, pattern, period_degrees })
+    }
+
+    /// Returns the scale degree `key` plays, or `None` if the key is not played.
+    pub fn degree(&self, key: u8, scale_len: usize) -> Option<i64> {
+        if key < self.first_key || key > self.last_key {
+            return None;
+        }
+        let offset = key as i64 - self.middle_key as i64;
+        if self.pattern.is_empty() {
+            return Some(offset);
+        }
+        let len = self.pattern.len() as i64;
+        let period = if self.period_degrees == 0 { scale_len as i64 } else { self.period_degrees };
+        self.pattern[offset.rem_euclid(len) as usize].map(|degree| offset.div_euclid(len) * period + degree)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("'{value}' is not a valid number!"))
+}
+
+fn parse_key(value: &str) -> Result<u8, String> {
+    match parse_number::<u8>(value)? {
+        key if (key as usize) < NUM_KEYS => Ok(key),
+        key => Err(format!("{key} is not a MIDI key!")),
+    }
+}
+
+/// A scale and keyboard mapping, with the frequency of every MIDI key worked out in advance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    name: String,
+    scale: Scale,
+    mapping: KeyboardMapping,
+    frequencies: [Option<f32>; NUM_KEYS],
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::equal_division(12, DEFAULT_REFERENCE_HZ).unwrap()
+    }
+}
+
+impl fmt::Display for Tuning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (key {} = {:.2} Hz)", self.name, self.mapping.reference_key, self.mapping.reference_hz)
+    }
+}
+
+impl Tuning {
+    /// Creates a tuning from a scale and keyboard mapping.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reference key of the mapping does not play a scale degree.
+    pub fn new(name: &str, scale: Scale, mapping: KeyboardMapping) -> Result<Self, String> {
+        let reference_degree = mapping.degree(mapping.reference_key, scale.len())
+            .ok_or("The reference key of the keyboard mapping is not mapped to a scale degree!")?;
+        let reference_cents = scale.degree_cents(reference_degree);
+        let mut frequencies = [None; NUM_KEYS];
+        for (key, frequency) in frequencies.iter_mut().enumerate() {
+            *frequency = mapping.degree(key as u8, scale.len()).map(|degree| {
+                let cents = scale.degree_cents(degree) - reference_cents;
+                (mapping.reference_hz * f64::powf(2.0, cents / 1200.0)) as f32
+            });
+        }
+        Ok(Self { name: name.to_owned(), scale, mapping, frequencies })
+    }
+
+    /// Creates an equal division of the octave, with A4 at `reference_hz`. Use 12 divisions for the
+    /// usual tuning, or 19 and 31 for their meantone-like relatives.
+    pub fn equal_division(divisions: u32, reference_hz: f32) -> Result<Self, String> {
+        let mapping = KeyboardMapping { reference_hz: reference_hz as f64, ..Default::default() };
+        Self::new(&format!("{divisions}-EDO"), Scale::equal_division(divisions)?, mapping)
+    }
+
+    /// Creates 5-limit just intonation on C, with A4 at `reference_hz`.
+    pub fn just_intonation(reference_hz: f32) -> Result<Self, String> {
+        let ratios: [(u32, u32); 12] = [
+            (16, 15), (9, 8), (6, 5), (5, 4), (4, 3), (45, 32), (3, 2), (8, 5), (5, 3), (9, 5), (15, 8), (2, 1),
+        ];
+        let scale = Scale {
+            description: "5-limit just intonation".to_owned(),
+            cents: ratios.iter().map(|(numerator, denominator)| 1200.0 * (*numerator as f64 / *denominator as f64).log2()).collect(),
+        };
+        let mapping = KeyboardMapping { reference_hz: reference_hz as f64, ..Default::default() };
+        Self::new("Just intonation", scale, mapping)
+    }
+
+    /// Creates a tuning from the text of a Scala `.scl` file and, optionally, a `.kbm` file. Without
+    /// a keyboard mapping, key 60 plays the first degree of the scale and A4 keeps the reference
+    /// pitch.
+    pub fn from_scala(scl: &str, kbm: Option<&str>) -> Result<Self, String> {
+        let scale = Scale::from_scl(scl)?;
+        let mapping = match kbm {
+            Some(kbm) => KeyboardMapping::from_kbm(kbm)?,
+            None => KeyboardMapping::default(),
+        };
+        let name = scale.description().to_owned();
+        Self::new(&name, scale, mapping)
+    }
+
+    /// Returns the name of the tuning.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the description of the scale.
+    pub fn description(&self) -> &str {
+        self.scale.description()
+    }
+
+    pub fn scale(&self) -> &Scale {
+        &self.scale
+    }
+
+    pub fn mapping(&self) -> &KeyboardMapping {
+        &self.mapping
+    }
+
+    /// Returns the frequency of the reference key in Hz.
+    pub fn reference_hz(&self) -> f32 {
+        self.mapping.reference_hz as f32
+    }
+
+    /// Moves the whole tuning so that the reference key sounds at `reference_hz`.
+    pub fn set_reference_hz(&mut self, reference_hz: f32) -> Result<(), String> {
+        if reference_hz <= 0.0 {
+            return Err("The reference frequency must be above 0 Hz!".to_owned());
+        }
+        let ratio = reference_hz as f64 / self.mapping.reference_hz;
+        for frequency in self.frequencies.iter_mut().flatten() {
+            *frequency = (*frequency as f64 * ratio) as f32;
+        }
+        self.mapping.reference_hz = reference_hz as f64;
+        Ok(())
+    }
+
+    /// Returns the frequency of a MIDI key in Hz.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is out of range or not mapped to a scale degree.
+    pub fn frequency(&self, key: u8) -> Result<f32, String> {
+        match self.frequencies.get(key as usize) {
+            Some(Some(frequency)) => Ok(*frequency),
+            Some(None) => Err(format!("Key {key} is not mapped in the tuning {}!", self.name)),
+            None => Err("MIDI must range between 0-127".to_owned()),
+        }
+    }
+
+    /// Sets the frequency of a MIDI key in Hz, leaving the scale and keyboard mapping as they are.
+    pub fn set_frequency(&mut self, key: u8, frequency: f32) -> Result<(), String> {
+        if frequency <= 0.0 {
+            return Err("The frequency must be above 0 Hz!".to_owned());
+        }
+        match self.frequencies.get_mut(key as usize) {
+            Some(slot) => {
+                *slot = Some(frequency);
+                Ok(())
+            },
+            None => Err("MIDI must range between 0-127".to_owned()),
+        }
+    }
+
+    /// Retunes keys as a MIDI Tuning Standard message says. A bulk dump renames the tuning after
+    /// the dump, and a scale/octave tuning detunes every key from equal temperament at the
+    /// reference pitch.
+    pub fn apply_mts(&mut self, message: &MtsMessage) -> Result<(), String> {
+        match message {
+            MtsMessage::NoteChange { changes, .. } => {
+                for (key, frequency) in changes {
+                    self.set_frequency(*key, *frequency)?;
+                }
+            },
+            MtsMessage::BulkDump { name, frequencies, .. } => {
+                for (key, frequency) in frequencies.iter().enumerate() {
+                    if let Some(frequency) = frequency {
+                        self.set_frequency(key as u8, *frequency)?;
+                    }
+                }
+                if !name.is_empty() {
+                    self.name = name.clone();
+                }
+            },
+            MtsMessage::ScaleOctave { cents, .. } => {
+                for (key, frequency) in self.frequencies.iter_mut().enumerate() {
+                    let semitones = key as f64 - self.mapping.reference_key as f64 + cents[key % 12] as f64 / 100.0;
+                    *frequency = Some((self.mapping.reference_hz * f64::powf(2.0, semitones / 12.0)) as f32);
+                }
+                self.name = "Scale/octave tuning".to_owned();
+            },
+        }
+        Ok(())
+    }
+
+    /// Returns the pitch of a MIDI key as a fractional note number in 12-tone equal temperament,
+    /// so that intervals between keys can be measured in semitones.
+    pub fn pitch_semitones(&self, key: u8) -> Result<f32, String> {
+        let frequency = self.frequency(key)?;
+        Ok(DEFAULT_REFERENCE_KEY as f32 + 12.0 * (frequency / DEFAULT_REFERENCE_HZ).log2())
+    }
+}
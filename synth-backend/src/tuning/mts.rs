@@ -0,0 +1,122 @@
+//! MIDI Tuning Standard
+//!
+//! Reads the MIDI Tuning Standard system exclusive messages that retune keys: single note tuning
+//! changes, bulk tuning dumps and scale/octave tunings. The synthesizer holds one tuning, so the
+//! tuning program, bank and channel numbers in the messages are read but not used to pick a tuning.
+use super::{DEFAULT_REFERENCE_HZ, DEFAULT_REFERENCE_KEY, NUM_KEYS};
+
+/// Sub-ID of MIDI Tuning Standard messages.
+const MTS_SUB_ID: u8 = 0x08;
+/// Length of the name of a bulk tuning dump.
+const DUMP_NAME_LENGTH: usize = 16;
+
+/// A MIDI Tuning Standard message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MtsMessage {
+    /// Sets the frequency of some keys, leaving the others alone.
+    NoteChange {
+        /// The tuning program the change is for.
+        program: u8,
+        /// The keys to retune and their new frequencies in Hz.
+        changes: Vec<(u8, f32)>,
+    },
+    /// Sets the frequency of every key.
+    BulkDump {
+        /// The tuning program the dump is for.
+        program: u8,
+        /// The name of the tuning.
+        name: String,
+        /// The frequency of each key in Hz, or `None` for keys that keep their frequency.
+        frequencies: Vec<Option<f32>>,
+    },
+    /// Detunes each of the 12 notes of the octave from equal temperament, in every octave.
+    ScaleOctave {
+        /// The MIDI channels the tuning is for, one bit per channel.
+        channels: u16,
+        /// How far each note, from C to B, is from equal temperament in cents.
+        cents: [f32; 12],
+    },
+}
+
+impl MtsMessage {
+    /// Reads a system exclusive message, with or without its `0xF0` and `0xF7` framing bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not a MIDI Tuning Standard message the synthesizer
+    /// understands, is cut short, or fails its checksum.
+    pub fn parse(sysex: &[u8]) -> Result<Self, String> {
+        let sysex = sysex.strip_prefix(&[0xF0]).unwrap_or(sysex);
+        let sysex = sysex.strip_suffix(&[0xF7]).unwrap_or(sysex);
+        let (universal, body) = match sysex {
+            [universal @ (0x7E | 0x7F), _device, MTS_SUB_ID, body @ ..] => (*universal, body),
+            _ => return Err("Not a MIDI Tuning Standard message!".to_owned()),
+        };
+        match (universal, body) {
+            (_, [0x02, program, count, data @ ..]) | (_, [0x07, _, program, count, data @ ..]) => {
+                Self::note_change(*program, *count, data)
+            },
+            (0x7E, [0x01, program, data @ ..]) | (0x7E, [0x04, _, program, data @ ..]) => {
+                // The checksum is the exclusive or of every byte from the universal ID on
+                let (checksum, data) = data.split_last().ok_or("The bulk tuning dump is empty!")?;
+                let expected = sysex[..sysex.len() - 1].iter().fold(0, |checksum, byte| checksum ^ byte) & 0x7F;
+                if *checksum != expected {
+                    return Err("The checksum of the bulk tuning dump does not match!".to_owned());
+                }
+                Self::bulk_dump(*program, data)
+            },
+            (_, [0x08, channels @ ..]) if channels.len() == 3 + 12 => {
+                let mut cents = [0.0; 12];
+                for (cents, value) in cents.iter_mut().zip(&channels[3..]) {
+                    *cents = *value as f32 - 64.0;
+                }
+                Ok(Self::ScaleOctave { channels: channel_mask(&channels[..3]), cents })
+            },
+            (_, [0x09, channels @ ..]) if channels.len() == 3 + 24 => {
+                let mut cents = [0.0; 12];
+                for (cents, value) in cents.iter_mut().zip(channels[3..].chunks(2)) {
+                    let value = ((value[0] as u16) << 7) | value[1] as u16;
+                    *cents = (value as f32 - 8192.0) * 100.0 / 8192.0;
+                }
+                Ok(Self::ScaleOctave { channels: channel_mask(&channels[..3]), cents })
+            },
+            _ => Err("Unsupported MIDI Tuning Standard message!".to_owned()),
+        }
+    }
+
+    fn note_change(program: u8, count: u8, data: &[u8]) -> Result<Self, String> {
+        if data.len() != count as usize * 4 {
+            return Err(format!("The single note tuning change should retune {count} keys!"));
+        }
+        let changes = data.chunks(4)
+            .filter_map(|change| frequency(&change[1..]).map(|frequency| (change[0], frequency)))
+            .collect();
+        Ok(Self::NoteChange { program, changes })
+    }
+
+    fn bulk_dump(program: u8, data: &[u8]) -> Result<Self, String> {
+        if data.len() != DUMP_NAME_LENGTH + NUM_KEYS * 3 {
+            return Err("The bulk tuning dump should tune all 128 keys!".to_owned());
+        }
+        let (name, data) = data.split_at(DUMP_NAME_LENGTH);
+        let name = name.iter().map(|byte| *byte as char).collect::<String>().trim().to_owned();
+        let frequencies = data.chunks(3).map(frequency).collect();
+        Ok(Self::BulkDump { program, name, frequencies })
+    }
+}
+
+/// Reads a frequency as a key number and a 14 bit fraction of a semitone above it, in 12-tone equal
+/// temperament with A4 at 440 Hz. `7F 7F 7F` means no change.
+fn frequency(data: &[u8]) -> Option<f32> {
+    if data == [0x7F, 0x7F, 0x7F] {
+        return None;
+    }
+    let fraction = (((data[1] as u16) << 7) | data[2] as u16) as f32 / 16384.0;
+    let semitones = data[0] as f32 + fraction - DEFAULT_REFERENCE_KEY as f32;
+    Some(DEFAULT_REFERENCE_HZ * f32::powf(2.0, semitones / 12.0))
+}
+
+/// Reads the three bytes of a channel mask: channels 15 and 16, then 8 to 14, then 1 to 7.
+fn channel_mask(data: &[u8]) -> u16 {
+    ((data[0] as u16 & 0x03) << 14) | ((data[1] as u16 & 0x7F) << 7) | (data[2] as u16 & 0x7F)
+}
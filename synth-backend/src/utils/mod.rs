@@ -1,9 +1,10 @@
 //! Utility functions and types used in the backend of the synthesizer.
 use std::{collections::HashMap, ops::{Deref, DerefMut}};
-use cpal::{traits::{DeviceTrait, StreamTrait}, Data, FromSample, OutputCallbackInfo, SampleFormat, SizedSample, Stream, StreamConfig};
-use crate::ring_buffer::IterablePolyphonyHashMap;
+use cpal::{traits::{DeviceTrait, StreamTrait}, Data, FromSample, OutputCallbackInfo, SampleFormat, SizedSample, Stream, StreamConfig, StreamError};
+use crate::engine::IterablePolyphonyHashMap;
+use crate::audio::Dither;
+#[cfg(feature = "web")]
 use web_sys::console;
-use gloo::console::log;
 
 use std::sync::{Arc, Mutex};
 /// Converts MIDI note number to frequency in Hz.
@@ -97,15 +98,28 @@ pub fn decrease_octave(midi_map: &mut HashMap<char, u8>) {
 }
 
 /// Creates a single stream which runs once
+///
+/// # Panics
+///
+/// Panics if the stream cannot be created or started. Use `try_create_stream` to handle the error instead.
 pub fn create_stream(device: &cpal::Device, config: &cpal::StreamConfig, polyphony: Arc<Mutex<IterablePolyphonyHashMap>>) -> Stream {
+    try_create_stream(device, config, polyphony).unwrap()
+}
+
+/// Creates a single stream which runs once, playing 32-bit float samples in blocks of 1024 frames.
+///
+/// `audio::CpalBackend` plays in whatever format the device prefers instead.
+///
+/// # Errors
+///
+/// Returns an error if the device does not accept the stream or the stream cannot be started.
+pub fn try_create_stream(device: &cpal::Device, config: &cpal::StreamConfig, polyphony: Arc<Mutex<IterablePolyphonyHashMap>>) -> Result<Stream, String> {
     let channels: usize = config.channels as usize;
-    let err_fn = |err| console::error_1(&format!("A stream error ocurred: {}", err).into());
-    // let err_fn = |err| eprintln!("{err}");
     let mut next_value = {
         let poly = Arc::clone(&polyphony);
         move || {
-            let sample = poly.lock().unwrap().get_sample();
-            sample
+            let frame = poly.lock().unwrap().get_stereo_sample();
+            frame
         }
     };
 
@@ -127,20 +141,54 @@ pub fn create_stream(device: &cpal::Device, config: &cpal::StreamConfig, polypho
             move |data: &mut Data, _info: &OutputCallbackInfo| {
                 write_data(data, channels, &mut next_value);
             }, 
-            err_fn,
+            report_stream_error,
             None,
         )
-        .unwrap();
-    stream.play().unwrap();
-    stream
+        .map_err(|err| format!("The output stream could not be created: {err}"))?;
+    stream.play().map_err(|err| format!("The output stream could not be started: {err}"))?;
+    Ok(stream)
+}
+
+/// Reports an error that stopped a stream, on the browser console.
+#[cfg(feature = "web")]
+pub(crate) fn report_stream_error(err: StreamError) {
+    console::error_1(&format!("A stream error ocurred: {}", err).into());
+}
+
+/// Reports an error that stopped a stream, on the standard error.
+#[cfg(not(feature = "web"))]
+pub(crate) fn report_stream_error(err: StreamError) {
+    eprintln!("A stream error ocurred: {}", err);
 }
 
 /// Writes audio data to the output buffer.
-fn write_data(output: &mut Data, channels: usize, next_sample: &mut dyn FnMut() -> f32){
-    for frame in output.as_slice_mut() {
-        // log!(frame.len());
-        for sample in frame.iter_mut() {
-            *sample = next_sample();
+fn write_data(output: &mut Data, channels: usize, next_frame: &mut dyn FnMut() -> (f32, f32)){
+    if let Some(buffer) = output.as_slice_mut::<f32>() {
+        write_frames(buffer, channels, next_frame, &mut Dither::none());
+    }
+}
+
+/// Writes audio frames to an interleaved buffer of any sample format, adding `dither` before the
+/// samples are converted.
+///
+/// Mono outputs receive the mix of both channels, stereo outputs receive the left and right channels,
+/// and any further channels are left silent.
+pub(crate) fn write_frames<T: SizedSample + FromSample<f32>>(
+    buffer: &mut [T],
+    channels: usize,
+    next_frame: &mut dyn FnMut() -> (f32, f32),
+    dither: &mut Dither
+) {
+    for frame in buffer.chunks_mut(channels) {
+        let (left, right) = next_frame();
+        match frame {
+            [mono] => *mono = T::from_sample(dither.apply((left + right) * 0.5)),
+            [first, second, rest @ ..] => {
+                *first = T::from_sample(dither.apply(left));
+                *second = T::from_sample(dither.apply(right));
+                rest.fill(T::EQUILIBRIUM);
+            },
+            [] => ()
         }
     }
-}
\ No newline at end of file
+}
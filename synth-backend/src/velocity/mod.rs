@@ -0,0 +1,120 @@
+//! Velocity
+//!
+//! This module describes how hard a note is played and how that changes the sound of the voice.
+//! Velocities are kept between 0.0 and 1.0; MIDI velocities from 0 to 127 are converted with
+//! `velocity_from_midi`.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::velocity::{velocity_from_midi, VelocityCurve, VelocitySettings};
+//!
+//! // Soft curve, full control over the amplitude and a filter that opens by two octaves
+//! let settings = VelocitySettings {
+//!     curve: VelocityCurve::Soft,
+//!     filter_octaves: 2.0,
+//!     ..Default::default()
+//! };
+//!
+//! let velocity = velocity_from_midi(100);
+//! let gain = settings.amplitude(velocity);
+//! let cutoff_ratio = settings.cutoff_ratio(velocity);
+//! ```
+use serde::{Deserialize, Serialize};
+
+/// Velocity used when the source of a note has no velocity of its own.
+pub const DEFAULT_VELOCITY: f32 = 100.0 / 127.0;
+
+/// Converts a MIDI velocity between 0 and 127 to a velocity between 0.0 and 1.0.
+pub fn velocity_from_midi(velocity: u8) -> f32 {
+    velocity.min(127) as f32 / 127.0
+}
+
+/// Converts a velocity between 0.0 and 1.0 to a MIDI velocity between 0 and 127.
+pub fn velocity_to_midi(velocity: f32) -> u8 {
+    (velocity.clamp(0.0, 1.0) * 127.0).round() as u8
+}
+
+/// Shapes how the velocity a note is played with maps to its effect on the sound.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VelocityCurve {
+    /// The response follows the velocity directly.
+    #[default]
+    Linear,
+    /// Soft playing already gets a strong response.
+    Soft,
+    /// Only hard playing gets a strong response.
+    Hard,
+    /// Every note responds as if played at full velocity.
+    Fixed,
+}
+
+impl VelocityCurve {
+    /// All velocity curves, in the order they are offered in the user interface.
+    pub const ALL: [VelocityCurve; 4] = [VelocityCurve::Linear, VelocityCurve::Soft, VelocityCurve::Hard, VelocityCurve::Fixed];
+
+    /// Returns the display name of the curve.
+    pub fn name(&self) -> &'static str {
+        match self {
+            VelocityCurve::Linear => "Linear",
+            VelocityCurve::Soft => "Soft",
+            VelocityCurve::Hard => "Hard",
+            VelocityCurve::Fixed => "Fixed",
+        }
+    }
+
+    /// Maps a velocity between 0.0 and 1.0 through the curve.
+    pub fn apply(&self, velocity: f32) -> f32 {
+        let velocity = velocity.clamp(0.0, 1.0);
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Soft => velocity.sqrt(),
+            VelocityCurve::Hard => velocity * velocity,
+            VelocityCurve::Fixed => 1.0,
+        }
+    }
+}
+
+/// Per-patch settings for how velocity changes a voice.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VelocitySettings {
+    /// The curve the velocity is passed through first.
+    pub curve: VelocityCurve,
+    /// How much velocity controls the amplitude, from 0.0 (not at all) to 1.0 (silent at velocity 0).
+    pub amplitude_amount: f32,
+    /// How many octaves the filter cutoff closes by at velocity 0. The cutoff set on the voice is
+    /// reached at full velocity.
+    pub filter_octaves: f32,
+    /// How much velocity shortens the attack, from 0.0 (not at all) to 1.0 (no attack at full velocity).
+    pub attack_amount: f32,
+}
+
+impl Default for VelocitySettings {
+    fn default() -> Self {
+        Self {
+            curve: VelocityCurve::Linear,
+            amplitude_amount: 1.0,
+            filter_octaves: 0.0,
+            attack_amount: 0.0,
+        }
+    }
+}
+
+impl VelocitySettings {
+    /// Returns the gain of a note played at `velocity`.
+    pub fn amplitude(&self, velocity: f32) -> f32 {
+        let amount = self.amplitude_amount.clamp(0.0, 1.0);
+        1.0 - amount * (1.0 - self.curve.apply(velocity))
+    }
+
+    /// Returns the factor the filter cutoff of a note played at `velocity` is multiplied by.
+    pub fn cutoff_ratio(&self, velocity: f32) -> f32 {
+        f32::powf(2.0, self.filter_octaves.max(0.0) * (self.curve.apply(velocity) - 1.0))
+    }
+
+    /// Returns the factor the attack time of a note played at `velocity` is multiplied by.
+    pub fn attack_scale(&self, velocity: f32) -> f32 {
+        1.0 - self.attack_amount.clamp(0.0, 1.0) * self.curve.apply(velocity)
+    }
+}
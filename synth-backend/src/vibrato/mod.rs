@@ -1,42 +1,122 @@
-use crate::oscillators::{WaveTableOscillator};
-use crate::utils::RingBuffer;
+//! Vibrato
+//!
+//! A delay line whose read position is modulated by a low-frequency oscillator. On its own it produces
+//! vibrato; with a longer base delay and feedback it is the building block of the chorus and flanger
+//! effects.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::oscillators::{Oscillator, WaveTableOscillator};
+//! use synth_backend::vibrato::Vibrato;
+//!
+//! // A 5 Hz sine LFO sweeping the delay by 2 ms around its centre
+//! let lfo = WaveTableOscillator::new(44100, 2048, Oscillator::Sine, 1.0, 5.0);
+//! let mut vibrato = Vibrato::new(44100.0, 0.002, lfo);
+//!
+//! // Turn it into a chorus voice: 15 ms base delay and a little feedback
+//! vibrato.set_delay(0.015);
+//! vibrato.set_feedback(0.2);
+//!
+//! let output = vibrato.process(0.5);
+//! ```
+use crate::oscillators::WaveTableOscillator;
+use crate::ring_buffer::RingBuffer;
+use crate::effects::{delay_tap, delay_write};
+use crate::lifecycle::Prepare;
 
 #[derive(Clone, Debug)]
 pub struct Vibrato {
     sample_rate_hz: f32,
-    width_sample: usize,
+    delay_sample: f32,
+    width_sample: f32,
+    feedback: f32,
+    last_output: f32,
     lfo: WaveTableOscillator,
     delay_line: RingBuffer<f32>,
 }
 
 impl Vibrato {
+    /// Creates a new `Vibrato`. The base delay starts just above the width, so the read position
+    /// never passes the write position.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate_hz` - Sample rate in Hertz.
+    /// * `width_sec` - Depth of the modulation in seconds.
+    /// * `lfo` - The oscillator driving the modulation.
     pub fn new(sample_rate_hz: f32, width_sec: f32, lfo: WaveTableOscillator) -> Self {
-        let width_sample = (width_sec * sample_rate_hz).round() as usize;
+        let width_sample = (width_sec * sample_rate_hz).round();
         Self {
-            sample_rate_hz: sample_rate_hz,
-            width_sample: width_sample,
-            lfo: lfo,
-            delay_line: RingBuffer::new(2 + width_sample * 3),
+            sample_rate_hz,
+            delay_sample: width_sample + 1.0,
+            width_sample,
+            feedback: 0.0,
+            last_output: 0.0,
+            lfo,
+            delay_line: RingBuffer::new(2 + width_sample as usize * 3),
         }
     }
 
     pub fn reset(&mut self) {
         self.delay_line.reset();
+        self.last_output = 0.0;
     }
 
     pub fn process(&mut self, input: f32) -> f32 {
         let modulator = self.lfo.get_sample();
-        let offset = 1.0 + self.width_sample as f32 + self.width_sample as f32 * modulator;
-        let _ = self.delay_line.pop();
-        self.delay_line.push(input);
-        self.delay_line.get_frac(offset)
+        let max_delay = self.delay_line.capacity() as f32;
+        let delay = (self.delay_sample + self.width_sample * modulator).clamp(1.0, max_delay);
+        self.last_output = delay_tap(&self.delay_line, delay);
+        delay_write(&mut self.delay_line, input + self.feedback * self.last_output);
+        self.last_output
     }
 
-    pub fn set_frequency(&mut self, frequency: f32) {
-        self.lfo.set_frequency(frequency);
+    pub fn set_frequency(&mut self, frequency: f32) -> Result<(), String> {
+        self.lfo.set_frequency(frequency)
+    }
+
+    /// Sets the phase of the modulating oscillator, between 0.0 and 1.0 of a cycle.
+    pub fn set_phase(&mut self, phase: f32) {
+        self.lfo.set_phase(phase);
     }
 
     pub fn set_width(&mut self, width_sec: f32) {
-        self.width_sample = (width_sec * self.sample_rate_hz).round() as usize;
+        self.width_sample = (width_sec * self.sample_rate_hz).round();
+        self.ensure_capacity();
+    }
+
+    /// Sets the delay around which the read position is modulated.
+    pub fn set_delay(&mut self, delay_sec: f32) {
+        self.delay_sample = (delay_sec * self.sample_rate_hz).max(1.0);
+        self.ensure_capacity();
     }
-}
\ No newline at end of file
+
+    /// Sets how much of the output is fed back into the delay line, between -1.0 and 1.0 (exclusive).
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(-0.99, 0.99);
+    }
+
+    /// Grows the delay line when the base delay plus the width no longer fits in it.
+    fn ensure_capacity(&mut self) {
+        let needed = (self.delay_sample + self.width_sample) as usize + 2;
+        if needed > self.delay_line.capacity() {
+            self.delay_line = RingBuffer::new(needed);
+        }
+    }
+}
+
+impl Prepare for Vibrato {
+    /// Keeps the delay, width and rate in seconds and Hertz. The delay line is sized again for the
+    /// new rate.
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        let ratio = sample_rate as f32 / self.sample_rate_hz;
+        self.sample_rate_hz = sample_rate as f32;
+        self.lfo.prepare(sample_rate, max_block_size);
+        self.width_sample = (self.width_sample * ratio).round();
+        self.delay_sample = (self.delay_sample * ratio).max(1.0);
+        self.delay_line = RingBuffer::new(2 + self.width_sample as usize * 3);
+        self.last_output = 0.0;
+        self.ensure_capacity();
+    }
+}
@@ -0,0 +1,251 @@
+//! Voice mode
+//!
+//! This module describes how the synthesizer allocates voices to the keys that are held. In `Poly`
+//! mode every key gets a voice of its own. In `Mono` and `Legato` mode a single voice plays the held
+//! key chosen by the note priority, and returns to the keys that are still held when that key is let
+//! go. `Mono` restarts the envelope on every note, `Legato` only when no other key was held.
+//!
+//! A single voice can glide from the pitch of the previous note to the next one. The glide is linear
+//! in pitch and either takes the same time for every interval or moves at a constant rate.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::voice_mode::{GlideMode, VoiceMode, VoiceModeSettings};
+//!
+//! // A legato bass that glides by an octave every 200 milliseconds
+//! let settings = VoiceModeSettings {
+//!     mode: VoiceMode::Legato,
+//!     glide_ms: 200.0,
+//!     glide_mode: GlideMode::ConstantRate,
+//!     ..Default::default()
+//! };
+//! ```
+use serde::{Deserialize, Serialize};
+
+/// How voices are allocated to held keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VoiceMode {
+    /// Every key gets a voice of its own.
+    #[default]
+    Poly,
+    /// A single voice that restarts its envelope on every note.
+    Mono,
+    /// A single voice that only restarts its envelope when no other key was held.
+    Legato,
+}
+
+impl VoiceMode {
+    /// All voice modes, in the order they are offered in the user interface.
+    pub const ALL: [VoiceMode; 3] = [VoiceMode::Poly, VoiceMode::Mono, VoiceMode::Legato];
+
+    /// Returns the display name of the voice mode.
+    pub fn name(&self) -> &'static str {
+        match self {
+            VoiceMode::Poly => "Poly",
+            VoiceMode::Mono => "Mono",
+            VoiceMode::Legato => "Legato",
+        }
+    }
+
+    /// Returns whether the mode plays a single voice.
+    pub fn is_mono(&self) -> bool {
+        !matches!(self, VoiceMode::Poly)
+    }
+}
+
+/// Which held key a single voice plays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NotePriority {
+    /// The key pressed most recently.
+    #[default]
+    Last,
+    /// The lowest held key.
+    Low,
+    /// The highest held key.
+    High,
+}
+
+impl NotePriority {
+    /// All note priorities, in the order they are offered in the user interface.
+    pub const ALL: [NotePriority; 3] = [NotePriority::Last, NotePriority::Low, NotePriority::High];
+
+    /// Returns the display name of the note priority.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotePriority::Last => "Last",
+            NotePriority::Low => "Low",
+            NotePriority::High => "High",
+        }
+    }
+}
+
+/// How the glide time relates to the interval between two notes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GlideMode {
+    /// Every glide takes the glide time, whatever the interval.
+    #[default]
+    ConstantTime,
+    /// The glide time is the time taken to glide by an octave.
+    ConstantRate,
+}
+
+impl GlideMode {
+    /// All glide modes, in the order they are offered in the user interface.
+    pub const ALL: [GlideMode; 2] = [GlideMode::ConstantTime, GlideMode::ConstantRate];
+
+    /// Returns the display name of the glide mode.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GlideMode::ConstantTime => "Time",
+            GlideMode::ConstantRate => "Rate",
+        }
+    }
+}
+
+/// Per-patch settings of the voice mode and portamento.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceModeSettings {
+    /// How voices are allocated to held keys.
+    pub mode: VoiceMode,
+    /// Which held key a single voice plays.
+    pub priority: NotePriority,
+    /// The glide time in milliseconds, 0.0 turns portamento off.
+    pub glide_ms: f32,
+    /// How the glide time relates to the interval between two notes.
+    pub glide_mode: GlideMode,
+    /// Only glide when the previous key is still held.
+    pub legato_glide_only: bool,
+}
+
+impl Default for VoiceModeSettings {
+    fn default() -> Self {
+        Self {
+            mode: VoiceMode::Poly,
+            priority: NotePriority::Last,
+            glide_ms: 0.0,
+            glide_mode: GlideMode::ConstantTime,
+            legato_glide_only: false,
+        }
+    }
+}
+
+/// Keys held down while a single voice plays, with the velocity they were pressed at.
+#[derive(Clone, Debug, Default)]
+pub struct HeldNotes {
+    notes: Vec<(u8, f32)>,
+}
+
+impl HeldNotes {
+    pub fn new() -> Self {
+        Self { notes: Vec::with_capacity(128) }
+    }
+
+    /// Adds a key, moving it to the end if it was already held.
+    pub fn press(&mut self, key: u8, velocity: f32) {
+        self.release(key);
+        self.notes.push((key, velocity));
+    }
+
+    /// Removes a key. Returns whether it was held.
+    pub fn release(&mut self, key: u8) -> bool {
+        match self.notes.iter().position(|(held, _)| *held == key) {
+            Some(index) => {
+                self.notes.remove(index);
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.notes.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    /// Returns the key and velocity a single voice should play.
+    pub fn select(&self, priority: NotePriority) -> Option<(u8, f32)> {
+        match priority {
+            NotePriority::Last => self.notes.last().copied(),
+            NotePriority::Low => self.notes.iter().min_by_key(|(key, _)| *key).copied(),
+            NotePriority::High => self.notes.iter().max_by_key(|(key, _)| *key).copied(),
+        }
+    }
+}
+
+/// Portamento of a single voice, as an offset in semitones from the note it glides to.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Glide {
+    offset_semitones: f32,
+    step: f32,
+    samples_left: u32,
+}
+
+impl Glide {
+    /// Starts a glide `offset_semitones` away from the target note.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset_semitones` - How far the glide starts from the target note, in semitones.
+    /// * `glide_ms` - The glide time in milliseconds.
+    /// * `glide_mode` - How the glide time relates to the interval.
+    /// * `sample_rate` - The sample rate in Hz.
+    pub fn start(&mut self, offset_semitones: f32, glide_ms: f32, glide_mode: GlideMode, sample_rate: u32) {
+        let glide_samples = glide_ms * sample_rate as f32 / 1000.0;
+        if glide_samples < 1.0 || offset_semitones == 0.0 {
+            self.stop();
+            return;
+        }
+        let samples = match glide_mode {
+            GlideMode::ConstantTime => glide_samples,
+            GlideMode::ConstantRate => glide_samples * offset_semitones.abs() / 12.0,
+        };
+        self.samples_left = samples.round().max(1.0) as u32;
+        self.offset_semitones = offset_semitones;
+        self.step = offset_semitones / self.samples_left as f32;
+    }
+
+    /// Stretches what is left of the glide by `ratio`, the new sample rate over the old one, so
+    /// that it ends at the same time.
+    pub fn rescale(&mut self, ratio: f32) {
+        if !self.is_active() {
+            return;
+        }
+        self.samples_left = (self.samples_left as f32 * ratio).round().max(1.0) as u32;
+        self.step = self.offset_semitones / self.samples_left as f32;
+    }
+
+    pub fn stop(&mut self) {
+        self.offset_semitones = 0.0;
+        self.step = 0.0;
+        self.samples_left = 0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.samples_left > 0
+    }
+
+    /// Returns how far the voice currently is from the target note, in semitones.
+    pub fn offset_semitones(&self) -> f32 {
+        self.offset_semitones
+    }
+
+    /// Moves the glide on by one sample and returns the factor the target frequency is multiplied by.
+    pub fn next_ratio(&mut self) -> f32 {
+        if self.samples_left <= 1 {
+            self.stop();
+        } else {
+            self.samples_left -= 1;
+            self.offset_semitones -= self.step;
+        }
+        f32::powf(2.0, self.offset_semitones / 12.0)
+    }
+}
@@ -0,0 +1,261 @@
+//! Voice pool
+//!
+//! `VoicePool` holds the voices of the engine in a fixed list of slots. A slot that stops playing keeps
+//! its voice, so the next note that is copied from a template reuses its oscillators, delay lines and
+//! oversampling stages instead of allocating new ones.
+//!
+//! In real-time mode the pool never grows past its polyphony: the slots are allocated up front by
+//! `reserve`, and when every slot is playing a new note takes the voice of the oldest one. Notes that
+//! the arpeggiator and sequencer start while samples are generated then do not allocate. Outside
+//! real-time mode the pool grows whenever every slot is playing, so that no note is ever cut off.
+//!
+//! # Examples
+//!
+//! ```
+//! use synth_backend::voice_pool::{VoicePool, VoiceSource};
+//! use synth_backend::oscillators::{MultiOscillator, Oscillator, WaveTableOscillator};
+//! use synth_backend::wrapper::Synth;
+//! use synth_backend::lfo::LFOType;
+//!
+//! let osc = MultiOscillator::from(WaveTableOscillator::new(44100, 4096, Oscillator::Saw, 0.5, 0.0));
+//! let template = Synth::new(osc, 44100, None, None, None, LFOType::Amplitude);
+//!
+//! // Eight voices, all allocated now
+//! let mut pool = VoicePool::new(8);
+//! pool.set_real_time(true);
+//! pool.reserve(&template);
+//!
+//! // Copies the template into a free voice without allocating
+//! pool.start(60, VoiceSource::Template(&template)).note_on(261.63, 1.0).unwrap();
+//! assert_eq!(pool.len(), 1);
+//! pool.release(60);
+//! assert!(pool.is_empty());
+//! ```
+use crate::lifecycle::Prepare;
+use crate::wrapper::Synth;
+
+/// Number of voices a pool plays at once in real-time mode unless it is set otherwise.
+pub const DEFAULT_POLYPHONY: usize = 32;
+
+/// Where the voice for a new note comes from.
+// Only ever passed straight to `start`, and boxing the voice would allocate
+#[allow(clippy::large_enum_variant)]
+pub enum VoiceSource<'a> {
+    /// A voice made for the note, which is moved into the pool.
+    Voice(Synth),
+    /// A voice the note is copied from, such as the one the arpeggiator plays its pattern with.
+    Template(&'a Synth),
+}
+
+/// A voice and the key it plays, if it is playing.
+#[derive(Clone, Debug)]
+struct Slot {
+    key: Option<u8>,
+    voice: Option<Synth>,
+    started: u64,
+}
+
+/// Preallocated voices, looked up by the MIDI key they play.
+#[derive(Clone, Debug)]
+pub struct VoicePool {
+    slots: Vec<Slot>,
+    polyphony: usize,
+    real_time: bool,
+    notes_started: u64,
+}
+
+impl VoicePool {
+    /// Creates a new `VoicePool` that plays up to `polyphony` voices at once in real-time mode. The
+    /// pool starts outside real-time mode.
+    pub fn new(polyphony: usize) -> Self {
+        let polyphony = polyphony.max(1);
+        Self {
+            slots: Vec::with_capacity(polyphony),
+            polyphony,
+            real_time: false,
+            notes_started: 0,
+        }
+    }
+
+    pub fn polyphony(&self) -> usize {
+        self.polyphony
+    }
+
+    /// Sets how many voices play at once in real-time mode. Every voice is stopped and the voices kept
+    /// for reuse are dropped, so `reserve` should be called again.
+    pub fn set_polyphony(&mut self, polyphony: usize) {
+        self.polyphony = polyphony.max(1);
+        self.slots.clear();
+        self.slots.shrink_to(self.polyphony);
+        self.slots.reserve_exact(self.polyphony);
+    }
+
+    /// Returns whether the pool keeps to its polyphony instead of growing.
+    pub fn is_real_time(&self) -> bool {
+        self.real_time
+    }
+
+    /// Turns real-time mode on or off. Turning it on drops the voices beyond the polyphony, spare
+    /// voices first and then the oldest notes.
+    pub fn set_real_time(&mut self, real_time: bool) {
+        self.real_time = real_time;
+        if !real_time {
+            return;
+        }
+        while self.slots.len() > self.polyphony {
+            let index = self.slots.iter().position(|slot| slot.key.is_none()).unwrap_or_else(|| self.oldest());
+            self.slots.swap_remove(index);
+        }
+        self.slots.reserve_exact(self.polyphony - self.slots.len());
+    }
+
+    /// Makes every voice that is not playing a copy of `template`, so that notes copied from it later
+    /// do not allocate. In real-time mode slots are added up to the polyphony first. Allocates, so it
+    /// should not be called from the audio callback.
+    pub fn reserve(&mut self, template: &Synth) {
+        if self.real_time {
+            while self.slots.len() < self.polyphony {
+                self.slots.push(Slot { key: None, voice: None, started: 0 });
+            }
+        }
+        for slot in self.slots.iter_mut().filter(|slot| slot.key.is_none()) {
+            match slot.voice {
+                Some(ref mut voice) => voice.clone_from(template),
+                None => slot.voice = Some(template.clone())
+            }
+        }
+    }
+
+    /// Starts a voice for `key` and returns it. A voice already playing `key` is replaced, then a voice
+    /// that is not playing is used, preferring one laid out like the template. When every voice is
+    /// playing the pool grows, or in real-time mode the oldest note is taken over.
+    ///
+    /// A `Template` does not allocate when a voice laid out like it is free or taken over.
+    pub fn start(&mut self, key: u8, source: VoiceSource) -> &mut Synth {
+        let index = self.slot_for(key, &source);
+        self.notes_started += 1;
+        let slot = &mut self.slots[index];
+        slot.key = Some(key);
+        slot.started = self.notes_started;
+        match (source, &mut slot.voice) {
+            (VoiceSource::Voice(voice), _) => slot.voice = Some(voice),
+            (VoiceSource::Template(template), Some(voice)) => voice.clone_from(template),
+            (VoiceSource::Template(template), None) => slot.voice = Some(template.clone())
+        }
+        slot.voice.as_mut().unwrap()
+    }
+
+    /// Inserts `voice` playing `key`, replacing any voice already playing it.
+    pub fn insert(&mut self, key: u8, voice: Synth) {
+        self.start(key, VoiceSource::Voice(voice));
+    }
+
+    /// Stops the voice playing `key` and keeps it for reuse. Returns whether a voice was playing it.
+    pub fn release(&mut self, key: u8) -> bool {
+        match self.position(key) {
+            Some(index) => {
+                self.slots[index].key = None;
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Stops the voice playing `key` and takes it out of the pool.
+    pub fn remove(&mut self, key: u8) -> Option<Synth> {
+        let index = self.position(key)?;
+        self.slots[index].key = None;
+        self.slots[index].voice.take()
+    }
+
+    /// Moves the voice playing `from` over to `to`, stopping any other voice playing `to`. Returns
+    /// whether a voice was playing `from`.
+    pub fn rekey(&mut self, from: u8, to: u8) -> bool {
+        let Some(index) = self.position(from) else {
+            return false;
+        };
+        if from != to {
+            self.release(to);
+        }
+        self.slots[index].key = Some(to);
+        true
+    }
+
+    /// Stops every voice, keeping them for reuse.
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| slot.key = None);
+    }
+
+    pub fn get(&self, key: u8) -> Option<&Synth> {
+        self.position(key).and_then(|index| self.slots[index].voice.as_ref())
+    }
+
+    pub fn get_mut(&mut self, key: u8) -> Option<&mut Synth> {
+        self.position(key).and_then(|index| self.slots[index].voice.as_mut())
+    }
+
+    /// Returns the number of voices playing.
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.key.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(|slot| slot.key.is_none())
+    }
+
+    /// Iterates over the voices playing and their keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&u8, &Synth)> {
+        self.slots.iter().filter_map(|slot| slot.key.as_ref().zip(slot.voice.as_ref()))
+    }
+
+    /// Iterates mutably over the voices playing and their keys.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&u8, &mut Synth)> {
+        self.slots.iter_mut().filter_map(|slot| slot.key.as_ref().zip(slot.voice.as_mut()))
+    }
+
+    fn position(&self, key: u8) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.key == Some(key))
+    }
+
+    fn oldest(&self) -> usize {
+        self.slots.iter().enumerate()
+            .filter(|(_, slot)| slot.key.is_some())
+            .min_by_key(|(_, slot)| slot.started)
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn slot_for(&mut self, key: u8, source: &VoiceSource) -> usize {
+        if let Some(index) = self.position(key) {
+            return index;
+        }
+        let fits = |slot: &Slot| match (source, &slot.voice) {
+            (VoiceSource::Template(template), Some(voice)) => voice.has_layout_of(template),
+            _ => false
+        };
+        let free = self.slots.iter().position(|slot| slot.key.is_none() && fits(slot))
+            .or_else(|| self.slots.iter().position(|slot| slot.key.is_none()));
+        match free {
+            Some(index) => index,
+            None if !self.real_time || self.slots.len() < self.polyphony => {
+                self.slots.push(Slot { key: None, voice: None, started: 0 });
+                self.slots.len() - 1
+            },
+            None => self.oldest()
+        }
+    }
+}
+
+impl Default for VoicePool {
+    fn default() -> Self {
+        Self::new(DEFAULT_POLYPHONY)
+    }
+}
+
+impl Prepare for VoicePool {
+    /// Prepares the voices that are playing and the ones kept for reuse.
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        for voice in self.slots.iter_mut().filter_map(|slot| slot.voice.as_mut()) {
+            voice.prepare(sample_rate, max_block_size);
+        }
+    }
+}
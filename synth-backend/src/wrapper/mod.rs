@@ -22,24 +22,74 @@
 //! ```
 //!
 //! The `Synth` struct provides methods for configuring and generating audio samples from a synthesizer.
-use crate::oscillators::{MultiOscillator, Oscillator, WaveTableOscillator};
+use crate::oscillators::{MultiOscillator, Oscillator, WaveTableOscillator, DEFAULT_TABLE_SIZE};
 use crate::filters::{Filter, FilterParam, FilterType};
+use crate::distortion::{Distortion, DistortionParam, DistortionType, Oversampling};
 use crate::envelopes::{Envelope, EnvelopeParam};
 use crate::lfo::{LFOType, LFO};
+use crate::velocity::{VelocitySettings, DEFAULT_VELOCITY};
+use crate::voice_mode::{Glide, GlideMode};
+use crate::lifecycle::Prepare;
 use std::ops::Add;
 
 const GAIN: f32 = 1.0;
 const WIDTH: f32 = 0.010;
 
 /// Synth struct representing a synthesizer.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Synth {
     pub osc: MultiOscillator,
     pub sample_rate: u32,
     pub filter: Option<Filter>, // Make filter an optional field
+    pub distortion: Option<Distortion>,
     pub envelope: Option<Envelope>,
     pub lfo: Option<LFO>,
     pub lfo_type: LFOType,
+    pub velocity_settings: VelocitySettings,
+    velocity: f32,
+    velocity_gain: f32,
+    cutoff_ratio: f32,
+    attack_ms: Option<f32>,
+    expression_gain: f32,
+    expression_cutoff_ratio: f32,
+    bend_ratio: f32,
+    glide: Glide,
+}
+
+impl Clone for Synth {
+    fn clone(&self) -> Self {
+        Self {
+            osc: self.osc.clone(),
+            filter: self.filter.clone(),
+            distortion: self.distortion.clone(),
+            envelope: self.envelope.clone(),
+            lfo: self.lfo.clone(),
+            lfo_type: self.lfo_type.clone(),
+            ..*self
+        }
+    }
+
+    /// Copies `source` into this voice, reusing its oscillators, delay lines and oversampling stages.
+    /// When both voices have the same modules this does not allocate, which lets the `VoicePool`
+    /// start notes from a template in the audio callback.
+    fn clone_from(&mut self, source: &Self) {
+        self.osc.clone_from(&source.osc);
+        self.sample_rate = source.sample_rate;
+        self.filter.clone_from(&source.filter);
+        self.distortion.clone_from(&source.distortion);
+        self.envelope.clone_from(&source.envelope);
+        self.lfo.clone_from(&source.lfo);
+        self.lfo_type = source.lfo_type.clone();
+        self.velocity_settings = source.velocity_settings;
+        self.velocity = source.velocity;
+        self.velocity_gain = source.velocity_gain;
+        self.cutoff_ratio = source.cutoff_ratio;
+        self.attack_ms = source.attack_ms;
+        self.expression_gain = source.expression_gain;
+        self.expression_cutoff_ratio = source.expression_cutoff_ratio;
+        self.bend_ratio = source.bend_ratio;
+        self.glide = source.glide;
+    }
 }
 
 impl Synth {
@@ -61,23 +111,147 @@ impl Synth {
             osc,
             sample_rate,
             filter,
+            distortion: None,
             envelope,
             lfo,
-            lfo_type
+            lfo_type,
+            velocity_settings: VelocitySettings::default(),
+            velocity: DEFAULT_VELOCITY,
+            velocity_gain: 1.0,
+            cutoff_ratio: 1.0,
+            attack_ms: None,
+            expression_gain: 1.0,
+            expression_cutoff_ratio: 1.0,
+            bend_ratio: 1.0,
+            glide: Glide::default(),
         }
     }
 
+    /// Starts a note on this voice.
+    ///
+    /// The oscillators are set to `frequency`, the envelope restarts, and the velocity settings
+    /// scale the amplitude, filter cutoff and attack time of the voice.
+    ///
+    /// # Arguments
+    ///
+    /// * `frequency` - The frequency of the note in Hertz.
+    /// * `velocity` - How hard the note is played, between 0.0 and 1.0.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    pub fn note_on(&mut self, frequency: f32, velocity: f32) -> Result<(), String> {
+        self.global_set_frequency(frequency)?;
+        let velocity = velocity.clamp(0.0, 1.0);
+        let settings = self.velocity_settings;
+        self.velocity = velocity;
+        self.velocity_gain = settings.amplitude(velocity);
+
+        if let Some(ref mut filter) = self.filter {
+            let cutoff_hz = filter.get_param(FilterParam::FreqHz) / self.cutoff_ratio;
+            self.cutoff_ratio = settings.cutoff_ratio(velocity);
+            filter.set_param(FilterParam::FreqHz, cutoff_hz * self.cutoff_ratio);
+        }
+        if let Some(ref mut envelope) = self.envelope {
+            // The attack is scaled from the one set before the first note, not from the last note's
+            let attack_ms = *self.attack_ms.get_or_insert(envelope.get_param(EnvelopeParam::AttackMs));
+            envelope.set_param(EnvelopeParam::AttackMs, attack_ms * settings.attack_scale(velocity));
+            envelope.reset();
+        }
+        Ok(())
+    }
+
+    /// Returns whether `clone_from` can copy `source` into this voice without allocating, which it can
+    /// when both voices have the same oscillators, oversampling and LFO delay line.
+    pub fn has_layout_of(&self, source: &Synth) -> bool {
+        let distortion = match (&self.distortion, &source.distortion) {
+            (Some(own), Some(other)) => own.oversampling() == other.oversampling(),
+            (own, other) => own.is_none() == other.is_none()
+        };
+        let lfo = match (&self.lfo, &source.lfo) {
+            (Some(own), Some(other)) => own.delay_line_len() == other.delay_line_len(),
+            (own, other) => own.is_none() == other.is_none()
+        };
+        self.osc.num_sources() == source.osc.num_sources() && distortion && lfo
+    }
+
+    /// Bends the pitch of the voice without restarting its note.
+    ///
+    /// # Arguments
+    ///
+    /// * `pitch_ratio` - The factor the frequency of the note is multiplied by.
+    pub fn set_pitch_ratio(&mut self, pitch_ratio: f32) {
+        self.bend_ratio = pitch_ratio;
+        let glide_ratio = f32::powf(2.0, self.glide.offset_semitones() / 12.0);
+        self.osc.set_pitch_ratio(pitch_ratio * glide_ratio);
+    }
+
+    /// Sets the per-note expression of the voice, such as MPE pressure and timbre, without restarting
+    /// its note.
+    ///
+    /// # Arguments
+    ///
+    /// * `gain` - The factor the output of the voice is multiplied by.
+    /// * `cutoff_ratio` - The factor the filter cutoff is multiplied by.
+    pub fn set_expression(&mut self, gain: f32, cutoff_ratio: f32) {
+        self.expression_gain = gain;
+        if let Some(ref mut filter) = self.filter {
+            let cutoff_hz = filter.get_param(FilterParam::FreqHz) / self.expression_cutoff_ratio;
+            filter.set_param(FilterParam::FreqHz, cutoff_hz * cutoff_ratio);
+        }
+        self.expression_cutoff_ratio = cutoff_ratio;
+    }
+
+    /// Starts a portamento glide towards the frequency the voice is set to.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset_semitones` - How far from the current note the glide starts, in semitones.
+    /// * `glide_ms` - The glide time in milliseconds.
+    /// * `glide_mode` - Whether `glide_ms` is the time of the whole glide or of an octave.
+    pub fn start_glide(&mut self, offset_semitones: f32, glide_ms: f32, glide_mode: GlideMode) {
+        self.glide.start(offset_semitones, glide_ms, glide_mode, self.sample_rate);
+        self.set_pitch_ratio(self.bend_ratio);
+    }
+
+    /// Returns how far the voice still is from its note because of portamento, in semitones.
+    pub fn glide_offset_semitones(&self) -> f32 {
+        self.glide.offset_semitones()
+    }
+
+    /// Sets the depth of the low-frequency oscillator (LFO), between 0.0 and 1.0.
+    pub fn set_lfo_depth(&mut self, depth: f32) {
+        if let Some(ref mut lfo) = self.lfo {
+            lfo.set_depth(depth);
+        }
+    }
+
+    /// Returns the velocity of the current note, between 0.0 and 1.0.
+    pub fn velocity(&self) -> f32 {
+        self.velocity
+    }
+
     /// Generates the next audio sample from the synthesizer.
     ///
     /// This method calculates the next audio sample by processing the output of the multi-oscillator
-    /// and applying any configured filter, envelope, and amplitude modulation.
+    /// and applying any configured distortion, filter, envelope, amplitude modulation and the velocity of the note.
     ///
     /// # Returns
     ///
     /// The next audio sample as a 32-bit floating point value.
     pub fn get_sample(&mut self) -> f32 {
+        if self.glide.is_active() {
+            let glide_ratio = self.glide.next_ratio();
+            self.osc.set_pitch_ratio(self.bend_ratio * glide_ratio);
+        }
+
         // Call the get_sample method of MultiOscillator
-        let sample = self.osc.get_sample();
+        let mut sample = self.osc.get_sample();
+
+        // Drive the oscillators before they reach the filter
+        if let Some(ref mut distortion) = self.distortion {
+            sample = distortion.process(sample);
+        }
         let mut output_sample = sample;
 
         // Check if filter exists
@@ -94,6 +268,8 @@ impl Synth {
             output_sample = lfo.process(output_sample);
         }
 
+        output_sample *= self.velocity_gain * self.expression_gain;
+
         // If filter is None, return the sample directly
         output_sample
     }
@@ -191,6 +367,40 @@ impl Synth {
         }
     }
 
+    /// Sets the type of the synthesizer's pre-filter distortion, or removes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `distortion_type` - The transfer curve, or `None` to remove the distortion.
+    pub fn set_distortion(&mut self, distortion_type: Option<DistortionType>) {
+        match distortion_type {
+            None => self.distortion = None,
+            Some(distortion_type) => match self.distortion {
+                None => self.distortion = Some(Distortion::new(distortion_type, self.sample_rate as f32)),
+                Some(_) => self.distortion.as_mut().unwrap().set_type(distortion_type)
+            }
+        }
+    }
+
+    /// Sets the parameter value of the synthesizer's distortion.
+    ///
+    /// # Arguments
+    ///
+    /// * `distortion_param` - The distortion parameter to set.
+    /// * `value` - The value to set the parameter to.
+    pub fn set_distortion_params(&mut self, distortion_param: DistortionParam, value: f32) {
+        if let Some(ref mut distortion) = self.distortion {
+            distortion.set_param(distortion_param, value);
+        }
+    }
+
+    /// Sets the oversampling factor of the synthesizer's distortion.
+    pub fn set_distortion_oversampling(&mut self, oversampling: Oversampling) {
+        if let Some(ref mut distortion) = self.distortion {
+            distortion.set_oversampling(oversampling);
+        }
+    }
+
      /// Sets the parameter value of the synthesizer's filter.
     ///
     /// # Arguments
@@ -198,6 +408,11 @@ impl Synth {
     /// * `filterparam` - The filter parameter to set.
     /// * `value` - The value to set the parameter.
     pub fn set_filter_params(&mut self, filterparam: FilterParam, value: f32) {
+        // The velocity and expression of the current note keep scaling the cutoff
+        let value = match filterparam {
+            FilterParam::FreqHz => value * self.cutoff_ratio * self.expression_cutoff_ratio,
+            _ => value
+        };
         match self.filter {
             None => (),
             Some(_) => self.filter.as_mut().unwrap().set_param(filterparam, value)
@@ -211,6 +426,14 @@ impl Synth {
     /// * `envelope_param` - The envelope parameter to set.
     /// * `value` - The value to set the parameter to.
     pub fn set_envelope_params(&mut self, envelope_param: EnvelopeParam, value: f32) {
+        // The velocity of the current note keeps scaling the attack
+        let value = match (&envelope_param, self.attack_ms) {
+            (EnvelopeParam::AttackMs, Some(_)) => {
+                self.attack_ms = Some(value);
+                value * self.velocity_settings.attack_scale(self.velocity)
+            },
+            _ => value
+        };
         match self.envelope {
             None => (),
             Some(_) => self.envelope.as_mut().unwrap().set_param(envelope_param, value)
@@ -246,7 +469,7 @@ impl Synth {
                 None => self.lfo = Some(LFO::new(
                     lfo_type,
                     self.sample_rate as f32,
-                    WaveTableOscillator::new(self.sample_rate, self.sample_rate as usize, osc, GAIN, frequency),
+                    WaveTableOscillator::new(self.sample_rate, DEFAULT_TABLE_SIZE, osc, GAIN, frequency),
                     WIDTH
                 )),
                 Some(_) => self.lfo.as_mut().unwrap().set_oscillator(osc)
@@ -262,3 +485,25 @@ impl Synth {
         }
     }
 }
+
+impl Prepare for Synth {
+    /// Prepares the oscillators, distortion, filter, envelope and LFO of the voice. A note that is
+    /// playing keeps its pitch, its place in the envelope and what is left of its glide.
+    fn prepare(&mut self, sample_rate: u32, max_block_size: usize) {
+        self.glide.rescale(sample_rate as f32 / self.sample_rate as f32);
+        self.sample_rate = sample_rate;
+        self.osc.prepare(sample_rate, max_block_size);
+        if let Some(ref mut distortion) = self.distortion {
+            distortion.prepare(sample_rate, max_block_size);
+        }
+        if let Some(ref mut filter) = self.filter {
+            filter.prepare(sample_rate, max_block_size);
+        }
+        if let Some(ref mut envelope) = self.envelope {
+            envelope.prepare(sample_rate, max_block_size);
+        }
+        if let Some(ref mut lfo) = self.lfo {
+            lfo.prepare(sample_rate, max_block_size);
+        }
+    }
+}
\ No newline at end of file
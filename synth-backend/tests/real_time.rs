@@ -0,0 +1,187 @@
+//! Checks that the engine does not allocate while it generates samples in real-time mode.
+//!
+//! The test binary counts every allocation, reallocation and deallocation made on a thread while it
+//! is rendering, the way an audio callback would, and fails when there is any.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use synth_backend::arpeggiator::{ArpMode, ArpRate, ArpeggiatorSettings};
+use synth_backend::audio::Resampler;
+use synth_backend::distortion::{DistortionType, Oversampling};
+use synth_backend::effects::EffectType;
+use synth_backend::envelopes::Envelope;
+use synth_backend::filters::{Filter, FilterType};
+use synth_backend::lfo::{LFOType, LFO};
+use synth_backend::oscillators::{MultiOscillator, Oscillator, WaveTableOscillator};
+use synth_backend::engine::IterablePolyphonyHashMap;
+use synth_backend::sequencer::Step;
+use synth_backend::wrapper::Synth;
+
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    let _ = COUNTING.try_with(|counting| {
+        if counting.get() {
+            ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+        }
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count();
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs `render` and returns how many times it went to the allocator.
+fn allocations_in<T>(render: impl FnOnce() -> T) -> (usize, T) {
+    ALLOCATIONS.with(|allocations| allocations.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    let result = render();
+    COUNTING.with(|counting| counting.set(false));
+    (ALLOCATIONS.with(|allocations| allocations.get()), result)
+}
+
+/// A voice with every module that keeps buffers.
+fn voice() -> Synth {
+    let mut osc = MultiOscillator::from(WaveTableOscillator::new(44100, 4096, Oscillator::Saw, 0.5, 0.0));
+    osc.push(WaveTableOscillator::new(44100, 4096, Oscillator::BidirectionalSquare, 0.5, 0.0)).unwrap();
+    let filter = Filter::new(FilterType::LowPass, 44100.0, 2000.0, 0.0);
+    let envelope = Envelope::new(44100.0, 5.0, 50.0, 0.7, 20.0);
+    let mut voice = Synth::new(osc, 44100, Some(filter), Some(envelope), None, LFOType::Frequency);
+    voice.set_lfo_osc(Some(Oscillator::Triangle), 5.0, LFOType::Frequency);
+    voice.set_distortion(Some(DistortionType::Tube));
+    voice.set_distortion_oversampling(Oversampling::X4);
+    voice
+}
+
+/// A real-time engine with every effect in its chain.
+fn engine() -> IterablePolyphonyHashMap {
+    let mut engine = IterablePolyphonyHashMap::new(44100);
+    engine.set_real_time(true);
+    for effect_type in EffectType::ALL {
+        engine.effects_mut().push(effect_type);
+    }
+    engine
+}
+
+/// Generates `frames` frames and returns their energy.
+fn render(engine: &mut IterablePolyphonyHashMap, frames: usize) -> f32 {
+    (0..frames).map(|_| engine.get_stereo_sample()).map(|(left, right)| left * left + right * right).sum()
+}
+
+#[test]
+fn test_1_allocations_are_counted() {
+    let (allocations, buffer) = allocations_in(|| vec![0.0f32; 64]);
+    assert_eq!(allocations, 1);
+    drop(buffer);
+}
+
+#[test]
+fn test_2_chord_renders_without_allocating() {
+    let mut engine = engine();
+    for key in [48, 55, 60, 64, 67] {
+        engine.note_on(key, voice(), 1.0).unwrap();
+    }
+    let (allocations, energy) = allocations_in(|| render(&mut engine, 44100));
+    assert_eq!(allocations, 0);
+    assert!(energy > 0.0);
+}
+
+#[test]
+fn test_3_arpeggiator_starts_notes_without_allocating() {
+    let mut engine = engine();
+    engine.set_arpeggiator(ArpeggiatorSettings {
+        enabled: true,
+        mode: ArpMode::UpDown,
+        rate: ArpRate::ThirtySecond,
+        octaves: 3,
+        gate: 1.0,
+        ..Default::default()
+    });
+    for key in [60, 63, 67, 70] {
+        engine.note_on(key, voice(), 0.8).unwrap();
+    }
+    let (allocations, energy) = allocations_in(|| render(&mut engine, 2 * 44100));
+    assert_eq!(allocations, 0);
+    assert!(energy > 0.0);
+    assert!(engine.len() <= engine.polyphony());
+}
+
+#[test]
+fn test_4_sequencer_and_voice_stealing_do_not_allocate() {
+    let mut engine = engine();
+    engine.set_polyphony(2);
+    for step in 0..16 {
+        let value = Step { enabled: true, note: 48 + step as u8, velocity: 1.0, gate: 1.0, ..Default::default() };
+        engine.sequencer_mut().set_step(0, step, value).unwrap();
+    }
+    engine.start_sequencer(voice());
+    // Keys held on the keyboard fill the pool, so the sequencer has to take their voices over
+    engine.note_on(36, voice(), 1.0).unwrap();
+    engine.note_on(40, voice(), 1.0).unwrap();
+    let (allocations, energy) = allocations_in(|| render(&mut engine, 2 * 44100));
+    assert_eq!(allocations, 0);
+    assert!(energy > 0.0);
+    assert_eq!(engine.len(), 2);
+}
+
+#[test]
+fn test_5_resampled_engine_does_not_allocate() {
+    let mut engine = engine();
+    engine.note_on(69, voice(), 1.0).unwrap();
+    let mut resampler = Resampler::new(44100, 48000);
+    let (allocations, _) = allocations_in(|| {
+        for _ in 0..48000 {
+            resampler.next_frame(&mut || engine.get_stereo_sample());
+        }
+    });
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn test_6_switching_the_lfo_type_does_not_allocate() {
+    let mut lfo = LFO::new(LFOType::Amplitude, 44100.0, WaveTableOscillator::new(44100, 4096, Oscillator::Sine, 1.0, 5.0), 0.01);
+    let (allocations, _) = allocations_in(|| {
+        lfo.set_type(LFOType::Frequency);
+        (0..4410).map(|n| lfo.process((n as f32 * 0.01).sin())).sum::<f32>()
+    });
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn test_7_white_noise_does_not_allocate_on_a_new_thread() {
+    let mut engine = engine();
+    let osc = MultiOscillator::from(WaveTableOscillator::new(44100, 4096, Oscillator::WhiteNoise, 0.5, 0.0));
+    engine.note_on(60, Synth::new(osc, 44100, None, None, None, LFOType::Amplitude), 1.0).unwrap();
+    // A fresh thread, like an audio callback, has not set up any thread-local state yet
+    let (allocations, energy) = std::thread::spawn(move || allocations_in(|| render(&mut engine, 4410)))
+        .join()
+        .unwrap();
+    assert_eq!(allocations, 0);
+    assert!(energy > 0.0);
+}
@@ -0,0 +1,166 @@
+use yew::prelude::*;
+use yew::platform::spawn_local;
+use gloo::events::EventListener;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{MidiAccess, MidiInput, MidiMessageEvent, MidiOutput};
+
+/// A MIDI input device.
+#[derive(Clone, PartialEq)]
+pub struct MidiDevice {
+    /// The identifier of the device, which stays the same while it is connected.
+    pub id: String,
+    /// The name of the device.
+    pub name: String,
+}
+
+/// A MIDI output device, which MIDI messages can be sent to.
+#[derive(Clone)]
+pub struct MidiOutputPort {
+    /// The device the port belongs to.
+    pub device: MidiDevice,
+    port: MidiOutput,
+}
+
+impl MidiOutputPort {
+    /// Sends the bytes of one or more MIDI messages to the device straight away.
+    pub fn send(&self, data: &[u8]) {
+        // Sending to a device that was just unplugged fails, and the message is lost
+        let _ = self.port.send(&js_sys::Uint8Array::from(data));
+    }
+}
+
+impl PartialEq for MidiOutputPort {
+    fn eq(&self, other: &Self) -> bool {
+        self.device == other.device
+    }
+}
+
+// Yew component messages
+pub enum Msg {
+    /// MIDI access has been granted.
+    Access(MidiAccess),
+    /// A device was plugged in or out.
+    StateChange,
+    /// A device sent a MIDI message.
+    Message { device: String, data: Vec<u8> },
+}
+
+/// Properties for the `MidiListener` component.
+#[derive(Properties, PartialEq)]
+pub struct MidiListenerProps {
+    /// The identifier of the device to listen to, or `None` to listen to every device.
+    pub device: Option<String>,
+    /// Callback invoked with the connected devices, whenever a device is plugged in or out.
+    pub devices_change: Callback<Vec<MidiDevice>>,
+    /// Callback invoked with the bytes of every MIDI message from the selected device.
+    pub message: Callback<Vec<u8>>,
+    /// Callback invoked with the connected output devices, whenever a device is plugged in or out.
+    #[prop_or_default]
+    pub outputs_change: Callback<Vec<MidiOutputPort>>,
+}
+
+/// A Yew component that listens to the MIDI input devices of the browser through Web MIDI, and finds the
+/// output devices messages can be sent to.
+pub struct MidiListener {
+    /// Holds the access to the MIDI devices, once granted.
+    pub access: Option<MidiAccess>,
+    /// Holds the listener for devices being plugged in or out.
+    pub state_listener: Option<EventListener>,
+    /// Holds the listeners for the messages of each input device.
+    pub input_listeners: Vec<EventListener>,
+}
+
+impl MidiListener {
+    /// Listens to every connected input device and reports them, along with the output devices.
+    fn connect(&mut self, ctx: &Context<Self>) {
+        let Some(access) = self.access.as_ref() else {
+            return;
+        };
+        let mut devices = Vec::new();
+        self.input_listeners.clear();
+        for input in access.inputs().values().into_iter().flatten() {
+            let input: MidiInput = input.unchecked_into();
+            let id = input.id();
+            devices.push(MidiDevice { id: id.clone(), name: input.name().unwrap_or_else(|| id.clone()) });
+
+            let link = ctx.link().to_owned();
+            let listener = EventListener::new(&input, "midimessage", move |event| {
+                if let Ok(data) = event.unchecked_ref::<MidiMessageEvent>().data() {
+                    link.send_message(Msg::Message { device: id.clone(), data });
+                }
+            });
+            // Unlike setting `onmidimessage`, adding a listener does not open the port by itself
+            let _ = input.open();
+            self.input_listeners.push(listener);
+        }
+        ctx.props().devices_change.emit(devices);
+
+        let outputs = access.outputs().values().into_iter().flatten().map(|output| {
+            let port: MidiOutput = output.unchecked_into();
+            let id = port.id();
+            MidiOutputPort { device: MidiDevice { id: id.clone(), name: port.name().unwrap_or(id) }, port }
+        }).collect();
+        ctx.props().outputs_change.emit(outputs);
+    }
+}
+
+impl Component for MidiListener {
+    type Message = Msg;
+    type Properties = MidiListenerProps;
+
+    /// Creates a new `MidiListener` component.
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            access: None,
+            state_listener: None,
+            input_listeners: Vec::new(),
+        }
+    }
+
+    /// Renders the `MidiListener` component.
+    fn view(&self, _ctx: &Context<Self>) -> Html {
+        html! {}
+    }
+
+    /// Handles messages sent to the `MidiListener` component.
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Access(access) => {
+                let link = ctx.link().to_owned();
+                self.state_listener = Some(EventListener::new(&access, "statechange", move |_| {
+                    link.send_message(Msg::StateChange);
+                }));
+                self.access = Some(access);
+                self.connect(ctx);
+            },
+            Msg::StateChange => self.connect(ctx),
+            Msg::Message { device, data } => {
+                if ctx.props().device.as_ref().is_none_or(|selected| *selected == device) {
+                    ctx.props().message.emit(data);
+                }
+            }
+        }
+        false
+    }
+
+    /// Asks the browser for MIDI access after the first render.
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if !first_render {
+            return;
+        }
+        let Some(navigator) = web_sys::window().map(|window| window.navigator()) else {
+            return;
+        };
+        // Browsers without Web MIDI, or users who refuse access, leave the synth on the computer keyboard
+        let Ok(request) = navigator.request_midi_access() else {
+            return;
+        };
+        let link = ctx.link().to_owned();
+        spawn_local(async move {
+            if let Ok(access) = JsFuture::from(request).await {
+                link.send_message(Msg::Access(access.unchecked_into()));
+            }
+        });
+    }
+}
@@ -4,10 +4,12 @@
 ///
 /// - [`button`](crate::components::button): Contains components related to buttons.
 /// - [`keyboard_listener`](crate::components::keyboard_listener): Contains a component for listening to keyboard events.
+/// - [`midi_listener`](crate::components::midi_listener): Contains a component for listening to MIDI devices.
 /// - [`icon`](crate::components::icon): Contains components related to icons.
 /// - [`slider`](crate::components::slider): Contains components related to sliders.
 
 pub mod button;
 pub mod keyboard_listener;
+pub mod midi_listener;
 pub mod icon;
 pub mod slider;
@@ -0,0 +1,121 @@
+use stylist::yew::styled_component;
+use yew::prelude::*;
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// Describes one parameter of an effect as it is shown in the rack.
+#[derive(Clone, PartialEq)]
+pub struct EffectParamView {
+    /// The label displayed alongside the slider.
+    pub name: &'static str,
+    /// The current value of the parameter.
+    pub value: f64,
+    /// The minimum value of the parameter.
+    pub min: f64,
+    /// The maximum value of the parameter.
+    pub max: f64,
+}
+
+/// Describes one slot of the effects rack.
+#[derive(Clone, PartialEq)]
+pub struct EffectSlotView {
+    /// The name of the effect in the slot.
+    pub name: String,
+    /// Whether the slot is bypassed.
+    pub bypassed: bool,
+    /// The wet/dry mix of the slot, between 0.0 and 1.0.
+    pub mix: f64,
+    /// The parameters of the effect.
+    pub params: Vec<EffectParamView>,
+}
+
+/// Properties for the `EffectSlot` component.
+#[derive(Properties, PartialEq)]
+pub struct EffectSlotProps {
+    /// The slot to display.
+    pub slot: EffectSlotView,
+    /// The position of the slot in the rack.
+    pub index: usize,
+    /// The number of slots in the rack.
+    pub num_slots: usize,
+    /// Callback invoked with the index of the slot to remove.
+    pub remove: Callback<usize>,
+    /// Callback invoked with the current and new index of a slot that is moved.
+    pub move_slot: Callback<(usize, usize)>,
+    /// Callback invoked with the index of the slot whose bypass is toggled.
+    pub toggle_bypass: Callback<usize>,
+    /// Callback invoked with the index of the slot and its new mix.
+    pub mix_change: Callback<(usize, f64)>,
+    /// Callback invoked with the index of the slot, the index of the parameter and its new value.
+    pub param_change: Callback<(usize, usize, f64)>,
+}
+
+/// The `effect_slot` component displays one effect of the rack with its controls.
+#[styled_component(EffectSlot)]
+pub fn effect_slot(props: &EffectSlotProps) -> Html {
+    let index = props.index;
+
+    let remove = props.remove.clone();
+    let remove = Callback::from(move |_| remove.emit(index));
+
+    let move_slot = props.move_slot.clone();
+    let move_up = Callback::from(move |_| {
+        if index > 0 {
+            move_slot.emit((index, index - 1));
+        }
+    });
+
+    let move_slot = props.move_slot.clone();
+    let num_slots = props.num_slots;
+    let move_down = Callback::from(move |_| {
+        if index + 1 < num_slots {
+            move_slot.emit((index, index + 1));
+        }
+    });
+
+    let toggle_bypass = props.toggle_bypass.clone();
+    let toggle_bypass = Callback::from(move |_| toggle_bypass.emit(index));
+
+    let mix_change = props.mix_change.reform(move |mix: f64| (index, mix));
+
+    let params: Vec<Html> = props.slot.params.iter().enumerate().map(|(param_index, param)| {
+        let param_change = props.param_change.reform(move |value: f64| (index, param_index, value));
+        html! {
+            <Slider
+                label={param.name}
+                value={param.value}
+                onchange={param_change}
+                precision={Some(2)}
+                percentage={false}
+                min={param.min}
+                max={param.max}
+                step={Some((param.max - param.min) / 200.0)}
+            />
+        }
+    }).collect();
+
+    let bypass_class = if props.slot.bypassed { "effect_button_active" } else { "effect_button" };
+
+    html! {
+        <div class="effect_slot">
+            <div class="effect_header">
+                <h2>{props.slot.name.clone()}</h2>
+                <CustomButton class={"effect_button"} label={"^"} mouse_down={move_up} mouse_up={&None} />
+                <CustomButton class={"effect_button"} label={"v"} mouse_down={move_down} mouse_up={&None} />
+                <CustomButton class={bypass_class} label={"Bypass"} mouse_down={toggle_bypass} mouse_up={&None} />
+                <CustomButton class={"remove_button"} label={"x"} mouse_down={remove} mouse_up={&None} />
+            </div>
+            <Slider
+                label={"Mix"}
+                value={props.slot.mix}
+                onchange={mix_change}
+                precision={Some(0)}
+                percentage={true}
+                min={0.0}
+                max={1.0}
+                step={Some(0.01)}
+            />
+            {params}
+        </div>
+    }
+}
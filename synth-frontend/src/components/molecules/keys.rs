@@ -1,7 +1,11 @@
 use stylist::yew::styled_component;
+use web_sys::HtmlElement;
 use yew::prelude::*;
 use crate::components::atoms::button::CustomButton;
 
+/// Lowest velocity a click on a key can produce.
+const MIN_CLICK_VELOCITY: f64 = 0.05;
+
 /// Represents the color of a keyboard key.
 #[derive(PartialEq)]
 pub enum KeyColor {
@@ -34,14 +38,29 @@ pub struct KeyProps {
     pub on_mouse_down: Callback<(char, usize)>,
     /// Callback invoked when the mouse button is released on the key.
     pub on_mouse_up: Callback<(char, usize)>,
+    /// Callback invoked with the velocity of a click, between 0.0 and 1.0, just before `on_mouse_down`.
+    #[prop_or_default]
+    pub on_velocity: Callback<f64>,
+}
+
+/// Derives a velocity from how far down the key it was clicked: the bottom edge plays loudest, like
+/// pressing the front of a real key.
+fn click_velocity(event: &MouseEvent) -> f64 {
+    let height = event.target_unchecked_into::<HtmlElement>().client_height() as f64;
+    if height <= 0.0 {
+        return 1.0;
+    }
+    (event.offset_y() as f64 / height).clamp(MIN_CLICK_VELOCITY, 1.0)
 }
 
 /// The `key` component represents a keyboard key with a customizable label and appearance.
 #[styled_component(Key)]
 pub fn key(props: &KeyProps) -> Html {
     let mouse_down = props.on_mouse_down.clone();
+    let velocity = props.on_velocity.clone();
     let label = props.label.clone();
-    let mouse_down = Callback::from(move |_| {
+    let mouse_down = Callback::from(move |event: MouseEvent| {
+        velocity.emit(click_velocity(&event));
         mouse_down.emit(label);
     });
 
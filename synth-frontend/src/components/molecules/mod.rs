@@ -7,9 +7,13 @@
 /// - [`add_button`](crate::components::keyboard::add_button): Contains components for adding new keyboard keys.
 /// - [`remove_button`](crate::components::keyboard::remove_button): Contains components for removing existing keyboard keys.
 /// - [`multi_selector`](crate::components::keyboard::multi_selector): Contains components for selecting multiple keyboard keys.
+/// - [`effect_slot`](crate::components::keyboard::effect_slot): Contains components for displaying one slot of the effects rack.
+/// - [`wheel`](crate::components::keyboard::wheel): Contains the strip used for the pitch bend and mod wheels.
 
 pub mod keys;
 pub mod selector;
 pub mod add_button;
 pub mod remove_button;
-pub mod multi_selector;
\ No newline at end of file
+pub mod multi_selector;
+pub mod effect_slot;
+pub mod wheel;
\ No newline at end of file
@@ -0,0 +1,51 @@
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+/// Properties for the `Wheel` component.
+#[derive(Properties, PartialEq)]
+pub struct WheelProps {
+    /// The label displayed below the wheel.
+    pub label: &'static str,
+    /// The current position of the wheel.
+    pub value: f64,
+    /// The lowest position of the wheel.
+    pub min: f64,
+    /// The highest position of the wheel.
+    pub max: f64,
+    /// Callback invoked with the new position while the wheel moves.
+    pub onchange: Callback<f64>,
+    /// The position the wheel springs back to when it is let go, if any.
+    #[prop_or_default]
+    pub spring_back: Option<f64>,
+}
+
+/// The `Wheel` component is a vertical strip that behaves like the pitch bend and mod wheels of a
+/// hardware keyboard.
+#[function_component(Wheel)]
+pub fn wheel(props: &WheelProps) -> Html {
+    let oninput = props.onchange.reform(|event: InputEvent| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        input.value_as_number()
+    });
+
+    // Range inputs fire `change` once the user lets go, whether by mouse, touch or keyboard
+    let release = props.spring_back.map(|rest| {
+        let onchange = props.onchange.clone();
+        Callback::from(move |_: Event| onchange.emit(rest))
+    });
+
+    html! {
+        <div class="wheel">
+            <input type="range"
+                class="wheel__input"
+                min={props.min.to_string()}
+                max={props.max.to_string()}
+                step={((props.max - props.min) / 1000.0).to_string()}
+                value={props.value.to_string()}
+                {oninput}
+                onchange={release}
+            />
+            <span class="wheel__label">{ props.label }</span>
+        </div>
+    }
+}
@@ -0,0 +1,116 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// CSS styling for the arpeggiator settings.
+const ARPEGGIATOR_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/arpeggiator_settings.css");
+
+/// Properties for the `ArpeggiatorSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct ArpeggiatorProperties {
+    /// Whether the arpeggiator is on.
+    pub enabled: bool,
+    /// Callback invoked when the arpeggiator is switched on or off.
+    pub toggle_enabled: Callback<()>,
+    /// Names of the available modes.
+    pub modes: Vec<String>,
+    /// Index of the selected mode.
+    pub active_mode: usize,
+    /// Callback invoked with the index of the newly selected mode.
+    pub mode_change: Callback<usize>,
+    /// Names of the available rates.
+    pub rates: Vec<String>,
+    /// Index of the selected rate.
+    pub active_rate: usize,
+    /// Callback invoked with the index of the newly selected rate.
+    pub rate_change: Callback<usize>,
+    /// Over how many octaves the pattern runs.
+    pub octaves: f64,
+    /// Callback invoked when the octave range changes.
+    pub octaves_change: Callback<f64>,
+    /// How much of each step a note is held for.
+    pub gate: f64,
+    /// Callback invoked when the gate length changes.
+    pub gate_change: Callback<f64>,
+    /// How much the steps are swung.
+    pub swing: f64,
+    /// Callback invoked when the swing changes.
+    pub swing_change: Callback<f64>,
+    /// Whether the pattern keeps playing after the keys are let go.
+    pub latch: bool,
+    /// Callback invoked when latch is toggled.
+    pub toggle_latch: Callback<()>,
+}
+
+/// Renders a row of buttons of which the one at `active` is highlighted.
+fn choices(names: &[String], active: usize, change: &Callback<usize>) -> Html {
+    names.iter().enumerate().map(|(index, name)| {
+        let change = change.clone();
+        let select = Callback::from(move |_| change.emit(index));
+        let class = if index == active { "arp_choice_active" } else { "arp_choice" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect()
+}
+
+/// Renders a button that switches an option on or off.
+fn toggle(label: &'static str, active: bool, change: &Callback<()>) -> Html {
+    let change = change.clone();
+    let class = if active { "arp_choice_active" } else { "arp_choice" };
+    html! {
+        <CustomButton class={class} label={label} mouse_down={Callback::from(move |_| change.emit(()))} mouse_up={&None} />
+    }
+}
+
+/// The `ArpeggiatorSettings` component switches the arpeggiator on and sets its pattern and timing.
+#[styled_component(ArpeggiatorSettings)]
+pub fn arpeggiator_settings(props: &ArpeggiatorProperties) -> Html {
+    let overall_css = Style::new(ARPEGGIATOR_SETTINGS_CSS).unwrap();
+
+    html! {
+        <div class={overall_css}>
+            <div class="arp_choices">
+                {toggle("On", props.enabled, &props.toggle_enabled)}
+                {toggle("Latch", props.latch, &props.toggle_latch)}
+            </div>
+            <div class="arp_choices">
+                {choices(&props.modes, props.active_mode, &props.mode_change)}
+            </div>
+            <div class="arp_choices">
+                {choices(&props.rates, props.active_rate, &props.rate_change)}
+            </div>
+            <Slider
+                label={"Octaves"}
+                value={props.octaves}
+                onchange={props.octaves_change.clone()}
+                precision={Some(0)}
+                percentage={false}
+                min={1.0}
+                max={4.0}
+                step={Some(1.0)}
+            />
+            <Slider
+                label={"Gate"}
+                value={props.gate}
+                onchange={props.gate_change.clone()}
+                precision={Some(0)}
+                percentage={true}
+                min={0.05}
+                max={1.0}
+                step={Some(0.01)}
+            />
+            <Slider
+                label={"Swing"}
+                value={props.swing}
+                onchange={props.swing_change.clone()}
+                precision={Some(0)}
+                percentage={true}
+                min={0.0}
+                max={0.5}
+                step={Some(0.01)}
+            />
+        </div>
+    }
+}
@@ -0,0 +1,82 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+
+/// CSS styling for the audio settings.
+const AUDIO_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/audio_settings.css");
+
+/// Properties for the `AudioSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct AudioProperties {
+    /// Names of the output devices, starting with the default device.
+    pub devices: Vec<String>,
+    /// Index of the selected device.
+    pub active_device: usize,
+    /// Callback invoked with the index of the newly selected device.
+    pub device_change: Callback<usize>,
+    /// Names of the sample rates to choose from.
+    pub sample_rates: Vec<String>,
+    /// Index of the selected sample rate.
+    pub active_sample_rate: usize,
+    /// Callback invoked with the index of the newly selected sample rate.
+    pub sample_rate_change: Callback<usize>,
+    /// Names of the rates the engine can run at, whatever the device plays at.
+    pub engine_rates: Vec<String>,
+    /// Index of the selected engine rate.
+    pub active_engine_rate: usize,
+    /// Callback invoked with the index of the newly selected engine rate.
+    pub engine_rate_change: Callback<usize>,
+    /// Names of the buffer sizes to choose from.
+    pub buffer_sizes: Vec<String>,
+    /// Index of the selected buffer size.
+    pub active_buffer_size: usize,
+    /// Callback invoked with the index of the newly selected buffer size.
+    pub buffer_size_change: Callback<usize>,
+    /// Description of the format the device plays, or why it does not play.
+    pub status: Result<String, String>,
+}
+
+/// Renders a row of buttons of which the one at `active` is highlighted.
+fn choices(names: &[String], active: usize, change: &Callback<usize>) -> Html {
+    names.iter().enumerate().map(|(index, name)| {
+        let change = change.clone();
+        let select = Callback::from(move |_| change.emit(index));
+        let class = if index == active { "audio_choice_active" } else { "audio_choice" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect()
+}
+
+/// The `AudioSettings` component picks the output device, sample rate and buffer size, and the rate
+/// the engine runs at, and shows the format agreed with the device.
+#[styled_component(AudioSettings)]
+pub fn audio_settings(props: &AudioProperties) -> Html {
+    let overall_css = Style::new(AUDIO_SETTINGS_CSS).unwrap();
+    let status = match &props.status {
+        Ok(status) => html! { <span>{status}</span> },
+        Err(err) => html! { <span class="audio_error">{err}</span> },
+    };
+
+    html! {
+        <div class={overall_css}>
+            <span>{"Device"}</span>
+            <div class="audio_choices">
+                {choices(&props.devices, props.active_device, &props.device_change)}
+            </div>
+            <span>{"Sample Rate"}</span>
+            <div class="audio_choices">
+                {choices(&props.sample_rates, props.active_sample_rate, &props.sample_rate_change)}
+            </div>
+            <span>{"Engine Rate"}</span>
+            <div class="audio_choices">
+                {choices(&props.engine_rates, props.active_engine_rate, &props.engine_rate_change)}
+            </div>
+            <span>{"Buffer"}</span>
+            <div class="audio_choices">
+                {choices(&props.buffer_sizes, props.active_buffer_size, &props.buffer_size_change)}
+            </div>
+            {status}
+        </div>
+    }
+}
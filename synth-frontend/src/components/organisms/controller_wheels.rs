@@ -0,0 +1,46 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::molecules::wheel::Wheel;
+
+/// CSS styling for the controller wheels.
+const CONTROLLER_WHEELS_CSS: &str = include_str!("../../UI_components/key_controllers/controller_wheels.css");
+
+/// Properties for the `ControllerWheels` component.
+#[derive(Properties, PartialEq)]
+pub struct ControllerWheelsProperties {
+    /// The pitch bend, between -1.0 and 1.0.
+    pub pitch_bend: f64,
+    /// Callback invoked when the pitch bend wheel moves.
+    pub pitch_bend_change: Callback<f64>,
+    /// The mod wheel position, between 0.0 and 1.0.
+    pub mod_wheel: f64,
+    /// Callback invoked when the mod wheel moves.
+    pub mod_wheel_change: Callback<f64>,
+}
+
+/// The `ControllerWheels` component shows the pitch bend wheel, which springs back to the centre,
+/// and the mod wheel, which stays where it is left.
+#[styled_component(ControllerWheels)]
+pub fn controller_wheels(props: &ControllerWheelsProperties) -> Html {
+    let overall_css = Style::new(CONTROLLER_WHEELS_CSS).unwrap();
+
+    html! {
+        <div class={overall_css}>
+            <Wheel
+                label={"Pitch"}
+                value={props.pitch_bend}
+                min={-1.0}
+                max={1.0}
+                onchange={props.pitch_bend_change.clone()}
+                spring_back={Some(0.0)}
+            />
+            <Wheel
+                label={"Mod"}
+                value={props.mod_wheel}
+                min={0.0}
+                max={1.0}
+                onchange={props.mod_wheel_change.clone()}
+            />
+        </div>
+    }
+}
@@ -0,0 +1,129 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// CSS styling for the dynamics settings.
+const DYNAMICS_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/dynamics_settings.css");
+
+/// Gain reduction, in decibels, that fills the whole meter.
+const METER_RANGE_DB: f64 = 24.0;
+
+/// Describes the state of the master dynamics section.
+#[derive(Clone, PartialEq)]
+pub struct DynamicsView {
+    /// Whether the compressor is enabled.
+    pub compressor_enabled: bool,
+    /// Whether the limiter is enabled.
+    pub limiter_enabled: bool,
+    /// Compressor threshold in decibels.
+    pub threshold_db: f64,
+    /// Compressor ratio.
+    pub ratio: f64,
+    /// Compressor attack in milliseconds.
+    pub attack_ms: f64,
+    /// Compressor release in milliseconds.
+    pub release_ms: f64,
+    /// Limiter ceiling in decibels.
+    pub ceiling_db: f64,
+    /// Current gain reduction in decibels, as a positive value.
+    pub gain_reduction_db: f64,
+}
+
+/// Properties for the `DynamicsSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct DynamicsProperties {
+    /// The state to display.
+    pub dynamics: DynamicsView,
+    /// Callback invoked when the compressor is switched on or off.
+    pub toggle_compressor: Callback<()>,
+    /// Callback invoked when the limiter is switched on or off.
+    pub toggle_limiter: Callback<()>,
+    /// Callback invoked when the threshold changes.
+    pub threshold_change: Callback<f64>,
+    /// Callback invoked when the ratio changes.
+    pub ratio_change: Callback<f64>,
+    /// Callback invoked when the attack changes.
+    pub attack_change: Callback<f64>,
+    /// Callback invoked when the release changes.
+    pub release_change: Callback<f64>,
+    /// Callback invoked when the ceiling changes.
+    pub ceiling_change: Callback<f64>,
+}
+
+/// The `DynamicsSettings` component shows the master compressor and limiter with a gain reduction meter.
+#[styled_component(DynamicsSettings)]
+pub fn dynamics_settings(props: &DynamicsProperties) -> Html {
+    let overall_css = Style::new(DYNAMICS_SETTINGS_CSS).unwrap();
+    let dynamics = &props.dynamics;
+
+    let toggle_compressor = props.toggle_compressor.reform(|_: MouseEvent| ());
+    let toggle_limiter = props.toggle_limiter.reform(|_: MouseEvent| ());
+    let compressor_class = if dynamics.compressor_enabled { "dynamics_button_active" } else { "dynamics_button" };
+    let limiter_class = if dynamics.limiter_enabled { "dynamics_button_active" } else { "dynamics_button" };
+
+    let meter_width = format!("width: {:.0}%;", (dynamics.gain_reduction_db / METER_RANGE_DB).clamp(0.0, 1.0) * 100.0);
+
+    html! {
+        <div class={overall_css}>
+            <div class="dynamics_header">
+                <CustomButton class={compressor_class} label={"Compressor"} mouse_down={toggle_compressor} mouse_up={&None} />
+                <CustomButton class={limiter_class} label={"Limiter"} mouse_down={toggle_limiter} mouse_up={&None} />
+            </div>
+            <Slider
+                label={"Threshold (dB)"}
+                value={dynamics.threshold_db}
+                onchange={props.threshold_change.clone()}
+                precision={Some(1)}
+                percentage={false}
+                min={-60.0}
+                max={0.0}
+                step={Some(0.5)}
+            />
+            <Slider
+                label={"Ratio"}
+                value={dynamics.ratio}
+                onchange={props.ratio_change.clone()}
+                precision={Some(1)}
+                percentage={false}
+                min={1.0}
+                max={20.0}
+                step={Some(0.1)}
+            />
+            <Slider
+                label={"Attack (ms)"}
+                value={dynamics.attack_ms}
+                onchange={props.attack_change.clone()}
+                precision={Some(1)}
+                percentage={false}
+                min={0.1}
+                max={200.0}
+                step={Some(0.1)}
+            />
+            <Slider
+                label={"Release (ms)"}
+                value={dynamics.release_ms}
+                onchange={props.release_change.clone()}
+                precision={Some(0)}
+                percentage={false}
+                min={5.0}
+                max={2000.0}
+                step={Some(1.0)}
+            />
+            <Slider
+                label={"Ceiling (dB)"}
+                value={dynamics.ceiling_db}
+                onchange={props.ceiling_change.clone()}
+                precision={Some(1)}
+                percentage={false}
+                min={-12.0}
+                max={0.0}
+                step={Some(0.1)}
+            />
+            <div class="gain_reduction">
+                <span>{format!("Gain Reduction: {:.1} dB", dynamics.gain_reduction_db)}</span>
+                <div class="meter"><div class="meter_fill" style={meter_width}></div></div>
+            </div>
+        </div>
+    }
+}
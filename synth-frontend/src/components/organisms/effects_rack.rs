@@ -0,0 +1,67 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::molecules::effect_slot::{EffectSlot, EffectSlotView};
+
+/// CSS for the effects rack component.
+const EFFECTS_RACK_CSS: &str = include_str!("../../UI_components/selectors/effects_rack.css");
+
+/// Properties for the `EffectsRack` component.
+#[derive(Properties, PartialEq)]
+pub struct EffectsRackProperties {
+    /// Names of the effects that can be added to the rack.
+    pub available: Vec<String>,
+    /// The slots currently in the rack, in processing order.
+    pub slots: Vec<EffectSlotView>,
+    /// Callback invoked with the index (in `available`) of the effect to add.
+    pub add_effect: Callback<usize>,
+    /// Callback invoked with the index of the slot to remove.
+    pub remove_effect: Callback<usize>,
+    /// Callback invoked with the current and new index of a slot that is moved.
+    pub move_effect: Callback<(usize, usize)>,
+    /// Callback invoked with the index of the slot whose bypass is toggled.
+    pub toggle_bypass: Callback<usize>,
+    /// Callback invoked with the index of the slot and its new mix.
+    pub mix_change: Callback<(usize, f64)>,
+    /// Callback invoked with the index of the slot, the index of the parameter and its new value.
+    pub param_change: Callback<(usize, usize, f64)>,
+}
+
+/// The `effects_rack` component lists the master effects and lets the user add, remove and reorder them.
+#[styled_component(EffectsRack)]
+pub fn effects_rack(props: &EffectsRackProperties) -> Html {
+    let effects_rack_style = Style::new(EFFECTS_RACK_CSS).unwrap();
+
+    let add_buttons: Vec<Html> = props.available.iter().enumerate().map(|(index, name)| {
+        let add_effect = props.add_effect.clone();
+        let add_effect = Callback::from(move |_| add_effect.emit(index));
+        html! {
+            <CustomButton class={"add_effect"} label={format!("+ {name}")} mouse_down={add_effect} mouse_up={&None} />
+        }
+    }).collect();
+
+    let num_slots = props.slots.len();
+    let slots: Vec<Html> = props.slots.iter().enumerate().map(|(index, slot)| {
+        html! {
+            <EffectSlot
+                slot={slot.clone()}
+                index={index}
+                num_slots={num_slots}
+                remove={props.remove_effect.clone()}
+                move_slot={props.move_effect.clone()}
+                toggle_bypass={props.toggle_bypass.clone()}
+                mix_change={props.mix_change.clone()}
+                param_change={props.param_change.clone()}
+            />
+        }
+    }).collect();
+
+    html! {
+        <div class={effects_rack_style}>
+            <div class="add_effects">
+                {add_buttons}
+            </div>
+            {slots}
+        </div>
+    }
+}
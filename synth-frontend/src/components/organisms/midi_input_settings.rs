@@ -0,0 +1,54 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+
+/// CSS styling for the MIDI input settings.
+const MIDI_INPUT_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/midi_input_settings.css");
+
+/// Properties for the `MidiInputSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct MidiInputProperties {
+    /// Names of the devices to choose from, starting with the choice of every device.
+    pub devices: Vec<String>,
+    /// Index of the selected device.
+    pub active_device: usize,
+    /// Callback invoked with the index of the newly selected device.
+    pub device_change: Callback<usize>,
+    /// Names of the channels to choose from, starting with the choice of every channel.
+    pub channels: Vec<String>,
+    /// Index of the selected channel.
+    pub active_channel: usize,
+    /// Callback invoked with the index of the newly selected channel.
+    pub channel_change: Callback<usize>,
+}
+
+/// Renders a row of buttons of which the one at `active` is highlighted.
+fn choices(names: &[String], active: usize, change: &Callback<usize>) -> Html {
+    names.iter().enumerate().map(|(index, name)| {
+        let change = change.clone();
+        let select = Callback::from(move |_| change.emit(index));
+        let class = if index == active { "midi_input_choice_active" } else { "midi_input_choice" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect()
+}
+
+/// The `MidiInputSettings` component picks the MIDI device and channel the synthesizer listens to.
+#[styled_component(MidiInputSettings)]
+pub fn midi_input_settings(props: &MidiInputProperties) -> Html {
+    let overall_css = Style::new(MIDI_INPUT_SETTINGS_CSS).unwrap();
+
+    html! {
+        <div class={overall_css}>
+            <span>{"Device"}</span>
+            <div class="midi_input_choices">
+                {choices(&props.devices, props.active_device, &props.device_change)}
+            </div>
+            <span>{"Channel"}</span>
+            <div class="midi_input_choices">
+                {choices(&props.channels, props.active_channel, &props.channel_change)}
+            </div>
+        </div>
+    }
+}
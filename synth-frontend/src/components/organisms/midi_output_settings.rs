@@ -0,0 +1,71 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+
+/// CSS styling for the MIDI output settings.
+const MIDI_OUTPUT_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/midi_output_settings.css");
+
+/// Properties for the `MidiOutputSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct MidiOutputProperties {
+    /// Whether notes are sent.
+    pub enabled: bool,
+    /// Callback invoked when sending is switched on or off.
+    pub toggle_enabled: Callback<()>,
+    /// Whether notes sound on the synthesizer as well as being sent.
+    pub local_control: bool,
+    /// Callback invoked when local control is switched on or off.
+    pub toggle_local_control: Callback<()>,
+    /// Names of the output devices.
+    pub devices: Vec<String>,
+    /// Index of the selected device, if any.
+    pub active_device: Option<usize>,
+    /// Callback invoked with the index of the newly selected device.
+    pub device_change: Callback<usize>,
+    /// Names of the channels.
+    pub channels: Vec<String>,
+    /// Index of the selected channel.
+    pub active_channel: usize,
+    /// Callback invoked with the index of the newly selected channel.
+    pub channel_change: Callback<usize>,
+}
+
+/// Renders a row of buttons of which the one at `active` is highlighted.
+fn choices(names: &[String], active: Option<usize>, change: &Callback<usize>) -> Html {
+    names.iter().enumerate().map(|(index, name)| {
+        let change = change.clone();
+        let select = Callback::from(move |_| change.emit(index));
+        let class = if Some(index) == active { "midi_output_choice_active" } else { "midi_output_choice" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect()
+}
+
+/// The `MidiOutputSettings` component picks the MIDI device and channel the notes are sent to, and
+/// switches local control.
+#[styled_component(MidiOutputSettings)]
+pub fn midi_output_settings(props: &MidiOutputProperties) -> Html {
+    let overall_css = Style::new(MIDI_OUTPUT_SETTINGS_CSS).unwrap();
+    let toggle_enabled = props.toggle_enabled.reform(|_: MouseEvent| ());
+    let enabled_class = if props.enabled { "midi_output_choice_active" } else { "midi_output_choice" };
+    let toggle_local_control = props.toggle_local_control.reform(|_: MouseEvent| ());
+    let local_class = if props.local_control { "midi_output_choice_active" } else { "midi_output_choice" };
+
+    html! {
+        <div class={overall_css}>
+            <div class="midi_output_choices">
+                <CustomButton class={enabled_class} label={"On"} mouse_down={toggle_enabled} mouse_up={&None} />
+                <CustomButton class={local_class} label={"Local"} mouse_down={toggle_local_control} mouse_up={&None} />
+            </div>
+            <span>{"Device"}</span>
+            <div class="midi_output_choices">
+                {choices(&props.devices, props.active_device, &props.device_change)}
+            </div>
+            <span>{"Channel"}</span>
+            <div class="midi_output_choices">
+                {choices(&props.channels, Some(props.active_channel), &props.channel_change)}
+            </div>
+        </div>
+    }
+}
@@ -6,6 +6,31 @@ pub mod filter_selector;
 pub mod envelope_settings;
 /// This module contains components related to LFO settings.
 pub mod lfo_settings;
-
-
-
+/// This module contains components related to the master effects rack.
+pub mod effects_rack;
+/// This module contains components related to the master dynamics section.
+pub mod dynamics_settings;
+/// This module contains components related to velocity settings.
+pub mod velocity_settings;
+/// This module contains components related to the pitch bend and mod wheels.
+pub mod controller_wheels;
+/// This module contains components related to the pitch bend range and mod wheel settings.
+pub mod wheel_settings;
+/// This module contains components related to the MPE settings.
+pub mod mpe_settings;
+/// This module contains components related to the MIDI input device and channel.
+pub mod midi_input_settings;
+/// This module contains components related to the MIDI output device, channel and local control.
+pub mod midi_output_settings;
+/// This module contains components related to the voice mode and portamento.
+pub mod voice_mode_settings;
+/// This module contains components related to the transport.
+pub mod transport_settings;
+/// This module contains components related to the arpeggiator.
+pub mod arpeggiator_settings;
+/// This module contains components related to the step sequencer.
+pub mod sequencer_grid;
+/// This module contains components related to the tuning.
+pub mod tuning_settings;
+/// This module contains components related to the audio output device and latency.
+pub mod audio_settings;
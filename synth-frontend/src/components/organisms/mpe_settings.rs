@@ -0,0 +1,97 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// CSS styling for the MPE settings.
+const MPE_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/mpe_settings.css");
+
+/// Properties for the `MpeSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct MpeProperties {
+    /// Whether MPE is on.
+    pub enabled: bool,
+    /// Callback invoked when MPE is switched on or off.
+    pub toggle_enabled: Callback<()>,
+    /// Names of the available zones.
+    pub zones: Vec<String>,
+    /// Index of the selected zone.
+    pub active_zone: usize,
+    /// Callback invoked with the index of the newly selected zone.
+    pub zone_change: Callback<usize>,
+    /// The number of member channels of the zone.
+    pub member_channels: f64,
+    /// Callback invoked when the number of member channels changes.
+    pub member_channels_change: Callback<f64>,
+    /// How many semitones a member channel bends its note by.
+    pub bend_range: f64,
+    /// Callback invoked when the bend range changes.
+    pub bend_range_change: Callback<f64>,
+    /// Names of the available expression targets.
+    pub targets: Vec<String>,
+    /// Index of the target of channel pressure.
+    pub active_pressure_target: usize,
+    /// Callback invoked with the index of the new target of channel pressure.
+    pub pressure_target_change: Callback<usize>,
+    /// Index of the target of timbre.
+    pub active_timbre_target: usize,
+    /// Callback invoked with the index of the new target of timbre.
+    pub timbre_target_change: Callback<usize>,
+}
+
+/// Renders a row of buttons of which the one at `active` is highlighted.
+fn choices(names: &[String], active: usize, change: &Callback<usize>) -> Html {
+    names.iter().enumerate().map(|(index, name)| {
+        let change = change.clone();
+        let select = Callback::from(move |_| change.emit(index));
+        let class = if index == active { "mpe_choice_active" } else { "mpe_choice" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect()
+}
+
+/// The `MpeSettings` component sets the MPE zone and routes pressure and timbre to the voices.
+#[styled_component(MpeSettings)]
+pub fn mpe_settings(props: &MpeProperties) -> Html {
+    let overall_css = Style::new(MPE_SETTINGS_CSS).unwrap();
+    let toggle_enabled = props.toggle_enabled.reform(|_: MouseEvent| ());
+    let enabled_class = if props.enabled { "mpe_choice_active" } else { "mpe_choice" };
+
+    html! {
+        <div class={overall_css}>
+            <div class="mpe_choices">
+                <CustomButton class={enabled_class} label={"On"} mouse_down={toggle_enabled} mouse_up={&None} />
+                {choices(&props.zones, props.active_zone, &props.zone_change)}
+            </div>
+            <Slider
+                label={"Channels"}
+                value={props.member_channels}
+                onchange={props.member_channels_change.clone()}
+                precision={Some(0)}
+                percentage={false}
+                min={1.0}
+                max={15.0}
+                step={Some(1.0)}
+            />
+            <Slider
+                label={"Bend Range (st)"}
+                value={props.bend_range}
+                onchange={props.bend_range_change.clone()}
+                precision={Some(0)}
+                percentage={false}
+                min={1.0}
+                max={96.0}
+                step={Some(1.0)}
+            />
+            <span>{"Pressure"}</span>
+            <div class="mpe_choices">
+                {choices(&props.targets, props.active_pressure_target, &props.pressure_target_change)}
+            </div>
+            <span>{"Timbre"}</span>
+            <div class="mpe_choices">
+                {choices(&props.targets, props.active_timbre_target, &props.timbre_target_change)}
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,206 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// CSS styling for the sequencer grid.
+const SEQUENCER_GRID_CSS: &str = include_str!("../../UI_components/selectors/sequencer_grid.css");
+
+/// Describes one step of a sequencer pattern.
+#[derive(Clone, Copy, PartialEq)]
+pub struct StepView {
+    /// Whether the step plays a note.
+    pub enabled: bool,
+    /// The MIDI key of the note.
+    pub note: u8,
+    /// The velocity of the note, between 0.0 and 1.0.
+    pub velocity: f64,
+    /// How much of the step the note is held for.
+    pub gate: f64,
+    /// Whether the step holds the note of the step before.
+    pub tie: bool,
+    /// The chance that the step plays.
+    pub probability: f64,
+}
+
+/// Describes the state of the step sequencer.
+#[derive(Clone, PartialEq)]
+pub struct SequencerView {
+    /// How much the steps are swung.
+    pub swing: f64,
+    /// The number of patterns.
+    pub patterns: usize,
+    /// Index of the pattern being edited.
+    pub active_pattern: usize,
+    /// Whether all patterns are chained one after another.
+    pub chained: bool,
+    /// The steps of the pattern being edited.
+    pub steps: Vec<StepView>,
+    /// The step that is playing, if the edited pattern is playing.
+    pub current_step: Option<usize>,
+}
+
+/// Properties for the `SequencerGrid` component.
+#[derive(Properties, PartialEq)]
+pub struct SequencerProperties {
+    /// The state to display.
+    pub sequencer: SequencerView,
+    /// Callback invoked when the swing changes.
+    pub swing_change: Callback<f64>,
+    /// Callback invoked with the index of the pattern to edit.
+    pub pattern_change: Callback<usize>,
+    /// Callback invoked when a pattern is added.
+    pub add_pattern: Callback<()>,
+    /// Callback invoked when chaining is switched on or off.
+    pub toggle_chain: Callback<()>,
+    /// Callback invoked with the new number of steps of the edited pattern.
+    pub length_change: Callback<usize>,
+    /// Callback invoked with the index and new value of a step of the edited pattern.
+    pub step_change: Callback<(usize, StepView)>,
+}
+
+/// Renders a button that is highlighted when `active` is set.
+fn choice(label: String, active: bool, change: Callback<MouseEvent>) -> Html {
+    let class = if active { "seq_choice_active" } else { "seq_choice" };
+    html! {
+        <CustomButton class={class} label={label} mouse_down={change} mouse_up={&None} />
+    }
+}
+
+/// The `SequencerGrid` component shows the steps of a pattern and edits the selected step.
+#[styled_component(SequencerGrid)]
+pub fn sequencer_grid(props: &SequencerProperties) -> Html {
+    let overall_css = Style::new(SEQUENCER_GRID_CSS).unwrap();
+    let sequencer = &props.sequencer;
+    let selected = use_state(|| 0);
+    let selected_index = (*selected).min(sequencer.steps.len().saturating_sub(1));
+
+    let add_pattern = props.add_pattern.reform(|_: MouseEvent| ());
+    let toggle_chain = props.toggle_chain.reform(|_: MouseEvent| ());
+
+    let patterns: Html = (0..sequencer.patterns).map(|index| {
+        let change = props.pattern_change.reform(move |_: MouseEvent| index);
+        choice(format!("{}", index + 1), index == sequencer.active_pattern, change)
+    }).collect();
+
+    let lengths: Html = [16, 32].into_iter().map(|length| {
+        let change = props.length_change.reform(move |_: MouseEvent| length);
+        choice(format!("{length}"), length == sequencer.steps.len(), change)
+    }).collect();
+
+    // Clicking a step turns it on or off and selects it for editing
+    let steps: Html = sequencer.steps.iter().enumerate().map(|(index, step)| {
+        let step = *step;
+        let step_change = props.step_change.clone();
+        let cloned_selected = selected.clone();
+        let select = Callback::from(move |_: MouseEvent| {
+            cloned_selected.set(index);
+            step_change.emit((index, StepView { enabled: !step.enabled, ..step }));
+        });
+        let mut class = String::from(if step.enabled { "seq_step_on" } else { "seq_step" });
+        if index == selected_index {
+            class.push_str(" seq_step_selected");
+        }
+        if sequencer.current_step == Some(index) {
+            class.push_str(" seq_step_playing");
+        }
+        let label = if step.enabled && step.tie { String::from("~") } else { format!("{}", index + 1) };
+        html! {
+            <CustomButton class={class} label={label} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect();
+
+    let editor = match sequencer.steps.get(selected_index) {
+        Some(step) => {
+            let step = *step;
+            let step_change = |update: fn(&mut StepView, f64)| {
+                let step_change = props.step_change.clone();
+                Callback::from(move |value: f64| {
+                    let mut changed = step;
+                    update(&mut changed, value);
+                    step_change.emit((selected_index, changed));
+                })
+            };
+            let step_change_clone = props.step_change.clone();
+            let toggle_tie = Callback::from(move |_: MouseEvent| {
+                step_change_clone.emit((selected_index, StepView { tie: !step.tie, ..step }));
+            });
+            html! {
+                <div class="seq_editor">
+                    <div class="seq_choices">
+                        <span class="seq_label">{format!("Step {}", selected_index + 1)}</span>
+                        {choice(String::from("Tie"), step.tie, toggle_tie)}
+                    </div>
+                    <Slider
+                        label={"Note"}
+                        value={step.note as f64}
+                        onchange={step_change(|step, note| step.note = note as u8)}
+                        precision={Some(0)}
+                        percentage={false}
+                        min={0.0}
+                        max={127.0}
+                        step={Some(1.0)}
+                    />
+                    <Slider
+                        label={"Velocity"}
+                        value={step.velocity}
+                        onchange={step_change(|step, velocity| step.velocity = velocity)}
+                        precision={Some(0)}
+                        percentage={true}
+                        min={0.0}
+                        max={1.0}
+                        step={Some(0.01)}
+                    />
+                    <Slider
+                        label={"Gate"}
+                        value={step.gate}
+                        onchange={step_change(|step, gate| step.gate = gate)}
+                        precision={Some(0)}
+                        percentage={true}
+                        min={0.05}
+                        max={1.0}
+                        step={Some(0.01)}
+                    />
+                    <Slider
+                        label={"Chance"}
+                        value={step.probability}
+                        onchange={step_change(|step, probability| step.probability = probability)}
+                        precision={Some(0)}
+                        percentage={true}
+                        min={0.0}
+                        max={1.0}
+                        step={Some(0.01)}
+                    />
+                </div>
+            }
+        },
+        None => html! {}
+    };
+
+    html! {
+        <div class={overall_css}>
+            <div class="seq_choices">
+                {choice(String::from("Chain"), sequencer.chained, toggle_chain)}
+                {lengths}
+            </div>
+            <div class="seq_choices">
+                {patterns}
+                {choice(String::from("+"), false, add_pattern)}
+            </div>
+            <Slider
+                label={"Swing"}
+                value={sequencer.swing}
+                onchange={props.swing_change.clone()}
+                precision={Some(0)}
+                percentage={true}
+                min={0.0}
+                max={0.5}
+                step={Some(0.01)}
+            />
+            <div class="seq_grid">
+                {steps}
+            </div>
+            {editor}
+        </div>
+    }
+}
@@ -0,0 +1,104 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// CSS styling for the transport settings.
+const TRANSPORT_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/transport_settings.css");
+
+/// Describes the state of the transport.
+#[derive(Clone, PartialEq)]
+pub struct TransportView {
+    /// Whether the transport is playing, or waiting for an external clock to start.
+    pub playing: bool,
+    /// The tempo in beats per minute.
+    pub bpm: f64,
+    /// The number of beats in a bar.
+    pub numerator: f64,
+    /// Index of the note value of a beat.
+    pub active_denominator: usize,
+    /// Index of the clock source.
+    pub active_source: usize,
+    /// The song position, as bar, beat and tick.
+    pub position: String,
+}
+
+/// Properties for the `TransportSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct TransportProperties {
+    /// The state to display.
+    pub transport: TransportView,
+    /// Callback invoked when the transport is started or stopped.
+    pub toggle_playing: Callback<()>,
+    /// Callback invoked when the tempo changes.
+    pub bpm_change: Callback<f64>,
+    /// Callback invoked when the number of beats in a bar changes.
+    pub numerator_change: Callback<f64>,
+    /// Names of the available note values of a beat.
+    pub denominators: Vec<String>,
+    /// Callback invoked with the index of the newly selected note value.
+    pub denominator_change: Callback<usize>,
+    /// Names of the available clock sources.
+    pub sources: Vec<String>,
+    /// Callback invoked with the index of the newly selected clock source.
+    pub source_change: Callback<usize>,
+}
+
+/// Renders a row of buttons of which the one at `active` is highlighted.
+fn choices(names: &[String], active: usize, change: &Callback<usize>) -> Html {
+    names.iter().enumerate().map(|(index, name)| {
+        let change = change.clone();
+        let select = Callback::from(move |_| change.emit(index));
+        let class = if index == active { "transport_choice_active" } else { "transport_choice" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect()
+}
+
+/// The `TransportSettings` component starts and stops the transport and sets the tempo, time
+/// signature and clock source shared by the arpeggiator, the sequencer and the effects.
+#[styled_component(TransportSettings)]
+pub fn transport_settings(props: &TransportProperties) -> Html {
+    let overall_css = Style::new(TRANSPORT_SETTINGS_CSS).unwrap();
+    let transport = &props.transport;
+
+    let toggle_playing = props.toggle_playing.reform(|_: MouseEvent| ());
+    let play_class = if transport.playing { "transport_choice_active" } else { "transport_choice" };
+    let play_label = if transport.playing { "Stop" } else { "Play" };
+
+    html! {
+        <div class={overall_css}>
+            <div class="transport_choices">
+                <CustomButton class={play_class} label={play_label} mouse_down={toggle_playing} mouse_up={&None} />
+                <span class="transport_position">{&transport.position}</span>
+            </div>
+            <div class="transport_choices">
+                {choices(&props.sources, transport.active_source, &props.source_change)}
+            </div>
+            <Slider
+                label={"BPM"}
+                value={transport.bpm}
+                onchange={props.bpm_change.clone()}
+                precision={Some(0)}
+                percentage={false}
+                min={20.0}
+                max={300.0}
+                step={Some(1.0)}
+            />
+            <Slider
+                label={"Beats"}
+                value={transport.numerator}
+                onchange={props.numerator_change.clone()}
+                precision={Some(0)}
+                percentage={false}
+                min={1.0}
+                max={16.0}
+                step={Some(1.0)}
+            />
+            <div class="transport_choices">
+                {choices(&props.denominators, transport.active_denominator, &props.denominator_change)}
+            </div>
+        </div>
+    }
+}
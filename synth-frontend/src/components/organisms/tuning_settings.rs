@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use gloo::file::{callbacks::{read_as_text, FileReader}, File};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// CSS styling for the tuning settings.
+const TUNING_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/tuning_settings.css");
+
+/// Describes the tuning that is loaded.
+#[derive(Clone, PartialEq)]
+pub struct TuningView {
+    /// The name of the tuning.
+    pub name: String,
+    /// The description of its scale.
+    pub description: String,
+    /// The frequency of the reference key in Hz.
+    pub reference_hz: f64,
+    /// Why the last scale or keyboard mapping could not be loaded, if it could not.
+    pub error: Option<String>,
+}
+
+/// Properties for the `TuningSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct TuningProperties {
+    /// The tuning to display.
+    pub tuning: TuningView,
+    /// Names of the built-in tunings.
+    pub presets: Vec<String>,
+    /// Callback invoked with the index of the built-in tuning to load.
+    pub preset_change: Callback<usize>,
+    /// Callback invoked when the reference pitch changes.
+    pub reference_change: Callback<f64>,
+    /// Callback invoked with the text of a Scala `.scl` file.
+    pub load_scale: Callback<String>,
+    /// Callback invoked with the text of a Scala `.kbm` file.
+    pub load_mapping: Callback<String>,
+}
+
+/// Creates a callback that reads the file picked in a file input as text and passes it on to `load`.
+/// The reader is kept in `reader` until it has finished.
+fn read_file(reader: &Rc<RefCell<Option<FileReader>>>, load: &Callback<String>) -> Callback<Event> {
+    let reader = reader.clone();
+    let load = load.clone();
+    Callback::from(move |event: Event| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        if let Some(file) = input.files().and_then(|files| files.get(0)) {
+            let load = load.clone();
+            *reader.borrow_mut() = Some(read_as_text(&File::from(file), move |text| {
+                if let Ok(text) = text {
+                    load.emit(text);
+                }
+            }));
+        }
+        input.set_value("");
+    })
+}
+
+/// The `TuningSettings` component shows the loaded tuning, picks a built-in one or loads Scala files,
+/// and sets the reference pitch.
+#[styled_component(TuningSettings)]
+pub fn tuning_settings(props: &TuningProperties) -> Html {
+    let overall_css = Style::new(TUNING_SETTINGS_CSS).unwrap();
+    let reader = use_mut_ref(|| None::<FileReader>);
+    let tuning = &props.tuning;
+
+    let presets: Html = props.presets.iter().enumerate().map(|(index, name)| {
+        let select = props.preset_change.reform(move |_: MouseEvent| index);
+        let class = if *name == tuning.name { "tuning_choice_active" } else { "tuning_choice" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect();
+
+    html! {
+        <div class={overall_css}>
+            <span class="tuning_name">{&tuning.name}</span>
+            <span class="tuning_description">{&tuning.description}</span>
+            <div class="tuning_choices">
+                {presets}
+            </div>
+            <label class="tuning_file">
+                {"Scale (.scl) "}
+                <input type="file" accept=".scl" onchange={read_file(&reader, &props.load_scale)} />
+            </label>
+            <label class="tuning_file">
+                {"Mapping (.kbm) "}
+                <input type="file" accept=".kbm" onchange={read_file(&reader, &props.load_mapping)} />
+            </label>
+            {
+                match &tuning.error {
+                    Some(error) => html! { <span class="tuning_error">{error}</span> },
+                    None => html! {}
+                }
+            }
+            <Slider
+                label={"Reference (Hz)"}
+                value={tuning.reference_hz}
+                onchange={props.reference_change.clone()}
+                precision={Some(1)}
+                percentage={false}
+                min={400.0}
+                max={480.0}
+                step={Some(0.1)}
+            />
+        </div>
+    }
+}
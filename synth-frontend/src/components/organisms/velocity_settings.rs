@@ -0,0 +1,98 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// CSS styling for the velocity settings.
+const VELOCITY_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/velocity_settings.css");
+
+/// Properties for the `VelocitySettings` component.
+#[derive(Properties, PartialEq)]
+pub struct VelocityProperties {
+    /// Names of the available velocity curves.
+    pub curves: Vec<String>,
+    /// Index of the selected curve.
+    pub active_curve: usize,
+    /// Callback invoked with the index of the newly selected curve.
+    pub curve_change: Callback<usize>,
+    /// How much velocity controls the amplitude, between 0.0 and 1.0.
+    pub amplitude: f64,
+    /// Callback invoked when the amplitude amount changes.
+    pub amplitude_change: Callback<f64>,
+    /// How many octaves velocity moves the filter cutoff.
+    pub filter_octaves: f64,
+    /// Callback invoked when the filter amount changes.
+    pub filter_change: Callback<f64>,
+    /// How much velocity shortens the attack, between 0.0 and 1.0.
+    pub attack: f64,
+    /// Callback invoked when the attack amount changes.
+    pub attack_change: Callback<f64>,
+    /// Velocity of notes played on the computer keyboard, between 0.0 and 1.0.
+    pub keyboard_velocity: f64,
+    /// Callback invoked when the computer keyboard velocity changes.
+    pub keyboard_velocity_change: Callback<f64>,
+}
+
+/// The `VelocitySettings` component selects the velocity curve of the patch and how much velocity
+/// changes the amplitude, filter and attack.
+#[styled_component(VelocitySettings)]
+pub fn velocity_settings(props: &VelocityProperties) -> Html {
+    let overall_css = Style::new(VELOCITY_SETTINGS_CSS).unwrap();
+
+    let curves: Vec<Html> = props.curves.iter().enumerate().map(|(index, name)| {
+        let curve_change = props.curve_change.clone();
+        let select = Callback::from(move |_| curve_change.emit(index));
+        let class = if index == props.active_curve { "velocity_curve_active" } else { "velocity_curve" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect();
+
+    html! {
+        <div class={overall_css}>
+            <div class="velocity_curves">
+                {curves}
+            </div>
+            <Slider
+                label={"Amplitude"}
+                value={props.amplitude}
+                onchange={props.amplitude_change.clone()}
+                precision={Some(0)}
+                percentage={true}
+                min={0.0}
+                max={1.0}
+                step={Some(0.01)}
+            />
+            <Slider
+                label={"Filter (oct)"}
+                value={props.filter_octaves}
+                onchange={props.filter_change.clone()}
+                precision={Some(1)}
+                percentage={false}
+                min={0.0}
+                max={6.0}
+                step={Some(0.1)}
+            />
+            <Slider
+                label={"Attack"}
+                value={props.attack}
+                onchange={props.attack_change.clone()}
+                precision={Some(0)}
+                percentage={true}
+                min={0.0}
+                max={1.0}
+                step={Some(0.01)}
+            />
+            <Slider
+                label={"Keyboard Velocity"}
+                value={props.keyboard_velocity}
+                onchange={props.keyboard_velocity_change.clone()}
+                precision={Some(0)}
+                percentage={true}
+                min={0.0}
+                max={1.0}
+                step={Some(0.01)}
+            />
+        </div>
+    }
+}
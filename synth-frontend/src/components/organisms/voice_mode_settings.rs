@@ -0,0 +1,91 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// CSS styling for the voice mode settings.
+const VOICE_MODE_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/voice_mode_settings.css");
+
+/// Properties for the `VoiceModeSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct VoiceModeProperties {
+    /// Names of the available voice modes.
+    pub modes: Vec<String>,
+    /// Index of the selected voice mode.
+    pub active_mode: usize,
+    /// Callback invoked with the index of the newly selected voice mode.
+    pub mode_change: Callback<usize>,
+    /// Names of the available note priorities.
+    pub priorities: Vec<String>,
+    /// Index of the selected note priority.
+    pub active_priority: usize,
+    /// Callback invoked with the index of the newly selected note priority.
+    pub priority_change: Callback<usize>,
+    /// The glide time in milliseconds.
+    pub glide_ms: f64,
+    /// Callback invoked when the glide time changes.
+    pub glide_change: Callback<f64>,
+    /// Names of the available glide modes.
+    pub glide_modes: Vec<String>,
+    /// Index of the selected glide mode.
+    pub active_glide_mode: usize,
+    /// Callback invoked with the index of the newly selected glide mode.
+    pub glide_mode_change: Callback<usize>,
+    /// Whether the voice only glides between overlapping notes.
+    pub legato_glide_only: bool,
+    /// Callback invoked when the legato glide option is toggled.
+    pub toggle_legato_glide: Callback<()>,
+}
+
+/// Renders a row of buttons of which the one at `active` is highlighted.
+fn choices(names: &[String], active: usize, change: &Callback<usize>) -> Html {
+    names.iter().enumerate().map(|(index, name)| {
+        let change = change.clone();
+        let select = Callback::from(move |_| change.emit(index));
+        let class = if index == active { "voice_choice_active" } else { "voice_choice" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect()
+}
+
+/// The `VoiceModeSettings` component selects between polyphonic and single voice playing, the note
+/// priority of a single voice and its portamento.
+#[styled_component(VoiceModeSettings)]
+pub fn voice_mode_settings(props: &VoiceModeProperties) -> Html {
+    let overall_css = Style::new(VOICE_MODE_SETTINGS_CSS).unwrap();
+
+    let toggle_legato_glide = props.toggle_legato_glide.clone();
+    let legato_glide_class = if props.legato_glide_only { "voice_choice_active" } else { "voice_choice" };
+
+    html! {
+        <div class={overall_css}>
+            <div class="voice_choices">
+                {choices(&props.modes, props.active_mode, &props.mode_change)}
+            </div>
+            <span>{"Priority"}</span>
+            <div class="voice_choices">
+                {choices(&props.priorities, props.active_priority, &props.priority_change)}
+            </div>
+            <Slider
+                label={"Glide (ms)"}
+                value={props.glide_ms}
+                onchange={props.glide_change.clone()}
+                precision={Some(0)}
+                percentage={false}
+                min={0.0}
+                max={2000.0}
+                step={Some(1.0)}
+            />
+            <div class="voice_choices">
+                {choices(&props.glide_modes, props.active_glide_mode, &props.glide_mode_change)}
+                <CustomButton
+                    class={legato_glide_class}
+                    label={"Legato Only"}
+                    mouse_down={Callback::from(move |_| toggle_legato_glide.emit(()))}
+                    mouse_up={&None}
+                />
+            </div>
+        </div>
+    }
+}
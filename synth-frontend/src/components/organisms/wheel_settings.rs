@@ -0,0 +1,70 @@
+use yew::prelude::*;
+use stylist::{yew::styled_component, Style};
+use crate::components::atoms::button::CustomButton;
+use crate::components::atoms::slider::Slider;
+
+/// CSS styling for the wheel settings.
+const WHEEL_SETTINGS_CSS: &str = include_str!("../../UI_components/selectors/wheel_settings.css");
+
+/// Properties for the `WheelSettings` component.
+#[derive(Properties, PartialEq)]
+pub struct WheelSettingsProperties {
+    /// How many semitones the pitch bend wheel bends up.
+    pub bend_up: f64,
+    /// Callback invoked when the upward bend range changes.
+    pub bend_up_change: Callback<f64>,
+    /// How many semitones the pitch bend wheel bends down.
+    pub bend_down: f64,
+    /// Callback invoked when the downward bend range changes.
+    pub bend_down_change: Callback<f64>,
+    /// Names of the available mod wheel targets.
+    pub targets: Vec<String>,
+    /// Index of the selected mod wheel target.
+    pub active_target: usize,
+    /// Callback invoked with the index of the newly selected target.
+    pub target_change: Callback<usize>,
+}
+
+/// The `WheelSettings` component sets the pitch bend range and what the mod wheel controls.
+#[styled_component(WheelSettings)]
+pub fn wheel_settings(props: &WheelSettingsProperties) -> Html {
+    let overall_css = Style::new(WHEEL_SETTINGS_CSS).unwrap();
+
+    let targets: Vec<Html> = props.targets.iter().enumerate().map(|(index, name)| {
+        let target_change = props.target_change.clone();
+        let select = Callback::from(move |_| target_change.emit(index));
+        let class = if index == props.active_target { "mod_target_active" } else { "mod_target" };
+        html! {
+            <CustomButton class={class} label={name.clone()} mouse_down={select} mouse_up={&None} />
+        }
+    }).collect();
+
+    html! {
+        <div class={overall_css}>
+            <Slider
+                label={"Bend Up (st)"}
+                value={props.bend_up}
+                onchange={props.bend_up_change.clone()}
+                precision={Some(0)}
+                percentage={false}
+                min={0.0}
+                max={24.0}
+                step={Some(1.0)}
+            />
+            <Slider
+                label={"Bend Down (st)"}
+                value={props.bend_down}
+                onchange={props.bend_down_change.clone()}
+                precision={Some(0)}
+                percentage={false}
+                min={0.0}
+                max={24.0}
+                step={Some(1.0)}
+            />
+            <span>{"Mod Wheel"}</span>
+            <div class="mod_targets">
+                {targets}
+            </div>
+        </div>
+    }
+}
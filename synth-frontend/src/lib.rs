@@ -49,6 +49,13 @@ pub struct MIDIKeyboardProperties {
     pub key_down: Callback<char>,
     /// Callback for key up events.
     pub key_up: Callback<char>,
+    /// Callback invoked with the velocity of a click on a key, between 0.0 and 1.0, just before
+    /// `mouse_down`.
+    #[prop_or_default]
+    pub velocity_change: Callback<f64>,
+    /// Keys lit up by notes from outside the keyboard, such as a MIDI controller.
+    #[prop_or_default]
+    pub lit_keys: Vec<char>,
 }
 
 /// Renders a MIDI keyboard component.
@@ -78,6 +85,7 @@ pub fn midi_keyboard(props: &MIDIKeyboardProperties) -> Html {
 
     let mouse_down = props.mouse_down.clone();
     let mouse_up = props.mouse_up.clone();
+    let velocity_change = props.velocity_change.clone();
 
     let key_down = props.key_down.clone();
     let cloned_class_hashmap = class_hashmap.clone();
@@ -117,6 +125,8 @@ pub fn midi_keyboard(props: &MIDIKeyboardProperties) -> Html {
         key_up.emit(key_pressed);
     });
 
+    let key_class = |key: char| if props.lit_keys.contains(&key) { "keycodes_active" } else { class_hashmap.deref()[&key] };
+
     let octave_down_mouse_down = props.mouse_down.clone();
     let octave_down_mouse_down = Callback::from(move |_| {
         octave_down_mouse_down.emit(('Z', 0))
@@ -130,26 +140,26 @@ pub fn midi_keyboard(props: &MIDIKeyboardProperties) -> Html {
             <KeyboardListener key_down={&key_down} key_up={&key_up}/>
             <div class={black_keys_style}>
                 <div id="corner-left" class="filler" ></div>
-                <Key button_class={class_hashmap.deref()[&'W']} label={('W', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up}/>
-                <Key button_class={class_hashmap.deref()[&'E']} label={('E', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up}/>
+                <Key button_class={key_class('W')} label={('W', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up} on_velocity={&velocity_change}/>
+                <Key button_class={key_class('E')} label={('E', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up} on_velocity={&velocity_change}/>
                 <div class="filler"></div>
                 <div id="corner-left" class="filler"></div>
-                <Key button_class={class_hashmap.deref()[&'T']} label={('T', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up}/>
-                <Key button_class={class_hashmap.deref()[&'Y']} label={('Y', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up}/>
-                <Key button_class={class_hashmap.deref()[&'U']} label={('U', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up}/>
+                <Key button_class={key_class('T')} label={('T', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up} on_velocity={&velocity_change}/>
+                <Key button_class={key_class('Y')} label={('Y', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up} on_velocity={&velocity_change}/>
+                <Key button_class={key_class('U')} label={('U', 0)} key_color={KeyColor::Black} on_mouse_down={&mouse_down} on_mouse_up={&mouse_up} on_velocity={&velocity_change}/>
                 <div class="filler"></div>
                 <div id="corner-left" class="filler"></div>
                 <div id="corner-right" class="filler"></div>
             </div>
             <div class={white_keys_style}>
-                <Key button_class={class_hashmap.deref()[&'A']} label={('A', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} />
-                <Key button_class={class_hashmap.deref()[&'S']} label={('S', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} />
-                <Key button_class={class_hashmap.deref()[&'D']} label={('D', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} />
-                <Key button_class={class_hashmap.deref()[&'F']} label={('F', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} />
-                <Key button_class={class_hashmap.deref()[&'G']} label={('G', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} />
-                <Key button_class={class_hashmap.deref()[&'H']} label={('H', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} />
-                <Key button_class={class_hashmap.deref()[&'J']} label={('J', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} />
-                <Key button_class={class_hashmap.deref()[&'K']} label={('K', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} />
+                <Key button_class={key_class('A')} label={('A', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} on_velocity={&velocity_change} />
+                <Key button_class={key_class('S')} label={('S', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} on_velocity={&velocity_change} />
+                <Key button_class={key_class('D')} label={('D', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} on_velocity={&velocity_change} />
+                <Key button_class={key_class('F')} label={('F', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} on_velocity={&velocity_change} />
+                <Key button_class={key_class('G')} label={('G', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} on_velocity={&velocity_change} />
+                <Key button_class={key_class('H')} label={('H', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} on_velocity={&velocity_change} />
+                <Key button_class={key_class('J')} label={('J', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} on_velocity={&velocity_change} />
+                <Key button_class={key_class('K')} label={('K', 0)} key_color={KeyColor::White} on_mouse_down={&mouse_down} on_mouse_up= {&mouse_up} on_velocity={&velocity_change} />
             </div>
             <div class={octave_change_style}>
                 <CustomButton 
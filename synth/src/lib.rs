@@ -43,47 +43,190 @@
 
 use std::collections::HashMap;
 use std::{ops::Deref, sync::{Arc, Mutex}};
-use synth_backend::{filters::FilterParam, ring_buffer::IterablePolyphonyHashMap, utils::{decrease_octave, increase_octave}};
-use synth_backend::oscillators::{MultiOscillator, Oscillator, WaveTableOscillator};
+use synth_backend::{filters::FilterParam, engine::IterablePolyphonyHashMap, utils::{decrease_octave, increase_octave}};
+use synth_backend::oscillators::{MultiOscillator, Oscillator, WaveTableOscillator, DEFAULT_TABLE_SIZE};
 use synth_backend::envelopes::{EnvelopeParam, Envelope};
 use synth_backend::lfo::{LFO, LFOType};
 use yew::prelude::*;
 use stylist::yew::styled_component;
 use gloo::console::log;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{FromSample, SampleRate, SizedSample, Stream, StreamConfig};
 
 use synth_frontend::{components::organisms::lfo_settings::LFOSelector, MIDIKeyboard};
 use synth_frontend::components::molecules::add_button::AddButton;
 use synth_frontend::components::organisms::{oscillator_selector::OscillatorSelector, filter_selector::FilterSelector, envelope_settings::EnvelopeSettings};
-use synth_backend::utils::{midi_to_hz, create_stream};
+use synth_backend::audio::{output_device_names, AudioBackend, CpalBackend, StreamFormat, StreamPreferences};
+use synth_frontend::components::organisms::audio_settings::AudioSettings;
+use std::cell::RefCell;
 use synth_backend::filters::{Filter, FilterType};
 use synth_backend::wrapper::Synth;
+use synth_backend::effects::{EffectsChain, EffectType};
+use synth_frontend::components::organisms::effects_rack::EffectsRack;
+use synth_frontend::components::molecules::effect_slot::{EffectSlotView, EffectParamView};
+use synth_backend::dynamics::{CompressorParam, LimiterParam, MasterDynamics};
+use synth_frontend::components::organisms::dynamics_settings::{DynamicsSettings, DynamicsView};
+use gloo::timers::callback::Interval;
+use synth_backend::velocity::{VelocityCurve, DEFAULT_VELOCITY};
+use synth_frontend::components::organisms::velocity_settings::VelocitySettings;
+use synth_backend::controllers::{ControllerSettings, Controllers, ModWheelTarget};
+use synth_frontend::components::organisms::controller_wheels::ControllerWheels;
+use synth_frontend::components::organisms::wheel_settings::WheelSettings;
+use synth_backend::mpe::{ExpressionTarget, MpeSettings, MpeZone, NUM_CHANNELS};
+use synth_frontend::components::organisms::mpe_settings::MpeSettings as MpeSelector;
+use synth_backend::midi::{MidiDispatcher, MidiOutputSettings};
+use synth_frontend::components::atoms::midi_listener::{MidiDevice, MidiListener, MidiOutputPort};
+use synth_frontend::components::organisms::midi_input_settings::MidiInputSettings;
+use synth_frontend::components::organisms::midi_output_settings::MidiOutputSettings as MidiOutputSelector;
+use synth_backend::voice_mode::{GlideMode, NotePriority, VoiceMode, VoiceModeSettings};
+use synth_frontend::components::organisms::voice_mode_settings::VoiceModeSettings as VoiceModeSelector;
+use synth_backend::arpeggiator::{ArpMode, ArpRate, ArpeggiatorSettings};
+use synth_frontend::components::organisms::arpeggiator_settings::ArpeggiatorSettings as ArpeggiatorSelector;
+use synth_backend::sequencer::{Sequencer, Step};
+use synth_frontend::components::organisms::sequencer_grid::{SequencerGrid, SequencerView, StepView};
+use synth_backend::transport::{ClockSource, TimeSignature};
+use synth_frontend::components::organisms::transport_settings::{TransportSettings, TransportView};
+use synth_backend::tuning::{KeyboardMapping, Scale, Tuning};
+use synth_frontend::components::organisms::tuning_settings::{TuningSettings, TuningView};
 
 const OVERALL_CSS: &str = include_str!("../../synth-frontend/src/UI_components/overall.css");
+/// Sample rate the engine runs at when there is no output device.
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+/// Number of frames the output device asks for at a time, unless another size is picked.
+const BUFFER_SIZE: u32 = 1024;
+/// Sample rates offered in the audio settings, `None` leaving it to the negotiation.
+const SAMPLE_RATES: [Option<u32>; 4] = [None, Some(44100), Some(48000), Some(96000)];
+/// Rates the engine can be fixed at in the audio settings, `None` following the device.
+const ENGINE_RATES: [Option<u32>; 5] = [None, Some(44100), Some(48000), Some(88200), Some(96000)];
+/// Buffer sizes offered in the audio settings, `None` leaving it to the device.
+const BUFFER_SIZES: [Option<u32>; 6] = [None, Some(128), Some(256), Some(512), Some(1024), Some(2048)];
 
 #[styled_component(App)]
 pub fn app() -> Html {
-    let host = cpal::default_host();
-    let device = host.default_output_device().expect("No default output device found");
-    let mut supported_configs_range = device.supported_output_configs()
-    .expect("error while querying configs");
-    let supported_config = supported_configs_range.next()
-        .expect("no supported config?!")
-        .with_max_sample_rate();
-    let sample_rate = supported_config.sample_rate().0;
-    let supported_configs = device.supported_output_configs().unwrap();
-    let config = 
-        match supported_configs
-        .filter(|c| c.channels() == 1)
-        .max_by(|a, b| a.cmp_default_heuristics(b)) {
-            Some(config) => config.with_sample_rate(SampleRate(sample_rate)).into(),
-            _ => panic!("No supported configuration found for output device")
+    // The audio backend is opened once, and again when the device, sample rate or buffer size changes.
+    // The engine is prepared for the rate of the device it is started on, or resampled to it from a
+    // fixed engine rate. Without a usable output device the synth still runs, silently
+    let audio_preferences = StreamPreferences { buffer_size: Some(BUFFER_SIZE), ..Default::default() };
+    let backend = use_mut_ref(|| CpalBackend::open_named(None, audio_preferences));
+    let device_rate = backend.borrow().as_ref().map_or(DEFAULT_SAMPLE_RATE, |backend| backend.sample_rate());
+    // In real-time mode the engine never allocates while the audio callback holds it
+    let polyphony = use_state(|| {
+        let mut engine = IterablePolyphonyHashMap::new(device_rate);
+        engine.set_real_time(true);
+        Arc::new(Mutex::new(engine))
+    });
+    // Voices are made at the rate the engine runs at
+    let sample_rate = polyphony.lock().unwrap().sample_rate();
+    let audio_devices = use_state(|| output_device_names().unwrap_or_default());
+    let audio_device = use_state(|| None::<String>);
+    let audio_sample_rate = use_state(|| None::<u32>);
+    let engine_rate = use_state(|| None::<u32>);
+    let audio_buffer_size = use_state(|| Some(BUFFER_SIZE));
+    let audio_status = use_state(|| Err::<StreamFormat, String>("Starting".to_owned()));
+
+    let cloned_backend = backend.clone();
+    let cloned_poly = polyphony.clone();
+    let cloned_audio_status = audio_status.clone();
+    use_effect_with((), move |_| {
+        let status = match cloned_backend.borrow_mut().as_mut() {
+            Ok(backend) => backend.start(Arc::clone(cloned_poly.deref())).map(|_| backend.format()),
+            Err(err) => Err(err.clone())
         };
-    // log!(sample_rate);
-    // let config = use_state(|| supported_config.into());
-    let polyphony = use_state(|| Arc::new(Mutex::new(IterablePolyphonyHashMap::new(sample_rate))));
-    let stream = use_state(|| create_stream(&device, &config, Arc::clone(polyphony.deref())));
+        if let Err(err) = &status {
+            log!(format!("No audio output: {err}"));
+        }
+        cloned_audio_status.set(status);
+        move || {
+            if let Ok(backend) = cloned_backend.borrow_mut().as_mut() {
+                backend.stop();
+            }
+        }
+    });
+
+    let cloned_backend = backend.clone();
+    let cloned_poly = polyphony.clone();
+    let cloned_audio_devices = audio_devices.clone();
+    let cloned_audio_device = audio_device.clone();
+    let cloned_sample_rate = audio_sample_rate.clone();
+    let cloned_buffer_size = audio_buffer_size.clone();
+    let cloned_audio_status = audio_status.clone();
+    let audio_device_change = Callback::from(move |index: usize| {
+        let device = index.checked_sub(1).and_then(|index| cloned_audio_devices.get(index)).cloned();
+        let preferences = StreamPreferences { sample_rate: *cloned_sample_rate.deref(), buffer_size: *cloned_buffer_size.deref(), ..Default::default() };
+        cloned_audio_status.set(restart_audio(&cloned_backend, cloned_poly.deref(), device.as_deref(), preferences));
+        cloned_audio_device.set(device);
+        // Devices may have been plugged in or out since the list was made
+        cloned_audio_devices.set(output_device_names().unwrap_or_default());
+    });
+
+    let cloned_backend = backend.clone();
+    let cloned_poly = polyphony.clone();
+    let cloned_audio_device = audio_device.clone();
+    let cloned_sample_rate = audio_sample_rate.clone();
+    let cloned_buffer_size = audio_buffer_size.clone();
+    let cloned_audio_status = audio_status.clone();
+    let audio_sample_rate_change = Callback::from(move |index: usize| {
+        let rate = SAMPLE_RATES[index];
+        let preferences = StreamPreferences { sample_rate: rate, buffer_size: *cloned_buffer_size.deref(), ..Default::default() };
+        cloned_audio_status.set(restart_audio(&cloned_backend, cloned_poly.deref(), cloned_audio_device.deref().as_deref(), preferences));
+        cloned_sample_rate.set(rate);
+    });
+
+    let cloned_backend = backend.clone();
+    let cloned_poly = polyphony.clone();
+    let cloned_audio_device = audio_device.clone();
+    let cloned_sample_rate = audio_sample_rate.clone();
+    let cloned_buffer_size = audio_buffer_size.clone();
+    let cloned_engine_rate = engine_rate.clone();
+    let cloned_audio_status = audio_status.clone();
+    let engine_rate_change = Callback::from(move |index: usize| {
+        let rate = ENGINE_RATES[index];
+        cloned_poly.lock().unwrap().set_internal_sample_rate(rate);
+        let preferences = StreamPreferences { sample_rate: *cloned_sample_rate.deref(), buffer_size: *cloned_buffer_size.deref(), ..Default::default() };
+        cloned_audio_status.set(restart_audio(&cloned_backend, cloned_poly.deref(), cloned_audio_device.deref().as_deref(), preferences));
+        cloned_engine_rate.set(rate);
+    });
+
+    let cloned_backend = backend.clone();
+    let cloned_poly = polyphony.clone();
+    let cloned_audio_device = audio_device.clone();
+    let cloned_sample_rate = audio_sample_rate.clone();
+    let cloned_buffer_size = audio_buffer_size.clone();
+    let cloned_audio_status = audio_status.clone();
+    let audio_buffer_size_change = Callback::from(move |index: usize| {
+        let buffer_size = BUFFER_SIZES[index];
+        let preferences = StreamPreferences { sample_rate: *cloned_sample_rate.deref(), buffer_size, ..Default::default() };
+        cloned_audio_status.set(restart_audio(&cloned_backend, cloned_poly.deref(), cloned_audio_device.deref().as_deref(), preferences));
+        cloned_buffer_size.set(buffer_size);
+    });
+
+    let audio_device_names: Vec<String> = std::iter::once("Default".to_owned())
+        .chain(audio_devices.iter().cloned())
+        .collect();
+    let active_audio_device = audio_device.deref().as_ref()
+        .and_then(|selected| audio_devices.iter().position(|device| device == selected))
+        .map_or(0, |index| index + 1);
+    let sample_rate_names: Vec<String> = SAMPLE_RATES.iter()
+        .map(|rate| rate.map_or("Auto".to_owned(), |rate| format!("{rate} Hz")))
+        .collect();
+    let active_sample_rate = SAMPLE_RATES.iter().position(|rate| rate == audio_sample_rate.deref()).unwrap_or(0);
+    let engine_rate_names: Vec<String> = ENGINE_RATES.iter()
+        .map(|rate| rate.map_or("Device".to_owned(), |rate| format!("{rate} Hz")))
+        .collect();
+    let active_engine_rate = ENGINE_RATES.iter().position(|rate| rate == engine_rate.deref()).unwrap_or(0);
+    let buffer_size_names: Vec<String> = BUFFER_SIZES.iter()
+        .map(|frames| frames.map_or("Auto".to_owned(), |frames| frames.to_string()))
+        .collect();
+    let active_buffer_size = BUFFER_SIZES.iter().position(|frames| frames == audio_buffer_size.deref()).unwrap_or(0);
+    let audio_status_text = audio_status.deref().as_ref()
+        .map(|format| {
+            let latency = format.latency_ms().map_or(String::new(), |latency| format!(", {latency:.1} ms"));
+            let resampled = match sample_rate != format.sample_rate {
+                true => format!(", engine at {sample_rate} Hz"),
+                false => String::new(),
+            };
+            format!("{} Hz, {} channels, {}{}{}", format.sample_rate, format.channels, format.sample_format, latency, resampled)
+        })
+        .map_err(|err| err.clone());
+
     let keycode_maps = use_state(|| HashMap::from([
         ('A', 60),
         ('W', 61),
@@ -126,9 +269,9 @@ pub fn app() -> Html {
     // lfo.set_type(LFOType::Frequency);
     // lfo.set_oscillator(Oscillator::Triangle);
 
-    let gain = use_state(|| vec![0.5]);
+    let gain = use_state(|| vec![0.25]);
     let detune_semitones = use_state(|| vec![0]);
-    let osc1 = MultiOscillator::from(WaveTableOscillator::new(sample_rate, 44100, Oscillator::Sine, gain.deref().clone()[0], 0.0));
+    let osc1 = MultiOscillator::from(WaveTableOscillator::new(sample_rate, DEFAULT_TABLE_SIZE, Oscillator::Sine, gain.deref().clone()[0], 0.0));
     let oscillator = use_state(|| Synth::new(
         osc1,
         sample_rate,
@@ -198,6 +341,536 @@ pub fn app() -> Html {
         }
     });
 
+    let effect_slots = use_state(Vec::<EffectSlotView>::new);
+
+    let cloned_poly = polyphony.clone();
+    let cloned_effect_slots = effect_slots.clone();
+    let add_effect = Callback::from(move |index: usize| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        poly.effects_mut().push(EffectType::ALL[index]);
+        cloned_effect_slots.set(effect_slot_views(poly.effects()));
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_effect_slots = effect_slots.clone();
+    let remove_effect = Callback::from(move |index: usize| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let _ = poly.effects_mut().remove(index);
+        cloned_effect_slots.set(effect_slot_views(poly.effects()));
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_effect_slots = effect_slots.clone();
+    let move_effect = Callback::from(move |(from, to): (usize, usize)| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let _ = poly.effects_mut().move_slot(from, to);
+        cloned_effect_slots.set(effect_slot_views(poly.effects()));
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_effect_slots = effect_slots.clone();
+    let toggle_bypass = Callback::from(move |index: usize| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let bypassed = poly.effects().get(index).map(|slot| slot.is_bypassed()).unwrap_or(false);
+        let _ = poly.effects_mut().set_bypass(index, !bypassed);
+        cloned_effect_slots.set(effect_slot_views(poly.effects()));
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_effect_slots = effect_slots.clone();
+    let effect_mix_change = Callback::from(move |(index, mix): (usize, f64)| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let _ = poly.effects_mut().set_mix(index, mix as f32);
+        cloned_effect_slots.set(effect_slot_views(poly.effects()));
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_effect_slots = effect_slots.clone();
+    let effect_param_change = Callback::from(move |(index, param_index, value): (usize, usize, f64)| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let _ = poly.effects_mut().set_param(index, param_index, value as f32);
+        cloned_effect_slots.set(effect_slot_views(poly.effects()));
+    });
+    let available_effects: Vec<String> = EffectType::ALL.iter().map(|effect| effect.name().to_owned()).collect();
+
+    let dynamics = use_state(|| dynamics_view(polyphony.deref().lock().unwrap().dynamics()));
+    let playhead: UseStateHandle<Option<(usize, usize)>> = use_state(|| None);
+    let transport = use_state(|| transport_view(polyphony.deref().lock().unwrap().deref()));
+
+    // The gain reduction, the song position and the sequencer playhead change with the audio, so
+    // they are refreshed on a timer
+    let cloned_poly = polyphony.clone();
+    let cloned_dynamics = dynamics.clone();
+    let cloned_playhead = playhead.clone();
+    let cloned_transport = transport.clone();
+    use_effect_with((), move |_| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let interval = Interval::new(100, move || {
+            let poly = buffer.lock().unwrap();
+            cloned_dynamics.set(dynamics_view(poly.dynamics()));
+            cloned_transport.set(transport_view(poly.deref()));
+            let sequencer = poly.sequencer();
+            cloned_playhead.set(sequencer.current_step().map(|step| (sequencer.current_pattern(), step)));
+        });
+        move || drop(interval)
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_dynamics = dynamics.clone();
+    let toggle_compressor = Callback::from(move |_: ()| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let enabled = poly.dynamics().is_compressor_enabled();
+        poly.dynamics_mut().set_compressor_enabled(!enabled);
+        cloned_dynamics.set(dynamics_view(poly.dynamics()));
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_dynamics = dynamics.clone();
+    let toggle_limiter = Callback::from(move |_: ()| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let enabled = poly.dynamics().is_limiter_enabled();
+        poly.dynamics_mut().set_limiter_enabled(!enabled);
+        cloned_dynamics.set(dynamics_view(poly.dynamics()));
+    });
+
+    let threshold_change = compressor_param_change(&polyphony, &dynamics, CompressorParam::ThresholdDb);
+    let ratio_change = compressor_param_change(&polyphony, &dynamics, CompressorParam::Ratio);
+    let compressor_attack_change = compressor_param_change(&polyphony, &dynamics, CompressorParam::AttackMs);
+    let compressor_release_change = compressor_param_change(&polyphony, &dynamics, CompressorParam::ReleaseMs);
+
+    let cloned_poly = polyphony.clone();
+    let cloned_dynamics = dynamics.clone();
+    let ceiling_change = Callback::from(move |ceiling: f64| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        poly.dynamics_mut().limiter_mut().set_param(LimiterParam::CeilingDb, ceiling as f32);
+        cloned_dynamics.set(dynamics_view(poly.dynamics()));
+    });
+
+    // Clicks on the on-screen keys report their velocity just before the note starts, so it is kept
+    // in a ref that the mouse down handler can read straight away
+    let click_velocity = use_mut_ref(|| DEFAULT_VELOCITY);
+    let keyboard_velocity = use_state(|| DEFAULT_VELOCITY);
+
+    let cloned_click_velocity = click_velocity.clone();
+    let velocity_change = Callback::from(move |velocity: f64| {
+        *cloned_click_velocity.borrow_mut() = velocity as f32;
+    });
+
+    let cloned_keyboard_velocity = keyboard_velocity.clone();
+    let keyboard_velocity_change = Callback::from(move |velocity: f64| {
+        cloned_keyboard_velocity.set(velocity as f32);
+    });
+
+    let cloned_oscillator = oscillator.clone();
+    let velocity_curve_change = Callback::from(move |index: usize| {
+        let mut oscillator_type = cloned_oscillator.deref().clone();
+        oscillator_type.velocity_settings.curve = VelocityCurve::ALL[index];
+        cloned_oscillator.set(oscillator_type);
+    });
+
+    let cloned_oscillator = oscillator.clone();
+    let velocity_amplitude_change = Callback::from(move |amount: f64| {
+        let mut oscillator_type = cloned_oscillator.deref().clone();
+        oscillator_type.velocity_settings.amplitude_amount = amount as f32;
+        cloned_oscillator.set(oscillator_type);
+    });
+
+    let cloned_oscillator = oscillator.clone();
+    let velocity_filter_change = Callback::from(move |octaves: f64| {
+        let mut oscillator_type = cloned_oscillator.deref().clone();
+        oscillator_type.velocity_settings.filter_octaves = octaves as f32;
+        cloned_oscillator.set(oscillator_type);
+    });
+
+    let cloned_oscillator = oscillator.clone();
+    let velocity_attack_change = Callback::from(move |amount: f64| {
+        let mut oscillator_type = cloned_oscillator.deref().clone();
+        oscillator_type.velocity_settings.attack_amount = amount as f32;
+        cloned_oscillator.set(oscillator_type);
+    });
+    let velocity_settings = oscillator.deref().velocity_settings;
+    let velocity_curves: Vec<String> = VelocityCurve::ALL.iter().map(|curve| curve.name().to_owned()).collect();
+    let active_velocity_curve = VelocityCurve::ALL.iter().position(|curve| *curve == velocity_settings.curve).unwrap_or(0);
+
+    let controllers = use_state(|| *polyphony.deref().lock().unwrap().controllers());
+
+    let cloned_poly = polyphony.clone();
+    let cloned_controllers = controllers.clone();
+    let pitch_bend_change = Callback::from(move |bend: f64| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        poly.set_pitch_bend(bend as f32);
+        cloned_controllers.set(*poly.controllers());
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_controllers = controllers.clone();
+    let mod_wheel_change = Callback::from(move |position: f64| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        poly.set_mod_wheel(position as f32);
+        cloned_controllers.set(*poly.controllers());
+    });
+
+    let bend_up_change = controller_settings_change(&polyphony, &controllers, |settings, semitones: f64| {
+        settings.bend_up_semitones = semitones as f32;
+    });
+    let bend_down_change = controller_settings_change(&polyphony, &controllers, |settings, semitones: f64| {
+        settings.bend_down_semitones = semitones as f32;
+    });
+    let mod_target_change = controller_settings_change(&polyphony, &controllers, |settings, index: usize| {
+        settings.mod_wheel_target = ModWheelTarget::ALL[index];
+    });
+    let controller_settings = controllers.deref().settings();
+    let mod_targets: Vec<String> = ModWheelTarget::ALL.iter().map(|target| target.name().to_owned()).collect();
+    let active_mod_target = ModWheelTarget::ALL.iter().position(|target| *target == controller_settings.mod_wheel_target).unwrap_or(0);
+
+    let mpe = use_state(|| polyphony.deref().lock().unwrap().mpe().settings());
+    let toggle_mpe = mpe_settings_change(&polyphony, &mpe, |settings, _: ()| {
+        settings.enabled = !settings.enabled;
+    });
+    let mpe_zone_change = mpe_settings_change(&polyphony, &mpe, |settings, index: usize| {
+        settings.zone = MpeZone::ALL[index];
+    });
+    let member_channels_change = mpe_settings_change(&polyphony, &mpe, |settings, channels: f64| {
+        settings.member_channels = channels as u8;
+    });
+    let mpe_bend_range_change = mpe_settings_change(&polyphony, &mpe, |settings, semitones: f64| {
+        settings.bend_range_semitones = semitones as f32;
+    });
+    let pressure_target_change = mpe_settings_change(&polyphony, &mpe, |settings, index: usize| {
+        settings.pressure_target = ExpressionTarget::ALL[index];
+    });
+    let timbre_target_change = mpe_settings_change(&polyphony, &mpe, |settings, index: usize| {
+        settings.timbre_target = ExpressionTarget::ALL[index];
+    });
+    let mpe_settings = *mpe.deref();
+    let mpe_zones: Vec<String> = MpeZone::ALL.iter().map(|zone| zone.name().to_owned()).collect();
+    let active_mpe_zone = MpeZone::ALL.iter().position(|zone| *zone == mpe_settings.zone).unwrap_or(0);
+    let expression_targets: Vec<String> = ExpressionTarget::ALL.iter().map(|target| target.name().to_owned()).collect();
+    let active_pressure_target = ExpressionTarget::ALL.iter().position(|target| *target == mpe_settings.pressure_target).unwrap_or(0);
+    let active_timbre_target = ExpressionTarget::ALL.iter().position(|target| *target == mpe_settings.timbre_target).unwrap_or(0);
+
+    let midi_devices = use_state(Vec::<MidiDevice>::new);
+    let midi_device = use_state(|| None::<String>);
+    let midi_channel = use_state(|| None::<u8>);
+    let midi_keys = use_state(Vec::<u8>::new);
+    let midi_dispatcher = use_mut_ref(|| MidiDispatcher::new(oscillator.deref().clone()));
+
+    let cloned_midi_devices = midi_devices.clone();
+    let cloned_midi_device = midi_device.clone();
+    let midi_devices_change = Callback::from(move |devices: Vec<MidiDevice>| {
+        // A device that was unplugged can no longer be listened to
+        if let Some(selected) = cloned_midi_device.deref() {
+            if !devices.iter().any(|device| device.id == *selected) {
+                cloned_midi_device.set(None);
+            }
+        }
+        cloned_midi_devices.set(devices);
+    });
+
+    let cloned_midi_devices = midi_devices.clone();
+    let cloned_midi_device = midi_device.clone();
+    let midi_device_change = Callback::from(move |index: usize| {
+        cloned_midi_device.set(index.checked_sub(1).and_then(|index| cloned_midi_devices.get(index)).map(|device| device.id.clone()));
+    });
+
+    let cloned_dispatcher = midi_dispatcher.clone();
+    let cloned_midi_channel = midi_channel.clone();
+    let midi_channel_change = Callback::from(move |index: usize| {
+        let channel = index.checked_sub(1).map(|channel| channel as u8);
+        cloned_dispatcher.borrow_mut().set_channel(channel);
+        cloned_midi_channel.set(channel);
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_oscillator = oscillator.clone();
+    let cloned_dispatcher = midi_dispatcher.clone();
+    let cloned_controllers = controllers.clone();
+    let cloned_midi_keys = midi_keys.clone();
+    let midi_message = Callback::from(move |bytes: Vec<u8>| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let mut dispatcher = cloned_dispatcher.borrow_mut();
+        dispatcher.set_voice(cloned_oscillator.deref().clone());
+        for message in dispatcher.receive(&bytes, &mut poly) {
+            log!(format!("Unused MIDI message: {:?}", message));
+        }
+        // Clock messages arrive 24 times per beat, so only redraw when something changed
+        if *poly.controllers() != *cloned_controllers.deref() {
+            cloned_controllers.set(*poly.controllers());
+        }
+        if dispatcher.keys() != cloned_midi_keys.deref().as_slice() {
+            cloned_midi_keys.set(dispatcher.keys().to_vec());
+        }
+    });
+
+    let midi_device_names: Vec<String> = std::iter::once("All".to_owned())
+        .chain(midi_devices.iter().map(|device| device.name.clone()))
+        .collect();
+    let active_midi_device = midi_device.deref().as_ref()
+        .and_then(|selected| midi_devices.iter().position(|device| device.id == *selected))
+        .map_or(0, |index| index + 1);
+    let midi_channels: Vec<String> = std::iter::once("All".to_owned())
+        .chain((1..=NUM_CHANNELS).map(|channel| channel.to_string()))
+        .collect();
+    let active_midi_channel = midi_channel.deref().map_or(0, |channel| channel as usize + 1);
+    let lit_keys: Vec<char> = keycode_maps.iter()
+        .filter(|(_, key)| midi_keys.contains(key))
+        .map(|(key, _)| *key)
+        .collect();
+
+    let midi_output = use_state(|| polyphony.deref().lock().unwrap().midi_output().settings());
+    let midi_outputs = use_state(Vec::<MidiOutputPort>::new);
+    let midi_output_port = use_mut_ref(|| None::<MidiOutputPort>);
+    let midi_output_device = use_state(|| None::<String>);
+    let toggle_midi_output = midi_output_settings_change(&polyphony, &midi_output, |settings, _: ()| {
+        settings.enabled = !settings.enabled;
+    });
+    let toggle_local_control = midi_output_settings_change(&polyphony, &midi_output, |settings, _: ()| {
+        settings.local_control = !settings.local_control;
+    });
+    let midi_output_channel_change = midi_output_settings_change(&polyphony, &midi_output, |settings, index: usize| {
+        settings.channel = index as u8;
+    });
+
+    let cloned_midi_outputs = midi_outputs.clone();
+    let cloned_output_port = midi_output_port.clone();
+    let cloned_output_device = midi_output_device.clone();
+    let midi_outputs_change = Callback::from(move |outputs: Vec<MidiOutputPort>| {
+        // The port is looked up again, as it is lost when its device is unplugged
+        let port = cloned_output_device.deref().as_ref()
+            .and_then(|selected| outputs.iter().find(|output| output.device.id == *selected))
+            .cloned();
+        if port.is_none() {
+            cloned_output_device.set(None);
+        }
+        *cloned_output_port.borrow_mut() = port;
+        cloned_midi_outputs.set(outputs);
+    });
+
+    let cloned_midi_outputs = midi_outputs.clone();
+    let cloned_output_port = midi_output_port.clone();
+    let cloned_output_device = midi_output_device.clone();
+    let midi_output_device_change = Callback::from(move |index: usize| {
+        let port = cloned_midi_outputs.get(index).cloned();
+        cloned_output_device.set(port.as_ref().map(|port| port.device.id.clone()));
+        *cloned_output_port.borrow_mut() = port;
+    });
+
+    // Notes from the arpeggiator and the sequencer are produced with the audio, so the messages
+    // waiting for the MIDI output are sent on a short timer
+    let cloned_poly = polyphony.clone();
+    let cloned_output_port = midi_output_port.clone();
+    use_effect_with((), move |_| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let interval = Interval::new(5, move || {
+            let messages = buffer.lock().unwrap().take_midi_output();
+            if let Some(port) = cloned_output_port.borrow().as_ref() {
+                for message in messages {
+                    port.send(&message.to_bytes());
+                }
+            }
+        });
+        move || drop(interval)
+    });
+
+    let midi_output_settings = *midi_output.deref();
+    let midi_output_names: Vec<String> = midi_outputs.iter().map(|output| output.device.name.clone()).collect();
+    let active_midi_output = midi_output_device.deref().as_ref()
+        .and_then(|selected| midi_outputs.iter().position(|output| output.device.id == *selected));
+    let midi_output_channels: Vec<String> = (1..=NUM_CHANNELS).map(|channel| channel.to_string()).collect();
+
+    let voice_mode = use_state(|| polyphony.deref().lock().unwrap().voice_mode());
+    let voice_mode_change = voice_mode_settings_change(&polyphony, &voice_mode, |settings, index: usize| {
+        settings.mode = VoiceMode::ALL[index];
+    });
+    let note_priority_change = voice_mode_settings_change(&polyphony, &voice_mode, |settings, index: usize| {
+        settings.priority = NotePriority::ALL[index];
+    });
+    let glide_change = voice_mode_settings_change(&polyphony, &voice_mode, |settings, glide_ms: f64| {
+        settings.glide_ms = glide_ms as f32;
+    });
+    let glide_mode_change = voice_mode_settings_change(&polyphony, &voice_mode, |settings, index: usize| {
+        settings.glide_mode = GlideMode::ALL[index];
+    });
+    let toggle_legato_glide = voice_mode_settings_change(&polyphony, &voice_mode, |settings, _: ()| {
+        settings.legato_glide_only = !settings.legato_glide_only;
+    });
+    let voice_mode_settings = *voice_mode.deref();
+    let voice_modes: Vec<String> = VoiceMode::ALL.iter().map(|mode| mode.name().to_owned()).collect();
+    let active_voice_mode = VoiceMode::ALL.iter().position(|mode| *mode == voice_mode_settings.mode).unwrap_or(0);
+    let note_priorities: Vec<String> = NotePriority::ALL.iter().map(|priority| priority.name().to_owned()).collect();
+    let active_note_priority = NotePriority::ALL.iter().position(|priority| *priority == voice_mode_settings.priority).unwrap_or(0);
+    let glide_modes: Vec<String> = GlideMode::ALL.iter().map(|mode| mode.name().to_owned()).collect();
+    let active_glide_mode = GlideMode::ALL.iter().position(|mode| *mode == voice_mode_settings.glide_mode).unwrap_or(0);
+
+    let arpeggiator = use_state(|| polyphony.deref().lock().unwrap().arpeggiator().settings());
+    let toggle_arpeggiator = arpeggiator_settings_change(&polyphony, &arpeggiator, |settings, _: ()| {
+        settings.enabled = !settings.enabled;
+    });
+    let toggle_latch = arpeggiator_settings_change(&polyphony, &arpeggiator, |settings, _: ()| {
+        settings.latch = !settings.latch;
+    });
+    let arp_mode_change = arpeggiator_settings_change(&polyphony, &arpeggiator, |settings, index: usize| {
+        settings.mode = ArpMode::ALL[index];
+    });
+    let arp_rate_change = arpeggiator_settings_change(&polyphony, &arpeggiator, |settings, index: usize| {
+        settings.rate = ArpRate::ALL[index];
+    });
+    let arp_octaves_change = arpeggiator_settings_change(&polyphony, &arpeggiator, |settings, octaves: f64| {
+        settings.octaves = octaves as u8;
+    });
+    let arp_gate_change = arpeggiator_settings_change(&polyphony, &arpeggiator, |settings, gate: f64| {
+        settings.gate = gate as f32;
+    });
+    let arp_swing_change = arpeggiator_settings_change(&polyphony, &arpeggiator, |settings, swing: f64| {
+        settings.swing = swing as f32;
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_transport = transport.clone();
+    let cloned_oscillator = oscillator.clone();
+    let toggle_transport = Callback::from(move |_: ()| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        if poly.is_sequencer_armed() {
+            poly.stop_sequencer();
+        } else {
+            poly.start_sequencer(cloned_oscillator.deref().clone());
+        }
+        cloned_transport.set(transport_view(poly.deref()));
+    });
+    let tempo_change = transport_change(&polyphony, &transport, |poly, bpm: f64| {
+        poly.set_tempo(bpm as f32);
+    });
+    let numerator_change = transport_change(&polyphony, &transport, |poly, numerator: f64| {
+        let denominator = poly.transport().time_signature().denominator;
+        if let Ok(time_signature) = TimeSignature::new(numerator as u8, denominator) {
+            poly.set_time_signature(time_signature);
+        }
+    });
+    let denominator_change = transport_change(&polyphony, &transport, |poly, index: usize| {
+        let numerator = poly.transport().time_signature().numerator;
+        if let Ok(time_signature) = TimeSignature::new(numerator, BEAT_NOTE_VALUES[index]) {
+            poly.set_time_signature(time_signature);
+        }
+    });
+    let clock_source_change = transport_change(&polyphony, &transport, |poly, index: usize| {
+        poly.set_clock_source(ClockSource::ALL[index]);
+    });
+    let beat_note_values: Vec<String> = BEAT_NOTE_VALUES.iter().map(|value| format!("1/{value}")).collect();
+    let clock_sources: Vec<String> = ClockSource::ALL.iter().map(|source| source.name().to_owned()).collect();
+
+    let tuning = use_state(|| tuning_view(polyphony.deref().lock().unwrap().tuning(), None));
+    let tuning_presets: Vec<String> = TUNING_PRESETS.iter().map(|preset| preset.to_string()).collect();
+    let tuning_preset_change = tuning_change(&polyphony, &tuning, |current, index: usize| {
+        match TUNING_PRESETS[index] {
+            "Just intonation" => Tuning::just_intonation(current.reference_hz()),
+            _ => Tuning::equal_division([12, 19, 31][index], current.reference_hz()),
+        }
+    });
+    let tuning_reference_change = tuning_change(&polyphony, &tuning, |current, reference_hz: f64| {
+        let mut tuning = current.clone();
+        tuning.set_reference_hz(reference_hz as f32)?;
+        Ok(tuning)
+    });
+    let load_scale = tuning_change(&polyphony, &tuning, |current, text: String| {
+        let scale = Scale::from_scl(&text)?;
+        Tuning::new(&scale.description().to_owned(), scale, current.mapping().clone())
+    });
+    let load_mapping = tuning_change(&polyphony, &tuning, |current, text: String| {
+        let mapping = KeyboardMapping::from_kbm(&text)?;
+        Tuning::new(current.name(), current.scale().clone(), mapping)
+    });
+
+    let sequencer = use_state(|| polyphony.deref().lock().unwrap().sequencer().clone());
+    let edited_pattern = use_state(|| 0);
+
+    let sequencer_swing_change = sequencer_change(&polyphony, &sequencer, |sequencer, swing: f64| {
+        sequencer.set_swing(swing as f32);
+    });
+    let toggle_chain = sequencer_change(&polyphony, &sequencer, |sequencer, _: ()| {
+        let chain = match sequencer.chain().is_empty() {
+            true => (0..sequencer.patterns().len()).collect(),
+            false => Vec::new()
+        };
+        let _ = sequencer.set_chain(chain);
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_sequencer = sequencer.clone();
+    let cloned_edited_pattern = edited_pattern.clone();
+    let pattern_change = Callback::from(move |index: usize| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        // Without a chain the pattern being edited is the one that loops
+        if poly.sequencer().chain().is_empty() {
+            let _ = poly.sequencer_mut().select_pattern(index);
+        }
+        cloned_edited_pattern.set(index);
+        cloned_sequencer.set(poly.sequencer().clone());
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_sequencer = sequencer.clone();
+    let cloned_edited_pattern = edited_pattern.clone();
+    let add_pattern = Callback::from(move |_: ()| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let sequencer = poly.sequencer_mut();
+        if let Ok(index) = sequencer.add_pattern(16) {
+            if !sequencer.chain().is_empty() {
+                let _ = sequencer.set_chain((0..=index).collect());
+            }
+            cloned_edited_pattern.set(index);
+        }
+        cloned_sequencer.set(poly.sequencer().clone());
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_sequencer = sequencer.clone();
+    let pattern = *edited_pattern.deref();
+    let pattern_length_change = Callback::from(move |length: usize| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let _ = poly.sequencer_mut().set_pattern_len(pattern, length);
+        cloned_sequencer.set(poly.sequencer().clone());
+    });
+
+    let cloned_poly = polyphony.clone();
+    let cloned_sequencer = sequencer.clone();
+    let step_change = Callback::from(move |(index, step): (usize, StepView)| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let step = Step {
+            enabled: step.enabled,
+            note: step.note,
+            velocity: step.velocity as f32,
+            gate: step.gate as f32,
+            tie: step.tie,
+            probability: step.probability as f32,
+        };
+        let _ = poly.sequencer_mut().set_step(pattern, index, step);
+        cloned_sequencer.set(poly.sequencer().clone());
+    });
+    let sequencer_display = sequencer_view(sequencer.deref(), pattern, *playhead.deref());
+
+    let arpeggiator_settings = *arpeggiator.deref();
+    let arp_modes: Vec<String> = ArpMode::ALL.iter().map(|mode| mode.name().to_owned()).collect();
+    let active_arp_mode = ArpMode::ALL.iter().position(|mode| *mode == arpeggiator_settings.mode).unwrap_or(0);
+    let arp_rates: Vec<String> = ArpRate::ALL.iter().map(|rate| rate.name().to_owned()).collect();
+    let active_arp_rate = ArpRate::ALL.iter().position(|rate| *rate == arpeggiator_settings.rate).unwrap_or(0);
+
     let active_oscillators = use_state(|| vec![0; oscillator.deref().num_sources()]);
     let active_lfo = use_state(|| 0);
     let active_lfo_type = use_state(|| 0);
@@ -216,6 +889,7 @@ pub fn app() -> Html {
     let cloned_type_lfo = lfo_type.clone();
     let cloned_osc_gain = gain.clone();
     let cloned_osc_detune = detune_semitones.clone();
+    let cloned_click_velocity = click_velocity.clone();
     let mouse_down = Callback::from(move |label: (char, usize)| {
         let key_label = key_map_down.get(&label.0).unwrap_or(&0);
         log!("Holding key", label.0.to_string(), ", MIDI Note:", key_label.to_string());
@@ -331,12 +1005,12 @@ pub fn app() -> Html {
                 log!("Filter off");
             },
             '+' => {
-                let _ = oscillator_type.push(WaveTableOscillator::new(sample_rate, 44100, Oscillator::Sine, 0.7, 0.0));
+                let _ = oscillator_type.push(WaveTableOscillator::new(sample_rate, DEFAULT_TABLE_SIZE, Oscillator::Sine, 0.25, 0.0));
                 for (_, synths) in buffer.lock().unwrap().iterate_hashmap_mut() {
-                    let _ = synths.push(WaveTableOscillator::new(sample_rate, 44100, Oscillator::Sine, 0.7, 0.0));
+                    let _ = synths.push(WaveTableOscillator::new(sample_rate, DEFAULT_TABLE_SIZE, Oscillator::Sine, 0.25, 0.0));
                 }
                 active_indices.push(0);
-                list_of_gains.push(0.5);
+                list_of_gains.push(0.25);
                 list_of_detunes.push(0);
                 log!("Add an oscillator");
             }
@@ -401,10 +1075,8 @@ pub fn app() -> Html {
                 active_lfo_index = 4;
             },
             _ => {
-                let frequency = midi_to_hz(*key_label).unwrap_or(1.0);
-                let mut source = cloned_oscillator.deref().clone();
-                let _ = source.global_set_frequency(frequency);
-                new_buffer.lock().unwrap().insert(*key_label, source.clone());
+                let velocity = *cloned_click_velocity.borrow();
+                let _ = new_buffer.lock().unwrap().note_on(*key_label, cloned_oscillator.deref().clone(), velocity);
             }
         }
         cloned_poly.set(buffer);
@@ -423,7 +1095,7 @@ pub fn app() -> Html {
         let key_label = key_map_up.get(&label.0).unwrap_or(&0);
         let buffer = cloned_poly.deref().clone();
         let new_buffer = Arc::clone(&buffer);
-        let _ = new_buffer.lock().unwrap().remove(key_label);
+        let _ = new_buffer.lock().unwrap().note_off(*key_label);
         cloned_poly.set(buffer);
         log!("Lifted key", label.0.to_string(), ", MIDI Note:", key_map_up.get(&label.0).unwrap_or(&0).to_string());
     });
@@ -432,6 +1104,7 @@ pub fn app() -> Html {
     let key_map_down = keycode_maps.clone();
     let cloned_poly = polyphony.clone();
     let cloned_oscillator = oscillator.clone();
+    let cloned_keyboard_velocity = keyboard_velocity.clone();
     let key_down = Callback::from(move |label: char| {
         let key_label = key_map_down.get(&label).unwrap_or(&0);
         let cloned_key_map = &mut key_map_down.deref().clone();
@@ -463,10 +1136,8 @@ pub fn app() -> Html {
                     match exists_label {
                         Some(_) => (),
                         None => {
-                            let frequency = midi_to_hz(*key_label).unwrap_or(1.0);
-                            let mut source = cloned_oscillator.deref().clone();
-                            let _ = source.global_set_frequency(frequency);
-                            let _ = new_buffer.lock().unwrap().insert(*key_label, source);
+                            let velocity = *cloned_keyboard_velocity.deref();
+                            let _ = new_buffer.lock().unwrap().note_on(*key_label, cloned_oscillator.deref().clone(), velocity);
                         }
                     }
                 }
@@ -481,7 +1152,7 @@ pub fn app() -> Html {
     let key_up = Callback::from(move |label: char| {
         let key_label = key_map_up.get(&label).unwrap_or(&0);
         let buffer = Arc::clone(cloned_poly.deref());
-        let _ = buffer.lock().unwrap().remove(key_label);
+        let _ = buffer.lock().unwrap().note_off(*key_label);
         cloned_poly.set(buffer);
         log!("Lifted key", label.to_string(), ", MIDI Note:", key_map_up.get(&label).unwrap_or(&0).to_string());
     });
@@ -524,14 +1195,196 @@ pub fn app() -> Html {
             <LFOSelector mouse_down={mouse_down.clone()} mouse_up={mouse_up.clone()} freq_change={freq_lfo_change} active_index={active_lfo.deref()} active_index_type={active_lfo_type.deref()} freq={*lfo_freq.deref() as f64}/>
             <h1>{"Envelope"}</h1>
             <EnvelopeSettings attack_change={attack_change} decay_change={decay_change} sustain_change={sustain_change} attack={*attack_ms.deref() as f64} decay={*decay_ms.deref() as f64} sustain={*sustain_percentage.deref() as f64}/>
+            <h1>{"Velocity"}</h1>
+            <VelocitySettings
+                curves={velocity_curves}
+                active_curve={active_velocity_curve}
+                curve_change={velocity_curve_change}
+                amplitude={velocity_settings.amplitude_amount as f64}
+                amplitude_change={velocity_amplitude_change}
+                filter_octaves={velocity_settings.filter_octaves as f64}
+                filter_change={velocity_filter_change}
+                attack={velocity_settings.attack_amount as f64}
+                attack_change={velocity_attack_change}
+                keyboard_velocity={*keyboard_velocity.deref() as f64}
+                keyboard_velocity_change={keyboard_velocity_change}
+            />
+            <h1>{"Wheels"}</h1>
+            <WheelSettings
+                bend_up={controller_settings.bend_up_semitones as f64}
+                bend_up_change={bend_up_change}
+                bend_down={controller_settings.bend_down_semitones as f64}
+                bend_down_change={bend_down_change}
+                targets={mod_targets}
+                active_target={active_mod_target}
+                target_change={mod_target_change}
+            />
+            <h1>{"MPE"}</h1>
+            <MpeSelector
+                enabled={mpe_settings.enabled}
+                toggle_enabled={toggle_mpe}
+                zones={mpe_zones}
+                active_zone={active_mpe_zone}
+                zone_change={mpe_zone_change}
+                member_channels={mpe_settings.member_channels as f64}
+                member_channels_change={member_channels_change}
+                bend_range={mpe_settings.bend_range_semitones as f64}
+                bend_range_change={mpe_bend_range_change}
+                targets={expression_targets}
+                active_pressure_target={active_pressure_target}
+                pressure_target_change={pressure_target_change}
+                active_timbre_target={active_timbre_target}
+                timbre_target_change={timbre_target_change}
+            />
+            <h1>{"MIDI Input"}</h1>
+            <MidiInputSettings
+                devices={midi_device_names}
+                active_device={active_midi_device}
+                device_change={midi_device_change}
+                channels={midi_channels}
+                active_channel={active_midi_channel}
+                channel_change={midi_channel_change}
+            />
+            <h1>{"MIDI Output"}</h1>
+            <MidiOutputSelector
+                enabled={midi_output_settings.enabled}
+                toggle_enabled={toggle_midi_output}
+                local_control={midi_output_settings.local_control}
+                toggle_local_control={toggle_local_control}
+                devices={midi_output_names}
+                active_device={active_midi_output}
+                device_change={midi_output_device_change}
+                channels={midi_output_channels}
+                active_channel={midi_output_settings.channel as usize}
+                channel_change={midi_output_channel_change}
+            />
+            <MidiListener
+                device={midi_device.deref().clone()}
+                devices_change={midi_devices_change}
+                message={midi_message}
+                outputs_change={midi_outputs_change}
+            />
+            <h1>{"Voice"}</h1>
+            <VoiceModeSelector
+                modes={voice_modes}
+                active_mode={active_voice_mode}
+                mode_change={voice_mode_change}
+                priorities={note_priorities}
+                active_priority={active_note_priority}
+                priority_change={note_priority_change}
+                glide_ms={voice_mode_settings.glide_ms as f64}
+                glide_change={glide_change}
+                glide_modes={glide_modes}
+                active_glide_mode={active_glide_mode}
+                glide_mode_change={glide_mode_change}
+                legato_glide_only={voice_mode_settings.legato_glide_only}
+                toggle_legato_glide={toggle_legato_glide}
+            />
+            <h1>{"Transport"}</h1>
+            <TransportSettings
+                transport={transport.deref().clone()}
+                toggle_playing={toggle_transport}
+                bpm_change={tempo_change}
+                numerator_change={numerator_change}
+                denominators={beat_note_values}
+                denominator_change={denominator_change}
+                sources={clock_sources}
+                source_change={clock_source_change}
+            />
+            <h1>{"Arpeggiator"}</h1>
+            <ArpeggiatorSelector
+                enabled={arpeggiator_settings.enabled}
+                toggle_enabled={toggle_arpeggiator}
+                modes={arp_modes}
+                active_mode={active_arp_mode}
+                mode_change={arp_mode_change}
+                rates={arp_rates}
+                active_rate={active_arp_rate}
+                rate_change={arp_rate_change}
+                octaves={arpeggiator_settings.octaves as f64}
+                octaves_change={arp_octaves_change}
+                gate={arpeggiator_settings.gate as f64}
+                gate_change={arp_gate_change}
+                swing={arpeggiator_settings.swing as f64}
+                swing_change={arp_swing_change}
+                latch={arpeggiator_settings.latch}
+                toggle_latch={toggle_latch}
+            />
                 
             </div>
+            <div class="column3">
+            <h1>{"Effects"}</h1>
+            <EffectsRack
+                available={available_effects}
+                slots={effect_slots.deref().clone()}
+                add_effect={add_effect}
+                remove_effect={remove_effect}
+                move_effect={move_effect}
+                toggle_bypass={toggle_bypass}
+                mix_change={effect_mix_change}
+                param_change={effect_param_change}
+            />
+            <h1>{"Dynamics"}</h1>
+            <DynamicsSettings
+                dynamics={dynamics.deref().clone()}
+                toggle_compressor={toggle_compressor}
+                toggle_limiter={toggle_limiter}
+                threshold_change={threshold_change}
+                ratio_change={ratio_change}
+                attack_change={compressor_attack_change}
+                release_change={compressor_release_change}
+                ceiling_change={ceiling_change}
+            />
+            <h1>{"Audio"}</h1>
+            <AudioSettings
+                devices={audio_device_names}
+                active_device={active_audio_device}
+                device_change={audio_device_change}
+                sample_rates={sample_rate_names}
+                active_sample_rate={active_sample_rate}
+                sample_rate_change={audio_sample_rate_change}
+                engine_rates={engine_rate_names}
+                active_engine_rate={active_engine_rate}
+                engine_rate_change={engine_rate_change}
+                buffer_sizes={buffer_size_names}
+                active_buffer_size={active_buffer_size}
+                buffer_size_change={audio_buffer_size_change}
+                status={audio_status_text}
+            />
+            <h1>{"Tuning"}</h1>
+            <TuningSettings
+                tuning={tuning.deref().clone()}
+                presets={tuning_presets}
+                preset_change={tuning_preset_change}
+                reference_change={tuning_reference_change}
+                load_scale={load_scale}
+                load_mapping={load_mapping}
+            />
+            <h1>{"Sequencer"}</h1>
+            <SequencerGrid
+                sequencer={sequencer_display}
+                swing_change={sequencer_swing_change}
+                pattern_change={pattern_change}
+                add_pattern={add_pattern}
+                toggle_chain={toggle_chain}
+                length_change={pattern_length_change}
+                step_change={step_change}
+            />
+            </div>
 
         </div>
         <div class="row">
 
      
-            <MIDIKeyboard mouse_down={mouse_down.clone()} mouse_up={&mouse_up} key_down={&key_down} key_up={&key_up}/>
+            <div class="performance">
+                <ControllerWheels
+                    pitch_bend={controllers.deref().pitch_bend() as f64}
+                    pitch_bend_change={pitch_bend_change}
+                    mod_wheel={controllers.deref().mod_wheel() as f64}
+                    mod_wheel_change={mod_wheel_change}
+                />
+                <MIDIKeyboard mouse_down={mouse_down.clone()} mouse_up={&mouse_up} key_down={&key_down} key_up={&key_up} velocity_change={velocity_change} lit_keys={lit_keys}/>
+            </div>
             <p style="color: white">{"Current MIDI Range: "}{&key_map_clone.deref()[&'A']}{" - "}{&key_map_clone.deref()[&'K']}</p>
         </div>
 
@@ -608,3 +1461,271 @@ pub fn display_oscillators(
     }
     display
 }
+
+/// Builds the view of every slot in the master effects chain, for display in the `EffectsRack`.
+pub fn effect_slot_views(chain: &EffectsChain) -> Vec<EffectSlotView> {
+    chain.iter().map(|slot| {
+        let effect = slot.effect();
+        EffectSlotView {
+            name: effect.effect_type().name().to_owned(),
+            bypassed: slot.is_bypassed(),
+            mix: slot.mix() as f64,
+            params: effect.parameters().iter().enumerate().map(|(index, info)| EffectParamView {
+                name: info.name,
+                value: effect.get_param(index) as f64,
+                min: info.min as f64,
+                max: info.max as f64,
+            }).collect(),
+        }
+    }).collect()
+}
+
+/// Builds the view of the master dynamics section, for display in `DynamicsSettings`.
+pub fn dynamics_view(dynamics: &MasterDynamics) -> DynamicsView {
+    let compressor = dynamics.compressor();
+    DynamicsView {
+        compressor_enabled: dynamics.is_compressor_enabled(),
+        limiter_enabled: dynamics.is_limiter_enabled(),
+        threshold_db: compressor.get_param(CompressorParam::ThresholdDb) as f64,
+        ratio: compressor.get_param(CompressorParam::Ratio) as f64,
+        attack_ms: compressor.get_param(CompressorParam::AttackMs) as f64,
+        release_ms: compressor.get_param(CompressorParam::ReleaseMs) as f64,
+        ceiling_db: dynamics.limiter().get_param(LimiterParam::CeilingDb) as f64,
+        gain_reduction_db: dynamics.gain_reduction_db() as f64,
+    }
+}
+
+/// Creates a callback that sets one parameter of the master compressor and refreshes the dynamics view.
+pub fn compressor_param_change(
+    polyphony: &UseStateHandle<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    dynamics: &UseStateHandle<DynamicsView>,
+    param: CompressorParam
+) -> Callback<f64> {
+    let cloned_poly = polyphony.clone();
+    let cloned_dynamics = dynamics.clone();
+    Callback::from(move |value: f64| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        poly.dynamics_mut().compressor_mut().set_param(param, value as f32);
+        cloned_dynamics.set(dynamics_view(poly.dynamics()));
+    })
+}
+
+/// Stops the audio backend and opens it again on the device called `device`, or the default device,
+/// returning the format agreed with the device.
+pub fn restart_audio(
+    backend: &RefCell<Result<CpalBackend, String>>,
+    polyphony: &Arc<Mutex<IterablePolyphonyHashMap>>,
+    device: Option<&str>,
+    preferences: StreamPreferences
+) -> Result<StreamFormat, String> {
+    let mut backend = backend.borrow_mut();
+    if let Ok(backend) = backend.as_mut() {
+        backend.stop();
+    }
+    *backend = CpalBackend::open_named(device, preferences);
+    let status = match backend.as_mut() {
+        Ok(backend) => backend.start(Arc::clone(polyphony)).map(|_| backend.format()),
+        Err(err) => Err(err.clone())
+    };
+    if let Err(err) = &status {
+        log!(format!("No audio output: {err}"));
+    }
+    status
+}
+
+pub fn controller_settings_change<T: 'static>(
+    polyphony: &UseStateHandle<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    controllers: &UseStateHandle<Controllers>,
+    update: fn(&mut ControllerSettings, T)
+) -> Callback<T> {
+    let cloned_poly = polyphony.clone();
+    let cloned_controllers = controllers.clone();
+    Callback::from(move |value: T| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let mut settings = poly.controllers().settings();
+        update(&mut settings, value);
+        poly.set_controller_settings(settings);
+        cloned_controllers.set(*poly.controllers());
+    })
+}
+
+pub fn mpe_settings_change<T: 'static>(
+    polyphony: &UseStateHandle<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    mpe: &UseStateHandle<MpeSettings>,
+    update: fn(&mut MpeSettings, T)
+) -> Callback<T> {
+    let cloned_poly = polyphony.clone();
+    let cloned_mpe = mpe.clone();
+    Callback::from(move |value: T| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let mut settings = poly.mpe().settings();
+        update(&mut settings, value);
+        poly.set_mpe_settings(settings);
+        cloned_mpe.set(poly.mpe().settings());
+    })
+}
+
+pub fn midi_output_settings_change<T: 'static>(
+    polyphony: &UseStateHandle<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    midi_output: &UseStateHandle<MidiOutputSettings>,
+    update: fn(&mut MidiOutputSettings, T)
+) -> Callback<T> {
+    let cloned_poly = polyphony.clone();
+    let cloned_midi_output = midi_output.clone();
+    Callback::from(move |value: T| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let mut settings = poly.midi_output().settings();
+        update(&mut settings, value);
+        poly.set_midi_output_settings(settings);
+        cloned_midi_output.set(poly.midi_output().settings());
+    })
+}
+
+pub fn voice_mode_settings_change<T: 'static>(
+    polyphony: &UseStateHandle<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    voice_mode: &UseStateHandle<VoiceModeSettings>,
+    update: fn(&mut VoiceModeSettings, T)
+) -> Callback<T> {
+    let cloned_poly = polyphony.clone();
+    let cloned_voice_mode = voice_mode.clone();
+    Callback::from(move |value: T| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let mut settings = poly.voice_mode();
+        update(&mut settings, value);
+        poly.set_voice_mode(settings);
+        cloned_voice_mode.set(settings);
+    })
+}
+
+pub fn arpeggiator_settings_change<T: 'static>(
+    polyphony: &UseStateHandle<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    arpeggiator: &UseStateHandle<ArpeggiatorSettings>,
+    update: fn(&mut ArpeggiatorSettings, T)
+) -> Callback<T> {
+    let cloned_poly = polyphony.clone();
+    let cloned_arpeggiator = arpeggiator.clone();
+    Callback::from(move |value: T| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        let mut settings = poly.arpeggiator().settings();
+        update(&mut settings, value);
+        poly.set_arpeggiator(settings);
+        cloned_arpeggiator.set(poly.arpeggiator().settings());
+    })
+}
+
+/// Creates a callback that edits the step sequencer and refreshes its snapshot.
+pub fn sequencer_change<T: 'static>(
+    polyphony: &UseStateHandle<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    sequencer: &UseStateHandle<Sequencer>,
+    update: fn(&mut Sequencer, T)
+) -> Callback<T> {
+    let cloned_poly = polyphony.clone();
+    let cloned_sequencer = sequencer.clone();
+    Callback::from(move |value: T| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        update(poly.sequencer_mut(), value);
+        cloned_sequencer.set(poly.sequencer().clone());
+    })
+}
+
+/// Builds the view of the step sequencer, for display in the `SequencerGrid`.
+///
+/// # Arguments
+///
+/// * `sequencer` - The sequencer to display.
+/// * `pattern` - The index of the pattern being edited.
+/// * `playhead` - The pattern and step that are playing, if any.
+pub fn sequencer_view(sequencer: &Sequencer, pattern: usize, playhead: Option<(usize, usize)>) -> SequencerView {
+    let pattern = pattern.min(sequencer.patterns().len() - 1);
+    SequencerView {
+        swing: sequencer.swing() as f64,
+        patterns: sequencer.patterns().len(),
+        active_pattern: pattern,
+        chained: !sequencer.chain().is_empty(),
+        steps: sequencer.patterns()[pattern].steps().iter().map(|step| StepView {
+            enabled: step.enabled,
+            note: step.note,
+            velocity: step.velocity as f64,
+            gate: step.gate as f64,
+            tie: step.tie,
+            probability: step.probability as f64,
+        }).collect(),
+        current_step: playhead.filter(|(playing, _)| *playing == pattern).map(|(_, step)| step),
+    }
+}
+
+/// Note values a beat of the time signature can have.
+const BEAT_NOTE_VALUES: [u8; 4] = [2, 4, 8, 16];
+
+/// Builds the view of the transport, for display in `TransportSettings`.
+pub fn transport_view(polyphony: &IterablePolyphonyHashMap) -> TransportView {
+    let transport = polyphony.transport();
+    let time_signature = transport.time_signature();
+    let position = transport.position();
+    TransportView {
+        playing: polyphony.is_sequencer_armed(),
+        bpm: transport.bpm() as f64,
+        numerator: time_signature.numerator as f64,
+        active_denominator: BEAT_NOTE_VALUES.iter().position(|value| *value == time_signature.denominator).unwrap_or(1),
+        active_source: ClockSource::ALL.iter().position(|source| *source == transport.source()).unwrap_or(0),
+        position: format!("{}.{}.{:03}", position.bar, position.beat, position.tick),
+    }
+}
+
+/// Creates a callback that changes the transport and refreshes its view.
+pub fn transport_change<T: 'static>(
+    polyphony: &UseStateHandle<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    transport: &UseStateHandle<TransportView>,
+    update: fn(&mut IterablePolyphonyHashMap, T)
+) -> Callback<T> {
+    let cloned_poly = polyphony.clone();
+    let cloned_transport = transport.clone();
+    Callback::from(move |value: T| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        update(&mut poly, value);
+        cloned_transport.set(transport_view(poly.deref()));
+    })
+}
+
+/// Built-in tunings offered next to loading Scala files.
+const TUNING_PRESETS: [&str; 4] = ["12-EDO", "19-EDO", "31-EDO", "Just intonation"];
+
+/// Builds the view of a tuning, for display in `TuningSettings`.
+pub fn tuning_view(tuning: &Tuning, error: Option<String>) -> TuningView {
+    TuningView {
+        name: tuning.name().to_owned(),
+        description: tuning.description().to_owned(),
+        reference_hz: tuning.reference_hz() as f64,
+        error,
+    }
+}
+
+/// Creates a callback that builds a new tuning from the current one and, if that succeeds, retunes
+/// the synthesizer to it. Otherwise the error is shown next to the current tuning.
+pub fn tuning_change<T: 'static>(
+    polyphony: &UseStateHandle<Arc<Mutex<IterablePolyphonyHashMap>>>,
+    tuning: &UseStateHandle<TuningView>,
+    update: fn(&Tuning, T) -> Result<Tuning, String>
+) -> Callback<T> {
+    let cloned_poly = polyphony.clone();
+    let cloned_tuning = tuning.clone();
+    Callback::from(move |value: T| {
+        let buffer = Arc::clone(cloned_poly.deref());
+        let mut poly = buffer.lock().unwrap();
+        match update(poly.tuning(), value) {
+            Ok(tuning) => {
+                poly.set_tuning(tuning);
+                cloned_tuning.set(tuning_view(poly.tuning(), None));
+            },
+            Err(error) => cloned_tuning.set(tuning_view(poly.tuning(), Some(error))),
+        }
+    })
+}